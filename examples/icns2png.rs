@@ -30,7 +30,7 @@ use std::str::FromStr;
 
 fn main() {
     let num_args = env::args().count();
-    if num_args < 2 || num_args > 3 {
+    if !(2..=3).contains(&num_args) {
         println!("Usage: icns2png <path> [<ostype>]");
         return;
     }
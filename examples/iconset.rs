@@ -0,0 +1,104 @@
+//! Converts between ICNS files and `.iconset` directories, in the same way as
+//! Apple's `iconutil` tool.
+//!
+//! To unpack an ICNS file into an iconset directory, run:
+//!
+//! ```shell
+//! cargo run --example iconset <path/to/file.icns>
+//! # images will be saved to path/to/file.iconset/icon_16x16.png, etc.
+//! ```
+//!
+//! To pack an iconset directory into an ICNS file, run:
+//!
+//! ```shell
+//! cargo run --example iconset <path/to/file.iconset>
+//! # ICNS will be saved to path/to/file.icns
+//! ```
+//!
+//! An iconset directory holds one PNG file per icon size, named after the
+//! size in points and the scale factor (for example, `icon_32x32.png` for
+//! the 32x32-pixel icon and `icon_32x32@2x.png` for the 64x64-pixel one).
+//! Only the sizes that `iconutil` understands (16x16, 32x32, 128x128,
+//! 256x256, and 512x512, each at 1x and 2x) are unpacked; anything else is
+//! skipped with a warning.  Unpacked images are tagged with their resolution
+//! (72 DPI for 1x images, and 144 DPI for 2x ones), as `iconutil` does.
+//! When packing, each image is stored as a PNG icon type, as `iconutil`
+//! does, and any image that doesn't fit an icon type is skipped with a
+//! warning.
+
+extern crate icns;
+
+use icns::{IconFamily, TypePreference};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// The point sizes allowed in an iconset directory:
+const ICONSET_POINT_SIZES: &[u32] = &[16, 32, 128, 256, 512];
+
+fn main() {
+    if env::args().count() != 2 {
+        println!("Usage: iconset <path.icns | path.iconset>");
+        return;
+    }
+    let path = env::args().nth(1).unwrap();
+    let path = Path::new(&path);
+    if path.is_dir() {
+        pack(path);
+    } else {
+        unpack(path);
+    }
+}
+
+/// Writes each icon in the ICNS file at `icns_path` to a PNG file in a
+/// sibling `.iconset` directory.
+fn unpack(icns_path: &Path) {
+    let icns_file = BufReader::new(File::open(icns_path)
+        .expect("failed to open ICNS file"));
+    let family = IconFamily::read(icns_file)
+        .expect("failed to read ICNS file");
+    let iconset_path = icns_path.with_extension("iconset");
+    fs::create_dir_all(&iconset_path)
+        .expect("failed to create iconset directory");
+    let available = family.available_icons();
+    for size in family.available_sizes() {
+        if !ICONSET_POINT_SIZES.contains(&size.points) || size.scale > 2 {
+            println!("Skipping {} icon (not allowed in an iconset)", size);
+            continue;
+        }
+        // Several icon types can share a size; use the first one present.
+        let icon_type = size.icon_types()
+            .into_iter()
+            .find(|icon_type| available.contains(icon_type))
+            .unwrap();
+        let image = family.get_icon_with_type(icon_type)
+            .expect("failed to decode icon");
+        let png_path = iconset_path.join(format!("icon_{}.png", size));
+        let png_file = BufWriter::new(File::create(&png_path)
+            .expect("failed to create PNG file"));
+        image.write_png_with_scale(png_file, size.scale)
+            .expect("failed to write PNG file");
+        println!("Wrote {}", png_path.display());
+    }
+}
+
+/// Reads each PNG file in the iconset directory at `iconset_path` and writes
+/// them all to a sibling ICNS file.
+fn pack(iconset_path: &Path) {
+    let mut family = IconFamily::new();
+    family.set_type_preference(TypePreference::ModernFirst);
+    let skipped = family.add_png_directory(iconset_path)
+        .expect("failed to read iconset directory");
+    for png_path in skipped {
+        println!("Skipping {} (not a valid iconset image)",
+                 png_path.display());
+    }
+    let icns_path = iconset_path.with_extension("icns");
+    let icns_file = BufWriter::new(File::create(&icns_path)
+        .expect("failed to create ICNS file"));
+    family.write(icns_file).expect("failed to write ICNS file");
+    println!("Wrote {} ({} icons)",
+             icns_path.display(),
+             family.available_icons().len());
+}
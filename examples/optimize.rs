@@ -0,0 +1,114 @@
+//! Shrinks an ICNS file by removing elements it doesn't need and
+//! recompressing its PNG data, reporting the savings from each step.
+//!
+//! To optimize an ICNS file for macOS 10.13 and later, run:
+//!
+//! ```shell
+//! cargo run --example optimize <path/to/file.icns>
+//! # ICNS will be saved to path/to/file.optimized.icns
+//! ```
+//!
+//! To keep the icons needed by an older version of macOS, run:
+//!
+//! ```shell
+//! cargo run --example optimize <path/to/file.icns> <version>
+//! ```
+//!
+//! Where <version> is the oldest version that the icon must support (e.g.
+//! 10.7, which still uses the legacy RLE-compressed icon types at the two
+//! smallest sizes).
+
+extern crate icns;
+
+use icns::{IconFamily, Image, MacOSVersion};
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+fn main() {
+    let num_args = env::args().count();
+    if !(2..=3).contains(&num_args) {
+        println!("Usage: optimize <path> [<min macOS version>]");
+        return;
+    }
+    let min_macos = if num_args == 3 {
+        parse_version(&env::args().nth(2).unwrap())
+            .expect("invalid macOS version")
+    } else {
+        MacOSVersion::new(10, 13)
+    };
+    let icns_path = env::args().nth(1).unwrap();
+    let icns_path = Path::new(&icns_path);
+    let icns_file = BufReader::new(File::open(icns_path)
+        .expect("failed to open ICNS file"));
+    let mut family = IconFamily::read(icns_file)
+        .expect("failed to read ICNS file");
+    let original_length = family.total_length();
+    println!("Original: {} bytes, {} elements",
+             original_length,
+             family.elements().len());
+
+    // List what looks worth doing before doing it.
+    let analysis = family.compression_report();
+    for finding in analysis.findings.iter() {
+        println!("  {}", finding);
+    }
+    println!("Estimated savings: {} bytes", analysis.total_savings());
+
+    // Drop legacy icon types that min_macos doesn't need, as long as another
+    // icon of the same size remains.
+    family = family.pruned_for(min_macos);
+    report(&format!("Pruned for macOS {}", min_macos), &family);
+
+    // Only the first element of each OSType is ever used, so drop the rest.
+    let mut seen = Vec::new();
+    family.retain_elements(|element| {
+        if seen.contains(&element.ostype) {
+            return false;
+        }
+        seen.push(element.ostype);
+        true
+    });
+    report("Removed duplicates", &family);
+
+    // Re-encoding PNG data drops ancillary chunks (such as embedded color
+    // profiles and metadata) and often compresses better.
+    family.recompress_with(|_, png_data| {
+        let image = Image::read_png(png_data).ok()?;
+        let mut compressed = Vec::new();
+        image.write_png(&mut compressed).ok()?;
+        Some(compressed)
+    });
+    report("Recompressed PNG data", &family);
+
+    let final_length = family.total_length();
+    println!("Saved {} bytes ({:.1}%)",
+             original_length - final_length,
+             100.0 * f64::from(original_length - final_length) /
+             f64::from(original_length));
+    let output_path = icns_path.with_extension("optimized.icns");
+    let output_file = BufWriter::new(File::create(&output_path)
+        .expect("failed to create ICNS file"));
+    family.write(output_file).expect("failed to write ICNS file");
+    println!("Wrote {}", output_path.display());
+}
+
+/// Prints the size of the family after an optimization step.
+fn report(step: &str, family: &IconFamily) {
+    println!("{}: {} bytes, {} elements",
+             step,
+             family.total_length(),
+             family.elements().len());
+}
+
+/// Parses a version number like `10.13` or `11`.
+fn parse_version(string: &str) -> Option<MacOSVersion> {
+    let mut parts = string.splitn(2, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+        Some(minor) => minor.parse().ok()?,
+        None => 0,
+    };
+    Some(MacOSVersion::new(major, minor))
+}
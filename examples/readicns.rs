@@ -1,6 +1,18 @@
+//! Inspects an ICNS file without decoding any of its images, printing any
+//! warnings from reading it (including mismatches between the file's table
+//! of contents and its actual elements), each element's detected payload
+//! format and pixel dimensions, and a summary of where the file's bytes go.
+//!
+//! To inspect an ICNS file, run:
+//!
+//! ```shell
+//! cargo run --example readicns <path/to/file.icns>
+//! ```
+
 extern crate icns;
 
-use icns::IconFamily;
+use icns::{ElementKind, IconFamily, ReadOptions};
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::File;
 use std::io::BufReader;
@@ -13,12 +25,86 @@ fn main() {
     let path = env::args().nth(1).unwrap();
     let file = File::open(path).expect("failed to open file");
     let buffered = BufReader::new(file);
-    let family = IconFamily::read(buffered).expect("failed to read ICNS file");
-    println!("ICNS file contains {} element(s).", family.elements.len());
-    for (index, element) in family.elements.iter().enumerate() {
-        println!("Element {}: {} ({} byte payload)",
-                 index,
-                 element.ostype,
-                 element.data.len());
+    let (family, warnings) =
+        IconFamily::read_with(buffered, &ReadOptions::new())
+            .expect("failed to read ICNS file");
+    println!("ICNS file contains {} element(s).", family.elements().len());
+    for warning in warnings.iter() {
+        println!("  warning: {}", warning);
+    }
+
+    println!();
+    println!("Elements:");
+    let description = family.description();
+    for (index, (element, desc)) in
+        family.iter().zip(description.elements.iter()).enumerate() {
+        let dimensions = match desc.dimensions {
+            Some((width, height)) => format!("{}x{}", width, height),
+            None => "-".to_string(),
+        };
+        print!("  {:>2}: {} at offset {}, {} byte payload, {}, {}",
+               index,
+               element.ostype,
+               desc.offset,
+               element.data.len(),
+               desc.format,
+               dimensions);
+        if let Some(info) = element.compression_info() {
+            print!(" ({:.1}:1)", info.ratio());
+        }
+        println!();
+        if let Some(header) = element.png_header() {
+            let expected = element.icon_type()
+                .map(|icon_type| (icon_type.pixel_width(),
+                                  icon_type.pixel_height()));
+            if expected.is_some() &&
+               expected != Some((header.width, header.height)) {
+                println!("      warning: PNG header doesn't match the \
+                          element's icon type");
+            }
+        }
+    }
+
+
+    println!();
+    println!("Size breakdown:");
+    let mut by_kind: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+    let mut by_format: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for element in family.iter() {
+        let length = u64::from(element.total_length());
+        let kind = match element.kind() {
+            ElementKind::IconData(_) => "icon data",
+            ElementKind::Mask(_) => "masks",
+            ElementKind::Unknown => "unknown",
+            _ => "metadata",
+        };
+        add_to(by_kind.entry(kind).or_insert((0, 0)), length);
+        if element.icon_type().is_some() {
+            let format = element.payload_format().to_string();
+            add_to(by_format.entry(format).or_insert((0, 0)), length);
+        }
     }
+    println!("  header: 8 bytes");
+    for (kind, &(count, length)) in by_kind.iter() {
+        println!("  {}: {} element(s), {} bytes", kind, count, length);
+    }
+    println!("  by payload format:");
+    for (format, &(count, length)) in by_format.iter() {
+        println!("    {}: {} element(s), {} bytes", format, count, length);
+    }
+    println!("  total: {} bytes ({})",
+             family.total_length(),
+             family.element_counts_by_kind());
+    let savings = family.compression_report().total_savings();
+    if savings > 0 {
+        println!("  estimated possible savings: {} bytes (see the optimize \
+                  example)",
+                 savings);
+    }
+}
+
+/// Adds one element of the given length to a `(count, length)` tally.
+fn add_to(tally: &mut (usize, u64), length: u64) {
+    tally.0 += 1;
+    tally.1 += length;
 }
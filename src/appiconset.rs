@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Error, ErrorKind};
+use std::path::Path;
+
+#[cfg(feature = "pngio")]
+use std::io::{BufWriter, Write};
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::filename::is_plain_file_name;
+use super::icontype::{ALL_ICON_TYPES, Encoding, IconType};
+use super::pngcheck::PngHeader;
+use super::size::IconSize;
+
+#[cfg(feature = "pngio")]
+use super::payload::PayloadFormat;
+#[cfg(feature = "pngio")]
+use super::pngcheck::png_with_scale;
+
+/// The name of the file describing the contents of an asset catalog entry:
+const CONTENTS_FILE_NAME: &str = "Contents.json";
+
+/// The idiom used by an asset catalog for macOS icon images:
+const MAC_IDIOM: &str = "mac";
+
+/// The point sizes of the image slots in a macOS app icon set, each of which
+/// is available at 1x and 2x:
+#[cfg(feature = "pngio")]
+const MAC_POINT_SIZES: &[u32] = &[16, 32, 128, 256, 512];
+
+/// The `Contents.json` file of an `.appiconset` directory.
+#[derive(Deserialize, Serialize)]
+struct Contents {
+    #[serde(default)]
+    images: Vec<ImageEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    info: Option<Info>,
+}
+
+/// One image slot in an `.appiconset` directory's `Contents.json` file.
+#[derive(Deserialize, Serialize)]
+struct ImageEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    idiom: String,
+    scale: String,
+    size: String,
+}
+
+/// The `info` section of a `Contents.json` file.
+#[derive(Deserialize, Serialize)]
+struct Info {
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    version: u32,
+}
+
+impl IconFamily {
+    /// Reads an icon family from an Xcode asset catalog's `.appiconset`
+    /// directory (such as `Assets.xcassets/AppIcon.appiconset`), using the
+    /// images listed in its `Contents.json` file for the `mac` idiom.  Slots
+    /// without a file name, and images for other idioms (such as iOS), are
+    /// ignored.  Each PNG file is stored verbatim, using the PNG-based icon
+    /// type for its size and scale (e.g. `ic11` for `16x16` at `2x`).
+    ///
+    /// Returns an error if `Contents.json` can't be read or parsed, if a
+    /// listed file name isn't a plain file name within the directory (such
+    /// as `../icon.png` or an absolute path), if a listed PNG file can't be
+    /// read, or if a PNG file's dimensions don't match its slot.
+    ///
+    /// This method is only available when the `appiconset` feature is
+    /// enabled.
+    pub fn read_appiconset<P: AsRef<Path>>(dir: P) -> io::Result<IconFamily> {
+        let dir = dir.as_ref();
+        let file = BufReader::new(File::open(dir.join(CONTENTS_FILE_NAME))?);
+        let contents: Contents = serde_json::from_reader(file)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        let mut family = IconFamily::new();
+        for entry in contents.images {
+            let filename = match entry.filename {
+                Some(ref filename) if entry.idiom == MAC_IDIOM => filename,
+                _ => continue,
+            };
+            let size = parse_slot(&entry.size, &entry.scale)?;
+            let icon_type = png_icon_type(size).ok_or_else(|| {
+                let msg = format!("no PNG icon type has size {}", size);
+                Error::new(ErrorKind::InvalidData, msg)
+            })?;
+            if !is_plain_file_name(filename) {
+                let msg = format!("image file name {:?} is not a plain file \
+                                   name",
+                                  filename);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+            let data = fs::read(dir.join(filename))?;
+            let header = PngHeader::parse(&data)?;
+            let pixels = size.pixel_size();
+            if header.width != pixels || header.height != pixels {
+                let msg = format!("{} has wrong dimensions for {} ({}x{} \
+                                   instead of {}x{})",
+                                  filename,
+                                  size,
+                                  header.width,
+                                  header.height,
+                                  pixels,
+                                  pixels);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+            family.elements.push(IconElement::new(icon_type.ostype(), data));
+        }
+        Ok(family)
+    }
+
+    /// Writes the family's icons to an Xcode asset catalog `.appiconset`
+    /// directory (creating it if necessary), as a `Contents.json` file listing
+    /// the ten standard `mac` image slots, plus a PNG file (named like
+    /// `icon_32x32@2x.png`) for each slot that the family has an icon for.
+    /// Icons of other sizes are not written.  PNG data is copied verbatim
+    /// where the family already has it; other icons are decoded and
+    /// re-encoded as PNG.  The PNG files for 2x slots are given a `pHYs`
+    /// chunk declaring a resolution of 144 DPI, so that design tools display
+    /// them at their size in points.
+    ///
+    /// Returns an error if an icon can't be decoded (for example, because it
+    /// is stored as JPEG 2000), or if the directory or its files can't be
+    /// written.
+    ///
+    /// This method is only available when both the `appiconset` and `pngio`
+    /// features are enabled.
+    #[cfg(feature = "pngio")]
+    pub fn write_appiconset<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let available = self.available_icons();
+        let mut images = Vec::new();
+        for &points in MAC_POINT_SIZES {
+            for scale in 1..3 {
+                let size = IconSize::new(points, scale);
+                let icon_types: Vec<IconType> = size.icon_types()
+                    .into_iter()
+                    .filter(|icon_type| available.contains(icon_type))
+                    .collect();
+                let filename = if icon_types.is_empty() {
+                    None
+                } else {
+                    let filename = format!("icon_{}.png", size);
+                    let mut data = self.png_data_for_types(&icon_types)?;
+                    if scale > 1 {
+                        // Mark retina images as such for design tools.
+                        if let Some(scaled) = png_with_scale(&data, scale) {
+                            data = scaled;
+                        }
+                    }
+                    fs::write(dir.join(&filename), data)?;
+                    Some(filename)
+                };
+                images.push(ImageEntry {
+                    filename,
+                    idiom: MAC_IDIOM.to_string(),
+                    scale: format!("{}x", scale),
+                    size: format!("{}x{}", points, points),
+                });
+            }
+        }
+        let contents = Contents {
+            images,
+            info: Some(Info {
+                author: "xcode".to_string(),
+                version: 1,
+            }),
+        };
+        let path = dir.join(CONTENTS_FILE_NAME);
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, &contents)?;
+        file.flush()
+    }
+
+    /// Returns PNG data for an icon of one of the given (equally-sized,
+    /// available) icon types, preferring an element that already holds PNG
+    /// data over re-encoding the first type's image.
+    #[cfg(feature = "pngio")]
+    fn png_data_for_types(&self,
+                          icon_types: &[IconType])
+                          -> io::Result<Vec<u8>> {
+        for &icon_type in icon_types {
+            let element = self.find_element(icon_type)?;
+            if element.payload_format() == PayloadFormat::PNG {
+                return Ok(element.data.clone());
+            }
+        }
+        let image = self.get_icon_with_type(icon_types[0])?;
+        let mut data = Vec::new();
+        image.write_png(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Parses the `size` and `scale` fields of an asset catalog image slot
+/// (such as `"32x32"` and `"2x"`).
+fn parse_slot(size: &str, scale: &str) -> io::Result<IconSize> {
+    let invalid = || {
+        let msg = format!("invalid icon slot (size {:?}, scale {:?})",
+                          size,
+                          scale);
+        Error::new(ErrorKind::InvalidData, msg)
+    };
+    let mut parts = size.splitn(2, 'x');
+    let width: u32 = parts.next()
+        .and_then(|width| width.parse().ok())
+        .ok_or_else(invalid)?;
+    let height: u32 = parts.next()
+        .and_then(|height| height.parse().ok())
+        .ok_or_else(invalid)?;
+    let scale: u32 = scale.strip_suffix('x')
+        .and_then(|scale| scale.parse().ok())
+        .ok_or_else(invalid)?;
+    if width != height || width == 0 || scale == 0 {
+        return Err(invalid());
+    }
+    Ok(IconSize::new(width, scale))
+}
+
+/// Returns the PNG-based icon type with the given size, if any.
+fn png_icon_type(size: IconSize) -> Option<IconType> {
+    ALL_ICON_TYPES.iter().cloned().find(|icon_type| {
+        icon_type.encoding() == Encoding::JP2PNG && icon_type.size() == size
+    })
+}
+
+#[cfg(all(test, feature = "pngio"))]
+mod tests {
+    use super::*;
+    use super::super::fixtures::TempDir;
+    use super::super::image::{Image, PixelFormat};
+    use std::path::PathBuf;
+
+    fn make_appiconset(temp: &TempDir, contents: &str) -> PathBuf {
+        let dir = temp.path().join("Test.appiconset");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(CONTENTS_FILE_NAME), contents).unwrap();
+        for &size in &[16, 32] {
+            let image = Image::new(PixelFormat::RGBA, size, size);
+            let mut data = Vec::new();
+            image.write_png(&mut data).unwrap();
+            fs::write(dir.join(format!("{}.png", size)), data).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn read_mac_images() {
+        let contents = r#"{
+          "images" : [
+            { "idiom" : "mac", "size" : "16x16", "scale" : "1x",
+              "filename" : "16.png" },
+            { "idiom" : "mac", "size" : "16x16", "scale" : "2x",
+              "filename" : "32.png" },
+            { "idiom" : "mac", "size" : "32x32", "scale" : "1x" },
+            { "idiom" : "iphone", "size" : "16x16", "scale" : "2x",
+              "filename" : "16.png" }
+          ],
+          "info" : { "version" : 1, "author" : "xcode" }
+        }"#;
+        let temp = TempDir::new("appiconset-read");
+        let dir = make_appiconset(&temp, contents);
+        let family = IconFamily::read_appiconset(&dir).unwrap();
+        assert_eq!(family.available_icons(),
+                   vec![IconType::RGBA32_16x16, IconType::RGBA32_16x16_2x]);
+        let png = fs::read(dir.join("32.png")).unwrap();
+        assert_eq!(family.elements[1].data, png);
+    }
+
+    #[test]
+    fn read_mismatched_image() {
+        let contents = r#"{ "images" : [
+            { "idiom" : "mac", "size" : "32x32", "scale" : "1x",
+              "filename" : "16.png" } ] }"#;
+        let temp = TempDir::new("appiconset-mismatch");
+        let dir = make_appiconset(&temp, contents);
+        let error = IconFamily::read_appiconset(&dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x)
+            .unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+        let temp = TempDir::new("appiconset-write");
+        let dir = temp.path().join("Test.appiconset");
+        family.write_appiconset(&dir).unwrap();
+        let json = fs::read(dir.join(CONTENTS_FILE_NAME)).unwrap();
+        let contents: Contents = serde_json::from_slice(&json).unwrap();
+        assert_eq!(contents.images.len(), 10);
+        assert_eq!(contents.images
+                       .iter()
+                       .filter_map(|entry| entry.filename.clone())
+                       .collect::<Vec<String>>(),
+                   vec!["icon_16x16.png".to_string(),
+                        "icon_16x16@2x.png".to_string(),
+                        "icon_32x32.png".to_string()]);
+        let read = IconFamily::read_appiconset(&dir).unwrap();
+        assert_eq!(read.available_icons(),
+                   vec![IconType::RGBA32_16x16,
+                        IconType::RGBA32_16x16_2x,
+                        IconType::RGBA32_32x32]);
+        // Existing PNG data is copied verbatim, except that retina images
+        // are marked as 144 DPI:
+        assert_eq!(read.elements[1].data,
+                   png_with_scale(&family.elements[2].data, 2).unwrap());
+        assert_eq!(read.elements[2].data, family.elements[3].data);
+        assert_eq!(read.get_icon_with_type(IconType::RGBA32_16x16).unwrap(),
+                   family.get_icon_with_type(IconType::RGB24_16x16).unwrap());
+    }
+
+    #[test]
+    fn parse_slots() {
+        assert_eq!(parse_slot("512x512", "2x").unwrap(),
+                   IconSize::new(512, 2));
+        assert!(parse_slot("16x32", "1x").is_err());
+        assert!(parse_slot("16x16", "@2x").is_err());
+        assert!(parse_slot("16", "1x").is_err());
+    }
+
+    #[test]
+    fn read_escaping_file_name() {
+        let contents = r#"{ "images" : [
+            { "idiom" : "mac", "size" : "16x16", "scale" : "1x",
+              "filename" : "../16.png" } ] }"#;
+        let temp = TempDir::new("appiconset-escape");
+        let dir = make_appiconset(&temp, contents);
+        let error = IconFamily::read_appiconset(&dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
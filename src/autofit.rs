@@ -0,0 +1,187 @@
+use super::family::TypePreference;
+use super::icontype::ALL_ICON_TYPES;
+use super::image::{Image, PixelFormat};
+
+/// Determines what [`IconFamily::add_icon`](
+/// struct.IconFamily.html#method.add_icon) does with an image whose
+/// dimensions don't match any icon type (such as a 500x500 logo).  Images
+/// that are padded or scaled are first converted to RGBA, so that any added
+/// space is transparent.
+///
+/// # Examples
+/// ```
+/// use icns::{AutoFit, IconFamily, IconType, Image, PixelFormat};
+/// let mut family = IconFamily::new();
+/// let image = Image::new(PixelFormat::RGBA, 40, 40);
+/// assert!(family.add_icon(&image).is_err());
+/// family.set_auto_fit(AutoFit::Pad);
+/// family.add_icon(&image).unwrap();
+/// assert_eq!(family.available_icons(), vec![IconType::RGB24_48x48]);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum AutoFit {
+    /// Return an error.
+    #[default]
+    Exact,
+    /// Center the image on a transparent square of the smallest icon size
+    /// that can hold it.  Images larger than every icon size are scaled down
+    /// to the largest one instead.
+    Pad,
+    /// Crop the center of the image to the largest icon size that fits
+    /// within it.  Images smaller than every icon size are scaled up to the
+    /// smallest one instead.
+    Crop,
+    /// Scale the image (preserving its aspect ratio, and centering it on a
+    /// transparent square if it isn't square) to the icon size nearest its
+    /// larger dimension.
+    Scale,
+}
+
+impl AutoFit {
+    /// Returns a copy of the image fitted to a square icon size supported
+    /// under the given type preference, or `None` if this is
+    /// `AutoFit::Exact` (or there are no icon sizes at all, or the image has
+    /// zero width or height, since there is nothing to fit).
+    pub(crate) fn fit(self,
+                      image: &Image,
+                      preference: TypePreference)
+                      -> Option<Image> {
+        if image.width() == 0 || image.height() == 0 {
+            return None;
+        }
+        let sizes: Vec<u32> = icon_pixel_sizes()
+            .into_iter()
+            .filter(|&size| {
+                !preference.icon_types_for_size(size, size).is_empty()
+            })
+            .collect();
+        let (&smallest, &largest) = (sizes.first()?, sizes.last()?);
+        let long_side = image.width().max(image.height());
+        let short_side = image.width().min(image.height());
+        match self {
+            AutoFit::Exact => None,
+            AutoFit::Pad => {
+                match sizes.iter().find(|&&size| size >= long_side) {
+                    Some(&size) => Some(pad_to_square(image, size)),
+                    None => Some(scale_to_square(image, largest)),
+                }
+            }
+            AutoFit::Crop => {
+                match sizes.iter().rev().find(|&&size| size <= short_side) {
+                    Some(&size) => Some(crop_to_square(image, size)),
+                    None => Some(scale_to_square(image, smallest)),
+                }
+            }
+            AutoFit::Scale => {
+                let size = sizes.iter()
+                    .cloned()
+                    .min_by_key(|&size| {
+                        (size.max(long_side) - size.min(long_side),
+                         u32::MAX - size)
+                    })?;
+                Some(scale_to_square(image, size))
+            }
+        }
+    }
+}
+
+/// Returns the distinct pixel sizes of the square (non-mask) icon types, in
+/// ascending order.
+fn icon_pixel_sizes() -> Vec<u32> {
+    let mut sizes: Vec<u32> = ALL_ICON_TYPES.iter()
+        .filter(|icon_type| {
+            !icon_type.is_mask() &&
+            icon_type.pixel_width() == icon_type.pixel_height()
+        })
+        .map(|icon_type| icon_type.pixel_width())
+        .collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+}
+
+/// Centers the image (which must fit) on a transparent `size` by `size`
+/// RGBA image.
+fn pad_to_square(image: &Image, size: u32) -> Image {
+    let mut output = Image::new(PixelFormat::RGBA, size, size);
+    output.paste(&image.convert_to(PixelFormat::RGBA),
+                 (size - image.width()) / 2,
+                 (size - image.height()) / 2);
+    output
+}
+
+/// Returns the centered `size` by `size` region of the image (which must be
+/// at least that big).
+fn crop_to_square(image: &Image, size: u32) -> Image {
+    image.crop((image.width() - size) / 2,
+               (image.height() - size) / 2,
+               size,
+               size)
+}
+
+/// Scales the image, preserving its aspect ratio, so that it just fits
+/// within a transparent `size` by `size` RGBA image.
+fn scale_to_square(image: &Image, size: u32) -> Image {
+    let long_side = u64::from(image.width().max(image.height())).max(1);
+    let scaled_width = (u64::from(image.width()) * u64::from(size) /
+                        long_side)
+        .max(1) as u32;
+    let scaled_height = (u64::from(image.height()) * u64::from(size) /
+                         long_side)
+        .max(1) as u32;
+    let scaled = image.convert_to(PixelFormat::RGBA)
+        .resize(scaled_width, scaled_height);
+    pad_to_square(&scaled, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::family::IconFamily;
+    use super::super::icontype::IconType;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn fit_modes() {
+        let image = Image::new(PixelFormat::RGB, 500, 300);
+        let preference = TypePreference::ModernFirst;
+        assert!(AutoFit::Exact.fit(&image, preference).is_none());
+        let padded = AutoFit::Pad.fit(&image, preference).unwrap();
+        assert_eq!((padded.width(), padded.height()), (512, 512));
+        assert_eq!(padded.pixel_format(), PixelFormat::RGBA);
+        assert_eq!(&padded.data()[0..4], &[0, 0, 0, 0]);
+        let cropped = AutoFit::Crop.fit(&image, preference).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (256, 256));
+        assert_eq!(cropped.pixel_format(), PixelFormat::RGB);
+        let scaled = AutoFit::Scale.fit(&image, preference).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (512, 512));
+        let tiny = Image::new(PixelFormat::RGBA, 10, 12);
+        let cropped = AutoFit::Crop.fit(&tiny, preference).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (16, 16));
+        let huge = Image::new(PixelFormat::RGBA, 1100, 1000);
+        let padded = AutoFit::Pad.fit(&huge, preference).unwrap();
+        assert_eq!((padded.width(), padded.height()), (1024, 1024));
+    }
+
+    #[test]
+    fn add_icon_with_auto_fit() {
+        let mut family = IconFamily::new();
+        assert_eq!(family.auto_fit(), AutoFit::Exact);
+        family.set_auto_fit(AutoFit::Scale);
+        let image = Image::new(PixelFormat::RGBA, 30, 30);
+        family.add_icon(&image).unwrap();
+        assert_eq!(family.available_icons(), vec![IconType::RGB24_32x32]);
+    }
+
+    #[test]
+    fn auto_fit_zero_area_image() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 0, 5);
+        for &auto_fit in &[AutoFit::Pad, AutoFit::Crop, AutoFit::Scale] {
+            family.set_auto_fit(auto_fit);
+            let error = family.add_icon(&image).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        assert!(family.is_empty());
+    }
+}
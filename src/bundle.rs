@@ -0,0 +1,141 @@
+use plist;
+use std::fs::File;
+use std::io::{self, BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::family::IconFamily;
+use super::filename::is_plain_file_name;
+
+/// The Info.plist key naming the bundle's icon file:
+const ICON_FILE_KEY: &str = "CFBundleIconFile";
+
+/// The Info.plist key naming the bundle's icon within its asset catalog:
+const ICON_NAME_KEY: &str = "CFBundleIconName";
+
+impl IconFamily {
+    /// Returns the path of the ICNS file used as the icon for the given
+    /// application (or other) bundle, as named by the `CFBundleIconFile` key
+    /// (or failing that, the `CFBundleIconName` key) in the bundle's
+    /// `Contents/Info.plist` file.  Returns an error if the Info.plist file
+    /// can't be read, or names no icon, or if the named icon file doesn't
+    /// exist.  An icon name that isn't a plain file name (and so might refer
+    /// to a file outside the bundle's `Contents/Resources` directory) is an
+    /// error of kind `InvalidData`.
+    pub fn app_bundle_icon_path<P: AsRef<Path>>(bundle: P)
+                                                -> io::Result<PathBuf> {
+        let contents = bundle.as_ref().join("Contents");
+        let info = plist::Value::from_file(contents.join("Info.plist"))
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        let dict = info.as_dictionary().ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData,
+                       "Info.plist is not a dictionary")
+        })?;
+        let resources = contents.join("Resources");
+        for &key in &[ICON_FILE_KEY, ICON_NAME_KEY] {
+            let name = match dict.get(key).and_then(|val| val.as_string()) {
+                Some(name) if !name.is_empty() => name,
+                _ => continue,
+            };
+            if !is_plain_file_name(name) {
+                let msg = format!("{} {:?} is not a plain file name",
+                                  key,
+                                  name);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+            // The icon file name is permitted to omit the extension.
+            let mut path = resources.join(name);
+            if path.extension().is_none_or(|ext| ext != "icns") {
+                path = resources.join(format!("{}.icns", name));
+            }
+            if path.is_file() {
+                return Ok(path);
+            }
+        }
+        let msg = format!("bundle at {} has no ICNS icon file",
+                          bundle.as_ref().display());
+        Err(Error::new(ErrorKind::NotFound, msg))
+    }
+
+    /// Reads the icon family used as the icon for the given application (or
+    /// other) bundle.  See the
+    /// [`app_bundle_icon_path`](#method.app_bundle_icon_path) method for
+    /// details on how the icon file is located.
+    pub fn read_app_bundle<P: AsRef<Path>>(bundle: P)
+                                           -> io::Result<IconFamily> {
+        let path = IconFamily::app_bundle_icon_path(bundle)?;
+        IconFamily::read(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::TempDir;
+    use std::fs;
+
+    fn make_bundle(temp: &TempDir,
+                   plist_body: &str,
+                   icon_name: &str)
+                   -> PathBuf {
+        let bundle = temp.path().join("Test.app");
+        let resources = bundle.join("Contents").join("Resources");
+        fs::create_dir_all(&resources).unwrap();
+        let plist = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                             <plist version=\"1.0\"><dict>{}</dict>\
+                             </plist>\n",
+                            plist_body);
+        fs::write(bundle.join("Contents").join("Info.plist"), plist)
+            .unwrap();
+        fs::write(resources.join(icon_name), b"icns\0\0\0\x08").unwrap();
+        bundle
+    }
+
+    #[test]
+    fn icon_file_without_extension() {
+        let temp = TempDir::new("bundle-no-extension");
+        let bundle = make_bundle(&temp,
+                                 "<key>CFBundleIconFile</key>\
+                                  <string>AppIcon</string>",
+                                 "AppIcon.icns");
+        let path = IconFamily::app_bundle_icon_path(&bundle).unwrap();
+        assert_eq!(path, bundle.join("Contents/Resources/AppIcon.icns"));
+        let family = IconFamily::read_app_bundle(&bundle).unwrap();
+        assert!(family.is_empty());
+    }
+
+    #[test]
+    fn icon_name_fallback() {
+        let temp = TempDir::new("bundle-icon-name");
+        let bundle = make_bundle(&temp,
+                                 "<key>CFBundleIconName</key>\
+                                  <string>Foo</string>",
+                                 "Foo.icns");
+        let path = IconFamily::app_bundle_icon_path(&bundle).unwrap();
+        assert_eq!(path, bundle.join("Contents/Resources/Foo.icns"));
+    }
+
+    #[test]
+    fn missing_icon_file() {
+        let temp = TempDir::new("bundle-missing");
+        let bundle = make_bundle(&temp,
+                                 "<key>CFBundleIconFile</key>\
+                                  <string>Bar.icns</string>",
+                                 "Other.icns");
+        let result = IconFamily::app_bundle_icon_path(&bundle);
+        assert_eq!(result.err().map(|err| err.kind()),
+                   Some(ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn escaping_icon_file_name() {
+        let temp = TempDir::new("bundle-escape");
+        let bundle = make_bundle(&temp,
+                                 "<key>CFBundleIconFile</key>\
+                                  <string>../../../Evil.icns</string>",
+                                 "Evil.icns");
+        fs::write(temp.path().join("Evil.icns"), b"icns\0\0\0\x08").unwrap();
+        let result = IconFamily::app_bundle_icon_path(&bundle);
+        assert_eq!(result.err().map(|err| err.kind()),
+                   Some(ErrorKind::InvalidData));
+    }
+}
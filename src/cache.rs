@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::family::IconFamily;
+use super::image::Image;
+
+/// Identifies a cached icon: the file it came from (as it was when the icon
+/// was decoded), and the size it was decoded at.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CacheKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    file_length: u64,
+    size: u32,
+}
+
+/// A cached icon, along with when it was last used.
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    image: Arc<Image>,
+    last_used: u64,
+}
+
+/// A cache of decoded icons, read from ICNS files and scaled to requested
+/// sizes, for programs (such as application launchers) that display the same
+/// icons over and over.
+///
+/// Icons are keyed by file path, modification time, and file length, as
+/// well as by size, so an icon is decoded afresh if its file changes.  When
+/// the total size of the cached pixel data would exceed the cache's memory
+/// cap, the least recently used icons are evicted.
+///
+/// # Examples
+/// ```no_run
+/// use icns::IconCache;
+/// let mut cache = IconCache::new(16 * 1024 * 1024);
+/// // The first call reads and decodes the file; later calls (as long as
+/// // the file doesn't change) return the cached icon.
+/// let icon = cache.get("/path/to/AppIcon.icns", 64).unwrap();
+/// assert_eq!(icon.width(), 64);
+/// ```
+#[derive(Clone, Debug)]
+pub struct IconCache {
+    max_memory: usize,
+    memory_usage: usize,
+    clock: u64,
+    entries: HashMap<CacheKey, CacheEntry>,
+    recency: BTreeMap<u64, CacheKey>,
+}
+
+impl IconCache {
+    /// Creates an empty cache that holds at most `max_memory` bytes of
+    /// decoded pixel data.
+    pub fn new(max_memory: usize) -> IconCache {
+        IconCache {
+            max_memory,
+            memory_usage: 0,
+            clock: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the icon from the ICNS file at `path` that is best suited
+    /// for display at `size` by `size` pixels, scaled to exactly that size
+    /// (as by [`IconFamily::preview_at`](
+    /// struct.IconFamily.html#method.preview_at)), decoding it only if it
+    /// isn't already cached.  Returns an error if the file can't be read,
+    /// or for any of the reasons that `preview_at` would; errors aren't
+    /// cached.
+    ///
+    /// The returned icon is shared with the cache, so getting a cached icon
+    /// doesn't copy its pixel data.
+    pub fn get<P: AsRef<Path>>(&mut self,
+                               path: P,
+                               size: u32)
+                               -> io::Result<Arc<Image>> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path)?;
+        let key = CacheKey {
+            path: path.to_path_buf(),
+            modified: metadata.modified().ok(),
+            file_length: metadata.len(),
+            size,
+        };
+        if let Some(image) = self.touch(&key) {
+            return Ok(image);
+        }
+        let family = IconFamily::read(BufReader::new(File::open(path)?))?;
+        let image = Arc::new(family.preview_at(size)?);
+        self.insert(key, image.clone());
+        Ok(image)
+    }
+
+    /// Returns the number of icons in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the cache contains no icons.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total size of the cached pixel data, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    /// Returns the maximum total size of cached pixel data, in bytes.
+    pub fn max_memory(&self) -> usize {
+        self.max_memory
+    }
+
+    /// Changes the maximum total size of cached pixel data, evicting the
+    /// least recently used icons as necessary to fit within it.
+    pub fn set_max_memory(&mut self, max_memory: usize) {
+        self.max_memory = max_memory;
+        self.evict(0);
+    }
+
+    /// Removes every icon decoded from the file at `path`, at any size.
+    pub fn invalidate<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        let keys: Vec<CacheKey> = self.entries
+            .keys()
+            .filter(|key| key.path == path)
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    /// Removes every icon from the cache.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.memory_usage = 0;
+    }
+
+    /// Returns the cached icon for the given key, if any, marking it as the
+    /// most recently used.
+    fn touch(&mut self, key: &CacheKey) -> Option<Arc<Image>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = self.clock;
+        self.recency.insert(self.clock, key.clone());
+        Some(entry.image.clone())
+    }
+
+    /// Adds an icon to the cache, evicting others as necessary.  Icons too
+    /// large to ever fit aren't cached.
+    fn insert(&mut self, key: CacheKey, image: Arc<Image>) {
+        let size = image.data().len();
+        if size > self.max_memory {
+            return;
+        }
+        self.remove(&key);
+        self.evict(size);
+        self.clock += 1;
+        self.recency.insert(self.clock, key.clone());
+        self.entries.insert(key,
+                            CacheEntry {
+                                image,
+                                last_used: self.clock,
+                            });
+        self.memory_usage += size;
+    }
+
+    /// Removes the icon for the given key, if any.
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+            self.memory_usage -= entry.image.data().len();
+        }
+    }
+
+    /// Evicts the least recently used icons until `extra` more bytes fit
+    /// within the memory cap.
+    fn evict(&mut self, extra: usize) {
+        while self.memory_usage + extra > self.max_memory {
+            let key = match self.recency.values().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            self.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::TempDir;
+    use super::super::icontype::IconType;
+    use super::super::image::PixelFormat;
+
+    fn write_icns(path: &Path, size: u32) {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, size, size);
+        let icon_type = IconType::from_pixel_size(size, size).unwrap();
+        family.add_icon_with_type(&image, icon_type).unwrap();
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let temp = TempDir::new("cache");
+        let dir = temp.path();
+        let (a, b) = (dir.join("a.icns"), dir.join("b.icns"));
+        write_icns(&a, 16);
+        write_icns(&b, 32);
+        // Room for exactly two 16x16 RGBA icons:
+        let mut cache = IconCache::new(2 * 16 * 16 * 4);
+        let first = cache.get(&a, 16).unwrap();
+        cache.get(&b, 16).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.memory_usage(), cache.max_memory());
+        assert!(Arc::ptr_eq(&cache.get(&a, 16).unwrap(), &first));
+        cache.get(&a, 8).unwrap();
+        // The b icon was least recently used, so it was evicted:
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries.keys().all(|key| key.path == a));
+        // Icons too big for the cache are returned but not cached:
+        assert_eq!(cache.get(&b, 32).unwrap().width(), 32);
+        assert_eq!(cache.len(), 2);
+        cache.invalidate(&a);
+        assert!(cache.is_empty());
+        assert_eq!(cache.memory_usage(), 0);
+        assert!(cache.get(dir.join("missing.icns"), 16).is_err());
+    }
+
+    #[test]
+    fn cache_notices_changed_files() {
+        let temp = TempDir::new("cache-changed");
+        let path = temp.path().join("icon.icns");
+        write_icns(&path, 16);
+        let mut cache = IconCache::new(1 << 20);
+        cache.get(&path, 16).unwrap();
+        write_icns(&path, 32);
+        cache.get(&path, 16).unwrap();
+        assert_eq!(cache.len(), 2);
+        cache.set_max_memory(16 * 16 * 4);
+        assert_eq!(cache.len(), 1);
+    }
+}
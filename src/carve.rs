@@ -0,0 +1,157 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use super::family::{ICNS_MAGIC_LITERAL, ICON_FAMILY_HEADER_LENGTH,
+                    IconFamily};
+use super::options::ReadOptions;
+use super::repair::ELEMENT_HEADER_LENGTH;
+
+/// An ICNS file found embedded within a larger blob of data, as returned by
+/// [`IconFamily::find_embedded`](
+/// struct.IconFamily.html#method.find_embedded).
+#[derive(Clone, Debug)]
+pub struct EmbeddedFamily {
+    /// The offset of the start of the ICNS file within the blob, in bytes.
+    pub offset: usize,
+    /// The length of the ICNS file, in bytes.
+    pub length: usize,
+    /// The icon family parsed from the ICNS file.
+    pub family: IconFamily,
+}
+
+impl EmbeddedFamily {
+    /// Returns the bytes of the ICNS file within the blob that it was found
+    /// in.
+    pub fn data<'a>(&self, blob: &'a [u8]) -> &'a [u8] {
+        &blob[self.offset..(self.offset + self.length)]
+    }
+}
+
+impl IconFamily {
+    /// Scans an arbitrary blob of data (such as an executable, a resource
+    /// archive, or a memory dump) for embedded ICNS files, and returns the
+    /// ones found, in order of their offset within the blob.
+    ///
+    /// A candidate is any occurrence of the `icns` magic literal followed by
+    /// a declared length that fits within the rest of the blob.  It is only
+    /// reported if it contains at least one element, and if its elements
+    /// parse successfully and exactly fill the declared length; this weeds
+    /// out the many false positives that a four-byte literal produces in
+    /// large blobs.  ICNS files nested within a reported file (such as
+    /// `tile` variants) aren't reported separately.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// let mut blob = b"MZ...icns is not a header here...".to_vec();
+    /// let offset = blob.len();
+    /// family.write(&mut blob).unwrap();
+    /// blob.extend_from_slice(b"trailing junk");
+    /// let found = IconFamily::find_embedded(&blob);
+    /// assert_eq!(found.len(), 1);
+    /// assert_eq!(found[0].offset, offset);
+    /// assert_eq!(found[0].family.available_icons(),
+    ///            vec![IconType::RGB24_16x16]);
+    /// ```
+    pub fn find_embedded(blob: &[u8]) -> Vec<EmbeddedFamily> {
+        let header_length = ICON_FAMILY_HEADER_LENGTH as usize;
+        let mut found = Vec::new();
+        let mut offset = 0;
+        while offset + header_length <= blob.len() {
+            let candidate = &blob[offset..];
+            if !candidate.starts_with(ICNS_MAGIC_LITERAL) {
+                offset += 1;
+                continue;
+            }
+            let length = BigEndian::read_u32(&candidate[4..8]) as usize;
+            if length > header_length && length <= candidate.len() &&
+               elements_fill(&candidate[header_length..length]) {
+                let data = &candidate[..length];
+                if let Ok((family, _)) =
+                    IconFamily::read_with(data, &ReadOptions::new()) {
+                    found.push(EmbeddedFamily {
+                        offset,
+                        length,
+                        family,
+                    });
+                    offset += length;
+                    continue;
+                }
+            }
+            offset += 1;
+        }
+        found
+    }
+}
+
+/// Returns true if the given data (the part of a candidate ICNS file after
+/// its header) consists of a sequence of element headers and payloads whose
+/// lengths exactly fill it.  Only the headers are examined, so that most
+/// false positives can be rejected without allocating anything.
+fn elements_fill(data: &[u8]) -> bool {
+    let mut position = 0;
+    while position < data.len() {
+        if data.len() - position < ELEMENT_HEADER_LENGTH {
+            return false;
+        }
+        let length = BigEndian::read_u32(&data[(position + 4)..]) as usize;
+        if length < ELEMENT_HEADER_LENGTH || length > data.len() - position {
+            return false;
+        }
+        position += length;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::FixtureBuilder;
+    use super::super::icontype::{IconType, OSType};
+
+    #[test]
+    fn find_multiple_embedded_families() {
+        let first = FixtureBuilder::new().icon(IconType::RGB24_16x16).build();
+        let nested = FixtureBuilder::new()
+            .element(OSType(*b"tile"), first.clone())
+            .build();
+        let mut blob = b"icns\0\0\0\x08".to_vec();
+        blob.extend_from_slice(&first);
+        blob.extend_from_slice(b"icns\xff\xff\xff\xff");
+        let nested_offset = blob.len();
+        blob.extend_from_slice(&nested);
+        blob.extend_from_slice(b"icns\0\0");
+        let found = IconFamily::find_embedded(&blob);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].offset, 8);
+        assert_eq!(found[0].data(&blob), first.as_slice());
+        assert_eq!(found[0].family.available_icons(),
+                   vec![IconType::RGB24_16x16]);
+        assert_eq!(found[1].offset, nested_offset);
+        assert_eq!(found[1].length, nested.len());
+    }
+
+    #[test]
+    fn reject_overlong_elements() {
+        let data = FixtureBuilder::new()
+            .icon(IconType::RGB24_16x16)
+            .declared_length(20)
+            .build();
+        assert!(IconFamily::find_embedded(&data).is_empty());
+    }
+
+    #[test]
+    fn element_headers_must_fill_candidate() {
+        let data = FixtureBuilder::new()
+            .icon(IconType::RGB24_16x16)
+            .build();
+        let elements = &data[8..];
+        assert!(elements_fill(elements));
+        assert!(elements_fill(&[]));
+        assert!(!elements_fill(&elements[..(elements.len() - 1)]));
+        assert!(!elements_fill(&elements[..4]));
+        assert!(!elements_fill(b"is32\0\0\0\x04"));
+    }
+}
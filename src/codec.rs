@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Error, ErrorKind};
+use std::sync::Arc;
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::icontype::{IconType, OSType};
+use super::image::Image;
+
+/// A handler for an element type that this library doesn't support natively
+/// (such as a private or vendor-specific OSType), which can be registered
+/// in a [`CodecRegistry`](struct.CodecRegistry.html) so that elements of
+/// that type can be decoded and encoded through [`IconFamily`](
+/// struct.IconFamily.html) like any other icon.
+pub trait ElementCodec: Send + Sync {
+    /// Decodes an element's data payload into an image.
+    fn decode(&self, data: &[u8]) -> io::Result<Image>;
+
+    /// Encodes an image into a data payload for a new element.
+    fn encode(&self, image: &Image) -> io::Result<Vec<u8>>;
+}
+
+/// A set of [`ElementCodec`](trait.ElementCodec.html)s for custom element
+/// types, keyed by OSType.  Each icon family has its own registry (see
+/// [`IconFamily::set_codecs`](struct.IconFamily.html#method.set_codecs)),
+/// which is initially empty; registries are cheap to clone, so the same one
+/// can be shared between many families.
+///
+/// # Examples
+/// ```
+/// use icns::{CodecRegistry, ElementCodec, IconFamily, Image, OSType,
+///            PixelFormat};
+/// use std::io;
+///
+/// // A (made-up) element type that stores a grayscale 8x8 image raw:
+/// struct RawGray;
+/// impl ElementCodec for RawGray {
+///     fn decode(&self, data: &[u8]) -> io::Result<Image> {
+///         Image::from_data(PixelFormat::Gray, 8, 8, data.to_vec())
+///     }
+///     fn encode(&self, image: &Image) -> io::Result<Vec<u8>> {
+///         Ok(image.convert_to(PixelFormat::Gray).data().to_vec())
+///     }
+/// }
+///
+/// let ostype = OSType(*b"gry8");
+/// let mut codecs = CodecRegistry::new();
+/// codecs.register(ostype, RawGray).unwrap();
+/// let mut family = IconFamily::new();
+/// family.set_codecs(codecs);
+/// let image = Image::new(PixelFormat::RGBA, 8, 8);
+/// family.add_icon_with_ostype(&image, ostype).unwrap();
+/// assert_eq!(family.elements()[0].data.len(), 64);
+/// let decoded = family.get_icon_with_ostype(ostype).unwrap();
+/// assert_eq!(decoded.pixel_format(), PixelFormat::Gray);
+/// ```
+#[derive(Clone, Default)]
+pub struct CodecRegistry {
+    codecs: HashMap<OSType, Arc<dyn ElementCodec>>,
+}
+
+impl CodecRegistry {
+    /// Creates a new, empty registry.
+    pub fn new() -> CodecRegistry {
+        CodecRegistry::default()
+    }
+
+    /// Registers a codec for the given OSType, replacing any codec that was
+    /// previously registered for it.  Returns an error if the OSType is one
+    /// of the [`IconType`](enum.IconType.html)s that this library already
+    /// supports, since those can't be overridden.
+    pub fn register<C>(&mut self, ostype: OSType, codec: C) -> io::Result<()>
+        where C: ElementCodec + 'static
+    {
+        if IconType::from_ostype(ostype).is_some() {
+            let msg = format!("cannot register a codec for built-in icon \
+                               type '{}'",
+                              ostype);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        self.codecs.insert(ostype, Arc::new(codec));
+        Ok(())
+    }
+
+    /// Removes the codec registered for the given OSType, returning true if
+    /// there was one.
+    pub fn unregister(&mut self, ostype: OSType) -> bool {
+        self.codecs.remove(&ostype).is_some()
+    }
+
+    /// Returns the codec registered for the given OSType, if any.
+    pub fn get(&self, ostype: OSType) -> Option<&dyn ElementCodec> {
+        self.codecs.get(&ostype).map(|codec| &**codec)
+    }
+
+    /// Returns true if no codecs are registered.
+    pub fn is_empty(&self) -> bool {
+        self.codecs.is_empty()
+    }
+
+    /// Returns the OSTypes that have registered codecs, in sorted order.
+    pub fn ostypes(&self) -> Vec<OSType> {
+        let mut ostypes: Vec<OSType> = self.codecs.keys().cloned().collect();
+        ostypes.sort();
+        ostypes
+    }
+}
+
+impl fmt::Debug for CodecRegistry {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        out.debug_struct("CodecRegistry")
+            .field("ostypes", &self.ostypes())
+            .finish()
+    }
+}
+
+impl IconFamily {
+    /// Decodes an image from the family with the given OSType.  If the
+    /// OSType is a supported [`IconType`](enum.IconType.html), this is the
+    /// same as [`get_icon_with_type`](#method.get_icon_with_type);
+    /// otherwise, the first element with that OSType is decoded using the
+    /// codec registered for it in the family's [`codecs`](#method.codecs).
+    /// Returns an error if there is no such element, if no codec is
+    /// registered, or if the codec fails.
+    pub fn get_icon_with_ostype(&self, ostype: OSType) -> io::Result<Image> {
+        if let Some(icon_type) = IconType::from_ostype(ostype) {
+            return self.get_icon_with_type(icon_type);
+        }
+        let codec = self.custom_codec(ostype)?;
+        let element = self.elements
+            .iter()
+            .find(|element| element.ostype == ostype)
+            .ok_or_else(|| {
+                let msg = format!("the icon family does not contain a '{}' \
+                                   element",
+                                  ostype);
+                Error::new(ErrorKind::NotFound, msg)
+            })?;
+        codec.decode(&element.data)
+    }
+
+    /// Encodes the image into the family using the given OSType.  If the
+    /// OSType is a supported [`IconType`](enum.IconType.html), this is the
+    /// same as [`add_icon_with_type`](#method.add_icon_with_type); otherwise,
+    /// the image is encoded using the codec registered for it in the
+    /// family's [`codecs`](#method.codecs), and the new element is added
+    /// according to the family's [duplicate policy](
+    /// #method.set_duplicate_policy).  Returns an error if no codec is
+    /// registered, or if the codec fails.
+    pub fn add_icon_with_ostype(&mut self,
+                                image: &Image,
+                                ostype: OSType)
+                                -> io::Result<()> {
+        if let Some(icon_type) = IconType::from_ostype(ostype) {
+            return self.add_icon_with_type(image, icon_type);
+        }
+        let data = self.custom_codec(ostype)?.encode(image)?;
+        self.insert_elements(vec![IconElement::new(ostype, data)])
+    }
+
+    /// Private helper method.
+    fn custom_codec(&self, ostype: OSType) -> io::Result<&dyn ElementCodec> {
+        self.codecs().get(ostype).ok_or_else(|| {
+            let msg = format!("no codec is registered for OSType '{}'",
+                              ostype);
+            Error::new(ErrorKind::InvalidInput, msg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::family::DuplicatePolicy;
+    use super::super::image::PixelFormat;
+
+    struct Failing;
+
+    impl ElementCodec for Failing {
+        fn decode(&self, _data: &[u8]) -> io::Result<Image> {
+            Err(Error::new(ErrorKind::InvalidData, "cannot decode"))
+        }
+
+        fn encode(&self, image: &Image) -> io::Result<Vec<u8>> {
+            Ok(vec![image.width() as u8])
+        }
+    }
+
+    #[test]
+    fn cannot_override_built_in_types() {
+        let mut codecs = CodecRegistry::new();
+        let error = codecs.register(IconType::RGBA32_16x16.ostype(), Failing)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(codecs.is_empty());
+    }
+
+    #[test]
+    fn custom_codecs_follow_family_settings() {
+        let ostype = OSType(*b"vndr");
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 5, 5);
+        let error = family.add_icon_with_ostype(&image, ostype).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let mut codecs = CodecRegistry::new();
+        codecs.register(ostype, Failing).unwrap();
+        assert_eq!(format!("{:?}", codecs),
+                   "CodecRegistry { ostypes: [OSType([118, 110, 100, \
+                    114])] }");
+        family.set_codecs(codecs);
+        let error = family.get_icon_with_ostype(ostype).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        family.set_duplicate_policy(DuplicatePolicy::Error);
+        family.add_icon_with_ostype(&image, ostype).unwrap();
+        assert!(family.add_icon_with_ostype(&image, ostype).is_err());
+        assert_eq!(family.elements.len(), 1);
+        assert_eq!(family.elements[0].data, vec![5]);
+        let error = family.get_icon_with_ostype(ostype).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        // Built-in types don't need a codec:
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        let ostype = IconType::RGB24_16x16.ostype();
+        family.add_icon_with_ostype(&image, ostype).unwrap();
+        assert_eq!(family.get_icon_with_ostype(ostype).unwrap(), image);
+    }
+}
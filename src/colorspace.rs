@@ -0,0 +1,130 @@
+use super::image::{Image, PixelFormat};
+
+/// Linear-light RGB conversion matrix from Display P3 to sRGB:
+const P3_TO_SRGB: [[f32; 3]; 3] = [[1.224_940_2, -0.224_940_4, 0.0],
+                                   [-0.042_056_955, 1.042_057, 0.0],
+                                   [-0.019_637_555,
+                                    -0.078_636_05,
+                                    1.098_273_6]];
+
+/// Linear-light RGB conversion matrix from sRGB to Display P3:
+const SRGB_TO_P3: [[f32; 3]; 3] = [[0.822_462_1, 0.177_538, 0.0],
+                                   [0.033_194_2, 0.966_805_8, 0.0],
+                                   [0.017_082_632, 0.072_397_44, 0.910_519_9]];
+
+/// An RGB color space that image data may be encoded in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ColorSpace {
+    /// The standard sRGB color space, which is what ICNS icons are normally
+    /// assumed to be in.
+    SRGB,
+    /// The Display P3 color space, which has a wider gamut than sRGB (but
+    /// the same transfer function), and which Apple design tools often use
+    /// when exporting artwork.
+    DisplayP3,
+}
+
+impl Image {
+    /// Creates a copy of this image with its color data converted from one
+    /// color space to another.  Colors that fall outside the destination
+    /// color space's gamut are clipped.  Grayscale and alpha-only images are
+    /// unaffected (since both supported color spaces have the same white
+    /// point and transfer function), as is the alpha channel.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{ColorSpace, Image, PixelFormat};
+    /// let data = vec![128, 128, 128];
+    /// let image = Image::from_data(PixelFormat::RGB, 1, 1, data).unwrap();
+    /// let srgb = image.convert_color_space(ColorSpace::DisplayP3,
+    ///                                      ColorSpace::SRGB);
+    /// assert_eq!(srgb.data(), &[128, 128, 128]);
+    /// ```
+    pub fn convert_color_space(&self,
+                               from: ColorSpace,
+                               to: ColorSpace)
+                               -> Image {
+        let matrix = match (from, to) {
+            (ColorSpace::DisplayP3, ColorSpace::SRGB) => &P3_TO_SRGB,
+            (ColorSpace::SRGB, ColorSpace::DisplayP3) => &SRGB_TO_P3,
+            _ => return self.clone(),
+        };
+        let channels = match self.format {
+            PixelFormat::RGBA => 4,
+            PixelFormat::RGB => 3,
+            PixelFormat::GrayAlpha |
+            PixelFormat::Gray |
+            PixelFormat::Alpha => return self.clone(),
+        };
+        let to_linear: Vec<f32> = (0..256)
+            .map(|value| decode_transfer(value as f32 / 255.0))
+            .collect();
+        let mut output = self.clone();
+        for pixel in output.data.chunks_mut(channels) {
+            let linear = [to_linear[pixel[0] as usize],
+                          to_linear[pixel[1] as usize],
+                          to_linear[pixel[2] as usize]];
+            for (channel, row) in matrix.iter().enumerate() {
+                let value = row[0] * linear[0] + row[1] * linear[1] +
+                            row[2] * linear[2];
+                let encoded = encode_transfer(value.clamp(0.0, 1.0));
+                pixel[channel] = (encoded * 255.0).round() as u8;
+            }
+        }
+        output
+    }
+}
+
+/// Converts a gamma-encoded sRGB/Display P3 component to linear light.
+fn decode_transfer(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear-light component to gamma-encoded sRGB/Display P3.
+fn encode_transfer(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn p3_red_is_clipped_in_srgb() {
+        let data = vec![255, 0, 0, 200];
+        let image = Image::from_data(PixelFormat::RGBA, 1, 1, data).unwrap();
+        let srgb = image.convert_color_space(ColorSpace::DisplayP3,
+                                             ColorSpace::SRGB);
+        assert_eq!(srgb.data(), &[255, 0, 0, 200]);
+    }
+
+    #[test]
+    fn srgb_red_is_less_saturated_in_p3() {
+        let data = vec![255, 0, 0];
+        let image = Image::from_data(PixelFormat::RGB, 1, 1, data).unwrap();
+        let p3 = image.convert_color_space(ColorSpace::SRGB,
+                                           ColorSpace::DisplayP3);
+        assert_eq!(p3.data(), &[234, 51, 35]);
+        let srgb = p3.convert_color_space(ColorSpace::DisplayP3,
+                                          ColorSpace::SRGB);
+        assert_eq!(srgb.data(), &[255, 0, 0]);
+    }
+
+    #[test]
+    fn gray_is_unchanged() {
+        let data = vec![17, 99];
+        let image = Image::from_data(PixelFormat::GrayAlpha, 1, 1, data)
+            .unwrap();
+        let srgb = image.convert_color_space(ColorSpace::DisplayP3,
+                                             ColorSpace::SRGB);
+        assert_eq!(srgb.data(), &[17, 99]);
+    }
+}
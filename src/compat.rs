@@ -0,0 +1,265 @@
+use std::fmt;
+
+use super::family::IconFamily;
+use super::icontype::{ALL_ICON_TYPES, IconType};
+use super::iconutil::refresh_toc;
+
+/// A version of macOS (or Mac OS X), used to describe the oldest version
+/// that an icon needs to support.
+///
+/// Versions are ordered by major version first, then by minor version.
+///
+/// # Examples
+/// ```
+/// use icns::MacOSVersion;
+/// assert!(MacOSVersion::new(10, 13) < MacOSVersion::new(11, 0));
+/// assert_eq!(MacOSVersion::new(10, 7).to_string(), "10.7");
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MacOSVersion {
+    /// The major version number (e.g. 10 for Mac OS X 10.7, or 11 for macOS
+    /// Big Sur).
+    pub major: u32,
+    /// The minor version number (e.g. 7 for Mac OS X 10.7).
+    pub minor: u32,
+}
+
+impl MacOSVersion {
+    /// Creates a new macOS version number.
+    pub fn new(major: u32, minor: u32) -> MacOSVersion {
+        MacOSVersion { major, minor }
+    }
+}
+
+impl fmt::Display for MacOSVersion {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The icon types understood by every version of Mac OS X:
+const CLASSIC_TYPES: &[IconType] = &[IconType::RGB24_16x16,
+                                     IconType::Mask8_16x16,
+                                     IconType::RGB24_32x32,
+                                     IconType::Mask8_32x32,
+                                     IconType::RGB24_48x48,
+                                     IconType::Mask8_48x48,
+                                     IconType::RGB24_128x128,
+                                     IconType::Mask8_128x128];
+
+/// The PNG/JPEG 2000 types added in Mac OS X 10.5:
+const LEOPARD_TYPES: &[IconType] = &[IconType::RGBA32_256x256,
+                                     IconType::RGBA32_512x512];
+
+/// The types written by `iconutil` from Mac OS X 10.7 onwards, which still
+/// uses the RLE-compressed types (with masks) for the two smallest sizes:
+const LION_TYPES: &[IconType] = &[IconType::RGB24_16x16,
+                                  IconType::Mask8_16x16,
+                                  IconType::RGB24_32x32,
+                                  IconType::Mask8_32x32,
+                                  IconType::RGBA32_16x16_2x,
+                                  IconType::RGBA32_32x32_2x,
+                                  IconType::RGBA32_128x128,
+                                  IconType::RGBA32_128x128_2x,
+                                  IconType::RGBA32_256x256,
+                                  IconType::RGBA32_256x256_2x,
+                                  IconType::RGBA32_512x512,
+                                  IconType::RGBA32_512x512_2x];
+
+/// The types needed from macOS 10.13 onwards, which no longer needs the
+/// RLE-compressed types at all:
+const HIGH_SIERRA_TYPES: &[IconType] = &[IconType::RGBA32_16x16,
+                                         IconType::RGBA32_16x16_2x,
+                                         IconType::RGBA32_32x32,
+                                         IconType::RGBA32_32x32_2x,
+                                         IconType::RGBA32_128x128,
+                                         IconType::RGBA32_128x128_2x,
+                                         IconType::RGBA32_256x256,
+                                         IconType::RGBA32_256x256_2x,
+                                         IconType::RGBA32_512x512,
+                                         IconType::RGBA32_512x512_2x];
+
+impl IconType {
+    /// Returns the icon types (including any mask types) that an
+    /// application icon should contain in order to display well on every
+    /// version of macOS from `min_macos` onwards:
+    ///
+    /// * Before 10.5, only the RLE-compressed types and their masks.
+    /// * From 10.5, the 256x256 and 512x512 PNG types as well.
+    /// * From 10.7, the same set of types that `iconutil` produces: PNG
+    ///   types at every size and density, except that the 16x16 and 32x32
+    ///   icons still use the RLE-compressed types with masks.
+    /// * From 10.13, PNG types only.
+    ///
+    /// (Newer versions of `iconutil` also write the 16x16 and 32x32 icons as
+    /// ARGB `ic04` and `ic05` elements, but those types are not yet
+    /// supported by this library; the `icp4` and `icp5` PNG types are
+    /// recommended instead.)
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconType, MacOSVersion};
+    /// let types = IconType::recommended_types_for(MacOSVersion::new(10, 13));
+    /// assert!(types.contains(&IconType::RGBA32_16x16));
+    /// assert!(!types.contains(&IconType::RGB24_128x128));
+    /// ```
+    pub fn recommended_types_for(min_macos: MacOSVersion) -> Vec<IconType> {
+        if min_macos >= MacOSVersion::new(10, 13) {
+            HIGH_SIERRA_TYPES.to_vec()
+        } else if min_macos >= MacOSVersion::new(10, 7) {
+            LION_TYPES.to_vec()
+        } else if min_macos >= MacOSVersion::new(10, 5) {
+            CLASSIC_TYPES.iter().chain(LEOPARD_TYPES).cloned().collect()
+        } else {
+            CLASSIC_TYPES.to_vec()
+        }
+    }
+}
+
+impl IconFamily {
+    /// Returns a copy of this family without the icons that aren't needed
+    /// on macOS `min_macos` or later, which can make a shipping icon file
+    /// considerably smaller.  An icon is removed only if its type isn't one
+    /// of the [`recommended_types_for`](
+    /// enum.IconType.html#method.recommended_types_for) that version _and_
+    /// the family has another icon of the same size whose type is, so no
+    /// size is ever lost entirely.  A mask element is removed along with its
+    /// color element, and is never removed while its color element stays.
+    /// Elements that aren't part of a complete icon (such as `icnV`
+    /// elements) are kept as they are, except that a `TOC ` element is
+    /// rebuilt to list only the elements that remain.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, MacOSVersion, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_16x16).unwrap();
+    /// let pruned = family.pruned_for(MacOSVersion::new(10, 13));
+    /// assert_eq!(pruned.available_icons(), vec![IconType::RGBA32_16x16]);
+    /// assert_eq!(pruned.elements().len(), 1);
+    /// # }
+    /// ```
+    pub fn pruned_for(&self, min_macos: MacOSVersion) -> IconFamily {
+        let recommended = IconType::recommended_types_for(min_macos);
+        let available = self.available_icons();
+        let removed: Vec<IconType> = available.iter()
+            .cloned()
+            .filter(|icon_type| {
+                !recommended.contains(icon_type) &&
+                available.iter().any(|other| {
+                    other.size() == icon_type.size() &&
+                    recommended.contains(other)
+                })
+            })
+            .collect();
+        let mut family = self.clone();
+        family.elements.retain(|element| {
+            let icon_type = match element.icon_type() {
+                Some(icon_type) => icon_type,
+                None => return true,
+            };
+            if !icon_type.is_mask() {
+                return !removed.contains(&icon_type);
+            }
+            // Remove a mask only if its color element is being removed.
+            !ALL_ICON_TYPES.iter().any(|color_type| {
+                color_type.mask_type() == Some(icon_type) &&
+                removed.contains(color_type)
+            })
+        });
+        refresh_toc(&mut family.elements);
+        family
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "pngio")]
+    use super::super::image::{Image, PixelFormat};
+
+    #[test]
+    fn recommended_types_include_masks() {
+        let versions = [(10, 0), (10, 5), (10, 7), (10, 13), (14, 0)];
+        for &(major, minor) in &versions {
+            let version = MacOSVersion::new(major, minor);
+            let types = IconType::recommended_types_for(version);
+            for icon_type in &types {
+                if let Some(mask_type) = icon_type.mask_type() {
+                    assert!(types.contains(&mask_type));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn recommended_types_by_version() {
+        let old = IconType::recommended_types_for(MacOSVersion::new(10, 4));
+        assert_eq!(old.len(), 8);
+        let lion = IconType::recommended_types_for(MacOSVersion::new(10, 12));
+        assert!(lion.contains(&IconType::RGB24_16x16));
+        assert!(lion.contains(&IconType::RGBA32_512x512_2x));
+        assert!(!lion.contains(&IconType::RGB24_128x128));
+        let modern = IconType::recommended_types_for(MacOSVersion::new(11, 0));
+        assert!(modern.iter().all(|icon_type| {
+            !icon_type.is_mask() && icon_type.mask_type().is_none()
+        }));
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn pruned_for_keeps_every_size() {
+        let mut family = IconFamily::new();
+        for &icon_type in &[IconType::RGB24_16x16,
+                            IconType::RGBA32_16x16,
+                            IconType::RGB24_48x48,
+                            IconType::RGB24_128x128,
+                            IconType::RGBA32_128x128] {
+            let size = icon_type.pixel_width();
+            let image = Image::new(PixelFormat::RGBA, size, size);
+            family.add_icon_with_type(&image, icon_type).unwrap();
+        }
+        // An incomplete icon (with no mask) is left alone.
+        let image = Image::new(PixelFormat::RGB, 32, 32);
+        family.add_icon_without_mask(&image, IconType::RGB24_32x32).unwrap();
+        let pruned = family.pruned_for(MacOSVersion::new(10, 13));
+        assert_eq!(pruned.available_icons(),
+                   vec![IconType::RGBA32_16x16,
+                        IconType::RGB24_48x48,
+                        IconType::RGBA32_128x128]);
+        let has_element = |icon_type| {
+            pruned.elements
+                .iter()
+                .any(|element| element.icon_type() == Some(icon_type))
+        };
+        assert!(has_element(IconType::Mask8_48x48));
+        assert!(has_element(IconType::RGB24_32x32));
+        assert_eq!(pruned.elements().len(), 5);
+        let pruned = family.pruned_for(MacOSVersion::new(10, 4));
+        assert_eq!(pruned.available_icons(),
+                   vec![IconType::RGB24_16x16,
+                        IconType::RGB24_48x48,
+                        IconType::RGB24_128x128]);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn pruned_for_rebuilds_table_of_contents() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_16x16).unwrap();
+        let family = family.to_iconutil_layout();
+        let pruned = family.pruned_for(MacOSVersion::new(10, 13));
+        let ostypes: Vec<String> = pruned.iter()
+            .map(|element| element.ostype.to_string())
+            .collect();
+        assert_eq!(ostypes, vec!["TOC ", "icp4"]);
+        let toc = &pruned.elements()[0];
+        assert_eq!(toc.data.len(), 8);
+        assert_eq!(&toc.data[0..4], b"icp4");
+    }
+}
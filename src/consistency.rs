@@ -0,0 +1,160 @@
+use std::io;
+
+use super::family::IconFamily;
+use super::icontype::IconType;
+use super::image::{Image, PixelFormat};
+
+/// The width and height to which icons are scaled before being compared
+/// (this is smaller than the smallest icon size, so that every icon is
+/// filtered in roughly the same way):
+const COMPARISON_SIZE: u32 = 8;
+
+/// The similarity between two icons of different sizes in the same icon
+/// family, as computed by
+/// [`IconFamily::consistency_report`](
+/// struct.IconFamily.html#method.consistency_report).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IconPairSimilarity {
+    /// The first icon type of the pair.
+    pub first: IconType,
+    /// The second icon type of the pair.
+    pub second: IconType,
+    /// How similar the two icons look, from 0.0 (completely different) to
+    /// 1.0 (identical, once scaled to the same size).
+    pub similarity: f32,
+}
+
+/// A report comparing every pair of icons in an icon family.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsistencyReport {
+    /// The similarity of each pair of icons in the family, in the order that
+    /// the icons appear in the family.
+    pub pairs: Vec<IconPairSimilarity>,
+}
+
+impl ConsistencyReport {
+    /// Returns the pairs of icons whose similarity is below the given
+    /// threshold, from least to most similar.  A threshold of around 0.8 is
+    /// a reasonable starting point for catching icons that depict
+    /// something different altogether (such as a stale 16x16 icon left over
+    /// from an old logo).
+    pub fn inconsistent_pairs(&self,
+                              threshold: f32)
+                              -> Vec<IconPairSimilarity> {
+        let mut pairs: Vec<IconPairSimilarity> = self.pairs
+            .iter()
+            .cloned()
+            .filter(|pair| pair.similarity < threshold)
+            .collect();
+        pairs.sort_by(|a, b| a.similarity.total_cmp(&b.similarity));
+        pairs
+    }
+}
+
+impl IconFamily {
+    /// Compares every pair of (complete) icons in the family, after scaling
+    /// them to a common small size, and reports how similar each pair looks.
+    /// This can be used to catch icon families in which one size has
+    /// accidentally been left out of date.  Returns an error if any of the
+    /// icons can't be decoded.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// let report = family.consistency_report().unwrap();
+    /// assert_eq!(report.pairs.len(), 1);
+    /// assert!(report.inconsistent_pairs(0.8).is_empty());
+    /// ```
+    pub fn consistency_report(&self) -> io::Result<ConsistencyReport> {
+        let mut icons = Vec::new();
+        for icon_type in self.available_icons() {
+            let image = self.get_icon_with_type(icon_type)?;
+            icons.push((icon_type, normalize(&image)));
+        }
+        let mut pairs = Vec::new();
+        for (index, &(first, ref first_data)) in icons.iter().enumerate() {
+            for &(second, ref second_data) in &icons[(index + 1)..] {
+                pairs.push(IconPairSimilarity {
+                    first,
+                    second,
+                    similarity: similarity(first_data, second_data),
+                });
+            }
+        }
+        Ok(ConsistencyReport { pairs })
+    }
+}
+
+/// Scales an icon to the comparison size and returns its RGBA data, with
+/// color premultiplied by alpha (so that the color of transparent pixels is
+/// ignored).
+fn normalize(image: &Image) -> Vec<u8> {
+    let mut data = image.convert_to(PixelFormat::RGBA)
+        .resize(COMPARISON_SIZE, COMPARISON_SIZE)
+        .into_data()
+        .into_vec();
+    for pixel in data.chunks_mut(4) {
+        let alpha = u32::from(pixel[3]);
+        for value in pixel[0..3].iter_mut() {
+            *value = ((u32::from(*value) * alpha + 127) / 255) as u8;
+        }
+    }
+    data
+}
+
+/// Returns the similarity between two normalized icons, from 0.0 to 1.0.
+fn similarity(first: &[u8], second: &[u8]) -> f32 {
+    let total_difference: u64 = first.iter()
+        .zip(second.iter())
+        .map(|(&a, &b)| u64::from(a.abs_diff(b)))
+        .sum();
+    let max_difference = 255 * first.len() as u64;
+    1.0 - (total_difference as f32 / max_difference as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(size: u32, invert: bool) -> Image {
+        let mut image = Image::new(PixelFormat::RGBA, size, size);
+        let cell = size / 4;
+        for (index, pixel) in image.data_mut().chunks_mut(4).enumerate() {
+            let x = index as u32 % size / cell;
+            let y = index as u32 / size / cell;
+            let white = (x + y).is_multiple_of(2) != invert;
+            let value = if white { 255 } else { 0 };
+            pixel.copy_from_slice(&[value, value, value, 255]);
+        }
+        image
+    }
+
+    #[test]
+    fn consistent_and_inconsistent_icons() {
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&checkerboard(32, false),
+                                  IconType::RGB24_32x32)
+            .unwrap();
+        family.add_icon_with_type(&checkerboard(16, false),
+                                  IconType::RGB24_16x16)
+            .unwrap();
+        family.add_icon_with_type(&checkerboard(48, true),
+                                  IconType::RGB24_48x48)
+            .unwrap();
+        let report = family.consistency_report().unwrap();
+        assert_eq!(report.pairs.len(), 3);
+        assert_eq!(report.pairs[0].first, IconType::RGB24_32x32);
+        assert_eq!(report.pairs[0].second, IconType::RGB24_16x16);
+        assert!(report.pairs[0].similarity > 0.95);
+        let inconsistent = report.inconsistent_pairs(0.8);
+        assert_eq!(inconsistent.len(), 2);
+        assert!(inconsistent.iter().all(|pair| {
+            pair.second == IconType::RGB24_48x48
+        }));
+    }
+}
@@ -0,0 +1,178 @@
+/// What to do when decoding a PNG or JPEG 2000 icon element whose image has
+/// different dimensions than the element's icon type calls for (e.g. a
+/// 256x256 PNG stored in an `ic07` element, which should be 128x128).
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum SizeMismatch {
+    /// Return an error.  This is the default.
+    #[default]
+    Error,
+    /// Resample the decoded image to the dimensions of the icon type.
+    Resize,
+    /// Return the decoded image at its actual size.  Callers can detect that
+    /// this has happened by comparing the image's dimensions to those of the
+    /// icon type.
+    KeepActualSize,
+}
+
+/// What to do when decoding an icon type that uses a separate mask element
+/// (such as `RGB24_32x32`, whose mask is `Mask8_32x32`), if the family
+/// contains the color element but not the mask element.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum MissingMask {
+    /// Return an error of kind `NotFound`.  This is the default.
+    #[default]
+    Error,
+    /// Return the color data as a fully opaque image.
+    Opaque,
+}
+
+/// What to do when decoding a mask element (such as `s8mk`) whose data
+/// payload is longer or shorter than the icon type's pixel count, as some
+/// third-party tools write masks with padded or truncated rows.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum MaskLength {
+    /// Return an error of kind `InvalidData`.  This is the default.
+    #[default]
+    Error,
+    /// Truncate the mask data to the expected length, or pad it with fully
+    /// opaque alpha values.
+    Lenient,
+}
+
+/// What to do when asked to decode an icon type that the family doesn't
+/// contain a complete icon for.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum MissingIcon {
+    /// Return an error of kind `NotFound`.  This is the default.
+    #[default]
+    Error,
+    /// Decode the nearest larger complete icon in the family and scale it
+    /// down to the requested icon type's dimensions (or, if the family has
+    /// no larger icon, scale up the nearest smaller one).  An error is
+    /// still returned if the family has no complete icons at all.
+    Synthesize,
+}
+
+/// Options that control how icon elements are decoded, for use with
+/// [`IconElement::decode_image_with_options`](
+/// struct.IconElement.html#method.decode_image_with_options) and
+/// [`IconFamily::get_icon_with_type_and_options`](
+/// struct.IconFamily.html#method.get_icon_with_type_and_options).
+///
+/// The default options are the strict ones used by
+/// [`IconElement::decode_image`](struct.IconElement.html#method.decode_image)
+/// and [`IconFamily::get_icon_with_type`](
+/// struct.IconFamily.html#method.get_icon_with_type).
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "pngio")] {
+/// use icns::{DecodeOptions, IconElement, IconType, Image, OSType,
+///            PixelFormat, SizeMismatch};
+/// // Store a 32x32 PNG in a 16x16 (icp4) element:
+/// let image = Image::new(PixelFormat::RGBA, 32, 32);
+/// let mut element =
+///     IconElement::encode_image_with_type(&image, IconType::RGBA32_16x16_2x)
+///         .unwrap();
+/// element.ostype = OSType(*b"icp4");
+/// assert!(element.decode_image().is_err());
+/// let options = DecodeOptions::new().size_mismatch(SizeMismatch::Resize);
+/// let decoded = element.decode_image_with_options(&options).unwrap();
+/// assert_eq!(decoded.width(), 16);
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DecodeOptions {
+    pub(crate) size_mismatch: SizeMismatch,
+    pub(crate) missing_mask: MissingMask,
+    pub(crate) mask_length: MaskLength,
+    pub(crate) missing_icon: MissingIcon,
+}
+
+impl DecodeOptions {
+    /// Creates a new set of decode options, initially the strict defaults.
+    pub fn new() -> DecodeOptions {
+        DecodeOptions::default()
+    }
+
+    /// Sets what to do when a PNG or JPEG 2000 element's image has the
+    /// wrong dimensions for its icon type.
+    pub fn size_mismatch(mut self,
+                         size_mismatch: SizeMismatch)
+                         -> DecodeOptions {
+        self.size_mismatch = size_mismatch;
+        self
+    }
+
+    /// Sets what to do when an icon's separate mask element is missing from
+    /// the family.  This only affects methods that decode icons from an
+    /// [`IconFamily`](struct.IconFamily.html), since a lone
+    /// [`IconElement`](struct.IconElement.html) never has a mask to find.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{DecodeOptions, IconFamily, IconType, Image, MissingMask,
+    ///            PixelFormat};
+    /// let image = Image::new(PixelFormat::RGB, 16, 16);
+    /// let mut family = IconFamily::new();
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// family.retain_elements(|element| element.ostype.0 != *b"s8mk");
+    /// let icon_type = IconType::RGB24_16x16;
+    /// assert!(family.get_icon_with_type(icon_type).is_err());
+    /// let options = DecodeOptions::new().missing_mask(MissingMask::Opaque);
+    /// let icon =
+    ///     family.get_icon_with_type_and_options(icon_type, &options)
+    ///         .unwrap();
+    /// assert_eq!(icon.pixel_format(), PixelFormat::RGBA);
+    /// assert_eq!(icon.data()[3], 255);
+    /// ```
+    pub fn missing_mask(mut self, missing_mask: MissingMask) -> DecodeOptions {
+        self.missing_mask = missing_mask;
+        self
+    }
+
+    /// Sets what to do when a mask element's data payload has the wrong
+    /// length for its icon type.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{DecodeOptions, IconElement, IconType, MaskLength};
+    /// let ostype = IconType::Mask8_16x16.ostype();
+    /// let element = IconElement::new(ostype, vec![0; 250]);
+    /// assert!(element.decode_image().is_err());
+    /// let options = DecodeOptions::new().mask_length(MaskLength::Lenient);
+    /// let mask = element.decode_image_with_options(&options).unwrap();
+    /// assert_eq!(mask.data()[249..251], [0, 255]);
+    /// ```
+    pub fn mask_length(mut self, mask_length: MaskLength) -> DecodeOptions {
+        self.mask_length = mask_length;
+        self
+    }
+
+    /// Sets what to do when the requested icon type isn't in the family.
+    /// Like [`missing_mask`](#method.missing_mask), this only affects
+    /// methods that decode icons from an [`IconFamily`](
+    /// struct.IconFamily.html).  This is useful for programs that just need
+    /// _something_ to display at a given size.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{DecodeOptions, IconFamily, IconType, Image, MissingIcon,
+    ///            PixelFormat};
+    /// let image = Image::new(PixelFormat::RGBA, 48, 48);
+    /// let mut family = IconFamily::new();
+    /// family.add_icon_with_type(&image, IconType::RGB24_48x48).unwrap();
+    /// let icon_type = IconType::RGB24_32x32;
+    /// assert!(family.get_icon_with_type(icon_type).is_err());
+    /// let options =
+    ///     DecodeOptions::new().missing_icon(MissingIcon::Synthesize);
+    /// let icon =
+    ///     family.get_icon_with_type_and_options(icon_type, &options)
+    ///         .unwrap();
+    /// assert_eq!(icon.width(), 32);
+    /// ```
+    pub fn missing_icon(mut self, missing_icon: MissingIcon) -> DecodeOptions {
+        self.missing_icon = missing_icon;
+        self
+    }
+}
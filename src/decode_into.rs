@@ -0,0 +1,214 @@
+use std::io::{self, Error, ErrorKind};
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::icontype::{Encoding, IconType};
+use super::image::{Image, PixelFormat};
+use super::payload::PayloadFormat;
+use super::rle;
+
+impl IconElement {
+    /// Decodes the icon element into an existing image, like
+    /// [`decode_image`](#method.decode_image), but reusing the image's
+    /// buffer instead of allocating a new one.  This is useful when decoding
+    /// many icons of the same size in a loop.
+    ///
+    /// The image must already have the pixel dimensions of this element's
+    /// icon type (otherwise an error of kind `InvalidInput` is returned), and
+    /// the decoded pixels are converted to the image's pixel format.  Decoding
+    /// RLE data into an `RGB` or `RGBA` image, or mask data into an `Alpha`
+    /// image, requires no allocation at all.  If decoding fails, the contents
+    /// of the image are unspecified.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconElement, IconType, Image, PixelFormat};
+    /// let image = Image::new(PixelFormat::RGB, 16, 16);
+    /// let element =
+    ///     IconElement::encode_image_with_type(&image, IconType::RGB24_16x16)
+    ///         .unwrap();
+    /// let mut buffer = Image::new(PixelFormat::RGBA, 16, 16);
+    /// element.decode_image_into(&mut buffer).unwrap();
+    /// assert_eq!(buffer.data()[3], 255);
+    /// ```
+    pub fn decode_image_into(&self, image: &mut Image) -> io::Result<()> {
+        let icon_type = self.icon_type().ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput,
+                       format!("unsupported OSType: {}", self.ostype))
+        })?;
+        check_dimensions(icon_type, image)?;
+        let is_rle = icon_type.encoding() == Encoding::RLE24 ||
+                     (icon_type.encoding() == Encoding::JP2PNG &&
+                      self.payload_format() == PayloadFormat::RLE24);
+        match image.pixel_format() {
+            PixelFormat::RGB if is_rle => {
+                rle::decode_strided(self.rle_data(), 3, image.data_mut(), 3)
+            }
+            PixelFormat::RGBA if is_rle => {
+                rle::decode_strided(self.rle_data(), 3, image.data_mut(), 4)?;
+                for pixel in image.data_mut().chunks_mut(4) {
+                    pixel[3] = 255;
+                }
+                Ok(())
+            }
+            PixelFormat::Alpha if icon_type.encoding() == Encoding::Mask8 => {
+                if self.data.len() != image.data().len() {
+                    let msg = format!("wrong data payload length ({} \
+                                       instead of {})",
+                                      self.data.len(),
+                                      image.data().len());
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+                image.data_mut().copy_from_slice(&self.data);
+                Ok(())
+            }
+            _ => copy_converted(&self.decode_image()?, image),
+        }
+    }
+}
+
+impl IconFamily {
+    /// Decodes an image from the family with the given icon type into an
+    /// existing image, like [`get_icon_with_type`](
+    /// #method.get_icon_with_type), but reusing the image's buffer instead of
+    /// allocating a new one.  As with [`IconElement::decode_image_into`](
+    /// struct.IconElement.html#method.decode_image_into), the image must
+    /// already have the icon type's pixel dimensions, and the decoded pixels
+    /// are converted to its pixel format.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+    /// let mut buffer = Image::new(PixelFormat::RGBA, 32, 32);
+    /// for _ in 0..3 {
+    ///     family.get_icon_into(IconType::RGB24_32x32, &mut buffer).unwrap();
+    /// }
+    /// assert_eq!(buffer, image);
+    /// ```
+    pub fn get_icon_into(&self,
+                         icon_type: IconType,
+                         image: &mut Image)
+                         -> io::Result<()> {
+        let element = self.find_element(icon_type)?;
+        match icon_type.mask_type() {
+            Some(mask_type) if image.pixel_format() == PixelFormat::RGBA => {
+                let mask = self.find_element(mask_type)?;
+                check_dimensions(icon_type, image)?;
+                if mask.data.len() * 4 != image.data().len() {
+                    let msg = format!("wrong mask data payload length ({} \
+                                       instead of {})",
+                                      mask.data.len(),
+                                      image.data().len() / 4);
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+                rle::decode_strided(element.rle_data(),
+                                    3,
+                                    image.data_mut(),
+                                    4)?;
+                for (pixel, &alpha) in image.data_mut()
+                    .chunks_mut(4)
+                    .zip(mask.data.iter()) {
+                    pixel[3] = alpha;
+                }
+                Ok(())
+            }
+            None if element.payload_format() != PayloadFormat::RLE24 => {
+                element.decode_image_into(image)
+            }
+            _ => {
+                check_dimensions(icon_type, image)?;
+                copy_converted(&self.get_icon_with_type(icon_type)?, image)
+            }
+        }
+    }
+}
+
+/// Returns an error if the image doesn't have the pixel dimensions of the
+/// given icon type.
+fn check_dimensions(icon_type: IconType, image: &Image) -> io::Result<()> {
+    let width = icon_type.pixel_width();
+    let height = icon_type.pixel_height();
+    if image.width() != width || image.height() != height {
+        let msg = format!("destination image has wrong dimensions ({}x{} \
+                           instead of {}x{})",
+                          image.width(),
+                          image.height(),
+                          width,
+                          height);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(())
+}
+
+/// Copies `source` into `dest`, converting it to the pixel format of `dest`.
+fn copy_converted(source: &Image, dest: &mut Image) -> io::Result<()> {
+    if source.width() != dest.width() || source.height() != dest.height() {
+        let msg = format!("decoded image has wrong dimensions ({}x{} instead \
+                           of {}x{})",
+                          source.width(),
+                          source.height(),
+                          dest.width(),
+                          dest.height());
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    if source.pixel_format() == dest.pixel_format() {
+        dest.data_mut().copy_from_slice(source.data());
+    } else {
+        let converted = source.convert_to(dest.pixel_format());
+        dest.data_mut().copy_from_slice(converted.data());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::test_image;
+
+    #[test]
+    fn decode_into_matches_decode() {
+        let image = test_image(PixelFormat::RGBA, 48, 48);
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, IconType::RGB24_48x48).unwrap();
+        for &format in &[PixelFormat::RGBA,
+                         PixelFormat::RGB,
+                         PixelFormat::GrayAlpha] {
+            let expected = family.get_icon_with_type(IconType::RGB24_48x48)
+                .unwrap()
+                .convert_to(format);
+            let mut buffer = Image::new(format, 48, 48);
+            family.get_icon_into(IconType::RGB24_48x48, &mut buffer)
+                .unwrap();
+            assert_eq!(buffer, expected);
+        }
+        let mut mask = Image::new(PixelFormat::Alpha, 48, 48);
+        family.elements[1].decode_image_into(&mut mask).unwrap();
+        assert_eq!(mask, family.elements[1].decode_image().unwrap());
+    }
+
+    #[test]
+    fn decode_into_wrong_size() {
+        let image = test_image(PixelFormat::RGB, 16, 16);
+        let element =
+            IconElement::encode_image_with_type(&image, IconType::RGB24_16x16)
+                .unwrap();
+        let mut buffer = Image::new(PixelFormat::RGB, 32, 32);
+        let error = element.decode_image_into(&mut buffer).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn decode_into_wrong_mask_length() {
+        let image = test_image(PixelFormat::RGBA, 16, 16);
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        family.elements[1].data.pop();
+        let mut buffer = Image::new(PixelFormat::RGBA, 16, 16);
+        let error = family.get_icon_into(IconType::RGB24_16x16, &mut buffer)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::element::IconElement;
+use super::family::{ICON_FAMILY_HEADER_LENGTH, IconFamily};
+use super::icontype::OSType;
+use super::kind::is_known_ostype;
+use super::payload::PayloadFormat;
+use super::pngcheck::PngHeader;
+use super::size::IconSize;
+
+/// A structured description of the layout of an icon family, as returned by
+/// [`IconFamily::description`](struct.IconFamily.html#method.description).
+/// Its `Display` implementation produces the same report as
+/// [`IconFamily::describe`](struct.IconFamily.html#method.describe).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FamilyDescription {
+    /// The total length of the ICNS file, in bytes (including the header).
+    pub total_length: u32,
+    /// Descriptions of each element, in order.
+    pub elements: Vec<ElementDescription>,
+}
+
+/// A description of a single element within an icon family.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ElementDescription {
+    /// The OSType of the element.
+    pub ostype: OSType,
+    /// The offset of the start of the element (its header) within the ICNS
+    /// file, in bytes.
+    pub offset: u32,
+    /// The length of the element, in bytes (including its header).
+    pub length: u32,
+    /// The detected format of the element's data payload.
+    pub format: PayloadFormat,
+    /// The width and height of the image encoded by the element, in pixels,
+    /// if known.  For PNG data, these are the dimensions stored in the PNG
+    /// header, which might not match the element's icon type; for other
+    /// image data, they are the dimensions of the element's icon type.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl IconFamily {
+    /// Returns a structured description of this family's layout in an ICNS
+    /// file: each element's OSType, offset, length, detected payload format,
+    /// and image dimensions.  No image data is decoded.
+    pub fn description(&self) -> FamilyDescription {
+        let mut offset = ICON_FAMILY_HEADER_LENGTH;
+        let mut elements = Vec::with_capacity(self.elements.len());
+        for element in &self.elements {
+            elements.push(ElementDescription {
+                ostype: element.ostype,
+                offset,
+                length: element.total_length(),
+                format: element.payload_format(),
+                dimensions: dimensions(element),
+            });
+            offset += element.total_length();
+        }
+        FamilyDescription {
+            total_length: self.total_length(),
+            elements,
+        }
+    }
+
+    /// Returns a human-readable report describing this family's layout in an
+    /// ICNS file, with one line for the header and one line for each
+    /// element (see [`description`](#method.description)).  This is useful
+    /// for debugging output and bug reports.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// assert_eq!(family.describe(),
+    ///            "icns file, 292 bytes, 2 elements\n\
+    ///             \x20 offset 8: 'is32', 20 bytes, RLE, 16x16\n\
+    ///             \x20 offset 28: 's8mk', 264 bytes, mask, 16x16\n");
+    /// ```
+    pub fn describe(&self) -> String {
+        self.description().to_string()
+    }
+}
+
+/// A summary of the kinds of elements in an icon family, as returned by
+/// [`IconFamily::element_counts_by_kind`](
+/// struct.IconFamily.html#method.element_counts_by_kind).  Its `Display`
+/// implementation produces a one-line overview.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ElementCounts {
+    /// The number of (non-mask) icon elements of each size.
+    pub icons: BTreeMap<IconSize, usize>,
+    /// The number of mask elements.
+    pub masks: usize,
+    /// The number of known non-icon elements (such as `TOC ` or `icnV`).
+    pub metadata: usize,
+    /// The number of other elements.
+    pub unknown: usize,
+}
+
+impl ElementCounts {
+    /// Returns the total number of (non-mask) icon elements.
+    pub fn total_icons(&self) -> usize {
+        self.icons.values().sum()
+    }
+}
+
+impl fmt::Display for ElementCounts {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        let total_icons = self.total_icons();
+        write!(out,
+               "{} icon{}",
+               total_icons,
+               if total_icons == 1 { "" } else { "s" })?;
+        if !self.icons.is_empty() {
+            write!(out, " (")?;
+            for (index, (size, &count)) in self.icons.iter().enumerate() {
+                if index > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{}", size)?;
+                if count > 1 {
+                    write!(out, " x{}", count)?;
+                }
+            }
+            write!(out, ")")?;
+        }
+        write!(out,
+               ", {} mask{}, {} metadata, {} unknown",
+               self.masks,
+               if self.masks == 1 { "" } else { "s" },
+               self.metadata,
+               self.unknown)
+    }
+}
+
+impl IconFamily {
+    /// Returns the number of elements in this family of each kind: icons
+    /// (grouped by size), masks, known metadata elements, and unknown
+    /// elements.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x).unwrap();
+    /// assert_eq!(family.element_counts_by_kind().to_string(),
+    ///            "3 icons (16x16@2x, 32x32 x2), 1 mask, 0 metadata, \
+    ///             0 unknown");
+    /// # }
+    /// ```
+    pub fn element_counts_by_kind(&self) -> ElementCounts {
+        let mut counts = ElementCounts::default();
+        for element in &self.elements {
+            match element.icon_type() {
+                Some(icon_type) if icon_type.is_mask() => counts.masks += 1,
+                Some(icon_type) => {
+                    *counts.icons.entry(icon_type.size()).or_insert(0) += 1;
+                }
+                None if is_known_ostype(element.ostype) => {
+                    counts.metadata += 1;
+                }
+                None => counts.unknown += 1,
+            }
+        }
+        counts
+    }
+}
+
+/// Returns the dimensions of the image encoded by the given element, if
+/// known, without decoding it.
+fn dimensions(element: &IconElement) -> Option<(u32, u32)> {
+    match element.payload_format() {
+        PayloadFormat::PNG => {
+            PngHeader::parse(&element.data)
+                .ok()
+                .map(|header| (header.width, header.height))
+        }
+        PayloadFormat::IconFamily | PayloadFormat::Unknown => None,
+        _ => {
+            element.icon_type().map(|icon_type| {
+                (icon_type.pixel_width(), icon_type.pixel_height())
+            })
+        }
+    }
+}
+
+impl fmt::Display for FamilyDescription {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(out,
+                 "icns file, {} bytes, {} element{}",
+                 self.total_length,
+                 self.elements.len(),
+                 if self.elements.len() == 1 { "" } else { "s" })?;
+        for element in &self.elements {
+            writeln!(out, "  {}", element)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ElementDescription {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out,
+               "offset {}: '{}', {} bytes, {}",
+               self.offset,
+               self.ostype,
+               self.length,
+               self.format)?;
+        if let Some((width, height)) = self.dimensions {
+            write!(out, ", {}x{}", width, height)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::icontype::IconType;
+    use super::super::fixtures::FixtureBuilder;
+
+    #[test]
+    fn describe_legacy_family() {
+        let family = FixtureBuilder::new()
+            .icon(IconType::RGB24_32x32)
+            .version_element()
+            .family();
+        let description = family.description();
+        assert_eq!(description.elements.len(), 3);
+        assert_eq!(description.elements[0].format, PayloadFormat::RLE24);
+        assert_eq!(description.elements[1].format, PayloadFormat::Mask8);
+        assert_eq!(description.elements[1].dimensions, Some((32, 32)));
+        assert_eq!(description.elements[2].dimensions, None);
+        let counts = family.element_counts_by_kind();
+        assert_eq!(counts.icons.get(&IconSize::new(32, 1)), Some(&1));
+        assert_eq!((counts.masks, counts.metadata, counts.unknown),
+                   (1, 1, 0));
+        assert_eq!(family.describe().lines().count(), 4);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn describe_family() {
+        let png = FixtureBuilder::new()
+            .icon(IconType::RGBA32_16x16_2x)
+            .family()
+            .elements()[0]
+            .data
+            .clone();
+        // The second PNG is the wrong size for its element type:
+        let family = FixtureBuilder::new()
+            .element(IconType::RGBA32_16x16_2x.ostype(), png.clone())
+            .element(IconType::RGBA32_128x128.ostype(), png)
+            .version_element()
+            .family();
+        let description = family.description();
+        assert_eq!(description.total_length, family.total_length());
+        assert_eq!(description.elements.len(), 3);
+        assert_eq!(description.elements[0].format, PayloadFormat::PNG);
+        assert_eq!(description.elements[1].dimensions, Some((32, 32)));
+        assert_eq!(description.elements[2].offset,
+                   8 + 2 * family.elements[0].total_length());
+        assert_eq!(description.elements[2].dimensions, None);
+        let counts = family.element_counts_by_kind();
+        assert_eq!(counts.icons.get(&IconSize::new(16, 2)), Some(&1));
+        assert_eq!(counts.icons.get(&IconSize::new(128, 1)), Some(&1));
+        assert_eq!(counts.total_icons(), 2);
+        assert_eq!(counts.metadata, 1);
+        let report = family.describe();
+        assert_eq!(report.lines().count(), 4);
+        assert!(report.lines().last().unwrap().ends_with("'icnV', 12 bytes, \
+                                                          unknown"));
+    }
+}
@@ -1,21 +1,39 @@
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use std::cmp;
+use std::fmt;
 use std::io::{self, Error, ErrorKind, Read, Write};
 
+use super::decode::{DecodeOptions, MaskLength};
+#[cfg(feature = "pngio")]
+use super::decode::SizeMismatch;
+use super::family::{ICNS_MAGIC_LITERAL, IconFamily};
 use super::icontype::{Encoding, IconType, OSType};
 use super::image::{Image, PixelFormat};
+use super::payload::PayloadFormat;
+#[cfg(feature = "pngio")]
+use super::pngcheck::PngHeader;
+use super::rle;
 
 /// The length of an icon element header, in bytes:
-const ICON_ELEMENT_HEADER_LENGTH: u32 = 8;
+pub(crate) const ICON_ELEMENT_HEADER_LENGTH: u32 = 8;
+
+/// The maximum depth to which `decode_as_family` will parse nested icon
+/// families:
+const MAX_NESTED_FAMILY_DEPTH: usize = 4;
+
+/// The four zero bytes that Apple's tools put at the start of `it32` data:
+pub(crate) const IT32_PREFIX: [u8; 4] = [0, 0, 0, 0];
+
+/// The four bytes that start RLE-compressed ARGB data:
+pub(crate) const ARGB_MAGIC: &[u8; 4] = b"ARGB";
 
 /// The first twelve bytes of a JPEG 2000 file are always this:
-#[cfg(feature = "pngio")]
-const JPEG_2000_FILE_MAGIC_NUMBER: [u8; 12] =
+pub(crate) const JPEG_2000_FILE_MAGIC_NUMBER: [u8; 12] =
     [0x00, 0x00, 0x00, 0x0C, 0x6A, 0x50, 0x20, 0x20, 0x0D, 0x0A, 0x87, 0x0A];
 
 /// One data block in an ICNS file.  Depending on the resource type, this may
 /// represent an icon, or part of an icon (such as an alpha mask, or color
 /// data without the mask).
+#[derive(Clone)]
 pub struct IconElement {
     /// The OSType for this element (e.g. `it32` or `t8mk`).
     pub ostype: OSType,
@@ -64,22 +82,28 @@ impl IconElement {
                 image.write_png(&mut data)?;
             }
             #[cfg(not(feature = "pngio"))]
-            Encoding::JP2PNG => unimplemented!(),
+            Encoding::JP2PNG => return Err(png_unsupported(icon_type)),
             Encoding::RLE24 => {
-                let num_pixels = (width * height) as usize;
-                match image.pixel_format() {
+                data = Vec::new();
+                if icon_type == IconType::RGB24_128x128 {
+                    // The 128x128 RLE icon (it32) starts with four extra
+                    // zeros.
+                    data.extend_from_slice(&IT32_PREFIX);
+                }
+                let encoded = match image.pixel_format() {
                     PixelFormat::RGBA => {
-                        data = encode_rle(image.data(), 4, num_pixels);
+                        rle::encode_strided(image.data(), 3, 4)?
                     }
                     PixelFormat::RGB => {
-                        data = encode_rle(image.data(), 3, num_pixels);
+                        rle::encode_strided(image.data(), 3, 3)?
                     }
                     // Convert to RGB if the image isn't already RGB or RGBA.
                     _ => {
                         let image = image.convert_to(PixelFormat::RGB);
-                        data = encode_rle(image.data(), 3, num_pixels);
+                        rle::encode_strided(image.data(), 3, 3)?
                     }
-                }
+                };
+                data.extend_from_slice(&encoded);
             }
             Encoding::Mask8 => {
                 // Convert to Alpha format unconditionally -- if the image is
@@ -104,12 +128,50 @@ impl IconElement {
     /// or the higher-level [`IconFamily.get_icon_with_type`](
     /// struct.IconFamily.html#method.get_icon_with_type) method.
     pub fn decode_image(&self) -> io::Result<Image> {
+        self.decode_image_with_options(&DecodeOptions::new())
+    }
+
+    /// Decodes the icon element into an image, like
+    /// [`decode_image`](#method.decode_image), using the given options to
+    /// tolerate some kinds of malformed data.
+    pub fn decode_image_with_options(&self,
+                                     options: &DecodeOptions)
+                                     -> io::Result<Image> {
         let icon_type = self.icon_type().ok_or_else(|| {
             Error::new(ErrorKind::InvalidInput,
                        format!("unsupported OSType: {}", self.ostype))
         })?;
         let width = icon_type.pixel_width();
         let height = icon_type.pixel_width();
+        if icon_type.encoding() == Encoding::JP2PNG {
+            // Some files store RLE data in `icp4`/`icp5` elements instead.
+            match self.payload_format() {
+                PayloadFormat::RLE24 => {
+                    let mut image = Image::new(PixelFormat::RGB,
+                                               width,
+                                               height);
+                    rle::decode_strided(self.rle_data(),
+                                        3,
+                                        image.data_mut(),
+                                        3)?;
+                    return Ok(image);
+                }
+                PayloadFormat::ARGB => {
+                    let num_pixels = (width * height) as usize;
+                    let mut data = rle::decode(&self.data[ARGB_MAGIC.len()..],
+                                               4,
+                                               num_pixels)?;
+                    for pixel in data.chunks_mut(4) {
+                        pixel.rotate_left(1);
+                    }
+                    return Image::from_data(PixelFormat::RGBA,
+                                            width,
+                                            height,
+                                            data);
+                }
+                _ => {}
+            }
+        }
         match icon_type.encoding() {
             #[cfg(feature = "pngio")]
             Encoding::JP2PNG => {
@@ -118,41 +180,72 @@ impl IconElement {
                                data, which is not yet supported";
                     return Err(Error::new(ErrorKind::InvalidInput, msg));
                 }
-                let image = Image::read_png(io::Cursor::new(&self.data))?;
-                if image.width() != width || image.height() != height {
-                    let msg = format!("decoded PNG has wrong dimensions \
-                                       ({}x{} instead of {}x{})",
-                                      image.width(),
-                                      image.height(),
-                                      width,
-                                      height);
-                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                let result = self.decode_png(width, height, options);
+                if result.is_err() {
+                    // Only scan the chunks once decoding has failed, to
+                    // explain the failure if the data was damaged.
+                    if let Some(problem) = self.check_png_chunks() {
+                        let msg = format!("'{}' element contains damaged \
+                                           PNG data: {}",
+                                          self.ostype,
+                                          problem);
+                        return Err(Error::new(ErrorKind::InvalidData, msg));
+                    }
                 }
-                Ok(image)
+                result
             }
             #[cfg(not(feature = "pngio"))]
-            Encoding::JP2PNG => unimplemented!(),
+            Encoding::JP2PNG => Err(png_unsupported(icon_type)),
             Encoding::RLE24 => {
                 let mut image = Image::new(PixelFormat::RGB, width, height);
-                decode_rle(&self.data, 3, image.data_mut())?;
+                rle::decode_strided(self.rle_data(), 3, image.data_mut(), 3)?;
                 Ok(image)
             }
             Encoding::Mask8 => {
-                let num_pixels = width * height;
-                if self.data.len() as u32 != num_pixels {
+                let num_pixels = (width * height) as usize;
+                if self.data.len() != num_pixels &&
+                   options.mask_length == MaskLength::Error {
                     let msg = format!("wrong data payload length ({} \
                                        instead of {})",
                                       self.data.len(),
                                       num_pixels);
                     return Err(Error::new(ErrorKind::InvalidData, msg));
                 }
-                let mut image = Image::new(PixelFormat::Alpha, width, height);
-                image.data_mut().clone_from_slice(&self.data);
-                Ok(image)
+                Image::from_data(PixelFormat::Alpha,
+                                 width,
+                                 height,
+                                 self.fitted_mask_data(num_pixels))
             }
         }
     }
 
+    /// Decodes the element's PNG data into an image of the given size.
+    #[cfg(feature = "pngio")]
+    fn decode_png(&self,
+                  width: u32,
+                  height: u32,
+                  options: &DecodeOptions)
+                  -> io::Result<Image> {
+        // Check the dimensions before decoding the whole image.
+        let header = PngHeader::parse(&self.data)?;
+        if (header.width != width || header.height != height) &&
+           options.size_mismatch == SizeMismatch::Error {
+            let msg = format!("decoded PNG has wrong dimensions ({}x{} \
+                               instead of {}x{})",
+                              header.width,
+                              header.height,
+                              width,
+                              height);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let image = Image::read_png(io::Cursor::new(&self.data))?;
+        if options.size_mismatch == SizeMismatch::Resize {
+            Ok(image.resize(width, height))
+        } else {
+            Ok(image)
+        }
+    }
+
     /// Decodes this element, together with a separate mask element, into a
     /// single image with alpha channel.  Returns an error if this element does
     /// not represent an icon type supported by this library, or if the given
@@ -191,13 +284,83 @@ impl IconElement {
             return Err(Error::new(ErrorKind::InvalidInput, msg));
         }
         let mut image = Image::new(PixelFormat::RGBA, width, height);
-        decode_rle(&self.data, 4, image.data_mut())?;
+        rle::decode_strided(self.rle_data(), 3, image.data_mut(), 4)?;
         for (i, &alpha) in mask.data.iter().enumerate() {
             image.data_mut()[4 * i + 3] = alpha;
         }
         Ok(image)
     }
 
+    /// Returns true if this element's data payload is itself an ICNS file
+    /// (as is the case for e.g. the `tile`, `over`, `open`, and `drop`
+    /// elements, which hold variants of the main icon).
+    pub fn contains_family(&self) -> bool {
+        self.data.len() >= ICON_ELEMENT_HEADER_LENGTH as usize &&
+        self.data.starts_with(ICNS_MAGIC_LITERAL)
+    }
+
+    /// Parses this element's data payload as a nested icon family.  Any
+    /// elements within the nested family that themselves contain icon
+    /// families are parsed too (up to four levels deep), so that a successful
+    /// result means the whole nested structure is well-formed.  Returns an
+    /// error if the payload doesn't
+    /// [`contain_family`](#method.contains_family), if it (or anything nested
+    /// within it) is malformed, or if the families are nested too deeply.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconElement, OSType};
+    /// let data = b"icns\0\0\0\x11quux\0\0\0\x09#".to_vec();
+    /// let element = IconElement::new(OSType(*b"tile"), data);
+    /// let family = element.decode_as_family().unwrap();
+    /// assert_eq!(family.elements()[0].ostype, OSType(*b"quux"));
+    /// ```
+    pub fn decode_as_family(&self) -> io::Result<IconFamily> {
+        self.decode_as_family_with_depth(MAX_NESTED_FAMILY_DEPTH)
+    }
+
+    fn decode_as_family_with_depth(&self,
+                                   depth: usize)
+                                   -> io::Result<IconFamily> {
+        if !self.contains_family() {
+            let msg = format!("'{}' element does not contain an icon family",
+                              self.ostype);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if depth == 0 {
+            let msg = format!("icon families are nested more than {} deep",
+                              MAX_NESTED_FAMILY_DEPTH);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let family = IconFamily::read(io::Cursor::new(&self.data))?;
+        for element in &family.elements {
+            if element.contains_family() {
+                element.decode_as_family_with_depth(depth - 1)?;
+            }
+        }
+        Ok(family)
+    }
+
+    /// Private helper method: returns this (mask) element's data payload,
+    /// truncated or padded with fully opaque alpha values to the given
+    /// number of pixels.
+    pub(crate) fn fitted_mask_data(&self, num_pixels: usize) -> Vec<u8> {
+        let mut data = self.data.clone();
+        data.resize(num_pixels, u8::MAX);
+        data
+    }
+
+    /// Private helper method: returns the RLE-compressed data within this
+    /// element's payload, skipping the four extra zeros that sometimes start
+    /// the data (always for `it32` elements, but we don't rely on that).
+    pub(crate) fn rle_data(&self) -> &[u8] {
+        if self.data.starts_with(&IT32_PREFIX) {
+            &self.data[IT32_PREFIX.len()..]
+        } else {
+            &self.data
+        }
+    }
+
     /// Returns the type of icon encoded by this element, or `None` if this
     /// element does not encode a supported icon type.
     pub fn icon_type(&self) -> Option<IconType> {
@@ -205,7 +368,16 @@ impl IconElement {
     }
 
     /// Reads an icon element from within an ICNS file.
-    pub fn read<R: Read>(mut reader: R) -> io::Result<IconElement> {
+    pub fn read<R: Read>(reader: R) -> io::Result<IconElement> {
+        IconElement::read_with_max_size(reader, u32::MAX)
+    }
+
+    /// Reads an icon element from within an ICNS file, returning an error
+    /// (without allocating space for the payload) if the element's data
+    /// payload is longer than `max_size` bytes.
+    pub(crate) fn read_with_max_size<R: Read>(mut reader: R,
+                                              max_size: u32)
+                                              -> io::Result<IconElement> {
         let mut raw_ostype = [0u8; 4];
         reader.read_exact(&mut raw_ostype)?;
         let element_length = reader.read_u32::<BigEndian>()?;
@@ -214,6 +386,14 @@ impl IconElement {
                                   "invalid element length"));
         }
         let data_length = element_length - ICON_ELEMENT_HEADER_LENGTH;
+        if data_length > max_size {
+            let msg = format!("'{}' element payload is too large ({} bytes, \
+                               but the limit is {})",
+                              OSType(raw_ostype),
+                              data_length,
+                              max_size);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
         let mut data = vec![0u8; data_length as usize];
         reader.read_exact(&mut data)?;
         Ok(IconElement::new(OSType(raw_ostype), data))
@@ -228,118 +408,76 @@ impl IconElement {
         Ok(())
     }
 
+    /// Writes the element's data payload (without the element header) to the
+    /// given writer, returning the number of bytes written.  This is useful
+    /// for extracting e.g. the PNG file embedded in an element without
+    /// decoding it.
+    pub fn copy_payload_to<W: Write>(&self, mut writer: W) -> io::Result<u64> {
+        writer.write_all(&self.data)?;
+        Ok(self.data.len() as u64)
+    }
+
     /// Returns the encoded length of the element, in bytes, including the
     /// length of the header.
     pub fn total_length(&self) -> u32 {
         ICON_ELEMENT_HEADER_LENGTH + (self.data.len() as u32)
     }
-}
 
-fn encode_rle(input: &[u8],
-              num_input_channels: usize,
-              num_pixels: usize)
-              -> Vec<u8> {
-    assert!(num_input_channels == 3 || num_input_channels == 4);
-    let mut output = Vec::new();
-    if num_pixels == 128 * 128 {
-        // The 128x128 RLE icon (it32) starts with four extra zeros.
-        output.extend_from_slice(&[0, 0, 0, 0]);
-    }
-    for channel in 0..3 {
-        let mut pixel: usize = 0;
-        let mut literal_start: usize = 0;
-        while pixel < num_pixels {
-            let value = input[num_input_channels * pixel + channel];
-            let mut run_length = 1;
-            while pixel + run_length < num_pixels &&
-                  input[num_input_channels * (pixel + run_length) +
-                  channel] == value && run_length < 130 {
-                run_length += 1;
-            }
-            if run_length >= 3 {
-                while literal_start < pixel {
-                    let literal_length = cmp::min(128, pixel - literal_start);
-                    output.push((literal_length - 1) as u8);
-                    for i in 0..literal_length {
-                        output.push(input[num_input_channels *
-                                          (literal_start + i) +
-                                          channel]);
-                    }
-                    literal_start += literal_length;
-                }
-                output.push((run_length + 125) as u8);
-                output.push(value);
-                pixel += run_length;
-                literal_start = pixel;
-            } else {
-                pixel += run_length;
-            }
-        }
-        while literal_start < pixel {
-            let literal_length = cmp::min(128, pixel - literal_start);
-            output.push((literal_length - 1) as u8);
-            for i in 0..literal_length {
-                output.push(input[num_input_channels * (literal_start + i) +
-                            channel]);
-            }
-            literal_start += literal_length;
+    /// If this is an `it32` element, returns a copy of it with the four-byte
+    /// zero prefix added to (if `include` is true) or removed from (if
+    /// `include` is false) its data, if it isn't that way already.  Returns
+    /// `None` if no change is needed.
+    pub(crate) fn with_it32_prefix(&self,
+                                   include: bool)
+                                   -> Option<IconElement> {
+        if self.ostype != IconType::RGB24_128x128.ostype() ||
+           self.data.starts_with(&IT32_PREFIX) == include {
+            return None;
         }
+        let data = if include {
+            let mut data = IT32_PREFIX.to_vec();
+            data.extend_from_slice(&self.data);
+            data
+        } else {
+            self.data[IT32_PREFIX.len()..].to_vec()
+        };
+        Some(IconElement::new(self.ostype, data))
     }
-    output
 }
 
-fn decode_rle(input: &[u8],
-              num_output_channels: usize,
-              output: &mut [u8])
-              -> io::Result<()> {
-    assert!(num_output_channels == 3 || num_output_channels == 4);
-    assert_eq!(output.len() % num_output_channels, 0);
-    let num_pixels = output.len() / num_output_channels;
-    // Sometimes, RLE-encoded data starts with four extra zeros that must be
-    // skipped.
-    let skip: usize = if input.starts_with(&[0, 0, 0, 0]) {
-        4
-    } else {
-        0
-    };
-    let input = &input[skip..input.len()];
-    let mut iter = input.iter();
-    let mut remaining: usize = 0;
-    let mut within_run = false;
-    let mut run_value: u8 = 0;
-    for channel in 0..3 {
-        for pixel in 0..num_pixels {
-            if remaining == 0 {
-                let next: u8 = *iter.next().ok_or_else(rle_error)?;
-                if next < 128 {
-                    remaining = (next as usize) + 1;
-                    within_run = false;
-                } else {
-                    remaining = (next as usize) - 125;
-                    within_run = true;
-                    run_value = *iter.next().ok_or_else(rle_error)?;
-                }
-            }
-            output[num_output_channels * pixel + channel] = if within_run {
-                run_value
-            } else {
-                *iter.next().ok_or_else(rle_error)?
-            };
-            remaining -= 1;
-        }
-        if remaining != 0 {
-            return Err(rle_error());
-        }
+impl fmt::Debug for IconElement {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out,
+               "IconElement('{}', {} byte payload)",
+               self.ostype,
+               self.data.len())
     }
-    if iter.next().is_some() {
-        Err(rle_error())
-    } else {
-        Ok(())
+}
+
+impl fmt::Display for IconElement {
+    /// Formats a one-line summary of the element: its OSType, the pixel
+    /// dimensions of its icon type (if any), its payload format, and its
+    /// payload size.
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "'{}': ", self.ostype)?;
+        if let Some(icon_type) = self.icon_type() {
+            write!(out,
+                   "{}x{}, ",
+                   icon_type.pixel_width(),
+                   icon_type.pixel_height())?;
+        }
+        write!(out, "{}, {} bytes", self.payload_format(), self.data.len())
     }
 }
 
-fn rle_error() -> Error {
-    Error::new(ErrorKind::InvalidData, "invalid RLE-compressed data")
+/// Returns the error for trying to encode or decode a PNG-based icon type
+/// when the crate was built without the `pngio` feature.
+#[cfg(not(feature = "pngio"))]
+fn png_unsupported(icon_type: IconType) -> Error {
+    let msg = format!("icon type {:?} requires PNG support, but the `pngio` \
+                       feature is disabled",
+                      icon_type);
+    Error::new(ErrorKind::InvalidInput, msg)
 }
 
 #[cfg(test)]
@@ -388,6 +526,74 @@ mod tests {
         assert_eq!(image.data()[2], 56);
     }
 
+    #[test]
+    #[cfg(not(feature = "pngio"))]
+    fn png_types_fail_without_pngio() {
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        let error =
+            IconElement::encode_image_with_type(&image, IconType::RGBA32_16x16)
+                .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        let element = IconElement::new(OSType(*b"icp4"), data);
+        let error = element.decode_image().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn decode_rle_in_icp4() {
+        let data: Vec<u8> = vec![0, 12, 255, 0, 250, 0, 128, 34, 255, 0, 248,
+                                 0, 1, 56, 99, 255, 0, 249, 0];
+        let element = IconElement::new(OSType(*b"icp4"), data);
+        let image = element.decode_image().expect("failed to decode image");
+        assert_eq!(image.pixel_format(), PixelFormat::RGB);
+        assert_eq!(image.width(), 16);
+        assert_eq!(&image.data()[0..3], &[12, 34, 56]);
+    }
+
+    #[test]
+    fn decode_argb_in_icp5() {
+        let pixel = [200, 10, 20, 30];
+        let argb: Vec<u8> = pixel.iter()
+            .cycle()
+            .take(32 * 32 * 4)
+            .cloned()
+            .collect();
+        let mut data = ARGB_MAGIC.to_vec();
+        data.extend(rle::encode(&argb, 4, 32 * 32).unwrap());
+        let element = IconElement::new(OSType(*b"icp5"), data);
+        let image = element.decode_image().expect("failed to decode image");
+        assert_eq!(image.pixel_format(), PixelFormat::RGBA);
+        assert_eq!(image.width(), 32);
+        assert_eq!(&image.data()[0..4], &[10, 20, 30, 200]);
+    }
+
+    #[test]
+    fn decode_nested_families() {
+        let inner = IconElement::new(OSType(*b"quux"), b"#".to_vec());
+        let mut element = inner.clone();
+        for _ in 0..MAX_NESTED_FAMILY_DEPTH {
+            let mut family = IconFamily::new();
+            family.elements.push(element);
+            let mut data = Vec::new();
+            family.write(&mut data).unwrap();
+            element = IconElement::new(OSType(*b"tile"), data);
+        }
+        assert!(element.contains_family());
+        assert!(!inner.contains_family());
+        assert!(element.decode_as_family().is_ok());
+        let error = inner.decode_as_family().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        let mut family = IconFamily::new();
+        family.elements.push(element);
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        let element = IconElement::new(OSType(*b"tile"), data);
+        let error = element.decode_as_family().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
     #[test]
     fn encode_mask() {
         let mut image = Image::new(PixelFormat::Alpha, 16, 16);
@@ -0,0 +1,229 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use super::element::{ICON_ELEMENT_HEADER_LENGTH, IconElement};
+use super::family::{ICNS_MAGIC_LITERAL, ICON_FAMILY_HEADER_LENGTH,
+                    IconFamily};
+use super::icontype::{IconType, OSType};
+use super::image::Image;
+
+impl IconFamily {
+    /// Decodes the icon with the given type directly from an ICNS file,
+    /// without reading the rest of the family.  Element headers are scanned
+    /// in order, and the payloads of elements other than the requested one
+    /// (and its mask, if any) are skipped over using `Seek`, so only the
+    /// needed data is ever buffered.  This gives the same result as reading
+    /// the whole family and calling
+    /// [`get_icon_with_type`](#method.get_icon_with_type), but is much
+    /// cheaper when only one icon is wanted from a large file.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// use std::io::Cursor;
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// let mut data = Vec::new();
+    /// family.write(&mut data).unwrap();
+    /// let icon = IconFamily::extract_icon_from_reader(Cursor::new(data),
+    ///                                                 IconType::RGB24_16x16)
+    ///     .unwrap();
+    /// assert_eq!(icon.width(), 16);
+    /// ```
+    pub fn extract_icon_from_reader<R: Read + Seek>(mut reader: R,
+                                                    icon_type: IconType)
+                                                    -> io::Result<Image> {
+        let file_length = read_family_header(reader.by_ref())?;
+        let wanted = wanted_ostypes(icon_type);
+        let mut family = IconFamily::new();
+        let mut file_position: u32 = ICON_FAMILY_HEADER_LENGTH;
+        while file_position < file_length &&
+              family.elements.len() < wanted.len() {
+            let (ostype, data_length) = read_element_header(reader.by_ref())?;
+            let is_new = !family.elements.iter().any(|el| el.ostype == ostype);
+            if wanted.contains(&ostype) && is_new {
+                // Read through `take` rather than preallocating, so that a
+                // bogus length can't make us allocate more than the file
+                // actually holds.
+                let mut data = Vec::new();
+                reader.by_ref()
+                    .take(u64::from(data_length))
+                    .read_to_end(&mut data)?;
+                if data.len() != data_length as usize {
+                    let msg = format!("'{}' element is truncated", ostype);
+                    return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+                }
+                family.elements.push(IconElement::new(ostype, data));
+            } else {
+                reader.seek(SeekFrom::Current(i64::from(data_length)))?;
+            }
+            file_position = file_position
+                .saturating_add(ICON_ELEMENT_HEADER_LENGTH)
+                .saturating_add(data_length);
+        }
+        family.get_icon_with_type(icon_type)
+    }
+
+    /// Copies the data payload of the first element with the given OSType
+    /// directly from an ICNS file to the given writer, returning the number
+    /// of bytes copied.  Like
+    /// [`extract_icon_from_reader`](#method.extract_icon_from_reader), this
+    /// skips over other elements using `Seek`; the payload itself is copied
+    /// in small chunks, so even a large embedded PNG file can be extracted
+    /// using a constant amount of memory.  Returns an error if the file has
+    /// no such element.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use icns::{IconFamily, OSType};
+    /// use std::fs::File;
+    /// let input = File::open("16.icns").unwrap();
+    /// let output = File::create("ic10.png").unwrap();
+    /// IconFamily::extract_payload_from_reader(input,
+    ///                                         OSType(*b"ic10"),
+    ///                                         output)
+    ///     .unwrap();
+    /// ```
+    pub fn extract_payload_from_reader<R, W>(mut reader: R,
+                                             ostype: OSType,
+                                             mut writer: W)
+                                             -> io::Result<u64>
+        where R: Read + Seek,
+              W: Write
+    {
+        let file_length = read_family_header(reader.by_ref())?;
+        let mut file_position: u32 = ICON_FAMILY_HEADER_LENGTH;
+        while file_position < file_length {
+            let (element_ostype, data_length) =
+                read_element_header(reader.by_ref())?;
+            if element_ostype == ostype {
+                let mut payload = reader.take(u64::from(data_length));
+                let copied = io::copy(&mut payload, &mut writer)?;
+                if copied < u64::from(data_length) {
+                    return Err(Error::new(ErrorKind::UnexpectedEof,
+                                          "icns file is truncated"));
+                }
+                return Ok(copied);
+            }
+            reader.seek(SeekFrom::Current(i64::from(data_length)))?;
+            file_position = file_position
+                .saturating_add(ICON_ELEMENT_HEADER_LENGTH)
+                .saturating_add(data_length);
+        }
+        let msg = format!("the icon family does not contain a '{}' element",
+                          ostype);
+        Err(Error::new(ErrorKind::NotFound, msg))
+    }
+}
+
+/// Reads the header of an ICNS file, returning the file length.
+pub(crate) fn read_family_header<R: Read>(mut reader: R) -> io::Result<u32> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != *ICNS_MAGIC_LITERAL {
+        let msg = "not an icns file (wrong magic literal)";
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    reader.read_u32::<BigEndian>()
+}
+
+/// Reads the header of an icon element, returning its OSType and the length
+/// of its data payload.
+pub(crate) fn read_element_header<R: Read>(mut reader: R)
+                                          -> io::Result<(OSType, u32)> {
+    let mut raw_ostype = [0u8; 4];
+    reader.read_exact(&mut raw_ostype)?;
+    let element_length = reader.read_u32::<BigEndian>()?;
+    if element_length < ICON_ELEMENT_HEADER_LENGTH {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              "invalid element length"));
+    }
+    Ok((OSType(raw_ostype), element_length - ICON_ELEMENT_HEADER_LENGTH))
+}
+
+/// Returns the OSTypes of the elements that might be needed to decode an
+/// icon of the given type: the type itself, its mask type (if any), and the
+/// mask type that would be paired with RLE data stored in its element.
+pub(crate) fn wanted_ostypes(icon_type: IconType) -> Vec<OSType> {
+    let mut wanted = vec![icon_type.ostype()];
+    let rle_mask_type =
+        IconType::from_pixel_size_and_density(icon_type.pixel_width(),
+                                              icon_type.pixel_height(),
+                                              1)
+            .and_then(IconType::mask_type);
+    for mask_type in icon_type.mask_type().into_iter().chain(rle_mask_type) {
+        if !wanted.contains(&mask_type.ostype()) {
+            wanted.push(mask_type.ostype());
+        }
+    }
+    wanted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::FixtureBuilder;
+    use std::io::Cursor;
+
+    fn sample_file() -> (IconFamily, Vec<u8>) {
+        let builder = FixtureBuilder::new()
+            .version_element()
+            .icon(IconType::RGB24_32x32)
+            .icon(IconType::RGB24_16x16);
+        (builder.family(), builder.build())
+    }
+
+    #[test]
+    fn extract_icon_with_mask() {
+        let (family, data) = sample_file();
+        for &icon_type in &[IconType::RGB24_32x32, IconType::RGB24_16x16] {
+            let expected = family.get_icon_with_type(icon_type).unwrap();
+            let icon =
+                IconFamily::extract_icon_from_reader(Cursor::new(&data),
+                                                     icon_type)
+                    .unwrap();
+            assert_eq!(icon.pixel_format(), expected.pixel_format());
+            assert_eq!(icon.data(), expected.data());
+        }
+    }
+
+    #[test]
+    fn extract_payload() {
+        let (family, data) = sample_file();
+        let mut payload = Vec::new();
+        let copied =
+            IconFamily::extract_payload_from_reader(Cursor::new(&data),
+                                                    OSType(*b"s8mk"),
+                                                    &mut payload)
+                .unwrap();
+        assert_eq!(copied, 256);
+        assert_eq!(payload, family.elements[4].data);
+        let error =
+            IconFamily::extract_payload_from_reader(Cursor::new(&data),
+                                                    OSType(*b"ic10"),
+                                                    &mut payload)
+                .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn extract_missing_icon() {
+        let (_, data) = sample_file();
+        let error = IconFamily::extract_icon_from_reader(Cursor::new(&data),
+                                                         IconType::Mask8_48x48)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn extract_with_bogus_length() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"icns\x00\x00\x00\x18is32\xff\xff\xff\xf0");
+        data.extend_from_slice(&[0; 8]);
+        let error = IconFamily::extract_icon_from_reader(Cursor::new(&data),
+                                                         IconType::RGB24_16x16)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
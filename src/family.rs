@@ -1,27 +1,258 @@
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
 use std::io::{self, Error, ErrorKind, Read, Write};
+use std::slice;
 
+use super::autofit::AutoFit;
+use super::codec::CodecRegistry;
+use super::decode::{DecodeOptions, MaskLength, MissingIcon, MissingMask};
 use super::element::IconElement;
-use super::icontype::IconType;
-use super::image::Image;
+use super::icontype::{ALL_ICON_TYPES, Encoding, IconType, OSType};
+use super::iconutil::refresh_toc;
+use super::image::{Image, PixelFormat};
+#[cfg(feature = "pngio")]
+use super::options::{PngCompression, recompress_png};
+use super::payload::PayloadFormat;
+use super::progress::Progress;
+use super::validate::ValidationError;
+use super::warning::Warning;
 
 /// The first four bytes of an ICNS file:
-const ICNS_MAGIC_LITERAL: &[u8; 4] = b"icns";
+pub(crate) const ICNS_MAGIC_LITERAL: &[u8; 4] = b"icns";
 
 /// The length of an icon family header, in bytes:
-const ICON_FAMILY_HEADER_LENGTH: u32 = 8;
+pub(crate) const ICON_FAMILY_HEADER_LENGTH: u32 = 8;
+
+/// The minimum width/height of an image passed to `from_master_image`:
+const MASTER_IMAGE_MIN_SIZE: u32 = 1024;
+
+/// The icon types generated by `from_master_image` (these are the same sizes
+/// and densities that make up a complete `.iconset` directory):
+pub(crate) const MASTER_ICON_TYPES: &[IconType] =
+    &[IconType::RGB24_16x16,
+      IconType::RGBA32_16x16_2x,
+      IconType::RGB24_32x32,
+      IconType::RGBA32_32x32_2x,
+      IconType::RGBA32_128x128,
+      IconType::RGBA32_128x128_2x,
+      IconType::RGBA32_256x256,
+      IconType::RGBA32_256x256_2x,
+      IconType::RGBA32_512x512,
+      IconType::RGBA32_512x512_2x];
 
 /// A set of icons stored in a single ICNS file.
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct IconFamily {
-    /// The icon elements stored in the ICNS file.
-    pub elements: Vec<IconElement>,
+    pub(crate) elements: Vec<IconElement>,
+    duplicate_policy: DuplicatePolicy,
+    type_preference: TypePreference,
+    auto_fit: AutoFit,
+    codecs: CodecRegistry,
 }
 
 impl IconFamily {
     /// Creates a new, empty icon family.
     pub fn new() -> IconFamily {
-        IconFamily { elements: Vec::new() }
+        IconFamily {
+            elements: Vec::new(),
+            duplicate_policy: DuplicatePolicy::Append,
+            type_preference: TypePreference::LegacyFirst,
+            auto_fit: AutoFit::Exact,
+            codecs: CodecRegistry::new(),
+        }
+    }
+
+    /// Creates an icon family containing the given elements.
+    pub fn from_elements(elements: Vec<IconElement>) -> IconFamily {
+        IconFamily {
+            elements,
+            duplicate_policy: DuplicatePolicy::Append,
+            type_preference: TypePreference::LegacyFirst,
+            auto_fit: AutoFit::Exact,
+            codecs: CodecRegistry::new(),
+        }
+    }
+
+    /// Returns an empty family with the same settings (duplicate policy,
+    /// type preference, and so on) as this one.
+    pub(crate) fn empty_copy(&self) -> IconFamily {
+        IconFamily {
+            elements: Vec::new(),
+            duplicate_policy: self.duplicate_policy,
+            type_preference: self.type_preference,
+            auto_fit: self.auto_fit,
+            codecs: self.codecs.clone(),
+        }
+    }
+
+    /// Returns the icon elements stored in the family, in the order that
+    /// they will be written to an ICNS file.
+    pub fn elements(&self) -> &[IconElement] {
+        &self.elements
+    }
+
+    /// Returns an iterator over the icon elements stored in the family.
+    pub fn iter(&self) -> slice::Iter<'_, IconElement> {
+        self.elements.iter()
+    }
+
+    /// Adds an element to the end of the family, according to the family's
+    /// [duplicate policy](#method.set_duplicate_policy) (so if the policy is
+    /// `Replace`, an existing element of the same type is replaced in place
+    /// instead).  Returns an error if the policy is `Error` and the family
+    /// already contains an element of the same type.  If the family has a
+    /// `TOC ` (table of contents) element, it is rebuilt to list the new
+    /// element too.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{DuplicatePolicy, IconElement, IconFamily, OSType};
+    /// let mut family = IconFamily::new();
+    /// family.set_duplicate_policy(DuplicatePolicy::Replace);
+    /// family.push_element(IconElement::new(OSType(*b"icnV"), vec![1]))
+    ///     .unwrap();
+    /// family.push_element(IconElement::new(OSType(*b"icnV"), vec![2]))
+    ///     .unwrap();
+    /// assert_eq!(family.elements().len(), 1);
+    /// assert_eq!(family.elements()[0].data, vec![2]);
+    /// ```
+    pub fn push_element(&mut self, element: IconElement) -> io::Result<()> {
+        self.insert_elements(vec![element])
+    }
+
+    /// Removes every element for which the predicate returns false, keeping
+    /// the order of the remaining elements.  If a `TOC ` (table of contents)
+    /// element is kept, it is rebuilt to list only the remaining elements.
+    pub fn retain_elements<F>(&mut self, predicate: F)
+        where F: FnMut(&IconElement) -> bool
+    {
+        self.elements.retain(predicate);
+        refresh_toc(&mut self.elements);
+    }
+
+    /// Consumes the family, returning its elements.
+    pub fn into_elements(self) -> Vec<IconElement> {
+        self.elements
+    }
+
+    /// Returns the policy used when adding an icon whose element type is
+    /// already present in the family.
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    /// Sets the policy used by the `add_icon*` methods when adding an icon
+    /// whose element type is already present in the family.  The default
+    /// policy is `DuplicatePolicy::Append`.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
+
+    /// Returns the preference used by [`add_icon`](#method.add_icon) when
+    /// more than one icon type matches an image's dimensions.
+    pub fn type_preference(&self) -> TypePreference {
+        self.type_preference
+    }
+
+    /// Sets the preference used by [`add_icon`](#method.add_icon) when more
+    /// than one icon type matches an image's dimensions.  The default
+    /// preference is `TypePreference::LegacyFirst`.
+    pub fn set_type_preference(&mut self, preference: TypePreference) {
+        self.type_preference = preference;
+    }
+
+    /// Returns what [`add_icon`](#method.add_icon) does with an image whose
+    /// dimensions don't match any icon type.
+    pub fn auto_fit(&self) -> AutoFit {
+        self.auto_fit
+    }
+
+    /// Sets what [`add_icon`](#method.add_icon) does with an image whose
+    /// dimensions don't match any icon type.  The default is
+    /// `AutoFit::Exact`, which returns an error.
+    pub fn set_auto_fit(&mut self, auto_fit: AutoFit) {
+        self.auto_fit = auto_fit;
+    }
+
+    /// Returns the registry of codecs used by
+    /// [`get_icon_with_ostype`](#method.get_icon_with_ostype) and
+    /// [`add_icon_with_ostype`](#method.add_icon_with_ostype) for element
+    /// types that this library doesn't support natively.
+    pub fn codecs(&self) -> &CodecRegistry {
+        &self.codecs
+    }
+
+    /// Sets the registry of codecs used for element types that this library
+    /// doesn't support natively.  The default registry is empty.
+    pub fn set_codecs(&mut self, codecs: CodecRegistry) {
+        self.codecs = codecs;
+    }
+
+    /// Creates a complete icon family from a single square master image that
+    /// is at least 1024x1024 pixels, by scaling the master image down to
+    /// every size and density that Apple recommends for application icons
+    /// (16x16, 32x32, 128x128, 256x256, and 512x512, each at both 1x and 2x
+    /// density).  Returns an error if the master image is not square, or is
+    /// smaller than 1024x1024.
+    pub fn from_master_image(image: &Image) -> io::Result<IconFamily> {
+        IconFamily::from_master_image_with_progress(image, |_| {})
+    }
+
+    /// Creates a complete icon family from a single master image, as with
+    /// [`from_master_image`](#method.from_master_image), calling the
+    /// `progress` callback as each element is encoded.
+    pub fn from_master_image_with_progress<F>(image: &Image,
+                                              mut progress: F)
+                                              -> io::Result<IconFamily>
+        where F: FnMut(Progress)
+    {
+        if image.width() != image.height() ||
+           image.width() < MASTER_IMAGE_MIN_SIZE {
+            let msg = format!("master image must be square and at least \
+                               {}x{} (was {}x{})",
+                              MASTER_IMAGE_MIN_SIZE,
+                              MASTER_IMAGE_MIN_SIZE,
+                              image.width(),
+                              image.height());
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let element_types: Vec<IconType> = MASTER_ICON_TYPES.iter()
+            .flat_map(|&icon_type| {
+                Some(icon_type).into_iter().chain(icon_type.mask_type())
+            })
+            .collect();
+        let mut family = IconFamily::new();
+        let mut scaled: Vec<Image> = Vec::new();
+        for (index, &icon_type) in element_types.iter().enumerate() {
+            progress(Progress::ElementStarted {
+                index,
+                elements_total: Some(element_types.len()),
+            });
+            let size = icon_type.pixel_width();
+            // Several icon types share the same pixel size, so reuse scaled
+            // images where possible.
+            let position = scaled.iter().position(|i| i.width() == size);
+            let scaled_index = match position {
+                Some(scaled_index) => scaled_index,
+                None => {
+                    scaled.push(image.resize(size, size));
+                    scaled.len() - 1
+                }
+            };
+            let element =
+                IconElement::encode_image_with_type(&scaled[scaled_index],
+                                                    icon_type)?;
+            family.elements.push(element);
+            progress(Progress::ElementFinished {
+                index,
+                ostype: icon_type.ostype(),
+                bytes_processed: u64::from(family.total_length()),
+                bytes_total: None,
+            });
+        }
+        Ok(family)
     }
 
     /// Returns true if the icon family contains no icons nor any other
@@ -31,19 +262,65 @@ impl IconFamily {
     }
 
     /// Encodes the image into the family, automatically choosing an
-    /// appropriate icon type based on the dimensions of the image.  Returns
-    /// an error if there is no supported icon type matching the image
-    /// dimensions.
+    /// appropriate icon type (or types) based on the dimensions of the image
+    /// and the family's [type preference](#method.set_type_preference).
+    /// If there is no supported icon type matching the image dimensions, the
+    /// image is padded, cropped, or scaled to fit the nearest one according
+    /// to the family's [auto-fit mode](#method.set_auto_fit); by default, an
+    /// error is returned instead.
     pub fn add_icon(&mut self, image: &Image) -> io::Result<()> {
-        if let Some(icon_type) = IconType::from_pixel_size(image.width(),
-                                                           image.height()) {
-            self.add_icon_with_type(image, icon_type)
-        } else {
+        let icon_types = self.type_preference
+            .icon_types_for_size(image.width(), image.height());
+        if icon_types.is_empty() {
+            if let Some(fitted) = self.auto_fit.fit(image,
+                                                    self.type_preference) {
+                return self.add_icon(&fitted);
+            }
             let msg = format!("no supported icon type has dimensions {}x{}",
                               image.width(),
                               image.height());
-            Err(Error::new(ErrorKind::InvalidInput, msg))
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        self.add_icon_with_types(image, &icon_types)
+    }
+
+    /// Encodes the image into the family, like [`add_icon`](#method.add_icon),
+    /// but choosing among only the icon types with the given pixel density
+    /// (1 for normal icons, or 2 for "retina" icons).  This disambiguates
+    /// images whose dimensions match types of both densities; for example, a
+    /// 64x64 image is added as a 64x64 icon by `add_icon`, but can be added
+    /// as a 32x32@2x icon instead with a density of 2.  Returns an error if
+    /// there is no supported icon type with the image's dimensions and the
+    /// given density.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 64, 64);
+    /// family.add_icon_with_density(&image, 2).unwrap();
+    /// assert_eq!(family.available_icons(), vec![IconType::RGBA32_32x32_2x]);
+    /// assert!(family.add_icon_with_density(&image, 3).is_err());
+    /// # }
+    /// ```
+    pub fn add_icon_with_density(&mut self,
+                                 image: &Image,
+                                 density: u32)
+                                 -> io::Result<()> {
+        let icon_types = self.type_preference
+            .icon_types_for_size_and_density(image.width(),
+                                             image.height(),
+                                             density);
+        if icon_types.is_empty() {
+            let msg = format!("no supported icon type has dimensions {}x{} \
+                               with a pixel density of {}",
+                              image.width(),
+                              image.height(),
+                              density);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
         }
+        self.add_icon_with_types(image, &icon_types)
     }
 
     /// Encodes the image into the family using the given icon type.  If the
@@ -54,14 +331,298 @@ impl IconFamily {
                               image: &Image,
                               icon_type: IconType)
                               -> io::Result<()> {
-        self.elements
-            .push(IconElement::encode_image_with_type(image, icon_type)?);
+        self.add_icon_with_types(image, &[icon_type])
+    }
+
+    /// Encodes the image (and its mask, where needed) as each of the given
+    /// icon types, and only then adds all the new elements to the family, so
+    /// that the family is left unchanged if any of them fails.
+    fn add_icon_with_types(&mut self,
+                           image: &Image,
+                           icon_types: &[IconType])
+                           -> io::Result<()> {
+        let mut elements = Vec::with_capacity(2 * icon_types.len());
+        for &icon_type in icon_types {
+            elements.push(IconElement::encode_image_with_type(image,
+                                                              icon_type)?);
+            if let Some(mask_type) = icon_type.mask_type() {
+                elements.push(IconElement::encode_image_with_type(image,
+                                                                  mask_type)?);
+            }
+        }
+        self.insert_elements(elements)
+    }
+
+    /// Encodes the image into the family using the given icon type, like
+    /// [`add_icon_with_type`](#method.add_icon_with_type), except that the
+    /// image mask is never added, even if the selected type has an associated
+    /// mask type.  This is useful when managing mask elements separately.
+    /// Note that an icon type with an associated mask type will not be
+    /// considered a complete icon (see
+    /// [`has_icon_with_type`](#method.has_icon_with_type)) until its mask is
+    /// added.  Returns an error if the image has the wrong dimensions for the
+    /// selected type.
+    pub fn add_icon_without_mask(&mut self,
+                                 image: &Image,
+                                 icon_type: IconType)
+                                 -> io::Result<()> {
+        let element = IconElement::encode_image_with_type(image, icon_type)?;
+        self.insert_elements(vec![element])
+    }
+
+    /// Encodes the image into the family using the given icon type, along
+    /// with a separately-supplied alpha mask (rather than deriving the mask
+    /// from the image's own alpha channel).  The mask image is converted to
+    /// `PixelFormat::Alpha` as by [`Image::convert_to`](
+    /// struct.Image.html#method.convert_to), so it should normally already be
+    /// in that format.  Returns an error if the selected type has no
+    /// associated mask type, or if either image has the wrong dimensions for
+    /// the selected type.
+    pub fn add_icon_with_mask(&mut self,
+                              image: &Image,
+                              mask: &Image,
+                              icon_type: IconType)
+                              -> io::Result<()> {
+        let mask_type = icon_type.mask_type().ok_or_else(|| {
+            let msg = format!("icon type {:?} does not use a separate mask",
+                              icon_type);
+            Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        let color = IconElement::encode_image_with_type(image, icon_type)?;
+        let mask = IconElement::encode_image_with_type(mask, mask_type)?;
+        self.insert_elements(vec![color, mask])
+    }
+
+    /// Encodes the image into the family using the given icon type, like
+    /// [`add_icon_with_type`](#method.add_icon_with_type), and if the icon
+    /// type is a 2x "retina" type whose 1x
+    /// [`density_counterpart`](enum.IconType.html#method.density_counterpart)
+    /// isn't already in the family, also adds a half-size copy of the image
+    /// as that counterpart type.  (Counterparts are never generated for 1x
+    /// types, since scaling up would lose quality; use
+    /// [`missing_density_counterparts`](
+    /// #method.missing_density_counterparts) to find those instead.)
+    /// Returns an error if the image has the wrong dimensions for the
+    /// selected type, in which case nothing is added.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 64, 64);
+    /// family.add_icon_with_counterpart(&image, IconType::RGBA32_32x32_2x)
+    ///     .unwrap();
+    /// assert!(family.has_icon_with_type(IconType::RGBA32_32x32));
+    /// # }
+    /// ```
+    pub fn add_icon_with_counterpart(&mut self,
+                                     image: &Image,
+                                     icon_type: IconType)
+                                     -> io::Result<()> {
+        let element = IconElement::encode_image_with_type(image, icon_type)?;
+        let mut elements = vec![element];
         if let Some(mask_type) = icon_type.mask_type() {
-            self.elements
-                .push(IconElement::encode_image_with_type(image,
-                                                               mask_type)?);
+            elements.push(IconElement::encode_image_with_type(image,
+                                                              mask_type)?);
         }
-        Ok(())
+        if let Some(counterpart) = icon_type.density_counterpart() {
+            if counterpart.pixel_density() < icon_type.pixel_density() &&
+               self.find_element(counterpart).is_err() {
+                let scaled = image.resize(counterpart.pixel_width(),
+                                          counterpart.pixel_height());
+                let element =
+                    IconElement::encode_image_with_type(&scaled,
+                                                        counterpart)?;
+                elements.push(element);
+            }
+        }
+        self.insert_elements(elements)
+    }
+
+    /// Returns a list of the icon types that are the
+    /// [`density_counterpart`](enum.IconType.html#method.density_counterpart)
+    /// of an icon in the family, but that are missing from the family.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+    /// assert_eq!(family.missing_density_counterparts(),
+    ///            vec![IconType::RGBA32_32x32_2x]);
+    /// # }
+    /// ```
+    pub fn missing_density_counterparts(&self) -> Vec<IconType> {
+        let mut missing = Vec::new();
+        for icon_type in self.available_icons() {
+            if let Some(counterpart) = icon_type.density_counterpart() {
+                if !self.has_icon_with_type(counterpart) &&
+                   !missing.contains(&counterpart) {
+                    missing.push(counterpart);
+                }
+            }
+        }
+        missing
+    }
+
+    /// Returns the number of bytes by which adding the image to an icon
+    /// family with [`add_icon_with_type`](#method.add_icon_with_type) would
+    /// grow the family's encoded length (including element headers, and the
+    /// mask element, if any), without actually adding it.  Since this runs
+    /// the same encoders that `add_icon_with_type` would, it is exact, but
+    /// not any faster than adding the icon.  Returns an error if the image
+    /// has the wrong dimensions for the selected type.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// let length = IconFamily::encoded_icon_length(&image,
+    ///                                              IconType::RGB24_16x16)
+    ///     .unwrap();
+    /// let mut family = IconFamily::new();
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// assert_eq!(family.total_length(), 8 + length);
+    /// ```
+    pub fn encoded_icon_length(image: &Image,
+                               icon_type: IconType)
+                               -> io::Result<u32> {
+        let mut length =
+            IconElement::encode_image_with_type(image, icon_type)?
+                .total_length();
+        if let Some(mask_type) = icon_type.mask_type() {
+            length += IconElement::encode_image_with_type(image, mask_type)?
+                .total_length();
+        }
+        Ok(length)
+    }
+
+    /// Shrinks the family until its encoded length is at most `max_bytes`.
+    /// If it doesn't already fit, its PNG data is first re-encoded at the
+    /// best compression level (where that is smaller, and only when the
+    /// `pngio` feature is enabled), keeping any color space and metadata
+    /// chunks as with [`WriteOptions::png_compression`](
+    /// struct.WriteOptions.html#method.png_compression); then, if it still
+    /// doesn't fit, icons are removed, least important first (as determined
+    /// by `priority`).
+    /// Returns the types of the icons that were removed, in the order they
+    /// were removed.  Elements that aren't icons (or masks of icons) are
+    /// never removed, although a `TOC ` (table of contents) element is
+    /// rebuilt to list the remaining elements.  If the family can't be made
+    /// to fit within the budget, returns an error and leaves the family
+    /// unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat, TrimPriority};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x).unwrap();
+    /// let budget = family.total_length() - 1;
+    /// let removed =
+    ///     family.trim_to_budget(budget, TrimPriority::PreferModern).unwrap();
+    /// assert_eq!(removed, vec![IconType::RGB24_32x32]);
+    /// # }
+    /// ```
+    pub fn trim_to_budget(&mut self,
+                          max_bytes: u32,
+                          priority: TrimPriority)
+                          -> io::Result<Vec<IconType>> {
+        let mut trimmed = self.clone();
+        #[cfg(feature = "pngio")]
+        {
+            if trimmed.total_length() > max_bytes {
+                for element in trimmed.elements.iter_mut() {
+                    if element.payload_format() == PayloadFormat::PNG {
+                        recompress_png(element, PngCompression::Best)?;
+                    }
+                }
+            }
+        }
+        let mut icon_types: Vec<IconType> = Vec::new();
+        let mut seen = HashSet::new();
+        for element in &trimmed.elements {
+            if let Some(icon_type) = element.icon_type() {
+                if !icon_type.is_mask() && seen.insert(icon_type) {
+                    icon_types.push(icon_type);
+                }
+            }
+        }
+        // Duplicate elements are counted (and removed) together.
+        let mut candidates: Vec<(IconType, u32)> = icon_types.into_iter()
+            .map(|icon_type| {
+                let mut length = trimmed.element_length(icon_type);
+                if let Some(mask_type) = icon_type.mask_type() {
+                    length += trimmed.element_length(mask_type);
+                }
+                (icon_type, length)
+            })
+            .collect();
+        let modern_sizes: Vec<u32> = candidates.iter()
+            .filter(|&&(icon_type, _)| !is_legacy(icon_type))
+            .map(|&(icon_type, _)| icon_type.pixel_width())
+            .collect();
+        candidates.sort_by_key(|&(icon_type, _)| {
+            let legacy = is_legacy(icon_type);
+            let retina = icon_type.pixel_density() > 1;
+            // Lower ranks are removed first, and within each rank, larger
+            // icons are removed first.
+            let rank = match priority {
+                TrimPriority::PreferModern => {
+                    if legacy &&
+                       modern_sizes.contains(&icon_type.pixel_width()) {
+                        0
+                    } else if retina {
+                        1
+                    } else if legacy {
+                        2
+                    } else {
+                        3
+                    }
+                }
+                TrimPriority::PreferLegacy => {
+                    if retina {
+                        0
+                    } else if !legacy {
+                        1
+                    } else {
+                        2
+                    }
+                }
+            };
+            (rank, Reverse(icon_type.pixel_width()))
+        });
+        let mut length = trimmed.total_length();
+        let mut removed = Vec::new();
+        for (icon_type, element_length) in candidates {
+            if length <= max_bytes {
+                break;
+            }
+            length = length.saturating_sub(element_length);
+            removed.push(icon_type);
+        }
+        if length > max_bytes {
+            let msg = format!("icon family cannot be trimmed to fit within \
+                               {} bytes",
+                              max_bytes);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let removed_ostypes: Vec<OSType> = removed.iter()
+            .flat_map(|&icon_type| {
+                Some(icon_type).into_iter().chain(icon_type.mask_type())
+            })
+            .map(|icon_type| icon_type.ostype())
+            .collect();
+        trimmed.retain_elements(|element| {
+            !removed_ostypes.contains(&element.ostype)
+        });
+        *self = trimmed;
+        Ok(removed)
     }
 
     /// Returns a list of all (non-mask) icon types for which the icon family
@@ -104,31 +665,426 @@ impl IconFamily {
     /// decoded together into a single image.  Returns an error if the
     /// element(s) for the selected type are not present in the icon family, or
     /// the if the encoded data is malformed.
+    ///
+    /// As a special case, if an `icp4` or `icp5` element contains RLE data
+    /// (which some files use instead of PNG data), it is decoded together
+    /// with the `s8mk` or `l8mk` mask element, if the family has one.
     pub fn get_icon_with_type(&self,
                               icon_type: IconType)
                               -> io::Result<Image> {
-        let element = self.find_element(icon_type)?;
-        if let Some(mask_type) = icon_type.mask_type() {
-            let mask = self.find_element(mask_type)?;
-            element.decode_image_with_mask(mask)
+        self.get_icon_with_type_and_options(icon_type, &DecodeOptions::new())
+    }
+
+    /// Decodes an image from the family with the given icon type, like
+    /// [`get_icon_with_type`](#method.get_icon_with_type), using the given
+    /// options to tolerate some kinds of malformed data.
+    pub fn get_icon_with_type_and_options(&self,
+                                          icon_type: IconType,
+                                          options: &DecodeOptions)
+                                          -> io::Result<Image> {
+        self.get_icon_internal(icon_type, options, &mut Vec::new())
+    }
+
+    /// Decodes an image from the family with the given icon type and
+    /// options, like [`get_icon_with_type_and_options`](
+    /// #method.get_icon_with_type_and_options), but also returns a list of
+    /// any problems that the options allowed decoding to recover from (such
+    /// as a [missing mask](enum.MissingMask.html)).
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{DecodeOptions, IconFamily, IconType, Image, MissingMask,
+    ///            PixelFormat, Warning};
+    /// let image = Image::new(PixelFormat::RGB, 16, 16);
+    /// let mut family = IconFamily::new();
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// family.retain_elements(|element| !element.ostype.0.ends_with(b"mk"));
+    /// let options = DecodeOptions::new().missing_mask(MissingMask::Opaque);
+    /// let (_, warnings) =
+    ///     family.get_icon_with_warnings(IconType::RGB24_16x16, &options)
+    ///         .unwrap();
+    /// assert_eq!(warnings,
+    ///            vec![Warning::MissingMask {
+    ///                     ostype: IconType::Mask8_16x16.ostype(),
+    ///                 }]);
+    /// ```
+    pub fn get_icon_with_warnings(&self,
+                                  icon_type: IconType,
+                                  options: &DecodeOptions)
+                                  -> io::Result<(Image, Vec<Warning>)> {
+        let mut warnings = Vec::new();
+        let image = self.get_icon_internal(icon_type, options, &mut warnings)?;
+        Ok((image, warnings))
+    }
+
+    /// Private helper method.
+    fn get_icon_internal(&self,
+                         icon_type: IconType,
+                         options: &DecodeOptions,
+                         warnings: &mut Vec<Warning>)
+                         -> io::Result<Image> {
+        if options.missing_icon == MissingIcon::Synthesize &&
+           !icon_type.is_mask() && !self.can_decode(icon_type, options) {
+            // Try each candidate source in turn, in case some of them
+            // can't be decoded (e.g. because they hold JPEG 2000 data).
+            let mut first_error = None;
+            for source in self.sources_for_size(icon_type.pixel_width(), true)
+                .into_iter()
+                .filter(|&source| source != icon_type) {
+                let mut source_warnings = Vec::new();
+                match self.get_icon_internal(source,
+                                             options,
+                                             &mut source_warnings) {
+                    Ok(image) => {
+                        warnings.push(Warning::SynthesizedIcon {
+                            ostype: icon_type.ostype(),
+                            source: source.ostype(),
+                        });
+                        warnings.extend(source_warnings);
+                        return Ok(image.resize(icon_type.pixel_width(),
+                                               icon_type.pixel_height()));
+                    }
+                    Err(error) => {
+                        first_error.get_or_insert(error);
+                    }
+                }
+            }
+            if let Some(error) = first_error {
+                return Err(error);
+            }
+        }
+        decode_icon(|icon_type| self.find_element(icon_type),
+                    icon_type,
+                    options,
+                    warnings)
+    }
+
+    /// Returns true if the icon type can be decoded from the family's own
+    /// elements with the given options (that is, without synthesizing it).
+    fn can_decode(&self,
+                  icon_type: IconType,
+                  options: &DecodeOptions)
+                  -> bool {
+        self.has_icon_with_type(icon_type) ||
+        (self.find_element(icon_type).is_ok() &&
+         options.missing_mask == MissingMask::Opaque)
+    }
+
+    /// Decodes an icon from the family with the given pixel dimensions and
+    /// pixel density, regardless of which icon type it is stored as (for
+    /// example, a 32x32-pixel icon at 1x density might be stored as either
+    /// `RGB24_32x32` or `RGBA32_32x32`).  If the family contains more than one
+    /// complete icon with the given geometry, the one that appears first in
+    /// the family is used.  Returns an error if there is no such icon in the
+    /// family, or if the encoded data is malformed.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 64, 64);
+    /// family.add_icon_with_type(&image, IconType::RGBA32_32x32_2x).unwrap();
+    /// assert!(family.get_icon_by_size(64, 64, 2).is_ok());
+    /// assert!(family.get_icon_by_size(64, 64, 1).is_err());
+    /// # }
+    /// ```
+    pub fn get_icon_by_size(&self,
+                            width: u32,
+                            height: u32,
+                            density: u32)
+                            -> io::Result<Image> {
+        let icon_type = self.available_icons()
+            .into_iter()
+            .find(|icon_type| {
+                icon_type.pixel_width() == width &&
+                icon_type.pixel_height() == height &&
+                icon_type.pixel_density() == density
+            })
+            .ok_or_else(|| {
+                let msg = format!("the icon family does not contain a {}x{} \
+                                   icon at {}x density",
+                                  width,
+                                  height,
+                                  density);
+                Error::new(ErrorKind::NotFound, msg)
+            })?;
+        self.get_icon_with_type(icon_type)
+    }
+
+    /// Decodes an image from the family with the given icon type, like
+    /// [`get_icon_with_type`](#method.get_icon_with_type), but scaled so
+    /// that its larger dimension is `max_dim` pixels.  If `upscale` is false,
+    /// images that are already small enough are returned at their full size
+    /// instead of being scaled up.  The decoded image is returned directly,
+    /// without an extra copy, if it doesn't need scaling.  Returns an error
+    /// if `max_dim` is zero, or for any of the reasons that
+    /// `get_icon_with_type` would.
+    ///
+    /// (JPEG 2000 data can't be decoded at all yet, so there is no
+    /// reduced-resolution decoding path; PNG and RLE data are always decoded
+    /// at full size before resampling.)
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+    /// let icon_type = IconType::RGB24_32x32;
+    /// let icon = family.get_icon_with_type_scaled(icon_type, 24, false)
+    ///     .unwrap();
+    /// assert_eq!((icon.width(), icon.height()), (24, 24));
+    /// let icon = family.get_icon_with_type_scaled(icon_type, 48, false)
+    ///     .unwrap();
+    /// assert_eq!((icon.width(), icon.height()), (32, 32));
+    /// let icon = family.get_icon_with_type_scaled(icon_type, 48, true)
+    ///     .unwrap();
+    /// assert_eq!((icon.width(), icon.height()), (48, 48));
+    /// ```
+    pub fn get_icon_with_type_scaled(&self,
+                                     icon_type: IconType,
+                                     max_dim: u32,
+                                     upscale: bool)
+                                     -> io::Result<Image> {
+        if max_dim == 0 {
+            let msg = "maximum dimension for scaled icon must be nonzero";
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let image = self.get_icon_with_type(icon_type)?;
+        let (width, height) = (image.width(), image.height());
+        let larger = width.max(height);
+        if larger == max_dim || (larger < max_dim && !upscale) {
+            return Ok(image);
+        }
+        let scaled_width = (u64::from(width) * u64::from(max_dim) /
+                            u64::from(larger))
+            .max(1) as u32;
+        let scaled_height = (u64::from(height) * u64::from(max_dim) /
+                             u64::from(larger))
+            .max(1) as u32;
+        Ok(image.resize(scaled_width, scaled_height))
+    }
+
+    /// Decodes the icon in the family that is best suited for display at
+    /// `size` by `size` pixels, and returns it scaled to exactly that size.
+    /// The best source is the smallest icon with at least `size` pixels
+    /// along each side (so that it only needs to be scaled down), or failing
+    /// that, the largest icon in the family.  Returns an error if the family
+    /// contains no complete icons, if `size` is zero, or if the chosen icon's
+    /// data is malformed.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 128, 128);
+    /// family.add_icon_with_type(&image, IconType::RGB24_128x128).unwrap();
+    /// let preview = family.preview_at(96).unwrap();
+    /// assert_eq!((preview.width(), preview.height()), (96, 96));
+    /// ```
+    pub fn preview_at(&self, size: u32) -> io::Result<Image> {
+        let icon_type = self.best_source_for_size(size, true)
+            .ok_or_else(|| {
+                let msg = "the icon family does not contain any complete \
+                           icons";
+                Error::new(ErrorKind::NotFound, msg)
+            })?;
+        self.get_icon_with_type_scaled(icon_type, size, true)
+    }
+
+    /// Returns the available icon type with the fewest pixels that still has
+    /// at least `pixels` pixels along each side.  If there is no such icon
+    /// and `allow_upscale` is true, returns the largest available icon type
+    /// instead.
+    pub(crate) fn best_source_for_size(&self,
+                                       pixels: u32,
+                                       allow_upscale: bool)
+                                       -> Option<IconType> {
+        self.sources_for_size(pixels, allow_upscale).into_iter().next()
+    }
+
+    /// Returns the available icon types in order of preference for scaling
+    /// to `pixels` pixels along each side: those with at least that many
+    /// pixels (smallest first), followed, if `allow_upscale` is true, by the
+    /// smaller ones (largest first).  Icon types of the same size keep the
+    /// order in which they appear in the family.
+    pub(crate) fn sources_for_size(&self,
+                                   pixels: u32,
+                                   allow_upscale: bool)
+                                   -> Vec<IconType> {
+        let mut sources = self.available_icons();
+        sources.retain(|icon_type| {
+            allow_upscale || icon_type.pixel_width() >= pixels
+        });
+        sources.sort_by_key(|icon_type| {
+            let width = icon_type.pixel_width();
+            (width < pixels, width.abs_diff(pixels))
+        });
+        sources
+    }
+
+    /// Decodes just the alpha mask for the given icon type into a
+    /// `PixelFormat::Alpha` image, without merging it with the color data.
+    /// The icon type may be either a mask type, or an icon type with an
+    /// associated mask type.  Returns an error if the icon type has no mask,
+    /// if the mask element is not present in the icon family, or if the
+    /// encoded data is malformed.
+    pub fn get_mask_with_type(&self,
+                              icon_type: IconType)
+                              -> io::Result<Image> {
+        let mask_type = if icon_type.is_mask() {
+            icon_type
         } else {
-            element.decode_image()
+            icon_type.mask_type().ok_or_else(|| {
+                let msg = format!("icon type {:?} does not use a separate \
+                                   mask",
+                                  icon_type);
+                Error::new(ErrorKind::InvalidInput, msg)
+            })?
+        };
+        self.find_element(mask_type)?.decode_image()
+    }
+
+    /// Adds newly-encoded elements to the family, according to the family's
+    /// duplicate policy.  If an error is returned, the family is left
+    /// unchanged.
+    pub(crate) fn insert_elements(&mut self,
+                                  elements: Vec<IconElement>)
+                                  -> io::Result<()> {
+        match self.duplicate_policy {
+            DuplicatePolicy::Append => self.elements.extend(elements),
+            DuplicatePolicy::Replace => {
+                for element in elements {
+                    // Replace the first existing element of the same type in
+                    // place, and remove any others.
+                    let ostype = element.ostype;
+                    let mut replacement = Some(element);
+                    self.elements.retain_mut(|el| {
+                        if el.ostype != ostype {
+                            return true;
+                        }
+                        match replacement.take() {
+                            Some(element) => {
+                                *el = element;
+                                true
+                            }
+                            None => false,
+                        }
+                    });
+                    self.elements.extend(replacement);
+                }
+            }
+            DuplicatePolicy::Error => {
+                if let Some(element) = elements.iter().find(|element| {
+                    self.elements.iter().any(|el| el.ostype == element.ostype)
+                }) {
+                    let msg = format!("the icon family already contains a \
+                                       '{}' element",
+                                      element.ostype);
+                    return Err(Error::new(ErrorKind::AlreadyExists, msg));
+                }
+                self.elements.extend(elements);
+            }
         }
+        refresh_toc(&mut self.elements);
+        Ok(())
+    }
+
+    /// Private helper method: returns the total length of all elements with
+    /// the given icon type.
+    fn element_length(&self, icon_type: IconType) -> u32 {
+        let ostype = icon_type.ostype();
+        self.elements
+            .iter()
+            .filter(|element| element.ostype == ostype)
+            .map(IconElement::total_length)
+            .sum()
     }
 
     /// Private helper method.
-    fn find_element(&self, icon_type: IconType) -> io::Result<&IconElement> {
+    pub(crate) fn find_element(&self,
+                               icon_type: IconType)
+                               -> io::Result<&IconElement> {
         let ostype = icon_type.ostype();
-        self.elements.iter().find(|el| el.ostype == ostype).ok_or_else(|| {
-            let msg = format!("the icon family does not contain a '{}' \
-                               element",
-                              ostype);
-            Error::new(ErrorKind::NotFound, msg)
-        })
+        self.elements
+            .iter()
+            .find(|el| el.ostype == ostype)
+            .ok_or_else(|| missing_element(ostype))
     }
 
     /// Reads an icon family from an ICNS file.
-    pub fn read<R: Read>(mut reader: R) -> io::Result<IconFamily> {
+    pub fn read<R: Read>(reader: R) -> io::Result<IconFamily> {
+        IconFamily::read_with_limits(reader, &ReadLimits::new())
+    }
+
+    /// Reads a stream of zero or more concatenated ICNS files, continuing
+    /// until the end of the stream is reached, and returns the icon families
+    /// in the order they were read.  Returns an error if the stream ends
+    /// partway through a family.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::IconFamily;
+    /// let data: &[u8] = b"icns\0\0\0\x08icns\0\0\0\x08";
+    /// let families = IconFamily::read_all(data).unwrap();
+    /// assert_eq!(families.len(), 2);
+    /// ```
+    pub fn read_all<R: Read>(mut reader: R) -> io::Result<Vec<IconFamily>> {
+        let mut families = Vec::new();
+        loop {
+            // Peek at the first byte of the next family, to see if we've
+            // reached the end of the stream.
+            let mut first = [0u8; 1];
+            match reader.read(&mut first) {
+                Ok(0) => return Ok(families),
+                Ok(_) => {}
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => {
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+            let chained = (&first[..]).chain(reader.by_ref());
+            families.push(IconFamily::read(chained)?);
+        }
+    }
+
+    /// Reads an icon family from an ICNS file, returning an error if the file
+    /// exceeds any of the given structural limits.  This is useful when
+    /// reading untrusted input, since otherwise a maliciously crafted element
+    /// header could cause a huge memory allocation.
+    pub fn read_with_limits<R: Read>(reader: R,
+                                     limits: &ReadLimits)
+                                     -> io::Result<IconFamily> {
+        IconFamily::read_internal(reader, limits, false, &mut |_| {})
+            .map(|(family, _)| family)
+    }
+
+    /// Reads an icon family from an ICNS file, calling the `progress`
+    /// callback as each element is read.
+    pub fn read_with_progress<R, F>(reader: R,
+                                    mut progress: F)
+                                    -> io::Result<IconFamily>
+        where R: Read,
+              F: FnMut(Progress)
+    {
+        IconFamily::read_internal(reader,
+                                  &ReadLimits::new(),
+                                  false,
+                                  &mut progress)
+            .map(|(family, _)| family)
+    }
+
+    /// Private helper method: reads an icon family, and also returns the
+    /// total file length declared in the family header (which may differ from
+    /// the number of bytes actually consumed, if the last element overran
+    /// it).  If `allow_slack` is true, the stream may also end before the
+    /// declared length, as long as it ends between two elements.
+    pub(crate) fn read_internal<R: Read>(mut reader: R,
+                                         limits: &ReadLimits,
+                                         allow_slack: bool,
+                                         progress: &mut dyn FnMut(Progress))
+                                         -> io::Result<(IconFamily, u32)> {
         let mut magic = [0u8; 4];
         reader.read_exact(&mut magic)?;
         if magic != *ICNS_MAGIC_LITERAL {
@@ -139,18 +1095,112 @@ impl IconFamily {
         let mut file_position: u32 = ICON_FAMILY_HEADER_LENGTH;
         let mut family = IconFamily::new();
         while file_position < file_length {
-            let element = IconElement::read(reader.by_ref())?;
+            let mut first = [0u8; 1];
+            if allow_slack {
+                // Peek at the first byte of the next element, to see if the
+                // stream ends early.
+                match reader.read(&mut first) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(ref err) if err.kind() == ErrorKind::Interrupted => {
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            } else {
+                reader.read_exact(&mut first)?;
+            }
+            if family.elements.len() >= limits.max_elements {
+                let msg = format!("icon family has too many elements (the \
+                                   limit is {})",
+                                  limits.max_elements);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+            let index = family.elements.len();
+            progress(Progress::ElementStarted {
+                index,
+                elements_total: None,
+            });
+            let chained = (&first[..]).chain(reader.by_ref());
+            let element =
+                IconElement::read_with_max_size(chained,
+                                                limits.max_element_size)?;
             file_position += element.total_length();
+            progress(Progress::ElementFinished {
+                index,
+                ostype: element.ostype,
+                bytes_processed: u64::from(file_position),
+                bytes_total: Some(u64::from(file_length)),
+            });
             family.elements.push(element);
         }
-        Ok(family)
+        if limits.validate_payloads {
+            limits.validate(&family)?;
+        }
+        Ok((family, file_length))
     }
 
     /// Writes the icon family to an ICNS file.
-    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_with_progress(writer, |_| {})
+    }
+
+    /// Writes the icon family to an ICNS file, calling the `progress`
+    /// callback as each element is written.
+    pub fn write_with_progress<W, F>(&self,
+                                     mut writer: W,
+                                     mut progress: F)
+                                     -> io::Result<()>
+        where W: Write,
+              F: FnMut(Progress)
+    {
+        let total_length = self.total_length();
         writer.write_all(ICNS_MAGIC_LITERAL)?;
-        writer.write_u32::<BigEndian>(self.total_length())?;
-        for element in &self.elements {
+        writer.write_u32::<BigEndian>(total_length)?;
+        let mut position = ICON_FAMILY_HEADER_LENGTH;
+        for (index, element) in self.elements.iter().enumerate() {
+            progress(Progress::ElementStarted {
+                index,
+                elements_total: Some(self.elements.len()),
+            });
+            element.write(writer.by_ref())?;
+            position += element.total_length();
+            progress(Progress::ElementFinished {
+                index,
+                ostype: element.ostype,
+                bytes_processed: u64::from(position),
+                bytes_total: Some(u64::from(total_length)),
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes the icon family to an ICNS file, making sure that any `it32`
+    /// element's data starts with the four zero bytes that Apple's own tools
+    /// always write there (if `include_prefix` is true), or that it doesn't
+    /// (if `include_prefix` is false).  Icons encoded by this library already
+    /// include the prefix, and some older decoders require it, but elements
+    /// read from other files may or may not have it.  The elements in this
+    /// family are not modified.
+    pub fn write_with_it32_prefix<W: Write>(&self,
+                                            mut writer: W,
+                                            include_prefix: bool)
+                                            -> io::Result<()> {
+        let adjusted: Vec<Option<IconElement>> = self.elements
+            .iter()
+            .map(|element| element.with_it32_prefix(include_prefix))
+            .collect();
+        let elements: Vec<&IconElement> = self.elements
+            .iter()
+            .zip(adjusted.iter())
+            .map(|(element, adjusted)| adjusted.as_ref().unwrap_or(element))
+            .collect();
+        let total_length = elements.iter()
+            .fold(ICON_FAMILY_HEADER_LENGTH,
+                  |length, element| length + element.total_length());
+        writer.write_all(ICNS_MAGIC_LITERAL)?;
+        writer.write_u32::<BigEndian>(total_length)?;
+        for element in elements {
             element.write(writer.by_ref())?;
         }
         Ok(())
@@ -167,14 +1217,382 @@ impl IconFamily {
     }
 }
 
+impl fmt::Display for IconFamily {
+    /// Formats a concise summary of the family, with one line per element
+    /// (see the `Display` implementation of
+    /// [`IconElement`](struct.IconElement.html)).  For a fuller report, see
+    /// [`describe`](#method.describe).
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        if self.elements.is_empty() {
+            return write!(out, "(empty icon family)");
+        }
+        for (index, element) in self.elements.iter().enumerate() {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{}", element)?;
+        }
+        Ok(())
+    }
+}
+
+/// Determines what the `add_icon*` methods of
+/// [`IconFamily`](struct.IconFamily.html) do when adding an icon whose
+/// element type is already present in the family.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Add the new element after the existing one, leaving both in the
+    /// family.  (Only the first of the two will be used when decoding.)
+    #[default]
+    Append,
+    /// Replace the existing element with the new one.
+    Replace,
+    /// Return an error, leaving the family unchanged.
+    Error,
+}
+
+/// Determines which icon type(s) [`IconFamily::add_icon`](
+/// struct.IconFamily.html#method.add_icon) uses when more than one type
+/// matches an image's dimensions (for example, a 128x128 image could be
+/// stored either as a legacy RLE-encoded `RGB24_128x128` icon with a mask, or
+/// as a PNG-encoded `RGBA32_128x128` icon).  Icon types with 1x density are
+/// always preferred over 2x "retina" types with the same pixel dimensions.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum TypePreference {
+    /// Use a legacy (RLE-encoded) type if there is one for the image's
+    /// dimensions, and a PNG/JPEG 2000 type otherwise.
+    #[default]
+    LegacyFirst,
+    /// Use a PNG/JPEG 2000 type if there is one for the image's dimensions,
+    /// and a legacy (RLE-encoded) type otherwise.
+    ModernFirst,
+    /// Use both a legacy type and a PNG/JPEG 2000 type, if there are both for
+    /// the image's dimensions, or whichever there is otherwise.
+    Both,
+}
+
+impl TypePreference {
+    /// Returns the (non-mask) icon types that should be used for an image
+    /// with the given dimensions under this preference, or an empty vector if
+    /// no icon type has those dimensions.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconType, TypePreference};
+    /// assert_eq!(TypePreference::LegacyFirst.icon_types_for_size(32, 32),
+    ///            vec![IconType::RGB24_32x32]);
+    /// assert_eq!(TypePreference::ModernFirst.icon_types_for_size(32, 32),
+    ///            vec![IconType::RGBA32_32x32]);
+    /// assert_eq!(TypePreference::Both.icon_types_for_size(1024, 1024),
+    ///            vec![IconType::RGBA32_512x512_2x]);
+    /// ```
+    pub fn icon_types_for_size(self,
+                               width: u32,
+                               height: u32)
+                               -> Vec<IconType> {
+        let icon_types = self.icon_types_for_size_and_density(width,
+                                                              height,
+                                                              1);
+        if icon_types.is_empty() {
+            self.icon_types_for_size_and_density(width, height, 2)
+        } else {
+            icon_types
+        }
+    }
+
+    /// Returns the (non-mask) icon types that should be used for an image
+    /// with the given dimensions and pixel density under this preference, or
+    /// an empty vector if no icon type has those dimensions and density.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconType, TypePreference};
+    /// let preference = TypePreference::LegacyFirst;
+    /// assert_eq!(preference.icon_types_for_size_and_density(64, 64, 1),
+    ///            vec![IconType::RGBA32_64x64]);
+    /// assert_eq!(preference.icon_types_for_size_and_density(64, 64, 2),
+    ///            vec![IconType::RGBA32_32x32_2x]);
+    /// assert!(preference.icon_types_for_size_and_density(48, 48, 2)
+    ///     .is_empty());
+    /// ```
+    pub fn icon_types_for_size_and_density(self,
+                                           width: u32,
+                                           height: u32,
+                                           density: u32)
+                                           -> Vec<IconType> {
+        let candidates: Vec<IconType> = ALL_ICON_TYPES.iter()
+            .cloned()
+            .filter(|icon_type| {
+                !icon_type.is_mask() && icon_type.pixel_width() == width &&
+                icon_type.pixel_height() == height &&
+                icon_type.pixel_density() == density
+            })
+            .collect();
+        let legacy = candidates.iter().cloned().find(|&t| is_legacy(t));
+        let modern = candidates.iter().cloned().find(|&t| !is_legacy(t));
+        let preferred = match self {
+            TypePreference::LegacyFirst => legacy.or(modern),
+            TypePreference::ModernFirst => modern.or(legacy),
+            TypePreference::Both => {
+                return legacy.into_iter().chain(modern).collect();
+            }
+        };
+        preferred.into_iter().collect()
+    }
+}
+
+/// Determines which icons are removed first by
+/// [`IconFamily::trim_to_budget`](
+/// struct.IconFamily.html#method.trim_to_budget).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TrimPriority {
+    /// Keep the modern PNG/JPEG 2000 icons where possible: remove legacy
+    /// (RLE-encoded) icons that duplicate the size of a modern icon first,
+    /// then high-density icons (largest first), then other legacy icons, then
+    /// everything else (largest first).
+    PreferModern,
+    /// Keep the icons that older systems can display where possible: remove
+    /// high-density icons first, then other modern icons, then legacy icons
+    /// (largest first within each group).
+    PreferLegacy,
+}
+
+/// Decodes the icon with the given type from the elements returned by
+/// `find_element`, which should return the first element for a given icon
+/// type (or a `NotFound` error if there is none).
+pub(crate) fn decode_icon<'a, F>(find_element: F,
+                                 icon_type: IconType,
+                                 options: &DecodeOptions,
+                                 warnings: &mut Vec<Warning>)
+                                 -> io::Result<Image>
+    where F: Fn(IconType) -> io::Result<&'a IconElement>
+{
+    let element = find_element(icon_type)?;
+    if let Some(mask_type) = icon_type.mask_type() {
+        let mask = match find_element(mask_type) {
+            Ok(mask) => mask,
+            Err(_) if options.missing_mask == MissingMask::Opaque => {
+                warnings.push(Warning::MissingMask {
+                    ostype: mask_type.ostype(),
+                });
+                let image = element.decode_image_with_options(options)?;
+                return Ok(image.convert_to(PixelFormat::RGBA));
+            }
+            Err(error) => return Err(error),
+        };
+        let num_pixels = (mask_type.pixel_width() *
+                          mask_type.pixel_height()) as usize;
+        if mask.data.len() != num_pixels &&
+           options.mask_length == MaskLength::Lenient {
+            warnings.push(Warning::MaskLengthMismatch {
+                ostype: mask.ostype,
+                actual: mask.data.len(),
+                expected: num_pixels,
+            });
+            let data = mask.fitted_mask_data(num_pixels);
+            let fitted = IconElement::new(mask.ostype, data);
+            return element.decode_image_with_mask(&fitted);
+        }
+        return element.decode_image_with_mask(mask);
+    }
+    let image = element.decode_image_with_options(options)?;
+    if element.payload_format() != PayloadFormat::RLE24 {
+        return Ok(image);
+    }
+    // Some files store RLE data in `icp4`/`icp5` elements instead of PNG
+    // data; like macOS, take the alpha for these from the mask element of
+    // the same size (`s8mk`/`l8mk`), if there is one.
+    let mask_type = IconType::from_pixel_size_and_density(image.width(),
+                                                          image.height(),
+                                                          1)
+        .and_then(IconType::mask_type);
+    let mask = match mask_type.map(&find_element) {
+        Some(Ok(mask)) => mask.decode_image()?,
+        _ => return Ok(image),
+    };
+    let mut image = image.convert_to(PixelFormat::RGBA);
+    for (pixel, &alpha) in image.data_mut()
+        .chunks_mut(4)
+        .zip(mask.data().iter()) {
+        pixel[3] = alpha;
+    }
+    Ok(image)
+}
+
+/// Returns the error for a family that has no element with the given OSType.
+pub(crate) fn missing_element(ostype: OSType) -> Error {
+    let msg = format!("the icon family does not contain a '{}' element",
+                      ostype);
+    Error::new(ErrorKind::NotFound, msg)
+}
+
+/// Returns true if the given icon type is a legacy (RLE-encoded) type.
+fn is_legacy(icon_type: IconType) -> bool {
+    icon_type.encoding() == Encoding::RLE24
+}
+
+/// Structural limits on the ICNS files accepted by
+/// [`IconFamily::read_with_limits`](
+/// struct.IconFamily.html#method.read_with_limits).
+///
+/// These limits restrict the structure of the file itself, and are checked
+/// before any memory is allocated for the data they describe.  By default,
+/// there are no limits.  Optionally, every element payload can also be
+/// validated once the file has been read (see
+/// [`validate_payloads`](#method.validate_payloads)).
+///
+/// # Examples
+/// ```
+/// use icns::{IconFamily, ReadLimits};
+/// let limits = ReadLimits::new()
+///     .max_elements(32)
+///     .max_element_size(4 * 1024 * 1024);
+/// let data: &[u8] = b"icns\0\0\0\x08";
+/// let family = IconFamily::read_with_limits(data, &limits).unwrap();
+/// assert!(family.is_empty());
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReadLimits {
+    max_elements: usize,
+    max_element_size: u32,
+    validate_payloads: bool,
+}
+
+impl ReadLimits {
+    /// Creates a new set of limits, initially unlimited.
+    pub fn new() -> ReadLimits {
+        ReadLimits {
+            max_elements: usize::MAX,
+            max_element_size: u32::MAX,
+            validate_payloads: false,
+        }
+    }
+
+    /// Sets the maximum number of elements permitted in the icon family.
+    pub fn max_elements(mut self, max_elements: usize) -> ReadLimits {
+        self.max_elements = max_elements;
+        self
+    }
+
+    /// Sets the maximum length, in bytes, of any single element's data
+    /// payload.
+    pub fn max_element_size(mut self, max_element_size: u32) -> ReadLimits {
+        self.max_element_size = max_element_size;
+        self
+    }
+
+    /// Sets whether every element payload should be validated as the file
+    /// is read (as by [`IconFamily::validate`](
+    /// struct.IconFamily.html#method.validate)), so that a file with invalid
+    /// icon data is rejected up front rather than when the icons are first
+    /// used.  If any problems are found, reading fails with an error of kind
+    /// `InvalidData` that wraps a [`ValidationError`](
+    /// struct.ValidationError.html) listing all of them.  This is off by
+    /// default.
+    pub fn validate_payloads(mut self, validate: bool) -> ReadLimits {
+        self.validate_payloads = validate;
+        self
+    }
+
+    /// Returns an error if an already-read icon family exceeds any of these
+    /// limits, or (if enabled) if any of its payloads are invalid.
+    pub(crate) fn check(&self, family: &IconFamily) -> io::Result<()> {
+        if family.elements.len() > self.max_elements {
+            let msg = format!("icon family has too many elements (the limit \
+                               is {})",
+                              self.max_elements);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        for element in family.elements.iter() {
+            if element.data.len() as u64 > u64::from(self.max_element_size) {
+                let msg = format!("'{}' element payload is too large ({} \
+                                   bytes, but the limit is {})",
+                                  element.ostype,
+                                  element.data.len(),
+                                  self.max_element_size);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+        }
+        if self.validate_payloads {
+            self.validate(family)?;
+        }
+        Ok(())
+    }
+
+    /// Private helper method.
+    fn validate(&self, family: &IconFamily) -> io::Result<()> {
+        let problems = family.validate();
+        if !problems.is_empty() {
+            let error = ValidationError { problems };
+            return Err(Error::new(ErrorKind::InvalidData, error));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ReadLimits {
+    fn default() -> ReadLimits {
+        ReadLimits::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::super::element::IconElement;
+    #[cfg(feature = "pngio")]
+    use super::super::decode::SizeMismatch;
+    use super::super::element::{IconElement, JPEG_2000_FILE_MAGIC_NUMBER};
+    use super::super::fixtures::FixtureBuilder;
     use super::super::icontype::{IconType, OSType};
-    use super::super::image::{Image, PixelFormat};
+    use super::super::iconutil::toc_element;
+    use super::super::rle;
+    use super::super::warning::read_warnings;
     use std::io::Cursor;
 
+    #[test]
+    fn display_family() {
+        assert_eq!(IconFamily::new().to_string(), "(empty icon family)");
+        let family = FixtureBuilder::new()
+            .icon(IconType::RGB24_16x16)
+            .version_element()
+            .family();
+        assert_eq!(family.to_string(),
+                   "'is32': 16x16, RLE, 548 bytes\n\
+                    's8mk': 16x16, mask, 256 bytes\n\
+                    'icnV': unknown, 4 bytes");
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn add_icon_with_density() {
+        let mut family = IconFamily::new();
+        family.set_type_preference(TypePreference::Both);
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_density(&image, 1).unwrap();
+        family.add_icon_with_density(&image, 2).unwrap();
+        assert_eq!(family.available_icons(),
+                   vec![IconType::RGB24_32x32,
+                        IconType::RGBA32_32x32,
+                        IconType::RGBA32_16x16_2x]);
+        let image = Image::new(PixelFormat::RGBA, 128, 128);
+        let error = family.add_icon_with_density(&image, 2).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn add_icon_failure_leaves_family_unchanged() {
+        let image = Image::new(PixelFormat::RGBA, 128, 128);
+        let mut family = IconFamily::new();
+        family.set_duplicate_policy(DuplicatePolicy::Error);
+        family.add_icon_with_type(&image, IconType::RGBA32_128x128).unwrap();
+        family.set_type_preference(TypePreference::Both);
+        let error = family.add_icon(&image).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(family.available_icons(), vec![IconType::RGBA32_128x128]);
+        assert_eq!(family.elements().len(), 1);
+    }
+
     #[test]
     fn icon_with_type() {
         let mut family = IconFamily::new();
@@ -185,6 +1603,506 @@ mod tests {
         assert!(family.get_icon_with_type(IconType::RGB24_16x16).is_ok());
     }
 
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn icon_family_from_master_image() {
+        let image = Image::new(PixelFormat::RGBA, 1024, 1024);
+        let family = IconFamily::from_master_image(&image).unwrap();
+        assert_eq!(family.available_icons(), MASTER_ICON_TYPES.to_vec());
+        let small = Image::new(PixelFormat::RGBA, 512, 512);
+        assert!(IconFamily::from_master_image(&small).is_err());
+        let wide = Image::new(PixelFormat::RGBA, 2048, 1024);
+        assert!(IconFamily::from_master_image(&wide).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn add_icon_with_type_preference() {
+        let image = Image::new(PixelFormat::RGBA, 128, 128);
+        let mut family = IconFamily::new();
+        assert_eq!(family.type_preference(), TypePreference::LegacyFirst);
+        family.add_icon(&image).unwrap();
+        assert_eq!(family.available_icons(), vec![IconType::RGB24_128x128]);
+
+        let mut family = IconFamily::new();
+        family.set_type_preference(TypePreference::ModernFirst);
+        family.add_icon(&image).unwrap();
+        assert_eq!(family.available_icons(), vec![IconType::RGBA32_128x128]);
+
+        let mut family = IconFamily::new();
+        family.set_type_preference(TypePreference::Both);
+        family.add_icon(&image).unwrap();
+        assert_eq!(family.available_icons(),
+                   vec![IconType::RGB24_128x128, IconType::RGBA32_128x128]);
+    }
+
+    #[test]
+    fn legacy_first_matches_from_pixel_size() {
+        for &size in &[16, 32, 48, 64, 128, 256, 512, 1024, 100] {
+            let expected: Vec<IconType> =
+                IconType::from_pixel_size(size, size).into_iter().collect();
+            assert_eq!(TypePreference::LegacyFirst
+                           .icon_types_for_size(size, size),
+                       expected);
+        }
+    }
+
+    #[test]
+    fn element_changes_rebuild_table_of_contents() {
+        let mut family = IconFamily::new();
+        family.push_element(IconElement::new(OSType(*b"TOC "), Vec::new()))
+            .unwrap();
+        family.push_element(IconElement::new(OSType(*b"icnV"), vec![0; 4]))
+            .unwrap();
+        family.push_element(IconElement::new(OSType(*b"info"), vec![0; 2]))
+            .unwrap();
+        assert_eq!(family.elements()[0].data,
+                   b"icnV\x00\x00\x00\x0cinfo\x00\x00\x00\x0a".to_vec());
+        family.retain_elements(|element| element.ostype != OSType(*b"icnV"));
+        assert_eq!(family.elements()[0].data,
+                   b"info\x00\x00\x00\x0a".to_vec());
+        assert_eq!(family.elements().len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn duplicate_policies() {
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        let mut other = Image::new(PixelFormat::RGBA, 16, 16);
+        other.data_mut()[3] = 255;
+        let icon_type = IconType::RGB24_16x16;
+
+        let mut family = IconFamily::new();
+        assert_eq!(family.duplicate_policy(), DuplicatePolicy::Append);
+        family.add_icon_with_type(&image, icon_type).unwrap();
+        family.add_icon_with_type(&other, icon_type).unwrap();
+        assert_eq!(family.elements.len(), 4);
+
+        family.set_duplicate_policy(DuplicatePolicy::Replace);
+        family.elements.insert(0, IconElement::new(OSType(*b"quux"), vec![]));
+        family.add_icon_with_type(&other, icon_type).unwrap();
+        assert_eq!(family.elements.len(), 3);
+        assert_eq!(family.elements[0].ostype, OSType(*b"quux"));
+        let icon = family.get_icon_with_type(icon_type).unwrap();
+        assert_eq!(icon.data()[3], 255);
+
+        family.set_duplicate_policy(DuplicatePolicy::Error);
+        let error = family.add_icon_with_type(&image, icon_type).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(family.elements.len(), 3);
+        family.add_icon_with_type(&image, IconType::RGBA32_16x16).unwrap();
+        assert_eq!(family.elements.len(), 4);
+    }
+
+    #[test]
+    fn icon_without_mask() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_without_mask(&image, IconType::RGB24_32x32).unwrap();
+        assert_eq!(family.elements.len(), 1);
+        assert!(!family.has_icon_with_type(IconType::RGB24_32x32));
+        family.add_icon_with_type(&image, IconType::Mask8_32x32).unwrap();
+        assert!(family.has_icon_with_type(IconType::RGB24_32x32));
+    }
+
+    #[test]
+    fn icon_with_separate_mask() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGB, 16, 16);
+        let mut mask = Image::new(PixelFormat::Alpha, 16, 16);
+        mask.data_mut()[5] = 200;
+        family.add_icon_with_mask(&image, &mask, IconType::RGB24_16x16)
+            .unwrap();
+        let icon = family.get_icon_with_type(IconType::RGB24_16x16).unwrap();
+        assert_eq!(icon.data()[4 * 5 + 3], 200);
+        assert_eq!(icon.data()[4 * 6 + 3], 0);
+
+        let wrong_size = Image::new(PixelFormat::Alpha, 32, 32);
+        assert!(family.add_icon_with_mask(&image,
+                                          &wrong_size,
+                                          IconType::RGB24_16x16)
+            .is_err());
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        assert!(family.add_icon_with_mask(&image,
+                                          &mask,
+                                          IconType::RGBA32_16x16)
+            .is_err());
+        assert_eq!(family.elements.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn icon_by_size() {
+        let mut family = IconFamily::new();
+        let mut image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_without_mask(&image, IconType::RGB24_32x32).unwrap();
+        image.data_mut()[3] = 255;
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x).unwrap();
+        // The RGB24_32x32 icon has no mask, so it isn't complete.
+        let icon = family.get_icon_by_size(32, 32, 1).unwrap();
+        assert_eq!(icon.data()[3], 255);
+        assert!(family.get_icon_by_size(32, 32, 2).is_ok());
+        let error = family.get_icon_by_size(16, 16, 1).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn icon_with_type_scaled() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        let icon_type = IconType::RGB24_32x32;
+        let icon = family.get_icon_with_type_scaled(icon_type, 16, false)
+            .unwrap();
+        assert_eq!((icon.width(), icon.height()), (16, 16));
+        assert_eq!(icon.pixel_format(), PixelFormat::RGBA);
+        let icon = family.get_icon_with_type_scaled(icon_type, 64, false)
+            .unwrap();
+        assert_eq!((icon.width(), icon.height()), (32, 32));
+        assert!(family.get_icon_with_type_scaled(icon_type, 0, false)
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn preview_picks_nearest_larger_icon() {
+        let mut family = IconFamily::new();
+        assert_eq!(family.preview_at(32).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+        let mut small = Image::new(PixelFormat::RGBA, 32, 32);
+        for pixel in small.data_mut().chunks_mut(4) {
+            pixel.copy_from_slice(&[255, 0, 0, 255]);
+        }
+        let mut large = Image::new(PixelFormat::RGBA, 128, 128);
+        for pixel in large.data_mut().chunks_mut(4) {
+            pixel.copy_from_slice(&[0, 0, 255, 255]);
+        }
+        family.add_icon_with_type(&small, IconType::RGBA32_32x32).unwrap();
+        family.add_icon_with_type(&large, IconType::RGBA32_128x128).unwrap();
+        assert_eq!(family.preview_at(32).unwrap(), small);
+        let preview = family.preview_at(24).unwrap();
+        assert_eq!(&preview.data()[..4], &[255, 0, 0, 255]);
+        let preview = family.preview_at(48).unwrap();
+        assert_eq!(&preview.data()[..4], &[0, 0, 255, 255]);
+        let preview = family.preview_at(256).unwrap();
+        assert_eq!(preview.width(), 256);
+        assert_eq!(&preview.data()[..4], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn icon_scaled_to_target_size() {
+        let mut family = IconFamily::new();
+        let mut image = Image::new(PixelFormat::RGBA, 16, 16);
+        for pixel in image.data_mut().chunks_mut(4) {
+            pixel.copy_from_slice(&[10, 20, 30, 255]);
+        }
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        let icon_type = IconType::RGB24_16x16;
+        let icon = family.get_icon_with_type_scaled(icon_type, 16, true)
+            .unwrap();
+        assert_eq!(icon, image);
+        let icon = family.get_icon_with_type_scaled(icon_type, 40, true)
+            .unwrap();
+        assert_eq!((icon.width(), icon.height()), (40, 40));
+        assert_eq!(&icon.data()[..4], &[10, 20, 30, 255]);
+        let error = family.get_icon_with_type_scaled(icon_type, 0, true)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error =
+            family.get_icon_with_type_scaled(IconType::RGB24_32x32, 16, true)
+                .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn icon_with_type_and_options() {
+        let image = Image::new(PixelFormat::RGBA, 256, 256);
+        let mut element =
+            IconElement::encode_image_with_type(&image,
+                                                IconType::RGBA32_256x256)
+                .unwrap();
+        element.ostype = IconType::RGBA32_128x128.ostype();
+        let family = IconFamily::from_elements(vec![element]);
+        let icon_type = IconType::RGBA32_128x128;
+        assert!(family.get_icon_with_type(icon_type).is_err());
+        let options = DecodeOptions::new().size_mismatch(SizeMismatch::Resize);
+        let icon = family.get_icon_with_type_and_options(icon_type, &options)
+            .unwrap();
+        assert_eq!((icon.width(), icon.height()), (128, 128));
+        let options =
+            DecodeOptions::new().size_mismatch(SizeMismatch::KeepActualSize);
+        let icon = family.get_icon_with_type_and_options(icon_type, &options)
+            .unwrap();
+        assert_eq!((icon.width(), icon.height()), (256, 256));
+    }
+
+    #[test]
+    fn icon_with_lenient_mask_length() {
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        family.elements[1].data.truncate(240);
+        let icon_type = IconType::RGB24_16x16;
+        assert!(family.get_icon_with_type(icon_type).is_err());
+        let options = DecodeOptions::new().mask_length(MaskLength::Lenient);
+        let (icon, warnings) =
+            family.get_icon_with_warnings(icon_type, &options).unwrap();
+        assert_eq!(icon.data()[4 * 239 + 3], 0);
+        assert_eq!(icon.data()[4 * 240 + 3], 255);
+        assert_eq!(warnings,
+                   vec![Warning::MaskLengthMismatch {
+                            ostype: IconType::Mask8_16x16.ostype(),
+                            actual: 240,
+                            expected: 256,
+                        }]);
+        family.elements[1].data.resize(300, 0);
+        assert!(family.get_icon_with_type_and_options(icon_type, &options)
+            .is_ok());
+    }
+
+    #[test]
+    fn synthesize_missing_icon() {
+        let mut family = IconFamily::new();
+        let options = DecodeOptions::new()
+            .missing_icon(MissingIcon::Synthesize);
+        let icon_type = IconType::RGB24_32x32;
+        let error = family.get_icon_with_type_and_options(icon_type, &options)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        let mut small = Image::new(PixelFormat::RGBA, 16, 16);
+        small.data_mut()[3] = 255;
+        family.add_icon_with_type(&small, IconType::RGB24_16x16).unwrap();
+        let large = Image::new(PixelFormat::RGBA, 48, 48);
+        family.add_icon_with_type(&large, IconType::RGB24_48x48).unwrap();
+        let (icon, warnings) =
+            family.get_icon_with_warnings(icon_type, &options).unwrap();
+        assert_eq!((icon.width(), icon.height()), (32, 32));
+        assert!(icon.data().iter().all(|&value| value == 0));
+        assert_eq!(warnings,
+                   vec![Warning::SynthesizedIcon {
+                            ostype: IconType::RGB24_32x32.ostype(),
+                            source: IconType::RGB24_48x48.ostype(),
+                        }]);
+        // With no larger icon available, the nearest smaller one is used:
+        let (icon, warnings) =
+            family.get_icon_with_warnings(IconType::RGB24_128x128, &options)
+                .unwrap();
+        assert_eq!(icon.width(), 128);
+        assert_eq!(warnings[0].to_string(),
+                   "'it32' was synthesized from 'ih32'");
+        // Icons that are present are decoded as usual:
+        let (_, warnings) =
+            family.get_icon_with_warnings(IconType::RGB24_16x16, &options)
+                .unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn synthesis_falls_back_to_next_source() {
+        let options = DecodeOptions::new()
+            .missing_icon(MissingIcon::Synthesize);
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 48, 48);
+        family.add_icon_with_type(&image, IconType::RGB24_48x48).unwrap();
+        let jp2_data = JPEG_2000_FILE_MAGIC_NUMBER.to_vec();
+        family.elements
+            .push(IconElement::new(IconType::RGBA32_256x256.ostype(),
+                                   jp2_data));
+        assert_eq!(family.sources_for_size(128, true),
+                   vec![IconType::RGBA32_256x256, IconType::RGB24_48x48]);
+        assert_eq!(family.sources_for_size(128, false),
+                   vec![IconType::RGBA32_256x256]);
+        let (icon, warnings) =
+            family.get_icon_with_warnings(IconType::RGB24_128x128, &options)
+                .unwrap();
+        assert_eq!(icon.width(), 128);
+        assert_eq!(warnings,
+                   vec![Warning::SynthesizedIcon {
+                            ostype: IconType::RGB24_128x128.ostype(),
+                            source: IconType::RGB24_48x48.ostype(),
+                        }]);
+        // If no source can be decoded, the first error is returned:
+        family.retain_elements(|element| {
+            element.ostype == IconType::RGBA32_256x256.ostype()
+        });
+        assert!(family.get_icon_with_warnings(IconType::RGB24_128x128,
+                                              &options)
+            .is_err());
+    }
+
+    #[test]
+    fn icon_with_rle_data_in_icp4() {
+        let rgb = vec![7u8; 16 * 16 * 3];
+        let color = IconElement::new(IconType::RGBA32_16x16.ostype(),
+                                     rle::encode(&rgb, 3, 256).unwrap());
+        let mut family = IconFamily::from_elements(vec![color]);
+        let icon = family.get_icon_with_type(IconType::RGBA32_16x16).unwrap();
+        assert_eq!(icon.pixel_format(), PixelFormat::RGB);
+        let mask = Image::from_data(PixelFormat::Alpha, 16, 16, vec![99; 256])
+            .unwrap();
+        family.add_icon_with_type(&mask, IconType::Mask8_16x16).unwrap();
+        let icon = family.get_icon_with_type(IconType::RGBA32_16x16).unwrap();
+        assert_eq!(icon.pixel_format(), PixelFormat::RGBA);
+        assert!(icon.data().chunks(4).all(|pixel| pixel == [7, 7, 7, 99]));
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn icon_with_counterpart() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_counterpart(&image, IconType::RGBA32_32x32)
+            .unwrap();
+        assert_eq!(family.elements.len(), 1);
+        assert_eq!(family.missing_density_counterparts(),
+                   vec![IconType::RGBA32_32x32_2x]);
+        family.add_icon_with_counterpart(&image, IconType::RGBA32_16x16_2x)
+            .unwrap();
+        assert_eq!(family.available_icons(),
+                   vec![IconType::RGBA32_32x32,
+                        IconType::RGBA32_16x16_2x,
+                        IconType::RGBA32_16x16]);
+        let icon = family.get_icon_with_type(IconType::RGBA32_16x16).unwrap();
+        assert_eq!(icon.width(), 16);
+        assert_eq!(family.missing_density_counterparts(),
+                   vec![IconType::RGBA32_32x32_2x]);
+    }
+
+    #[test]
+    fn mask_with_type() {
+        let mut family = IconFamily::new();
+        let mut image = Image::new(PixelFormat::RGBA, 16, 16);
+        image.data_mut()[4 * 7 + 3] = 99;
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        let mask = family.get_mask_with_type(IconType::RGB24_16x16).unwrap();
+        assert_eq!(mask.pixel_format(), PixelFormat::Alpha);
+        assert_eq!(mask.data()[7], 99);
+        let mask = family.get_mask_with_type(IconType::Mask8_16x16).unwrap();
+        assert_eq!(mask.data()[7], 99);
+        let error =
+            family.get_mask_with_type(IconType::Mask8_32x32).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        let error =
+            family.get_mask_with_type(IconType::RGBA32_16x16).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn trim_to_budget() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x).unwrap();
+        let small = Image::new(PixelFormat::RGBA, 16, 16);
+        family.add_icon_with_type(&small, IconType::RGB24_16x16).unwrap();
+        family.elements.push(IconElement::new(OSType(*b"quux"), vec![0; 4]));
+
+        let mut trimmed = family.clone();
+        let removed = trimmed.trim_to_budget(0, TrimPriority::PreferModern);
+        assert_eq!(removed.unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(trimmed.elements.len(), family.elements.len());
+
+        let budget = family.total_length() - 1;
+        let removed =
+            trimmed.trim_to_budget(budget, TrimPriority::PreferModern)
+                .unwrap();
+        assert_eq!(removed, vec![IconType::RGB24_32x32]);
+        assert_eq!(trimmed.available_icons(),
+                   vec![IconType::RGBA32_32x32,
+                        IconType::RGBA32_16x16_2x,
+                        IconType::RGB24_16x16]);
+
+        let mut trimmed = family.clone();
+        let budget = 8 + 12;
+        let removed =
+            trimmed.trim_to_budget(budget, TrimPriority::PreferLegacy)
+                .unwrap();
+        assert_eq!(removed,
+                   vec![IconType::RGBA32_16x16_2x,
+                        IconType::RGBA32_32x32,
+                        IconType::RGB24_32x32,
+                        IconType::RGB24_16x16]);
+        assert_eq!(trimmed.elements.len(), 1);
+        assert_eq!(trimmed.total_length(), budget);
+    }
+
+    #[test]
+    fn trim_to_budget_rebuilds_table_of_contents() {
+        let mut family = FixtureBuilder::new()
+            .icon(IconType::RGB24_16x16)
+            .icon(IconType::RGB24_32x32)
+            .family();
+        let toc = toc_element(&family.elements);
+        family.elements.insert(0, toc);
+        let budget = family.total_length() - 1;
+        let removed =
+            family.trim_to_budget(budget, TrimPriority::PreferLegacy)
+                .unwrap();
+        assert_eq!(removed, vec![IconType::RGB24_32x32]);
+        assert_eq!(family.elements[0].ostype, OSType(*b"TOC "));
+        assert_eq!(family.elements[0].data.len(), 16);
+        assert!(read_warnings(&family, family.total_length()).is_empty());
+    }
+
+    #[test]
+    fn trim_to_budget_with_scattered_duplicates() {
+        let mut family = IconFamily::new();
+        let small = Image::new(PixelFormat::RGBA, 16, 16);
+        family.add_icon_with_type(&small, IconType::RGB24_16x16).unwrap();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        let duplicate = family.elements[0].clone();
+        family.elements.push(duplicate);
+        let budget = family.total_length() - 1;
+        let removed =
+            family.trim_to_budget(budget, TrimPriority::PreferLegacy)
+                .unwrap();
+        assert_eq!(removed, vec![IconType::RGB24_32x32]);
+        let removed = family.trim_to_budget(8, TrimPriority::PreferLegacy)
+            .unwrap();
+        assert_eq!(removed, vec![IconType::RGB24_16x16]);
+        assert!(family.elements.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn trim_to_budget_recompresses_first() {
+        use super::super::pngcheck::crc32;
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        let mut data = Vec::new();
+        image.write_png(&mut data).unwrap();
+        // Insert a bulky private chunk and a small text chunk before the
+        // IEND chunk:
+        let iend = data.split_off(data.len() - 12);
+        for &(chunk_type, ref chunk_data) in
+            &[(b"zzZz", vec![0; 1000]), (b"tEXt", b"Title\0Icon".to_vec())] {
+            data.extend_from_slice(&(chunk_data.len() as u32).to_be_bytes());
+            let start = data.len();
+            data.extend_from_slice(chunk_type);
+            data.extend_from_slice(chunk_data);
+            let crc = crc32(&data[start..]);
+            data.extend_from_slice(&crc.to_be_bytes());
+        }
+        data.extend_from_slice(&iend);
+        let mut family = IconFamily::new();
+        let ostype = IconType::RGBA32_32x32.ostype();
+        family.elements.push(IconElement::new(ostype, data));
+        let budget = family.total_length() - 1000;
+        let removed = family.trim_to_budget(budget, TrimPriority::PreferModern)
+            .unwrap();
+        assert!(removed.is_empty());
+        assert!(family.total_length() <= budget);
+        assert!(family.has_icon_with_type(IconType::RGBA32_32x32));
+        // The private chunk is dropped, but the text chunk is kept:
+        let data = &family.elements[0].data;
+        assert!(!data.windows(4).any(|window| window == b"zzZz"));
+        assert!(data.windows(4).any(|window| window == b"tEXt"));
+    }
+
     #[test]
     fn write_empty_icon_family() {
         let family = IconFamily::new();
@@ -207,6 +2125,114 @@ mod tests {
         assert_eq!(1, family.elements[1].data.len());
     }
 
+    #[test]
+    fn read_concatenated_icon_families() {
+        let data: &[u8] = b"icns\0\0\0\x08\
+                            icns\0\0\0\x11quux\0\0\0\x09#\
+                            icns\0\0\0\x11baz!\0\0\0\x09%";
+        let families = IconFamily::read_all(Cursor::new(data)).unwrap();
+        assert_eq!(families.len(), 3);
+        assert!(families[0].is_empty());
+        assert_eq!(families[1].elements[0].ostype, OSType(*b"quux"));
+        assert_eq!(families[2].elements[0].data, b"%");
+
+        let truncated = &data[..data.len() - 1];
+        let error = IconFamily::read_all(Cursor::new(truncated)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(IconFamily::read_all(Cursor::new(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_and_write_with_progress() {
+        let data: &[u8] =
+            b"icns\0\0\0\x1fquux\0\0\0\x0efoobarbaz!\0\0\0\x09#";
+        let mut events = Vec::new();
+        let family = IconFamily::read_with_progress(data, |progress| {
+                events.push(progress)
+            })
+            .unwrap();
+        assert_eq!(events,
+                   vec![Progress::ElementStarted {
+                            index: 0,
+                            elements_total: None,
+                        },
+                        Progress::ElementFinished {
+                            index: 0,
+                            ostype: OSType(*b"quux"),
+                            bytes_processed: 22,
+                            bytes_total: Some(31),
+                        },
+                        Progress::ElementStarted {
+                            index: 1,
+                            elements_total: None,
+                        },
+                        Progress::ElementFinished {
+                            index: 1,
+                            ostype: OSType(*b"baz!"),
+                            bytes_processed: 31,
+                            bytes_total: Some(31),
+                        }]);
+
+        let mut finished = Vec::new();
+        let mut output = Vec::new();
+        family.write_with_progress(&mut output, |progress| {
+                if let Progress::ElementFinished { bytes_processed, .. } =
+                       progress {
+                    finished.push(bytes_processed);
+                }
+            })
+            .unwrap();
+        assert_eq!(output, data);
+        assert_eq!(finished, vec![22, 31]);
+    }
+
+    #[test]
+    fn read_icon_family_with_limits() {
+        let data: &[u8] =
+            b"icns\0\0\0\x1fquux\0\0\0\x0efoobarbaz!\0\0\0\x09#";
+        let limits = ReadLimits::new().max_elements(2).max_element_size(6);
+        assert!(IconFamily::read_with_limits(data, &limits).is_ok());
+        let limits = ReadLimits::new().max_elements(1);
+        let error = IconFamily::read_with_limits(data, &limits).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let limits = ReadLimits::new().max_element_size(5);
+        let error = IconFamily::read_with_limits(data, &limits).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_huge_element_with_limits() {
+        // This element claims to be almost 4 GB long, which should be
+        // rejected before we try to allocate space for it.
+        let data: &[u8] = b"icns\xff\xff\xff\xffquux\xff\xff\xff\xff";
+        let limits = ReadLimits::new().max_element_size(1024);
+        let error = IconFamily::read_with_limits(data, &limits).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn write_icon_family_with_it32_prefix() {
+        let mut family = IconFamily::new();
+        family.elements
+            .push(IconElement::new(OSType(*b"it32"), b"\x7f!".to_vec()));
+        family.elements
+            .push(IconElement::new(OSType(*b"is32"), b"\0\0\0\0".to_vec()));
+        let mut output = Vec::new();
+        family.write_with_it32_prefix(&mut output, true).unwrap();
+        let expected: &[u8] = b"icns\0\0\0\x22\
+                                it32\0\0\0\x0e\0\0\0\0\x7f!\
+                                is32\0\0\0\x0c\0\0\0\0";
+        assert_eq!(output, expected);
+
+        let family = IconFamily::read(Cursor::new(&output)).unwrap();
+        let mut output = Vec::new();
+        family.write_with_it32_prefix(&mut output, false).unwrap();
+        let expected: &[u8] = b"icns\0\0\0\x1e\
+                                it32\0\0\0\x0a\x7f!\
+                                is32\0\0\0\x0c\0\0\0\0";
+        assert_eq!(output, expected);
+    }
+
     #[test]
     fn write_icon_family_with_fake_elements() {
         let mut family = IconFamily::new();
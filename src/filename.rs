@@ -0,0 +1,25 @@
+use std::path::{Component, Path};
+
+/// Returns true if the name is a single normal path component (and so names
+/// a file directly within a directory, rather than e.g. `..` or an absolute
+/// path).
+pub(crate) fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!((components.next(), components.next()),
+             (Some(Component::Normal(_)), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_file_names() {
+        assert!(is_plain_file_name("icon_16x16.png"));
+        assert!(!is_plain_file_name("../icon.png"));
+        assert!(!is_plain_file_name("sub/icon.png"));
+        assert!(!is_plain_file_name("/etc/passwd"));
+        assert!(!is_plain_file_name(".."));
+        assert!(!is_plain_file_name(""));
+    }
+}
@@ -0,0 +1,287 @@
+//! Builders for synthetic ICNS files, for use in tests.
+//!
+//! Rather than checking in a binary ICNS file for every case they cover,
+//! tests can describe the file they need -- which elements it contains, and
+//! how (if at all) it should be damaged -- and build it on the fly.  (The
+//! golden tests in `tests/golden.rs` still use checked-in files, since their
+//! point is to compare against known-good reference output.)  This module
+//! is used by this crate's own tests, and is exported (when the `test-util`
+//! feature is enabled) so that downstream crates can do the same.
+//!
+//! # Examples
+//! ```
+//! use icns::{IconFamily, IconType, OSType};
+//! use icns::fixtures::FixtureBuilder;
+//!
+//! let data = FixtureBuilder::new()
+//!     .icon(IconType::RGB24_16x16)
+//!     .element(OSType(*b"icnV"), vec![0, 0, 0, 1])
+//!     .build();
+//! let family = IconFamily::read(data.as_slice()).unwrap();
+//! assert_eq!(family.available_icons(), vec![IconType::RGB24_16x16]);
+//!
+//! // The same file, cut off partway through the last element:
+//! let data = FixtureBuilder::new()
+//!     .icon(IconType::RGB24_16x16)
+//!     .element(OSType(*b"icnV"), vec![0, 0, 0, 1])
+//!     .truncate(2)
+//!     .build();
+//! assert!(IconFamily::read(data.as_slice()).is_err());
+//! ```
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::element::{IconElement, JPEG_2000_FILE_MAGIC_NUMBER};
+use super::family::{ICNS_MAGIC_LITERAL, IconFamily};
+use super::icontype::{IconType, OSType};
+use super::image::{Image, PixelFormat};
+
+/// Returns an image with a deterministic, non-uniform pattern of pixels
+/// (so that encoding bugs which scramble or drop pixels are detectable),
+/// in which every alpha value is opaque.
+pub fn test_image(format: PixelFormat, width: u32, height: u32) -> Image {
+    let mut image = Image::new(PixelFormat::RGBA, width, height);
+    for (index, pixel) in image.data_mut().chunks_mut(4).enumerate() {
+        let x = index as u32 % width;
+        let y = index as u32 / width;
+        pixel[0] = (x * 255 / width.max(2).saturating_sub(1)) as u8;
+        pixel[1] = (y * 255 / height.max(2).saturating_sub(1)) as u8;
+        pixel[2] = ((x + y) % 4 * 64) as u8;
+        pixel[3] = 255;
+    }
+    image.convert_to(format)
+}
+
+/// Returns a JPEG 2000 file header (the signature, file type, and image
+/// header boxes) describing an RGBA image of the given dimensions, followed
+/// by an empty codestream.  This is enough for format detection and header
+/// parsing, but (like all JPEG 2000 data) can't be decoded by this library.
+pub fn jpeg2000_payload(width: u32, height: u32) -> Vec<u8> {
+    let mut data = JPEG_2000_FILE_MAGIC_NUMBER.to_vec();
+    // File type box:
+    data.extend_from_slice(&20u32.to_be_bytes());
+    data.extend_from_slice(b"ftypjp2 \0\0\0\0jp2 ");
+    // JP2 header box, containing an image header box:
+    data.extend_from_slice(&30u32.to_be_bytes());
+    data.extend_from_slice(b"jp2h");
+    data.extend_from_slice(&22u32.to_be_bytes());
+    data.extend_from_slice(b"ihdr");
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&[0, 4, 7, 7, 0, 0]);
+    // Contiguous codestream box, with just a start-of-codestream marker:
+    data.extend_from_slice(&10u32.to_be_bytes());
+    data.extend_from_slice(b"jp2c\xff\x4f");
+    data
+}
+
+/// A new, empty directory under the system temporary directory, for tests
+/// that need to read or write files.  Each one has a unique name, so tests
+/// running in parallel never share a directory, and it is removed (along
+/// with everything in it) when dropped.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new temporary directory whose name includes the given
+    /// label.
+    pub fn new(label: &str) -> TempDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("icns-test-{}-{}-{}",
+                           label,
+                           process::id(),
+                           COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = env::temp_dir().join(name);
+        fs::create_dir_all(&path)
+            .expect("failed to create temporary directory");
+        TempDir { path }
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A builder for the bytes of a synthetic ICNS file.  By default, the file
+/// is well-formed; methods like [`declared_length`](#method.declared_length)
+/// and [`truncate`](#method.truncate) can be used to damage it in specific
+/// ways.
+#[derive(Clone, Debug)]
+pub struct FixtureBuilder {
+    magic: [u8; 4],
+    elements: Vec<IconElement>,
+    element_lengths: Vec<(usize, u32)>,
+    declared_length: Option<u32>,
+    truncate: usize,
+}
+
+impl FixtureBuilder {
+    /// Creates a builder for an empty ICNS file.
+    pub fn new() -> FixtureBuilder {
+        FixtureBuilder {
+            magic: *ICNS_MAGIC_LITERAL,
+            elements: Vec::new(),
+            element_lengths: Vec::new(),
+            declared_length: None,
+            truncate: 0,
+        }
+    }
+
+    /// Appends an element with the given OSType and raw data payload.
+    pub fn element(mut self, ostype: OSType, data: Vec<u8>) -> FixtureBuilder {
+        self.elements.push(IconElement::new(ostype, data));
+        self
+    }
+
+    /// Appends an `icnV` element (which records the version of the tool
+    /// that wrote the file), with a four-byte payload of zeros.
+    pub fn version_element(self) -> FixtureBuilder {
+        self.element(OSType(*b"icnV"), vec![0; 4])
+    }
+
+    /// Appends an icon of the given type, encoding a [`test_image`](
+    /// fn.test_image.html) of the right size (along with its mask element,
+    /// if the icon type has one).  PNG-based icon types require the `pngio`
+    /// feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image can't be encoded.
+    pub fn icon(mut self, icon_type: IconType) -> FixtureBuilder {
+        let image = test_image(PixelFormat::RGBA,
+                               icon_type.pixel_width(),
+                               icon_type.pixel_height());
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, icon_type)
+            .expect("failed to encode fixture icon");
+        self.elements.extend(family.elements);
+        self
+    }
+
+    /// Appends an element of the given (PNG/JPEG 2000) icon type containing
+    /// a [`jpeg2000_payload`](fn.jpeg2000_payload.html) of the right size.
+    pub fn jpeg2000_icon(self, icon_type: IconType) -> FixtureBuilder {
+        let data = jpeg2000_payload(icon_type.pixel_width(),
+                                    icon_type.pixel_height());
+        self.element(icon_type.ostype(), data)
+    }
+
+    /// Replaces the four magic bytes at the start of the file (normally
+    /// `icns`).
+    pub fn magic(mut self, magic: [u8; 4]) -> FixtureBuilder {
+        self.magic = magic;
+        self
+    }
+
+    /// Overrides the total file length written in the file header, which
+    /// is normally the actual length of the file (before any truncation).
+    pub fn declared_length(mut self, length: u32) -> FixtureBuilder {
+        self.declared_length = Some(length);
+        self
+    }
+
+    /// Overrides the length written in the header of the element at the
+    /// given index (counting from zero), which is normally the actual length
+    /// of the element, including its eight-byte header.
+    pub fn element_length(mut self,
+                          index: usize,
+                          length: u32)
+                          -> FixtureBuilder {
+        self.element_lengths.push((index, length));
+        self
+    }
+
+    /// Removes the given number of bytes from the end of the file.
+    pub fn truncate(mut self, num_bytes: usize) -> FixtureBuilder {
+        self.truncate = num_bytes;
+        self
+    }
+
+    /// Returns the elements added so far, as an icon family.
+    pub fn family(&self) -> IconFamily {
+        IconFamily::from_elements(self.elements.clone())
+    }
+
+    /// Returns the bytes of the ICNS file.
+    pub fn build(&self) -> Vec<u8> {
+        let family = self.family();
+        let total_length = family.total_length();
+        let mut data = Vec::with_capacity(total_length as usize);
+        data.extend_from_slice(&self.magic);
+        let declared_length = self.declared_length.unwrap_or(total_length);
+        data.extend_from_slice(&declared_length.to_be_bytes());
+        for (index, element) in family.elements.iter().enumerate() {
+            let length = self.element_lengths
+                .iter()
+                .rev()
+                .find(|&&(i, _)| i == index)
+                .map(|&(_, length)| length)
+                .unwrap_or_else(|| element.total_length());
+            data.extend_from_slice(&element.ostype.0);
+            data.extend_from_slice(&length.to_be_bytes());
+            data.extend_from_slice(&element.data);
+        }
+        let length = data.len().saturating_sub(self.truncate);
+        data.truncate(length);
+        data
+    }
+}
+
+impl Default for FixtureBuilder {
+    fn default() -> FixtureBuilder {
+        FixtureBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::payload::PayloadFormat;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn well_formed_fixture_round_trips() {
+        let builder = FixtureBuilder::new()
+            .icon(IconType::RGB24_32x32)
+            .jpeg2000_icon(IconType::RGBA32_256x256);
+        let data = builder.build();
+        let family = IconFamily::read(data.as_slice()).unwrap();
+        assert_eq!(family.elements.len(), 3);
+        assert_eq!(family.elements[2].payload_format(),
+                   PayloadFormat::JPEG2000);
+        let mut expected = Vec::new();
+        builder.family().write(&mut expected).unwrap();
+        assert_eq!(data, expected);
+        let image = family.get_icon_with_type(IconType::RGB24_32x32).unwrap();
+        assert_eq!(image, test_image(PixelFormat::RGBA, 32, 32));
+    }
+
+    #[test]
+    fn corrupt_fixtures() {
+        let builder = FixtureBuilder::new().icon(IconType::RGB24_16x16);
+        let data = builder.clone().magic(*b"icnx").build();
+        let error = IconFamily::read(data.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let data = builder.clone().truncate(1).build();
+        let error = IconFamily::read(data.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let data = builder.clone().element_length(1, 4).build();
+        assert!(IconFamily::read(data.as_slice()).is_err());
+        let data = builder.declared_length(12).build();
+        let family = IconFamily::read(data.as_slice()).unwrap();
+        assert_eq!(family.elements.len(), 1);
+    }
+}
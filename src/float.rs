@@ -0,0 +1,209 @@
+use std::io;
+
+use super::image::{Image, PixelFormat};
+use super::resize::resample;
+use super::transform::BlendMode;
+
+/// An RGBA image with a 32-bit floating-point value per channel, for
+/// chaining several image operations (such as resizing, tinting, and
+/// flattening) without rounding to 8 bits after each step.
+///
+/// Channel values are nominally between 0.0 and 1.0, and the color channels
+/// are not premultiplied by alpha.  Pixels are stored one complete pixel at a
+/// time, in row-major order, just as for [`Image`](struct.Image.html).
+///
+/// # Examples
+/// ```
+/// use icns::{BlendMode, Image, PixelFormat};
+/// let image = Image::new(PixelFormat::RGBA, 64, 64);
+/// let small = image.to_float()
+///     .resize(32, 32)
+///     .tint([1.0, 0.5, 0.0], BlendMode::Replace)
+///     .flatten_onto([1.0, 1.0, 1.0])
+///     .to_image(PixelFormat::RGB);
+/// assert_eq!((small.width(), small.height()), (32, 32));
+/// assert_eq!(&small.data()[0..3], &[255, 255, 255]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatImage {
+    width: u32,
+    height: u32,
+    data: Box<[f32]>,
+}
+
+impl FloatImage {
+    /// Creates a new, fully transparent image with all channels set to zero.
+    pub fn new(width: u32, height: u32) -> FloatImage {
+        let length = 4 * width as usize * height as usize;
+        FloatImage {
+            width,
+            height,
+            data: vec![0.0; length].into_boxed_slice(),
+        }
+    }
+
+    /// Creates a new image using the given RGBA channel data.  Returns an
+    /// error if the data array is not the correct length for the given
+    /// dimensions.
+    pub fn from_data(width: u32,
+                     height: u32,
+                     data: Vec<f32>)
+                     -> io::Result<FloatImage> {
+        let expected = 4 * width as usize * height as usize;
+        if data.len() == expected {
+            Ok(FloatImage {
+                width,
+                height,
+                data: data.into_boxed_slice(),
+            })
+        } else {
+            let msg = format!("incorrect channel data array length for \
+                               width={} and height={}: expected {} values, \
+                               but data has {} values",
+                              width,
+                              height,
+                              expected,
+                              data.len());
+            Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+        }
+    }
+
+    /// Returns the width of the image, in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image, in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns a reference to the image's RGBA channel data.
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// Returns a mutable reference to the image's RGBA channel data.
+    pub fn data_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    /// Converts this image to an 8-bit image in the given pixel format,
+    /// clamping each channel to the range 0.0 to 1.0 and rounding it to the
+    /// nearest 8-bit value.
+    pub fn to_image(&self, format: PixelFormat) -> Image {
+        let mut rgba = Image::new(PixelFormat::RGBA, self.width, self.height);
+        for (byte, &value) in rgba.data.iter_mut().zip(self.data.iter()) {
+            *byte = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        rgba.convert_to(format)
+    }
+
+    /// Creates a resized copy of this image; see
+    /// [`Image::resize`](struct.Image.html#method.resize).
+    pub fn resize(&self, width: u32, height: u32) -> FloatImage {
+        if width == self.width && height == self.height {
+            return self.clone();
+        }
+        if self.width == 0 || self.height == 0 || width == 0 || height == 0 {
+            return FloatImage::new(width, height);
+        }
+        let data = resample(self.data.to_vec(),
+                            4,
+                            Some(3),
+                            1.0,
+                            (self.width as usize, self.height as usize),
+                            (width as usize, height as usize));
+        FloatImage {
+            width,
+            height,
+            data: data.into_boxed_slice(),
+        }
+    }
+
+    /// Creates a copy of this image recolored with the given RGB tint color,
+    /// keeping the same alpha channel; see
+    /// [`Image::tint`](struct.Image.html#method.tint).
+    pub fn tint(&self, color: [f32; 3], mode: BlendMode) -> FloatImage {
+        let mut output = self.clone();
+        for pixel in output.data.chunks_mut(4) {
+            for (value, &tint) in pixel[0..3].iter_mut().zip(color.iter()) {
+                *value = mode.blend(*value, tint);
+            }
+        }
+        output
+    }
+
+    /// Creates an opaque copy of this image by alpha-compositing it over a
+    /// solid background of the given RGB color; see
+    /// [`Image::flatten_onto`](struct.Image.html#method.flatten_onto).
+    pub fn flatten_onto(&self, color: [f32; 3]) -> FloatImage {
+        let mut output = self.clone();
+        for pixel in output.data.chunks_mut(4) {
+            let alpha = pixel[3].clamp(0.0, 1.0);
+            for channel in 0..3 {
+                pixel[channel] = pixel[channel] * alpha +
+                                 color[channel] * (1.0 - alpha);
+            }
+            pixel[3] = 1.0;
+        }
+        output
+    }
+}
+
+impl Image {
+    /// Converts this image to a floating-point RGBA image, mapping each
+    /// 8-bit channel value to the range 0.0 to 1.0.
+    pub fn to_float(&self) -> FloatImage {
+        let rgba = self.convert_to(PixelFormat::RGBA);
+        let data: Vec<f32> =
+            rgba.data.iter().map(|&value| f32::from(value) / 255.0).collect();
+        FloatImage {
+            width: self.width,
+            height: self.height,
+            data: data.into_boxed_slice(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_round_trip() {
+        let data = vec![0, 64, 128, 255, 1, 2, 3, 4];
+        let image = Image::from_data(PixelFormat::RGBA, 2, 1, data).unwrap();
+        let float = image.to_float();
+        assert_eq!(float.data()[3], 1.0);
+        assert_eq!(float.to_image(PixelFormat::RGBA), image);
+        assert!(FloatImage::from_data(2, 2, vec![0.0; 4]).is_err());
+    }
+
+    #[test]
+    fn chained_operations_round_once() {
+        // Darkening and then brightening again loses precision when each
+        // step is rounded to 8 bits, but not when working in floating point.
+        let image = Image::from_data(PixelFormat::Gray, 1, 1, vec![201])
+            .unwrap();
+        let dark = [0.01, 0.01, 0.01];
+        let float = image.to_float().tint(dark, BlendMode::Multiply);
+        let mut restored = float.clone();
+        for value in restored.data_mut()[0..3].iter_mut() {
+            *value /= 0.01;
+        }
+        assert_eq!(restored.to_image(PixelFormat::Gray).data(), &[201]);
+        assert_eq!(float.to_image(PixelFormat::Gray).data(), &[2]);
+    }
+
+    #[test]
+    fn resize_matches_image_resize() {
+        let data: Vec<u8> = vec![255, 0, 0, 255, 0, 255, 0, 0];
+        let image = Image::from_data(PixelFormat::RGBA, 2, 1, data).unwrap();
+        let small = image.to_float().resize(1, 1);
+        assert_eq!(small.to_image(PixelFormat::RGBA), image.resize(1, 1));
+        let flat = small.flatten_onto([0.0, 0.0, 1.0]);
+        assert_eq!(flat.to_image(PixelFormat::RGBA).data(),
+                   &[128, 0, 128, 255]);
+    }
+}
@@ -0,0 +1,63 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{self, Read};
+
+use super::family::ICNS_MAGIC_LITERAL;
+
+/// The eight-byte header at the start of an ICNS file, as found in the file
+/// (which, for a damaged or non-ICNS file, may not be valid).
+///
+/// # Examples
+/// ```
+/// use icns::FileHeader;
+/// let header = FileHeader::read(&b"icnx\0\0\x01\0"[..]).unwrap();
+/// assert!(!header.has_icns_magic());
+/// assert_eq!(header.declared_length, 256);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FileHeader {
+    /// The four magic bytes at the start of the file (which should be
+    /// `icns`).
+    pub magic: [u8; 4],
+    /// The total length of the file in bytes (including the header), as
+    /// declared by the header.
+    pub declared_length: u32,
+}
+
+impl FileHeader {
+    /// Reads a file header, without checking that it is valid.  Returns an
+    /// error only if the reader fails or ends before eight bytes are read.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<FileHeader> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let declared_length = reader.read_u32::<BigEndian>()?;
+        Ok(FileHeader {
+            magic,
+            declared_length,
+        })
+    }
+
+    /// Returns true if the header starts with the `icns` magic literal.
+    pub fn has_icns_magic(&self) -> bool {
+        self.magic == *ICNS_MAGIC_LITERAL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn read_header_of_consistent_file() {
+        let data: &[u8] = b"icns\0\0\0\x11quux\0\0\0\x09#";
+        let header = FileHeader::read(data).unwrap();
+        assert!(header.has_icns_magic());
+        assert_eq!(header.declared_length, 17);
+    }
+
+    #[test]
+    fn read_truncated_header() {
+        let error = FileHeader::read(&b"icns\0\0"[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
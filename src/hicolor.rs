@@ -0,0 +1,102 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::family::IconFamily;
+use super::filename::is_plain_file_name;
+use super::size::IconSize;
+
+impl IconFamily {
+    /// Exports the icons in this family as PNG files laid out in the
+    /// directory structure of a freedesktop.org (XDG) icon theme, such as the
+    /// `hicolor` fallback theme used on Linux desktops.  Each icon is written
+    /// to `<theme_dir>/<size>x<size>/apps/<name>.png` (or, for 2x "retina"
+    /// icons, `<theme_dir>/<size>x<size>@2/apps/<name>.png`), creating
+    /// directories as needed.  If the family contains more than one icon with
+    /// the same size and scale, the first one is used.  Returns the paths of
+    /// the files written, in order of increasing size.  Returns an error of
+    /// kind `InvalidInput` (before writing anything) if `name.png` isn't a
+    /// plain file name, such as one containing a path separator.
+    pub fn export_hicolor<P: AsRef<Path>>(&self,
+                                          theme_dir: P,
+                                          name: &str)
+                                          -> io::Result<Vec<PathBuf>> {
+        let file_name = format!("{}.png", name);
+        if !is_plain_file_name(&file_name) {
+            let msg = format!("icon name {:?} is not a plain file name",
+                              name);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let mut icons = Vec::new();
+        for icon_type in self.available_icons() {
+            let size = icon_type.size();
+            if !icons.iter().any(|&(other, _)| other == size) {
+                icons.push((size, icon_type));
+            }
+        }
+        icons.sort_by_key(|&(size, _)| size);
+        let mut paths = Vec::with_capacity(icons.len());
+        for (size, icon_type) in icons {
+            let image = self.get_icon_with_type(icon_type)?;
+            let dir = theme_dir.as_ref()
+                .join(hicolor_size_dir(size))
+                .join("apps");
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(&file_name);
+            image.write_png(BufWriter::new(File::create(&path)?))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// Returns the name of the icon theme subdirectory for icons of the given
+/// size.
+fn hicolor_size_dir(size: IconSize) -> String {
+    if size.scale == 1 {
+        format!("{}x{}", size.points, size.points)
+    } else {
+        format!("{}x{}@{}", size.points, size.points, size.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::TempDir;
+    use super::super::icontype::IconType;
+    use super::super::image::{Image, PixelFormat};
+
+    #[test]
+    fn export_hicolor_theme() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x).unwrap();
+        let temp = TempDir::new("hicolor");
+        let dir = temp.path().join("hicolor");
+        let paths = family.export_hicolor(&dir, "foo").unwrap();
+        assert_eq!(paths,
+                   vec![dir.join("16x16@2/apps/foo.png"),
+                        dir.join("32x32/apps/foo.png")]);
+        let png = File::open(&paths[1]).unwrap();
+        let exported = Image::read_png(png).unwrap();
+        assert_eq!(exported.width(), 32);
+    }
+
+    #[test]
+    fn reject_escaping_icon_name() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+        let temp = TempDir::new("hicolor-escape");
+        let dir = temp.path().join("hicolor");
+        for name in &["../foo", "sub/foo", "/tmp/foo"] {
+            let error = family.export_hicolor(&dir, name).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        assert!(!dir.exists());
+        assert!(!temp.path().join("foo.png").exists());
+    }
+}
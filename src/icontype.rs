@@ -10,8 +10,22 @@ use std::fmt;
 /// [encode](struct.IconFamily.html#method.add_icon_with_type) and
 /// [decode](struct.IconFamily.html#method.get_icon_with_type) complete icons
 /// that consist of multiple `IconElements`.
+///
+/// Each icon type has both a logical size in points (see
+/// [`point_size`](#method.point_size)) and a stored size in pixels (see
+/// [`pixel_size`](#method.pixel_size)); these differ for the 2x "retina"
+/// types, whose variant names give their point size.  For example,
+/// `RGBA32_16x16_2x` holds a 32x32-pixel image.
+///
+/// Apple adds new icon types from time to time, so this enum is marked
+/// `#[non_exhaustive]`: code outside this crate that matches on an `IconType`
+/// must include a wildcard arm, and so won't break when a new type is added.
+/// Elements whose OSType isn't a known icon type are still preserved as
+/// [`IconElement`](struct.IconElement.html)s, and can be inspected via
+/// [`IconElement::ostype`](struct.IconElement.html#structfield.ostype).
 #[allow(non_camel_case_types)]
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
 pub enum IconType {
     /// 16x16 24-bit icon (without alpha).
     RGB24_16x16,
@@ -146,25 +160,25 @@ impl IconType {
     /// Get the OSType that represents this icon type.
     pub fn ostype(self) -> OSType {
         match self {
-            IconType::RGB24_16x16 => OSType(*b"is32"),
-            IconType::Mask8_16x16 => OSType(*b"s8mk"),
-            IconType::RGB24_32x32 => OSType(*b"il32"),
-            IconType::Mask8_32x32 => OSType(*b"l8mk"),
-            IconType::RGB24_48x48 => OSType(*b"ih32"),
-            IconType::Mask8_48x48 => OSType(*b"h8mk"),
-            IconType::RGB24_128x128 => OSType(*b"it32"),
-            IconType::Mask8_128x128 => OSType(*b"t8mk"),
-            IconType::RGBA32_16x16 => OSType(*b"icp4"),
-            IconType::RGBA32_16x16_2x => OSType(*b"ic11"),
-            IconType::RGBA32_32x32 => OSType(*b"icp5"),
-            IconType::RGBA32_32x32_2x => OSType(*b"ic12"),
-            IconType::RGBA32_64x64 => OSType(*b"icp6"),
-            IconType::RGBA32_128x128 => OSType(*b"ic07"),
-            IconType::RGBA32_128x128_2x => OSType(*b"ic13"),
-            IconType::RGBA32_256x256 => OSType(*b"ic08"),
-            IconType::RGBA32_256x256_2x => OSType(*b"ic14"),
-            IconType::RGBA32_512x512 => OSType(*b"ic09"),
-            IconType::RGBA32_512x512_2x => OSType(*b"ic10"),
+            IconType::RGB24_16x16 => ostype!("is32"),
+            IconType::Mask8_16x16 => ostype!("s8mk"),
+            IconType::RGB24_32x32 => ostype!("il32"),
+            IconType::Mask8_32x32 => ostype!("l8mk"),
+            IconType::RGB24_48x48 => ostype!("ih32"),
+            IconType::Mask8_48x48 => ostype!("h8mk"),
+            IconType::RGB24_128x128 => ostype!("it32"),
+            IconType::Mask8_128x128 => ostype!("t8mk"),
+            IconType::RGBA32_16x16 => ostype!("icp4"),
+            IconType::RGBA32_16x16_2x => ostype!("ic11"),
+            IconType::RGBA32_32x32 => ostype!("icp5"),
+            IconType::RGBA32_32x32_2x => ostype!("ic12"),
+            IconType::RGBA32_64x64 => ostype!("icp6"),
+            IconType::RGBA32_128x128 => ostype!("ic07"),
+            IconType::RGBA32_128x128_2x => ostype!("ic13"),
+            IconType::RGBA32_256x256 => ostype!("ic08"),
+            IconType::RGBA32_256x256_2x => ostype!("ic14"),
+            IconType::RGBA32_512x512 => ostype!("ic09"),
+            IconType::RGBA32_512x512_2x => ostype!("ic10"),
         }
     }
 
@@ -328,6 +342,37 @@ impl IconType {
         }
     }
 
+    /// Returns the icon type with the same screen size as this one but the
+    /// other pixel density (that is, the 2x "retina" type for a 1x type, or
+    /// vice versa), if there is one.  Only the PNG/JPEG 2000 icon types come
+    /// in such pairs.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::IconType;
+    /// assert_eq!(IconType::RGBA32_128x128.density_counterpart(),
+    ///            Some(IconType::RGBA32_128x128_2x));
+    /// assert_eq!(IconType::RGBA32_128x128_2x.density_counterpart(),
+    ///            Some(IconType::RGBA32_128x128));
+    /// assert_eq!(IconType::RGB24_128x128.density_counterpart(), None);
+    /// assert_eq!(IconType::RGBA32_64x64.density_counterpart(), None);
+    /// ```
+    pub fn density_counterpart(self) -> Option<IconType> {
+        match self {
+            IconType::RGBA32_16x16 => Some(IconType::RGBA32_16x16_2x),
+            IconType::RGBA32_16x16_2x => Some(IconType::RGBA32_16x16),
+            IconType::RGBA32_32x32 => Some(IconType::RGBA32_32x32_2x),
+            IconType::RGBA32_32x32_2x => Some(IconType::RGBA32_32x32),
+            IconType::RGBA32_128x128 => Some(IconType::RGBA32_128x128_2x),
+            IconType::RGBA32_128x128_2x => Some(IconType::RGBA32_128x128),
+            IconType::RGBA32_256x256 => Some(IconType::RGBA32_256x256_2x),
+            IconType::RGBA32_256x256_2x => Some(IconType::RGBA32_256x256),
+            IconType::RGBA32_512x512 => Some(IconType::RGBA32_512x512_2x),
+            IconType::RGBA32_512x512_2x => Some(IconType::RGBA32_512x512),
+            _ => None,
+        }
+    }
+
     /// Returns the encoding used within an ICNS file for this icon type.
     pub fn encoding(self) -> Encoding {
         match self {
@@ -354,6 +399,28 @@ impl IconType {
     }
 }
 
+/// All icon types, in the order they are declared:
+pub(crate) const ALL_ICON_TYPES: [IconType; 19] =
+    [IconType::RGB24_16x16,
+     IconType::Mask8_16x16,
+     IconType::RGB24_32x32,
+     IconType::Mask8_32x32,
+     IconType::RGB24_48x48,
+     IconType::Mask8_48x48,
+     IconType::RGB24_128x128,
+     IconType::Mask8_128x128,
+     IconType::RGBA32_16x16,
+     IconType::RGBA32_16x16_2x,
+     IconType::RGBA32_32x32,
+     IconType::RGBA32_32x32_2x,
+     IconType::RGBA32_64x64,
+     IconType::RGBA32_128x128,
+     IconType::RGBA32_128x128_2x,
+     IconType::RGBA32_256x256,
+     IconType::RGBA32_256x256_2x,
+     IconType::RGBA32_512x512,
+     IconType::RGBA32_512x512_2x];
+
 /// A Macintosh OSType (also known as a ResType), used in ICNS files to
 /// identify the type of each icon element.
 ///
@@ -363,11 +430,38 @@ impl IconType {
 /// `is32` represents 24-bit color data for a 16x16 icon, while OSType `s8mk`
 /// represents the 8-bit alpha mask for that same icon.
 ///
+/// An OSType can be converted to and from a `[u8; 4]` array or a big-endian
+/// `u32` with `From`/`Into` (and therefore also with `TryFrom`/`TryInto`,
+/// which never fail), and viewed as a byte slice with `AsRef<[u8]>`.
+///
 /// See the [`IconType`](enum.IconType.html) enum for an easier-to-use
 /// representation of icon data types.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct OSType(pub [u8; 4]);
 
+impl OSType {
+    /// Creates an OSType from a four-character ASCII string.  This is a
+    /// `const fn`, so that it can be used to define constants; when called
+    /// in a constant context (as by the [`ostype!`](macro.ostype.html)
+    /// macro), passing a string that isn't exactly four ASCII characters
+    /// long is a compile error.  Otherwise, it panics.  (To parse an OSType
+    /// from a string at runtime without panicking, use `str::parse`.)
+    pub const fn from_literal(literal: &str) -> OSType {
+        let bytes = literal.as_bytes();
+        if bytes.len() != 4 {
+            panic!("OSType literal must be exactly 4 chars long");
+        }
+        let mut index = 0;
+        while index < 4 {
+            if !bytes[index].is_ascii() {
+                panic!("OSType literal must only contain ASCII chars");
+            }
+            index += 1;
+        }
+        OSType([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
 impl fmt::Display for OSType {
     fn fmt(&self, out: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let &OSType(raw) = self;
@@ -403,6 +497,39 @@ impl std::str::FromStr for OSType {
     }
 }
 
+impl From<[u8; 4]> for OSType {
+    fn from(raw: [u8; 4]) -> OSType {
+        OSType(raw)
+    }
+}
+
+impl From<OSType> for [u8; 4] {
+    fn from(ostype: OSType) -> [u8; 4] {
+        ostype.0
+    }
+}
+
+/// Converts a big-endian `u32` (the usual in-memory representation of an
+/// OSType in Mac APIs, such as the `0x69633038` for `ic08`) into an OSType.
+impl From<u32> for OSType {
+    fn from(value: u32) -> OSType {
+        OSType(value.to_be_bytes())
+    }
+}
+
+/// Converts an OSType into its big-endian `u32` representation.
+impl From<OSType> for u32 {
+    fn from(ostype: OSType) -> u32 {
+        u32::from_be_bytes(ostype.0)
+    }
+}
+
+impl AsRef<[u8]> for OSType {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 /// Methods of encoding an image within an icon element.
 ///
 /// Each [`IconType`](enum.IconType.html) uses a particular encoding within
@@ -426,26 +553,6 @@ mod tests {
     use super::*;
     use std::str::FromStr;
 
-    const ALL_ICON_TYPES: [IconType; 19] = [IconType::RGB24_16x16,
-                                            IconType::Mask8_16x16,
-                                            IconType::RGB24_32x32,
-                                            IconType::Mask8_32x32,
-                                            IconType::RGB24_48x48,
-                                            IconType::Mask8_48x48,
-                                            IconType::RGB24_128x128,
-                                            IconType::Mask8_128x128,
-                                            IconType::RGBA32_16x16,
-                                            IconType::RGBA32_16x16_2x,
-                                            IconType::RGBA32_32x32,
-                                            IconType::RGBA32_32x32_2x,
-                                            IconType::RGBA32_64x64,
-                                            IconType::RGBA32_128x128,
-                                            IconType::RGBA32_128x128_2x,
-                                            IconType::RGBA32_256x256,
-                                            IconType::RGBA32_256x256_2x,
-                                            IconType::RGBA32_512x512,
-                                            IconType::RGBA32_512x512_2x];
-
     #[test]
     fn icon_type_ostype_round_trip() {
         for icon_type in &ALL_ICON_TYPES {
@@ -534,4 +641,14 @@ mod tests {
                         (found 0x2603)"
                        .to_string()));
     }
+
+    #[test]
+    fn ostype_conversions() {
+        let ostype = OSType(*b"ic08");
+        assert_eq!(OSType::from(0x6963_3038u32), ostype);
+        assert_eq!(u32::from(ostype), 0x6963_3038);
+        assert_eq!(OSType::from(*b"ic08"), ostype);
+        assert_eq!(<[u8; 4]>::from(ostype), *b"ic08");
+        assert_eq!(ostype.as_ref(), b"ic08");
+    }
 }
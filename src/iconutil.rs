@@ -0,0 +1,161 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use std::io::{self, Write};
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::icontype::{IconType, OSType};
+
+/// The OSType of a table of contents element:
+pub(crate) const TOC_OSTYPE: OSType = ostype!("TOC ");
+
+/// The order in which `iconutil -c icns` writes the icon elements it
+/// supports (after its table of contents):
+const ICONUTIL_ORDER: &[IconType] = &[IconType::RGBA32_32x32_2x,
+                                      IconType::RGBA32_128x128,
+                                      IconType::RGBA32_128x128_2x,
+                                      IconType::RGBA32_256x256,
+                                      IconType::RGBA32_16x16,
+                                      IconType::RGBA32_256x256_2x,
+                                      IconType::RGBA32_512x512,
+                                      IconType::RGBA32_32x32,
+                                      IconType::RGBA32_512x512_2x,
+                                      IconType::RGBA32_16x16_2x];
+
+/// Returns a table of contents element listing the OSType and total length of
+/// each of the given elements.
+pub(crate) fn toc_element(elements: &[IconElement]) -> IconElement {
+    let mut data = Vec::with_capacity(8 * elements.len());
+    for element in elements {
+        data.extend_from_slice(&element.ostype.0);
+        data.write_u32::<BigEndian>(element.total_length())
+            .expect("failed to write to a Vec");
+    }
+    IconElement::new(TOC_OSTYPE, data)
+}
+
+/// Rebuilds any `TOC ` element in the given list so that it lists the other
+/// elements as they now stand.  The first `TOC ` element is replaced in place
+/// and any others are removed; a list without one is left unchanged.
+pub(crate) fn refresh_toc(elements: &mut Vec<IconElement>) {
+    let index = match elements.iter().position(|el| el.ostype == TOC_OSTYPE) {
+        Some(index) => index,
+        None => return,
+    };
+    elements.retain(|el| el.ostype != TOC_OSTYPE);
+    let toc = toc_element(elements);
+    elements.insert(index, toc);
+}
+
+/// Returns copies of the given elements (other than any `TOC ` elements) in
+/// the order that `iconutil` writes them: the PNG-based icon elements that
+/// it supports first, followed by any other elements in their original
+/// order.  Only the first element of each of those icon types is kept;
+/// other elements are all kept, even if their OSTypes repeat.
+pub(crate) fn iconutil_order(elements: &[IconElement]) -> Vec<IconElement> {
+    let mut ordered = Vec::with_capacity(elements.len() + 1);
+    for icon_type in ICONUTIL_ORDER {
+        let ostype = icon_type.ostype();
+        if let Some(element) = elements.iter().find(|el| el.ostype == ostype) {
+            ordered.push(element.clone());
+        }
+    }
+    for element in elements {
+        if element.ostype != TOC_OSTYPE &&
+           !ICONUTIL_ORDER.iter()
+            .any(|icon_type| icon_type.ostype() == element.ostype) {
+            ordered.push(element.clone());
+        }
+    }
+    ordered
+}
+
+impl IconFamily {
+    /// Returns a copy of this family laid out the way `iconutil -c icns`
+    /// lays out its output: a `TOC ` (table of contents) element first,
+    /// followed by the PNG-based icon elements in the order that `iconutil`
+    /// writes them, followed by any other elements in their original order.
+    /// Any existing `TOC ` elements are replaced.
+    ///
+    /// `iconutil` stores the PNG files from its `.iconset` input verbatim, so
+    /// if this family's elements hold those same PNG files (e.g. as read by
+    /// [`Image::read_png`](struct.Image.html#method.read_png) and then
+    /// stored without re-encoding), writing the result produces the same
+    /// bytes as `iconutil` does.  Elements that this crate encodes itself
+    /// will generally differ from `iconutil`'s only in their PNG data.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, OSType, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 128, 128);
+    /// family.add_icon_with_type(&image, IconType::RGB24_128x128).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_128x128).unwrap();
+    /// let layout = family.to_iconutil_layout();
+    /// let ostypes: Vec<OSType> =
+    ///     layout.iter().map(|el| el.ostype).collect();
+    /// assert_eq!(ostypes,
+    ///            vec![OSType(*b"TOC "), OSType(*b"ic07"),
+    ///                 OSType(*b"it32"), OSType(*b"t8mk")]);
+    /// # }
+    /// ```
+    pub fn to_iconutil_layout(&self) -> IconFamily {
+        let mut elements = iconutil_order(&self.elements);
+        elements.insert(0, toc_element(&elements));
+        let mut family = self.clone();
+        family.elements = elements;
+        family
+    }
+
+    /// Writes the icon family to an ICNS file laid out the way `iconutil -c
+    /// icns` would write it (see
+    /// [`to_iconutil_layout`](#method.to_iconutil_layout)).
+    pub fn write_iconutil_compatible<W: Write>(&self,
+                                               writer: W)
+                                               -> io::Result<()> {
+        self.to_iconutil_layout().write(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, ByteOrder};
+
+    #[test]
+    fn iconutil_layout() {
+        let mut family = IconFamily::new();
+        for &(ostype, length) in &[(b"ic10", 30),
+                                   (b"TOC ", 8),
+                                   (b"icnV", 4),
+                                   (b"ic11", 20),
+                                   (b"ic12", 10)] {
+            family.elements
+                .push(IconElement::new(OSType(*ostype), vec![0; length]));
+        }
+        let mut data = Vec::new();
+        family.write_iconutil_compatible(&mut data).unwrap();
+        let layout = IconFamily::read(&data[..]).unwrap();
+        let ostypes: Vec<&[u8]> =
+            layout.iter().map(|el| &el.ostype.0[..]).collect();
+        assert_eq!(ostypes,
+                   vec![b"TOC ", b"ic12", b"ic10", b"ic11", b"icnV"]);
+        let toc = &layout.elements[0].data;
+        assert_eq!(toc.len(), 32);
+        assert_eq!(&toc[0..4], b"ic12");
+        assert_eq!(BigEndian::read_u32(&toc[4..]), 18);
+        assert_eq!(&toc[24..28], b"icnV");
+        assert_eq!(BigEndian::read_u32(&toc[28..]), 12);
+    }
+
+    #[test]
+    fn iconutil_order_keeps_repeated_other_elements() {
+        let elements = vec![IconElement::new(OSType(*b"ic10"), vec![1]),
+                            IconElement::new(OSType(*b"abcd"), vec![2]),
+                            IconElement::new(OSType(*b"ic10"), vec![3]),
+                            IconElement::new(OSType(*b"abcd"), vec![4])];
+        let ordered = iconutil_order(&elements);
+        let data: Vec<u8> = ordered.iter().map(|el| el.data[0]).collect();
+        assert_eq!(data, vec![1, 2, 4]);
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io;
 
 /// A decoded icon image.
@@ -11,7 +12,13 @@ use std::io;
 /// first, followed by the rest of the top row from left to right; then comes
 /// the second row down, again from left to right, and so on until finally the
 /// bottom-right pixel comes last).
-#[derive(Clone)]
+///
+/// Two images are equal (and hash identically) if they have the same pixel
+/// format, dimensions, and pixel data, so images can be used as keys in e.g.
+/// a `HashMap` caching work done on decoded icons.  Images with the same
+/// pixels stored in different formats are not equal; use
+/// [`convert_to`](#method.convert_to) first to compare those.
+#[derive(Clone, Eq, Hash, PartialEq)]
 pub struct Image {
     pub(crate) format: PixelFormat,
     pub(crate) width: u32,
@@ -23,7 +30,7 @@ impl Image {
     /// Creates a new image with all pixel data set to zero.
     pub fn new(format: PixelFormat, width: u32, height: u32) -> Image {
         let data_bits = format.bits_per_pixel() * width * height;
-        let data_bytes = ((data_bits + 7) / 8) as usize;
+        let data_bytes = data_bits.div_ceil(8) as usize;
         Image {
             format,
             width,
@@ -40,7 +47,7 @@ impl Image {
                      data: Vec<u8>)
                      -> io::Result<Image> {
         let data_bits = format.bits_per_pixel() * width * height;
-        let data_bytes = ((data_bits + 7) / 8) as usize;
+        let data_bytes = data_bits.div_ceil(8) as usize;
         if data.len() == data_bytes {
             Ok(Image {
                 format,
@@ -58,6 +65,70 @@ impl Image {
         }
     }
 
+    /// Creates a new image by copying pixel data whose rows are `stride`
+    /// bytes apart, as is common for framebuffers whose rows are padded for
+    /// alignment.  Any padding at the end of each row is ignored, and may be
+    /// omitted after the last row.  Returns an error if the stride is
+    /// shorter than a row of pixels, or if the data array is too short.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let data = [1, 2, 0, 0, 3, 4, 0, 0];
+    /// let image = Image::from_data_with_stride(PixelFormat::Gray, 2, 2, 4,
+    ///                                          &data).unwrap();
+    /// assert_eq!(image.data(), &[1, 2, 3, 4]);
+    /// ```
+    pub fn from_data_with_stride(format: PixelFormat,
+                                 width: u32,
+                                 height: u32,
+                                 stride: usize,
+                                 data: &[u8])
+                                 -> io::Result<Image> {
+        let row_bytes = (format.bits_per_pixel() / 8) as usize *
+                        width as usize;
+        if stride < row_bytes {
+            let msg = format!("row stride of {} bytes is shorter than a row \
+                               of pixels ({} bytes)",
+                              stride,
+                              row_bytes);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let rows = height as usize;
+        let needed = if rows == 0 {
+            Some(0)
+        } else {
+            stride.checked_mul(rows - 1)
+                .and_then(|length| length.checked_add(row_bytes))
+        };
+        let needed = match needed {
+            Some(needed) => needed,
+            None => {
+                let msg = format!("row stride of {} bytes is too large for \
+                                   {} rows",
+                                  stride,
+                                  rows);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+        };
+        if data.len() < needed {
+            let msg = format!("pixel data array is too short for specified \
+                               format, dimensions, and stride ({} instead of \
+                               at least {})",
+                              data.len(),
+                              needed);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let mut image = Image::new(format, width, height);
+        if row_bytes > 0 {
+            for (row, out) in image.data.chunks_mut(row_bytes).enumerate() {
+                let start = row * stride;
+                out.copy_from_slice(&data[start..(start + row_bytes)]);
+            }
+        }
+        Ok(image)
+    }
+
     /// Returns the format in which this image's pixel data is stored.
     pub fn pixel_format(&self) -> PixelFormat {
         self.format
@@ -148,6 +219,41 @@ impl Image {
             data: new_data,
         }
     }
+
+    /// Returns true if this image has the same pixel format and dimensions as
+    /// `other`, and each channel of each pixel differs from the corresponding
+    /// channel in `other` by at most `max_per_channel_delta`.  This is useful
+    /// for verifying the results of lossy operations (such as resampling, or
+    /// round-tripping through JPEG 2000) that can't be compared exactly.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let image = Image::from_data(PixelFormat::Gray, 2, 1, vec![100, 200])
+    ///     .unwrap();
+    /// let other = Image::from_data(PixelFormat::Gray, 2, 1, vec![103, 198])
+    ///     .unwrap();
+    /// assert!(image.approx_eq(&other, 3));
+    /// assert!(!image.approx_eq(&other, 2));
+    /// ```
+    pub fn approx_eq(&self, other: &Image, max_per_channel_delta: u8) -> bool {
+        self.format == other.format && self.width == other.width &&
+        self.height == other.height &&
+        self.data
+            .iter()
+            .zip(other.data.iter())
+            .all(|(&a, &b)| a.abs_diff(b) <= max_per_channel_delta)
+    }
+}
+
+impl fmt::Debug for Image {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out,
+               "Image({:?}, {}x{})",
+               self.format,
+               self.width,
+               self.height)
+    }
 }
 
 /// Formats for storing pixel data in an image.
@@ -425,6 +531,7 @@ fn alpha_to_gray(alpha: &[u8]) -> Box<[u8]> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "pngio")]
     use std::io::Cursor;
 
     #[test]
@@ -442,6 +549,71 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn image_from_data_with_stride() {
+        let data: Vec<u8> = vec![1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let image = Image::from_data_with_stride(PixelFormat::RGB,
+                                                 2,
+                                                 2,
+                                                 8,
+                                                 &data)
+            .unwrap();
+        assert_eq!(image.data(), &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert!(Image::from_data_with_stride(PixelFormat::RGB, 2, 2, 5, &data)
+            .is_err());
+        assert!(Image::from_data_with_stride(PixelFormat::RGB,
+                                             2,
+                                             2,
+                                             8,
+                                             &data[..13])
+            .is_err());
+        let empty =
+            Image::from_data_with_stride(PixelFormat::RGBA, 0, 3, 0, &[])
+                .unwrap();
+        assert_eq!(empty.height(), 3);
+        let error = Image::from_data_with_stride(PixelFormat::RGB,
+                                                 2,
+                                                 3,
+                                                 usize::MAX / 2,
+                                                 &data)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn image_approx_eq() {
+        let image = Image::from_data(PixelFormat::RGB, 1, 1, vec![0, 128, 255])
+            .unwrap();
+        assert!(image.approx_eq(&image, 0));
+        let other = Image::from_data(PixelFormat::RGB, 1, 1, vec![5, 120, 250])
+            .unwrap();
+        assert!(!image.approx_eq(&other, 5));
+        assert!(image.approx_eq(&other, 8));
+        let gray = Image::from_data(PixelFormat::Gray, 3, 1, vec![0, 128, 255])
+            .unwrap();
+        assert!(!image.approx_eq(&gray, 255));
+        let wide = Image::new(PixelFormat::RGB, 2, 1);
+        assert!(!image.approx_eq(&wide, 255));
+    }
+
+    #[test]
+    fn image_eq_and_hash() {
+        use std::collections::HashSet;
+        let image = Image::from_data(PixelFormat::Gray, 2, 1, vec![1, 2])
+            .unwrap();
+        let mut set = HashSet::new();
+        set.insert(image.clone());
+        assert!(set.contains(&image));
+        let tall = Image::from_data(PixelFormat::Gray, 1, 2, vec![1, 2])
+            .unwrap();
+        assert_ne!(tall, image);
+        assert!(!set.contains(&tall));
+        let alpha = Image::from_data(PixelFormat::Alpha, 2, 1, vec![1, 2])
+            .unwrap();
+        assert_ne!(alpha, image);
+        assert_eq!(alpha.convert_to(PixelFormat::Alpha), alpha);
+    }
+
     #[test]
     fn alpha_to_gray() {
         let alpha_data: Vec<u8> = vec![63, 127, 191, 255];
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use super::decode::DecodeOptions;
+use super::element::IconElement;
+use super::family::{IconFamily, decode_icon, missing_element};
+use super::icontype::{IconType, OSType};
+use super::image::Image;
+
+/// A read-only view of an icon family, indexed by OSType, for looking up
+/// elements in O(log n) time rather than by scanning the elements in order.
+/// This is worthwhile when making many lookups in a family with many
+/// elements (such as one with lots of variant or metadata elements).
+///
+/// The index borrows the family, so the family can't be modified while the
+/// index exists.  As with [`IconFamily`](struct.IconFamily.html)'s own
+/// lookup methods, if the family contains more than one element with the
+/// same OSType, only the first one is used.
+///
+/// # Examples
+/// ```
+/// use icns::{IconFamily, IconType, Image, PixelFormat};
+/// let mut family = IconFamily::new();
+/// let image = Image::new(PixelFormat::RGBA, 16, 16);
+/// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+/// let index = family.index();
+/// assert_eq!(index.len(), 2);
+/// assert!(index.has_icon_with_type(IconType::RGB24_16x16));
+/// assert!(!index.has_icon_with_type(IconType::RGBA32_16x16));
+/// assert_eq!(index.position(IconType::Mask8_16x16.ostype()), Some(1));
+/// assert_eq!(index.get_icon_with_type(IconType::RGB24_16x16).unwrap()
+///                .width(),
+///            16);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ElementIndex<'a> {
+    family: &'a IconFamily,
+    positions: BTreeMap<OSType, usize>,
+}
+
+impl<'a> ElementIndex<'a> {
+    /// Returns the number of distinct OSTypes in the family.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns true if the family has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Returns the position within the family's elements of the first
+    /// element with the given OSType, if any.
+    pub fn position(&self, ostype: OSType) -> Option<usize> {
+        self.positions.get(&ostype).cloned()
+    }
+
+    /// Returns the first element with the given OSType, if any.
+    pub fn get(&self, ostype: OSType) -> Option<&'a IconElement> {
+        let family = self.family;
+        self.position(ostype).map(|index| &family.elements[index])
+    }
+
+    /// Returns the element for the given icon type, if any.
+    pub fn find_element(&self,
+                        icon_type: IconType)
+                        -> Option<&'a IconElement> {
+        self.get(icon_type.ostype())
+    }
+
+    /// Returns true if the family contains the element(s) for a complete
+    /// icon of the given type; see [`IconFamily::has_icon_with_type`](
+    /// struct.IconFamily.html#method.has_icon_with_type).
+    pub fn has_icon_with_type(&self, icon_type: IconType) -> bool {
+        if self.find_element(icon_type).is_none() {
+            return false;
+        } else if let Some(mask_type) = icon_type.mask_type() {
+            return self.find_element(mask_type).is_some();
+        }
+        true
+    }
+
+    /// Decodes an image from the family with the given icon type, like
+    /// [`IconFamily::get_icon_with_type`](
+    /// struct.IconFamily.html#method.get_icon_with_type), but using the
+    /// index to find the element(s) to decode.
+    pub fn get_icon_with_type(&self,
+                              icon_type: IconType)
+                              -> io::Result<Image> {
+        let find_element = |icon_type: IconType| {
+            let ostype = icon_type.ostype();
+            self.get(ostype).ok_or_else(|| missing_element(ostype))
+        };
+        decode_icon(find_element,
+                    icon_type,
+                    &DecodeOptions::new(),
+                    &mut Vec::new())
+    }
+
+    /// Returns the family that this index was built from.
+    pub fn family(&self) -> &'a IconFamily {
+        self.family
+    }
+}
+
+impl IconFamily {
+    /// Builds an index of the family's elements by OSType, for fast repeated
+    /// lookups.  Building the index takes O(n log n) time.
+    pub fn index(&self) -> ElementIndex<'_> {
+        let mut positions = BTreeMap::new();
+        for (index, element) in self.elements.iter().enumerate() {
+            positions.entry(element.ostype).or_insert(index);
+        }
+        ElementIndex {
+            family: self,
+            positions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_uses_first_duplicate() {
+        let family = IconFamily::from_elements(vec![
+            IconElement::new(OSType(*b"info"), vec![1]),
+            IconElement::new(IconType::RGBA32_16x16.ostype(), vec![2]),
+            IconElement::new(OSType(*b"info"), vec![3]),
+        ]);
+        let index = family.index();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(OSType(*b"info")).unwrap().data, vec![1]);
+        assert_eq!(index.position(IconType::RGBA32_16x16.ostype()), Some(1));
+        assert!(index.has_icon_with_type(IconType::RGBA32_16x16));
+        assert!(index.get(OSType(*b"name")).is_none());
+        for &icon_type in &[IconType::RGBA32_16x16, IconType::RGB24_16x16] {
+            assert_eq!(index.has_icon_with_type(icon_type),
+                       family.has_icon_with_type(icon_type));
+        }
+        let error = index.get_icon_with_type(IconType::RGB24_16x16)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,105 @@
+use super::element::IconElement;
+use super::icontype::{IconType, OSType};
+
+/// OSTypes of the non-icon elements that commonly appear in ICNS files,
+/// along with their kinds:
+const KNOWN_OSTYPE_KINDS: &[(OSType, ElementKind)] =
+    &[(ostype!("TOC "), ElementKind::TableOfContents),
+      (ostype!("icnV"), ElementKind::Version),
+      (ostype!("name"), ElementKind::Name),
+      (ostype!("info"), ElementKind::Info),
+      (ostype!("tile"), ElementKind::VariantFamily),
+      (ostype!("over"), ElementKind::VariantFamily),
+      (ostype!("open"), ElementKind::VariantFamily),
+      (ostype!("drop"), ElementKind::VariantFamily),
+      (ostype!("odrp"), ElementKind::VariantFamily),
+      (ostype!("sbtp"), ElementKind::VariantFamily),
+      (ostype!("slct"), ElementKind::VariantFamily),
+      (OSType([0xfd, 0xd9, 0x2f, 0xa8]), ElementKind::VariantFamily)];
+
+/// Returns true if the given OSType is that of a commonly-used non-icon
+/// element (such as `TOC ` or `icnV`).
+pub(crate) fn is_known_ostype(ostype: OSType) -> bool {
+    KNOWN_OSTYPE_KINDS.iter().any(|entry| entry.0 == ostype)
+}
+
+/// The broad category of an icon element, as classified by
+/// [`IconElement::kind`](struct.IconElement.html#method.kind).
+///
+/// # Examples
+/// ```
+/// use icns::{ElementKind, IconElement, IconType, OSType};
+/// let element = IconElement::new(OSType(*b"icnV"), vec![0x42, 0, 0, 0]);
+/// assert_eq!(element.kind(), ElementKind::Version);
+/// let element = IconElement::new(OSType(*b"s8mk"), vec![0; 256]);
+/// assert_eq!(element.kind(), ElementKind::Mask(IconType::Mask8_16x16));
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ElementKind {
+    /// Image data for a (non-mask) icon type.
+    IconData(IconType),
+    /// An alpha mask for another icon type.
+    Mask(IconType),
+    /// A table of contents (`TOC `) listing the other elements.
+    TableOfContents,
+    /// An `icnV` element, holding the version of the tool that wrote the
+    /// file.
+    Version,
+    /// A `name` element, holding the name of the icon.
+    Name,
+    /// An `info` element, holding a property list of metadata.
+    Info,
+    /// A nested icon family holding a variant of the main icon (such as the
+    /// `tile`, `open`, or `sbtp` elements, or the dark-mode variant).
+    VariantFamily,
+    /// An element of some other type.
+    Unknown,
+}
+
+impl IconElement {
+    /// Classifies this element by its OSType (and, for element types not
+    /// otherwise recognized, by whether its payload is a nested icon
+    /// family).  This doesn't check that the payload is valid.
+    pub fn kind(&self) -> ElementKind {
+        if let Some(icon_type) = self.icon_type() {
+            return if icon_type.is_mask() {
+                ElementKind::Mask(icon_type)
+            } else {
+                ElementKind::IconData(icon_type)
+            };
+        }
+        let known = KNOWN_OSTYPE_KINDS.iter()
+            .find(|entry| entry.0 == self.ostype);
+        match known {
+            Some(&(_, kind)) => kind,
+            None if self.contains_family() => ElementKind::VariantFamily,
+            None => ElementKind::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_kinds() {
+        let element = IconElement::new(IconType::RGB24_16x16.ostype(),
+                                       vec![]);
+        assert_eq!(element.kind(),
+                   ElementKind::IconData(IconType::RGB24_16x16));
+        let kinds = [(*b"TOC ", ElementKind::TableOfContents),
+                     (*b"name", ElementKind::Name),
+                     (*b"info", ElementKind::Info),
+                     (*b"tile", ElementKind::VariantFamily),
+                     ([0xfd, 0xd9, 0x2f, 0xa8], ElementKind::VariantFamily),
+                     (*b"quux", ElementKind::Unknown)];
+        for &(ostype, kind) in kinds.iter() {
+            assert_eq!(IconElement::new(OSType(ostype), vec![]).kind(), kind);
+        }
+        let nested = b"icns\0\0\0\x08".to_vec();
+        assert_eq!(IconElement::new(OSType(*b"quux"), nested).kind(),
+                   ElementKind::VariantFamily);
+    }
+}
@@ -0,0 +1,525 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::element::{ICON_ELEMENT_HEADER_LENGTH, IconElement};
+use super::extract::wanted_ostypes;
+use super::family::{ICNS_MAGIC_LITERAL, ICON_FAMILY_HEADER_LENGTH,
+                    IconFamily};
+#[cfg(feature = "pngio")]
+use super::icontype::Encoding;
+use super::icontype::{IconType, OSType};
+use super::image::Image;
+#[cfg(feature = "pngio")]
+use super::pngcheck::{IHDR_LENGTH, PNG_SIGNATURE, PngHeader};
+
+/// The maximum number of bytes read from an element store at once when
+/// streaming a payload:
+const STREAM_CHUNK_SIZE: u64 = 0x10000;
+
+/// A backend holding the bytes of an ICNS file, from which a
+/// [`LazyIconFamily`](struct.LazyIconFamily.html) reads element payloads on
+/// demand.
+///
+/// This crate implements `ElementStore` for in-memory byte buffers (`Vec<u8>`
+/// and `[u8]`, along with references and smart pointers to stores, so that
+/// e.g. a memory-mapped file can be used by dereferencing it to a byte
+/// slice), and for files via [`FileStore`](struct.FileStore.html).  Other
+/// backends (such as a blob in a database) can be supported by implementing
+/// this trait.
+pub trait ElementStore {
+    /// Returns the total length of the stored ICNS file, in bytes.
+    fn len(&self) -> io::Result<u64>;
+
+    /// Returns true if the stored ICNS file is empty.
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns the `length` bytes starting at `offset` within the stored ICNS
+    /// file, or an error if that range extends past the end of the file.
+    fn read_at(&self,
+               offset: u64,
+               length: usize)
+               -> io::Result<Cow<'_, [u8]>>;
+}
+
+impl ElementStore for [u8] {
+    fn len(&self) -> io::Result<u64> {
+        Ok(<[u8]>::len(self) as u64)
+    }
+
+    fn read_at(&self,
+               offset: u64,
+               length: usize)
+               -> io::Result<Cow<'_, [u8]>> {
+        let start = offset as usize;
+        match start.checked_add(length) {
+            Some(end) if offset <= self.len() as u64 && end <= self.len() => {
+                Ok(Cow::Borrowed(&self[start..end]))
+            }
+            _ => {
+                Err(Error::new(ErrorKind::UnexpectedEof,
+                               "read past the end of the element store"))
+            }
+        }
+    }
+}
+
+impl ElementStore for Vec<u8> {
+    fn len(&self) -> io::Result<u64> {
+        ElementStore::len(self.as_slice())
+    }
+
+    fn read_at(&self,
+               offset: u64,
+               length: usize)
+               -> io::Result<Cow<'_, [u8]>> {
+        self.as_slice().read_at(offset, length)
+    }
+}
+
+impl<S: ElementStore + ?Sized> ElementStore for &S {
+    fn len(&self) -> io::Result<u64> {
+        (**self).len()
+    }
+
+    fn read_at(&self,
+               offset: u64,
+               length: usize)
+               -> io::Result<Cow<'_, [u8]>> {
+        (**self).read_at(offset, length)
+    }
+}
+
+impl<S: ElementStore + ?Sized> ElementStore for Box<S> {
+    fn len(&self) -> io::Result<u64> {
+        (**self).len()
+    }
+
+    fn read_at(&self,
+               offset: u64,
+               length: usize)
+               -> io::Result<Cow<'_, [u8]>> {
+        (**self).read_at(offset, length)
+    }
+}
+
+impl<S: ElementStore + ?Sized> ElementStore for Arc<S> {
+    fn len(&self) -> io::Result<u64> {
+        (**self).len()
+    }
+
+    fn read_at(&self,
+               offset: u64,
+               length: usize)
+               -> io::Result<Cow<'_, [u8]>> {
+        (**self).read_at(offset, length)
+    }
+}
+
+/// An [`ElementStore`](trait.ElementStore.html) that reads element payloads
+/// from a file on disk as they are requested.
+#[derive(Debug)]
+pub struct FileStore {
+    file: Mutex<File>,
+}
+
+impl FileStore {
+    /// Opens the file at the given path as an element store.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FileStore> {
+        Ok(FileStore::new(File::open(path)?))
+    }
+
+    /// Creates an element store that reads from the given file.
+    pub fn new(file: File) -> FileStore {
+        FileStore { file: Mutex::new(file) }
+    }
+}
+
+impl ElementStore for FileStore {
+    fn len(&self) -> io::Result<u64> {
+        let file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(file.metadata()?.len())
+    }
+
+    fn read_at(&self,
+               offset: u64,
+               length: usize)
+               -> io::Result<Cow<'_, [u8]>> {
+        let mut file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0u8; length];
+        file.read_exact(&mut data)?;
+        Ok(Cow::Owned(data))
+    }
+}
+
+/// The location of one element's payload within an
+/// [`ElementStore`](trait.ElementStore.html).
+#[derive(Clone, Copy, Debug)]
+struct ElementEntry {
+    ostype: OSType,
+    offset: u64,
+    length: u32,
+}
+
+/// An icon family whose element payloads stay in an
+/// [`ElementStore`](trait.ElementStore.html) until they are requested.
+///
+/// Opening a `LazyIconFamily` reads only the element headers; payloads are
+/// read from the store each time an element or icon is requested, so holding
+/// many families costs little more memory than their headers.  Use
+/// [`load`](#method.load) to read the whole family into an ordinary
+/// [`IconFamily`](struct.IconFamily.html) (for example, to modify it).
+///
+/// The store type can be erased by using a `Box<dyn ElementStore>`.  With a
+/// shared store such as `Arc<[u8]>` (see
+/// [`SharedIconFamily`](type.SharedIconFamily.html)), cloning a
+/// `LazyIconFamily` doesn't copy any payloads.
+///
+/// # Examples
+/// ```
+/// use icns::{IconFamily, IconType, Image, LazyIconFamily, PixelFormat};
+/// let mut family = IconFamily::new();
+/// let image = Image::new(PixelFormat::RGBA, 16, 16);
+/// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+/// let mut data = Vec::new();
+/// family.write(&mut data).unwrap();
+/// let lazy = LazyIconFamily::open(data).unwrap();
+/// assert_eq!(lazy.len(), 2);
+/// assert!(lazy.has_icon_with_type(IconType::RGB24_16x16));
+/// let icon = lazy.get_icon_with_type(IconType::RGB24_16x16).unwrap();
+/// assert_eq!(icon.width(), 16);
+/// ```
+#[derive(Clone, Debug)]
+pub struct LazyIconFamily<S> {
+    store: S,
+    entries: Arc<[ElementEntry]>,
+}
+
+/// An immutable icon family whose encoded data is reference-counted, so that
+/// it can be cloned cheaply (e.g. to hand a copy to each of several threads)
+/// without duplicating its payloads.  Create one with
+/// [`IconFamily::to_shared`](struct.IconFamily.html#method.to_shared).
+pub type SharedIconFamily = LazyIconFamily<Arc<[u8]>>;
+
+impl<S: ElementStore> LazyIconFamily<S> {
+    /// Reads the element headers of the ICNS file in the given store.
+    /// Returns an error if the store doesn't contain a well-formed ICNS
+    /// file.
+    pub fn open(store: S) -> io::Result<LazyIconFamily<S>> {
+        let header = store.read_at(0, ICON_FAMILY_HEADER_LENGTH as usize)?;
+        if &header[0..4] != ICNS_MAGIC_LITERAL {
+            let msg = "not an icns file (wrong magic literal)";
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let file_length = u64::from(BigEndian::read_u32(&header[4..]));
+        drop(header);
+        let mut entries = Vec::new();
+        let mut position = u64::from(ICON_FAMILY_HEADER_LENGTH);
+        while position < file_length {
+            let header =
+                store.read_at(position, ICON_ELEMENT_HEADER_LENGTH as usize)?;
+            let mut raw_ostype = [0u8; 4];
+            raw_ostype.copy_from_slice(&header[0..4]);
+            let element_length = BigEndian::read_u32(&header[4..]);
+            if element_length < ICON_ELEMENT_HEADER_LENGTH {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      "invalid element length"));
+            }
+            entries.push(ElementEntry {
+                ostype: OSType(raw_ostype),
+                offset: position + u64::from(ICON_ELEMENT_HEADER_LENGTH),
+                length: element_length - ICON_ELEMENT_HEADER_LENGTH,
+            });
+            position += u64::from(element_length);
+        }
+        if position > store.len()? {
+            return Err(Error::new(ErrorKind::UnexpectedEof,
+                                  "icns file is truncated"));
+        }
+        Ok(LazyIconFamily {
+            store,
+            entries: entries.into(),
+        })
+    }
+
+    /// Returns the number of elements in the family.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the family has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the OSTypes of the family's elements, in order.
+    pub fn ostypes(&self) -> Vec<OSType> {
+        self.entries.iter().map(|entry| entry.ostype).collect()
+    }
+
+    /// Returns the length of the payload of the element at the given index,
+    /// in bytes, without reading it.  Panics if the index is out of range.
+    pub fn payload_length(&self, index: usize) -> u32 {
+        self.entries[index].length
+    }
+
+    /// Reads the element at the given index from the store.  Panics if the
+    /// index is out of range.
+    pub fn element(&self, index: usize) -> io::Result<IconElement> {
+        let entry = self.entries[index];
+        let data = self.store.read_at(entry.offset, entry.length as usize)?;
+        Ok(IconElement::new(entry.ostype, data.into_owned()))
+    }
+
+    /// Copies the data payload of the element at the given index from the
+    /// store to the given writer, a chunk at a time, returning the number of
+    /// bytes copied.  Panics if the index is out of range.
+    pub fn copy_payload_to<W: Write>(&self,
+                                     index: usize,
+                                     mut writer: W)
+                                     -> io::Result<u64> {
+        let entry = self.entries[index];
+        let mut reader = StoreReader {
+            store: &self.store,
+            offset: entry.offset,
+            remaining: u64::from(entry.length),
+        };
+        io::copy(&mut reader, &mut writer)
+    }
+
+    /// Determines whether the family contains a complete icon with the given
+    /// type (including the mask, if the given icon type has an associated
+    /// mask type), without reading any payloads.
+    pub fn has_icon_with_type(&self, icon_type: IconType) -> bool {
+        let has = |ostype: OSType| {
+            self.entries.iter().any(|entry| entry.ostype == ostype)
+        };
+        has(icon_type.ostype()) &&
+        icon_type.mask_type().is_none_or(|mask| has(mask.ostype()))
+    }
+
+    /// Decodes the icon with the given type, reading only the payloads of the
+    /// elements it needs from the store.  This gives the same result as
+    /// [`IconFamily::get_icon_with_type`](
+    /// struct.IconFamily.html#method.get_icon_with_type).
+    ///
+    /// PNG data is streamed from the store into the PNG decoder a chunk at a
+    /// time, rather than first being read into memory in full.
+    pub fn get_icon_with_type(&self,
+                              icon_type: IconType)
+                              -> io::Result<Image> {
+        #[cfg(feature = "pngio")]
+        {
+            if let Some(image) = self.stream_png_icon(icon_type)? {
+                return Ok(image);
+            }
+        }
+        let wanted = wanted_ostypes(icon_type);
+        let mut family = IconFamily::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let is_new =
+                !family.elements.iter().any(|el| el.ostype == entry.ostype);
+            if wanted.contains(&entry.ostype) && is_new {
+                family.elements.push(self.element(index)?);
+            }
+        }
+        family.get_icon_with_type(icon_type)
+    }
+
+    /// Decodes the icon with the given type by streaming its payload from the
+    /// store into the PNG decoder, if its element holds PNG data with the
+    /// expected dimensions.  Returns `None` if the icon must instead be
+    /// decoded from a buffered payload.
+    #[cfg(feature = "pngio")]
+    fn stream_png_icon(&self,
+                       icon_type: IconType)
+                       -> io::Result<Option<Image>> {
+        if icon_type.encoding() != Encoding::JP2PNG {
+            return Ok(None);
+        }
+        let ostype = icon_type.ostype();
+        let entry = match self.entries.iter().find(|e| e.ostype == ostype) {
+            Some(&entry) => entry,
+            None => return Ok(None),
+        };
+        let header_length = PNG_SIGNATURE.len() + 8 + IHDR_LENGTH;
+        if (entry.length as usize) < header_length {
+            return Ok(None);
+        }
+        let data = self.store.read_at(entry.offset, header_length)?;
+        match PngHeader::parse(&data) {
+            Ok(ref header) if header.width == icon_type.pixel_width() &&
+                              header.height == icon_type.pixel_height() => {}
+            _ => return Ok(None),
+        }
+        let reader = StoreReader {
+            store: &self.store,
+            offset: entry.offset,
+            remaining: u64::from(entry.length),
+        };
+        Image::read_png(reader).map(Some)
+    }
+
+    /// Reads every element from the store into an in-memory
+    /// [`IconFamily`](struct.IconFamily.html).
+    pub fn load(&self) -> io::Result<IconFamily> {
+        let mut family = IconFamily::new();
+        for index in 0..self.entries.len() {
+            family.elements.push(self.element(index)?);
+        }
+        Ok(family)
+    }
+
+    /// Returns a reference to the underlying store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the family, returning the underlying store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+}
+
+impl IconFamily {
+    /// Encodes this family into a
+    /// [`SharedIconFamily`](type.SharedIconFamily.html), whose clones all
+    /// share a single copy of the encoded data.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 128, 128);
+    /// family.add_icon_with_type(&image, IconType::RGB24_128x128).unwrap();
+    /// let shared = family.to_shared();
+    /// let clone = shared.clone();
+    /// assert!(std::sync::Arc::ptr_eq(shared.store(), clone.store()));
+    /// assert!(clone.has_icon_with_type(IconType::RGB24_128x128));
+    /// ```
+    pub fn to_shared(&self) -> SharedIconFamily {
+        let mut data = Vec::with_capacity(self.total_length() as usize);
+        self.write(&mut data).expect("failed to write to a Vec");
+        LazyIconFamily::open(Arc::from(data))
+            .expect("failed to index a freshly-written icon family")
+    }
+}
+
+/// A reader over a range of bytes in an
+/// [`ElementStore`](trait.ElementStore.html).
+struct StoreReader<'a, S: ?Sized + 'a> {
+    store: &'a S,
+    offset: u64,
+    remaining: u64,
+}
+
+impl<'a, S: ElementStore + ?Sized> Read for StoreReader<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = (buf.len() as u64)
+            .min(self.remaining)
+            .min(STREAM_CHUNK_SIZE) as usize;
+        if length == 0 {
+            return Ok(0);
+        }
+        let data = self.store.read_at(self.offset, length)?;
+        buf[..length].copy_from_slice(&data);
+        self.offset += length as u64;
+        self.remaining -= length as u64;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::FixtureBuilder;
+    #[cfg(feature = "pngio")]
+    use super::super::fixtures::test_image;
+    #[cfg(feature = "pngio")]
+    use super::super::image::PixelFormat;
+
+    fn sample_data() -> Vec<u8> {
+        FixtureBuilder::new()
+            .version_element()
+            .icon(IconType::RGB24_32x32)
+            .build()
+    }
+
+    #[test]
+    fn lazy_family_from_slice() {
+        let data = sample_data();
+        let lazy = LazyIconFamily::open(&data[..]).unwrap();
+        assert_eq!(lazy.ostypes(),
+                   vec![OSType(*b"icnV"), OSType(*b"il32"), OSType(*b"l8mk")]);
+        assert_eq!(lazy.payload_length(2), 1024);
+        let mut payload = Vec::new();
+        assert_eq!(lazy.copy_payload_to(2, &mut payload).unwrap(), 1024);
+        assert_eq!(payload, vec![255; 1024]);
+        assert!(lazy.has_icon_with_type(IconType::RGB24_32x32));
+        assert!(!lazy.has_icon_with_type(IconType::RGB24_16x16));
+        let family = IconFamily::read(&data[..]).unwrap();
+        let icon = lazy.get_icon_with_type(IconType::RGB24_32x32).unwrap();
+        let expected = family.get_icon_with_type(IconType::RGB24_32x32)
+            .unwrap();
+        assert_eq!(icon.data(), expected.data());
+        let mut rewritten = Vec::new();
+        lazy.load().unwrap().write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, data);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn lazy_family_streams_png() {
+        let image = test_image(PixelFormat::RGBA, 64, 64);
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32_2x).unwrap();
+        let mut element = family.elements[0].clone();
+        element.ostype = IconType::RGBA32_128x128.ostype();
+        family.elements.push(element);
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        let lazy = LazyIconFamily::open(data).unwrap();
+        let icon = lazy.get_icon_with_type(IconType::RGBA32_32x32_2x)
+            .unwrap();
+        assert_eq!(icon.data(), image.data());
+        // PNG data of the wrong size is still handled like `IconFamily`:
+        let error = lazy.get_icon_with_type(IconType::RGBA32_128x128)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn lazy_family_with_dyn_store() {
+        let store: Box<dyn ElementStore> = Box::new(sample_data());
+        let lazy = LazyIconFamily::open(store).unwrap();
+        assert_eq!(lazy.element(0).unwrap().data, vec![0; 4]);
+    }
+
+    #[test]
+    fn shared_family_round_trip() {
+        let family = IconFamily::read(&sample_data()[..]).unwrap();
+        let shared = family.to_shared();
+        let clones: Vec<SharedIconFamily> =
+            (0..3).map(|_| shared.clone()).collect();
+        for clone in &clones {
+            assert!(Arc::ptr_eq(clone.store(), shared.store()));
+        }
+        let mut rewritten = Vec::new();
+        clones[2].load().unwrap().write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, sample_data());
+    }
+
+    #[test]
+    fn lazy_family_truncated() {
+        let mut data = sample_data();
+        data.truncate(100);
+        let error = LazyIconFamily::open(data).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
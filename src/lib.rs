@@ -95,9 +95,48 @@
 //! types used by earlier versions of Mac OS (such as `ICN#`, a 32x32 black and
 //! white icon).  Again, pull requests (with suitable tests) are welcome.
 //!
+//! # Cargo features
+//!
+//! * `pngio` (enabled by default): Adds PNG support, which is needed to
+//!   encode and decode the PNG-based icon types, as well as the
+//!   [`Image::read_png`](struct.Image.html#method.read_png),
+//!   [`Image::write_png`](struct.Image.html#method.write_png), and
+//!   [`IconFamily::export_hicolor`](
+//!   struct.IconFamily.html#method.export_hicolor) methods.  With this
+//!   feature disabled (via `default-features = false`), the crate has no
+//!   PNG dependency at all, but can still read, write, and reorganize icon
+//!   families, and encode and decode the RLE and mask icon types;
+//!   attempting to encode or decode a PNG-based icon type returns an error
+//!   of kind `InvalidInput`.
+//! * `appiconset`: Adds the [`IconFamily::read_appiconset`](
+//!   struct.IconFamily.html#method.read_appiconset) and
+//!   [`IconFamily::write_appiconset`](
+//!   struct.IconFamily.html#method.write_appiconset) methods, for reading
+//!   and writing the macOS icons in an Xcode asset catalog (writing also
+//!   requires the `pngio` feature).
+//! * `bundle`: Adds methods for locating and reading the icon of a macOS
+//!   application bundle, such as
+//!   [`IconFamily::read_app_bundle`](
+//!   struct.IconFamily.html#method.read_app_bundle).
+//! * `proptest`: Exports the [`strategies`](strategies/index.html) module,
+//!   containing [proptest](https://docs.rs/proptest) strategies for
+//!   generating images and icon families.
+//! * `rayon`: Adds the [`IconFamily::par_map_icons`](
+//!   struct.IconFamily.html#method.par_map_icons) method, for transforming
+//!   the icons in a family in parallel, and the
+//!   [`IconFamily::thumbnails_in_dir`](
+//!   struct.IconFamily.html#method.thumbnails_in_dir) method, for
+//!   extracting icons from every ICNS file in a directory tree in parallel.
+//! * `serde`: Adds the [`IconFamily::to_json_manifest`](
+//!   struct.IconFamily.html#method.to_json_manifest) method, for exporting
+//!   a JSON description of a family's contents.
+//! * `test-util`: Exports the [`fixtures`](fixtures/index.html) module,
+//!   for building synthetic (and optionally damaged) ICNS files in tests.
+//!
 //! # Example usage
 //!
 //! ```no_run
+//! # #[cfg(feature = "pngio")] {
 //! use icns::{IconFamily, IconType, Image};
 //! use std::fs::File;
 //! use std::io::{BufReader, BufWriter};
@@ -119,26 +158,181 @@
 //! // Save the updated icon family to a new ICNS file.
 //! let file = BufWriter::new(File::create("16-and-32.icns").unwrap());
 //! icon_family.write(file).unwrap();
+//! # }
 //! ```
 
 #![warn(missing_docs)]
 
 extern crate byteorder;
 
+#[cfg(feature = "bundle")]
+extern crate plist;
 #[cfg(feature = "pngio")]
 extern crate png;
+#[cfg(any(test, feature = "proptest"))]
+extern crate proptest;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(any(feature = "appiconset", feature = "serde"))]
+extern crate serde;
+#[cfg(any(feature = "appiconset", feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "serde")]
+extern crate sha2;
+
+#[macro_use]
+mod macros;
 
+#[cfg(any(feature = "appiconset", feature = "bundle", feature = "pngio"))]
+mod filename;
+
+#[cfg(feature = "pngio")]
+mod hicolor;
 #[cfg(feature = "pngio")]
 mod pngio;
 
+#[cfg(feature = "appiconset")]
+mod appiconset;
+
+#[cfg(feature = "bundle")]
+mod bundle;
+
+#[cfg(feature = "serde")]
+mod manifest;
+
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "rayon")]
+mod thumbnail;
+#[cfg(feature = "rayon")]
+pub use self::thumbnail::Thumbnail;
+
+mod autofit;
+pub use self::autofit::AutoFit;
+
+mod cache;
+pub use self::cache::IconCache;
+
+mod carve;
+pub use self::carve::EmbeddedFamily;
+
+mod codec;
+pub use self::codec::{CodecRegistry, ElementCodec};
+
+mod colorspace;
+pub use self::colorspace::ColorSpace;
+
+mod compat;
+pub use self::compat::MacOSVersion;
+
+mod consistency;
+pub use self::consistency::{ConsistencyReport, IconPairSimilarity};
+
+mod decode;
+pub use self::decode::{DecodeOptions, MaskLength, MissingIcon, MissingMask,
+                       SizeMismatch};
+
+mod decode_into;
+
+mod describe;
+pub use self::describe::{ElementCounts, ElementDescription,
+                         FamilyDescription};
+
 mod element;
 pub use self::element::IconElement;
 
+mod extract;
+
 mod family;
-pub use self::family::IconFamily;
+pub use self::family::{DuplicatePolicy, IconFamily, ReadLimits,
+                       TrimPriority, TypePreference};
+
+mod float;
+pub use self::float::FloatImage;
+
+mod header;
+pub use self::header::FileHeader;
 
 mod icontype;
 pub use self::icontype::{Encoding, IconType, OSType};
 
+mod iconutil;
+
 mod image;
 pub use self::image::{Image, PixelFormat};
+
+mod index;
+pub use self::index::ElementIndex;
+
+mod kind;
+pub use self::kind::ElementKind;
+
+mod lazy;
+pub use self::lazy::{ElementStore, FileStore, LazyIconFamily,
+                     SharedIconFamily};
+
+mod masks;
+
+mod options;
+pub use self::options::{ElementOrder, PngCompression, ReadOptions,
+                        WriteOptions};
+
+pub mod palette;
+
+mod payload;
+pub use self::payload::{CompressionInfo, PayloadFormat};
+
+mod pngcheck;
+pub use self::pngcheck::{PngColorType, PngDiagnostic, PngHeader,
+                         PngProblem};
+
+mod progress;
+pub use self::progress::Progress;
+
+mod recompress;
+mod repair;
+mod resize;
+mod resource;
+pub mod rle;
+
+mod savings;
+pub use self::savings::{CompressionFinding, CompressionReport,
+                        FindingKind};
+
+mod scan;
+pub use self::scan::ElementHeader;
+
+mod size;
+pub use self::size::IconSize;
+
+mod squircle;
+
+mod swizzle;
+pub use self::swizzle::ChannelOrder;
+
+mod target;
+pub use self::target::IconTarget;
+
+mod transform;
+pub use self::transform::BlendMode;
+
+mod typeset;
+pub use self::typeset::TypeSet;
+
+mod validate;
+pub use self::validate::{ValidationError, ValidationProblem};
+
+mod verify;
+pub use self::verify::{ReencodeCheck, ReencodeReport};
+
+mod warning;
+pub use self::warning::Warning;
+
+mod writer;
+pub use self::writer::IconFamilyWriter;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixtures;
+
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies;
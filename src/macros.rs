@@ -0,0 +1,38 @@
+/// Creates an [`OSType`](struct.OSType.html) from a four-character string
+/// literal, checking at compile time that the literal is exactly four ASCII
+/// characters long.
+///
+/// # Examples
+/// ```
+/// #[macro_use]
+/// extern crate icns;
+/// use icns::{IconType, OSType};
+///
+/// # fn main() {
+/// const TOC: OSType = ostype!("TOC ");
+/// assert_eq!(TOC, OSType(*b"TOC "));
+/// assert_eq!(IconType::from_ostype(ostype!("ic08")),
+///            Some(IconType::RGBA32_256x256));
+/// # }
+/// ```
+///
+/// A literal of the wrong length is a compile error:
+///
+/// ```compile_fail
+/// #[macro_use]
+/// extern crate icns;
+///
+/// # fn main() {
+/// let ostype = ostype!("ic8");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! ostype {
+    ($literal:expr) => {
+        {
+            const OSTYPE: $crate::OSType =
+                $crate::OSType::from_literal($literal);
+            OSTYPE
+        }
+    };
+}
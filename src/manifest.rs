@@ -0,0 +1,104 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::family::IconFamily;
+
+/// The JSON manifest of an icon family; see
+/// [`IconFamily::to_json_manifest`](
+/// struct.IconFamily.html#method.to_json_manifest).
+#[derive(Serialize)]
+struct Manifest {
+    total_length: u32,
+    elements: Vec<ManifestEntry>,
+}
+
+/// The entry in a JSON manifest for a single icon element.
+#[derive(Serialize)]
+struct ManifestEntry {
+    ostype: String,
+    offset: u32,
+    length: u32,
+    format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    sha256: String,
+}
+
+impl IconFamily {
+    /// Returns a JSON manifest describing the contents of this family: the
+    /// total file length, and for each element its OSType, offset, length,
+    /// detected payload format, image dimensions (if known), and the SHA-256
+    /// hash of its payload (see also [`description`](#method.description)).
+    /// No pixel data is included, so the manifest can be checked into
+    /// version control to track changes to an icon's contents.
+    ///
+    /// This method is only available when the `serde` feature is enabled.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconElement, IconFamily, OSType};
+    /// let mut family = IconFamily::new();
+    /// family.push_element(IconElement::new(OSType(*b"icnV"), vec![]))
+    ///     .unwrap();
+    /// let manifest = family.to_json_manifest();
+    /// assert!(manifest.contains("\"ostype\": \"icnV\""));
+    /// assert!(manifest.contains("\"sha256\": \"e3b0c44298fc1c14"));
+    /// ```
+    pub fn to_json_manifest(&self) -> String {
+        let description = self.description();
+        let elements = description.elements
+            .iter()
+            .zip(self.elements.iter())
+            .map(|(desc, element)| {
+                ManifestEntry {
+                    ostype: desc.ostype.to_string(),
+                    offset: desc.offset,
+                    length: desc.length,
+                    format: desc.format.to_string(),
+                    width: desc.dimensions.map(|(width, _)| width),
+                    height: desc.dimensions.map(|(_, height)| height),
+                    sha256: hex(&Sha256::digest(&element.data)),
+                }
+            })
+            .collect();
+        let manifest = Manifest {
+            total_length: description.total_length,
+            elements,
+        };
+        serde_json::to_string_pretty(&manifest)
+            .expect("failed to serialize manifest")
+    }
+}
+
+/// Formats the given bytes as a lowercase hexadecimal string.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::icontype::IconType;
+    use super::super::image::{Image, PixelFormat};
+
+    #[test]
+    fn json_manifest() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGB, 16, 16);
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&family.to_json_manifest()).unwrap();
+        assert_eq!(manifest["total_length"], family.total_length());
+        let elements = manifest["elements"].as_array().unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0]["ostype"], "is32");
+        assert_eq!(elements[0]["format"], "RLE");
+        assert_eq!(elements[1]["ostype"], "s8mk");
+        assert_eq!(elements[1]["offset"],
+                   8 + family.elements[0].total_length());
+        assert_eq!(elements[1]["width"], 16);
+        assert_eq!(elements[1]["sha256"].as_str().unwrap().len(), 64);
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::icontype::IconType;
+use super::image::PixelFormat;
+use super::payload::PayloadFormat;
+
+impl IconFamily {
+    /// Rebuilds missing mask elements.  For each legacy RGB icon element
+    /// (such as `il32` or `ih32`) whose mask element (such as `l8mk` or
+    /// `h8mk`) is missing, a new mask is derived from the alpha channel of
+    /// the best available icon that carries its own alpha (a PNG or ARGB
+    /// icon): one of the same pixel size if there is one, or else the
+    /// nearest larger one (scaled down), or else the nearest smaller one
+    /// (scaled up).  If that icon can't be decoded, the next best one is
+    /// tried instead.  Each new mask element is inserted just after its
+    /// color element.  RGB icons for which no such source exists are left
+    /// alone.
+    ///
+    /// Returns the mask types that were added.  Returns an error if none of
+    /// the candidate source icons for a mask can be decoded, in which case
+    /// the family is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+    /// family.add_icon_without_mask(&image, IconType::RGB24_32x32).unwrap();
+    /// assert!(!family.has_icon_with_type(IconType::RGB24_32x32));
+    /// assert_eq!(family.regenerate_masks().unwrap(),
+    ///            vec![IconType::Mask8_32x32]);
+    /// assert!(family.has_icon_with_type(IconType::RGB24_32x32));
+    /// # }
+    /// ```
+    pub fn regenerate_masks(&mut self) -> io::Result<Vec<IconType>> {
+        let sources: Vec<IconType> = self.available_icons()
+            .into_iter()
+            .filter(|&icon_type| {
+                icon_type.mask_type().is_none() &&
+                self.find_element(icon_type)
+                    .map(|element| has_own_alpha(element.payload_format()))
+                    .unwrap_or(false)
+            })
+            .collect();
+        let mut masks = Vec::new();
+        for (index, element) in self.elements.iter().enumerate() {
+            let mask_type = match element.icon_type()
+                .and_then(IconType::mask_type) {
+                Some(mask_type) => mask_type,
+                None => continue,
+            };
+            let ostype = mask_type.ostype();
+            if self.find_element(mask_type).is_ok() ||
+               masks.iter().any(|entry: &(usize, IconElement)| {
+                entry.1.ostype == ostype
+            }) {
+                continue;
+            }
+            let width = mask_type.pixel_width();
+            let mut image = None;
+            let mut first_error = None;
+            for source in self.sources_for_size(width, true)
+                .into_iter()
+                .filter(|source| sources.contains(source)) {
+                match self.get_icon_with_type(source) {
+                    Ok(decoded) => {
+                        image = Some(decoded);
+                        break;
+                    }
+                    Err(error) => {
+                        first_error.get_or_insert(error);
+                    }
+                }
+            }
+            let mut image = match (image, first_error) {
+                (Some(image), _) => image,
+                (None, Some(error)) => return Err(error),
+                (None, None) => continue,
+            };
+            if image.width() != width ||
+               image.height() != mask_type.pixel_height() {
+                image = image.resize(width, mask_type.pixel_height());
+            }
+            let alpha = image.convert_to(PixelFormat::Alpha);
+            let mask = IconElement::encode_image_with_type(&alpha,
+                                                           mask_type)?;
+            masks.push((index, mask));
+        }
+        let added = masks.iter()
+            .filter_map(|entry| entry.1.icon_type())
+            .collect();
+        for (index, mask) in masks.into_iter().rev() {
+            self.elements.insert(index + 1, mask);
+        }
+        Ok(added)
+    }
+}
+
+/// Returns true if icon data in the given format carries its own alpha
+/// channel (rather than relying on a separate mask element).
+fn has_own_alpha(format: PayloadFormat) -> bool {
+    format == PayloadFormat::PNG || format == PayloadFormat::ARGB
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::test_image;
+
+    #[cfg(feature = "pngio")]
+    #[test]
+    fn regenerate_masks_from_png_alpha() {
+        let large = test_image(PixelFormat::RGBA, 64, 64);
+        let small = test_image(PixelFormat::RGBA, 16, 16);
+        let mut family = IconFamily::new();
+        family.add_icon_without_mask(&small, IconType::RGB24_16x16)
+            .unwrap();
+        family.add_icon_with_type(&large, IconType::RGBA32_64x64).unwrap();
+        family.add_icon_without_mask(&large.resize(32, 32),
+                                     IconType::RGB24_32x32)
+            .unwrap();
+        let added = family.regenerate_masks().unwrap();
+        assert_eq!(added, vec![IconType::Mask8_16x16, IconType::Mask8_32x32]);
+        let ostypes: Vec<String> = family.iter()
+            .map(|element| element.ostype.to_string())
+            .collect();
+        assert_eq!(ostypes, vec!["is32", "s8mk", "icp6", "il32", "l8mk"]);
+        let mask = family.get_mask_with_type(IconType::Mask8_32x32).unwrap();
+        let expected = large.resize(32, 32).convert_to(PixelFormat::Alpha);
+        assert_eq!(mask.data(), expected.data());
+        // Running it again has nothing left to do:
+        assert!(family.regenerate_masks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn masks_need_a_source_with_alpha() {
+        let image = test_image(PixelFormat::RGBA, 16, 16);
+        let mut family = IconFamily::new();
+        family.add_icon_without_mask(&image, IconType::RGB24_16x16).unwrap();
+        assert!(family.regenerate_masks().unwrap().is_empty());
+        assert_eq!(family.elements().len(), 1);
+    }
+}
@@ -0,0 +1,418 @@
+use std::io::{self, Read, Write};
+
+use super::element::IconElement;
+use super::family::{IconFamily, ReadLimits};
+use super::iconutil::{TOC_OSTYPE, iconutil_order, toc_element};
+use super::payload::PayloadFormat;
+use super::warning::{Warning, read_warnings};
+
+#[cfg(feature = "pngio")]
+use super::image::Image;
+#[cfg(feature = "pngio")]
+use super::pngcheck::png_with_chunks_from;
+
+/// Options controlling how an ICNS file is read by
+/// [`IconFamily::read_with`](struct.IconFamily.html#method.read_with),
+/// gathered in one place so that new reading behaviors don't each need
+/// their own `read_with_*` method.
+///
+/// By default, there are no limits, payloads aren't validated, and a file
+/// with inconsistent lengths is an error, as with [`IconFamily::read`](
+/// struct.IconFamily.html#method.read).  The one exception is a file that
+/// ends between two elements before reaching the length declared in its
+/// header: `read` rejects it, but `read_with` accepts it and reports a
+/// [`Warning::LengthSlack`](enum.Warning.html#variant.LengthSlack).
+///
+/// # Examples
+/// ```
+/// use icns::{IconFamily, ReadOptions};
+/// let options = ReadOptions::new()
+///     .max_elements(32)
+///     .max_element_size(4 * 1024 * 1024)
+///     .validate_payloads(true);
+/// let data: &[u8] = b"icns\0\0\0\x08";
+/// let (family, warnings) = IconFamily::read_with(data, &options).unwrap();
+/// assert!(family.is_empty());
+/// assert!(warnings.is_empty());
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReadOptions {
+    limits: ReadLimits,
+    recover: bool,
+}
+
+impl ReadOptions {
+    /// Creates a new set of options, with the default settings.
+    pub fn new() -> ReadOptions {
+        ReadOptions::default()
+    }
+
+    /// Replaces all of the structural limits at once.
+    pub fn limits(mut self, limits: ReadLimits) -> ReadOptions {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the maximum number of elements permitted in the icon family
+    /// (see [`ReadLimits::max_elements`](
+    /// struct.ReadLimits.html#method.max_elements)).
+    pub fn max_elements(mut self, max_elements: usize) -> ReadOptions {
+        self.limits = self.limits.max_elements(max_elements);
+        self
+    }
+
+    /// Sets the maximum length, in bytes, of any single element's data
+    /// payload (see [`ReadLimits::max_element_size`](
+    /// struct.ReadLimits.html#method.max_element_size)).
+    pub fn max_element_size(mut self, max_element_size: u32) -> ReadOptions {
+        self.limits = self.limits.max_element_size(max_element_size);
+        self
+    }
+
+    /// Sets whether every element payload should be validated as the file
+    /// is read (see [`ReadLimits::validate_payloads`](
+    /// struct.ReadLimits.html#method.validate_payloads)).
+    pub fn validate_payloads(mut self, validate: bool) -> ReadOptions {
+        self.limits = self.limits.validate_payloads(validate);
+        self
+    }
+
+    /// Sets whether to recover from untrustworthy length fields by
+    /// re-deriving element boundaries from their contents, as
+    /// [`IconFamily::repair`](struct.IconFamily.html#method.repair) does.
+    /// When this is enabled, the whole file is read into memory before the
+    /// limits are checked, so the limits no longer bound memory use.  This
+    /// is off by default.
+    pub fn recover(mut self, recover: bool) -> ReadOptions {
+        self.recover = recover;
+        self
+    }
+}
+
+impl IconFamily {
+    /// Reads an icon family from an ICNS file, using the given options, and
+    /// also returns a list of recoverable oddities found in the file (see
+    /// [`Warning`](enum.Warning.html)), in the order they occur.  Unlike
+    /// [`read`](#method.read), this accepts a file that ends between two
+    /// elements before reaching the length declared in its header (reporting
+    /// a `LengthSlack` warning).  Genuinely malformed files still result in
+    /// an error, unless the options allow
+    /// [recovering](struct.ReadOptions.html#method.recover) from them.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, ReadOptions};
+    /// // The element's length field is off by one:
+    /// let data: &[u8] = b"icns\0\0\0\x11quux\0\0\0\x0a#";
+    /// assert!(IconFamily::read_with(data, &ReadOptions::new()).is_err());
+    /// let options = ReadOptions::new().recover(true);
+    /// let (family, _) = IconFamily::read_with(data, &options).unwrap();
+    /// assert_eq!(family.elements()[0].data, b"#");
+    ///
+    /// let data: &[u8] = b"icns\0\0\0\x18abcd\0\0\0\x08abcd\0\0\0\x08";
+    /// let (family, warnings) =
+    ///     IconFamily::read_with(data, &ReadOptions::new()).unwrap();
+    /// assert_eq!(family.elements().len(), 2);
+    /// assert_eq!(warnings.len(), 3);
+    /// assert_eq!(warnings[2].to_string(),
+    ///            "element 1 duplicates type 'abcd' of element 0");
+    /// ```
+    pub fn read_with<R: Read>(reader: R,
+                              options: &ReadOptions)
+                              -> io::Result<(IconFamily, Vec<Warning>)> {
+        let (family, declared) = if options.recover {
+            let family = IconFamily::repair(reader)?;
+            options.limits.check(&family)?;
+            let declared = family.total_length();
+            (family, declared)
+        } else {
+            IconFamily::read_internal(reader,
+                                      &options.limits,
+                                      true,
+                                      &mut |_| {})?
+        };
+        let warnings = read_warnings(&family, declared);
+        Ok((family, warnings))
+    }
+}
+
+/// The order in which [`IconFamily::write_with`](
+/// struct.IconFamily.html#method.write_with) writes a family's elements.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum ElementOrder {
+    /// Write the elements in the order they appear in the family.
+    #[default]
+    Preserve,
+    /// Write the elements in the order that `iconutil -c icns` does (see
+    /// [`IconFamily::to_iconutil_layout`](
+    /// struct.IconFamily.html#method.to_iconutil_layout)), keeping only the
+    /// first element of each icon type that `iconutil` writes.
+    Iconutil,
+    /// Write icon elements from smallest to largest pixel width, followed by
+    /// any non-icon elements; elements of the same width keep their
+    /// relative order.
+    AscendingSize,
+}
+
+/// How hard to try to compress PNG data.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum PngCompression {
+    /// Compress quickly, at the expense of size.  This is the level used by
+    /// [`Image::write_png`](struct.Image.html#method.write_png).
+    #[default]
+    Fast,
+    /// A balance of speed and size.
+    Balanced,
+    /// Compress as well as possible, at the expense of speed.
+    Best,
+}
+
+/// Options controlling how an icon family is written by
+/// [`IconFamily::write_with`](struct.IconFamily.html#method.write_with),
+/// gathered in one place so that new output policies don't each need their
+/// own `write_with_*` method.  None of the options modify the family
+/// itself.
+///
+/// By default, the options are the same as for [`IconFamily::write`](
+/// struct.IconFamily.html#method.write): the elements are written as they
+/// are, in order.  (Which icon types and codecs are used to encode new
+/// icons is controlled by the family's [type preference](
+/// struct.IconFamily.html#method.set_type_preference) and [codecs](
+/// struct.IconFamily.html#method.set_codecs) when they are added, rather
+/// than when it is written.)
+///
+/// # Examples
+/// ```
+/// use icns::{ElementOrder, IconFamily, IconType, Image, OSType, PixelFormat,
+///            WriteOptions};
+/// let mut family = IconFamily::new();
+/// let image = Image::new(PixelFormat::RGBA, 32, 32);
+/// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+/// let image = Image::new(PixelFormat::RGBA, 16, 16);
+/// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+/// let options = WriteOptions::new()
+///     .toc(true)
+///     .order(ElementOrder::AscendingSize);
+/// let mut data = Vec::new();
+/// family.write_with(&mut data, &options).unwrap();
+/// let written = IconFamily::read(data.as_slice()).unwrap();
+/// let ostypes: Vec<OSType> = written.iter().map(|el| el.ostype).collect();
+/// assert_eq!(ostypes,
+///            vec![OSType(*b"TOC "), OSType(*b"is32"), OSType(*b"s8mk"),
+///                 OSType(*b"il32"), OSType(*b"l8mk")]);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WriteOptions {
+    toc: bool,
+    order: ElementOrder,
+    it32_prefix: Option<bool>,
+    png_compression: Option<PngCompression>,
+}
+
+impl WriteOptions {
+    /// Creates a new set of options, with the default settings.
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    /// Sets whether to write a `TOC ` (table of contents) element at the
+    /// start of the file, listing every other element.  If this is enabled,
+    /// any existing `TOC ` elements are replaced; otherwise, they are written
+    /// as they are.  This is off by default.
+    pub fn toc(mut self, toc: bool) -> WriteOptions {
+        self.toc = toc;
+        self
+    }
+
+    /// Sets the order in which to write the elements.  The default is
+    /// [`ElementOrder::Preserve`](enum.ElementOrder.html#variant.Preserve).
+    pub fn order(mut self, order: ElementOrder) -> WriteOptions {
+        self.order = order;
+        self
+    }
+
+    /// Makes sure that any `it32` element's data starts with the four zero
+    /// bytes that Apple's own tools write there (if `include` is true), or
+    /// that it doesn't (if `include` is false), as with
+    /// [`IconFamily::write_with_it32_prefix`](
+    /// struct.IconFamily.html#method.write_with_it32_prefix).  By default,
+    /// `it32` data is written as it is.
+    pub fn it32_prefix(mut self, include: bool) -> WriteOptions {
+        self.it32_prefix = Some(include);
+        self
+    }
+
+    /// Re-encodes each PNG payload at the given compression level, keeping
+    /// the result only if it is smaller than the original.  Color space and
+    /// metadata chunks (such as embedded color profiles, resolutions, and
+    /// text) are carried over to the re-encoding, but other ancillary chunks
+    /// are dropped.  Payloads that can't be decoded are written as they are.
+    /// By default, PNG payloads are written as they are.
+    ///
+    /// Writing with this option returns an error of kind `InvalidInput`
+    /// unless the `pngio` feature is enabled.
+    pub fn png_compression(mut self,
+                           compression: PngCompression)
+                           -> WriteOptions {
+        self.png_compression = Some(compression);
+        self
+    }
+}
+
+impl IconFamily {
+    /// Writes the icon family to an ICNS file, using the given options.
+    pub fn write_with<W: Write>(&self,
+                                writer: W,
+                                options: &WriteOptions)
+                                -> io::Result<()> {
+        let mut elements = match options.order {
+            ElementOrder::Preserve => self.elements.clone(),
+            ElementOrder::Iconutil => iconutil_order(&self.elements),
+            ElementOrder::AscendingSize => {
+                let mut elements = self.elements.clone();
+                elements.sort_by_key(|element| match element.icon_type() {
+                    Some(icon_type) => (false, icon_type.pixel_width()),
+                    None => (true, 0),
+                });
+                elements
+            }
+        };
+        if let Some(include) = options.it32_prefix {
+            for element in elements.iter_mut() {
+                if let Some(adjusted) = element.with_it32_prefix(include) {
+                    *element = adjusted;
+                }
+            }
+        }
+        if let Some(compression) = options.png_compression {
+            for element in elements.iter_mut() {
+                if element.payload_format() == PayloadFormat::PNG {
+                    recompress_png(element, compression)?;
+                }
+            }
+        }
+        if options.toc {
+            elements.retain(|element| element.ostype != TOC_OSTYPE);
+            let toc = toc_element(&elements);
+            elements.insert(0, toc);
+        }
+        IconFamily::from_elements(elements).write(writer)
+    }
+}
+
+/// Replaces the element's PNG payload with a re-encoding of it at the given
+/// compression level, if that is smaller.  The original's color space and
+/// metadata chunks (such as an embedded color profile) are kept.
+#[cfg(feature = "pngio")]
+pub(crate) fn recompress_png(element: &mut IconElement,
+                             compression: PngCompression)
+                             -> io::Result<()> {
+    let image = match Image::read_png(element.data.as_slice()) {
+        Ok(image) => image,
+        Err(_) => return Ok(()),
+    };
+    let mut data = Vec::new();
+    image.write_png_with_compression(&mut data, compression)?;
+    let data = match png_with_chunks_from(&element.data, &data) {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+    if data.len() < element.data.len() {
+        element.data = data;
+    }
+    Ok(())
+}
+
+/// Returns an error, since PNG payloads can't be re-encoded without the
+/// `pngio` feature.
+#[cfg(not(feature = "pngio"))]
+fn recompress_png(_element: &mut IconElement,
+                  _compression: PngCompression)
+                  -> io::Result<()> {
+    let msg = "recompressing PNG data requires the pngio feature";
+    Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::test_image;
+    use super::super::icontype::IconType;
+    use super::super::image::PixelFormat;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn limits_apply_after_recovery() {
+        let data: &[u8] = b"icns\0\0\0\x19quux\0\0\0\x0a#icnV\0\0\0\x08";
+        let options = ReadOptions::new().recover(true);
+        let (family, _) = IconFamily::read_with(data, &options).unwrap();
+        assert_eq!(family.elements().len(), 2);
+        let error = IconFamily::read_with(data, &options.max_elements(1))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = IconFamily::read_with(data, &options.max_element_size(0))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let options = ReadOptions::new()
+            .limits(ReadLimits::new().max_elements(1));
+        assert!(IconFamily::read_with(data, &options).is_err());
+    }
+
+    #[test]
+    fn write_with_layout_options() {
+        let mut family = IconFamily::new();
+        let image = test_image(PixelFormat::RGBA, 128, 128);
+        family.add_icon_with_type(&image, IconType::RGB24_128x128).unwrap();
+        let options = WriteOptions::new()
+            .toc(true)
+            .order(ElementOrder::Iconutil);
+        let mut data = Vec::new();
+        family.write_with(&mut data, &options).unwrap();
+        let mut expected = Vec::new();
+        family.write_iconutil_compatible(&mut expected).unwrap();
+        assert_eq!(data, expected);
+        let options = WriteOptions::new().it32_prefix(false);
+        let mut data = Vec::new();
+        family.write_with(&mut data, &options).unwrap();
+        let mut expected = Vec::new();
+        family.write_with_it32_prefix(&mut expected, false).unwrap();
+        assert_eq!(data, expected);
+        assert_eq!(data.len() + 4, family.total_length() as usize);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn write_with_png_compression() {
+        let image = test_image(PixelFormat::RGBA, 64, 64);
+        let mut png = Vec::new();
+        image.write_png_with_compression(&mut png, PngCompression::Fast)
+            .unwrap();
+        let family = IconFamily::from_elements(vec![
+            IconElement::new(IconType::RGBA32_64x64.ostype(), png.clone()),
+        ]);
+        let options = WriteOptions::new()
+            .png_compression(PngCompression::Best);
+        let mut data = Vec::new();
+        family.write_with(&mut data, &options).unwrap();
+        assert!(data.len() < family.total_length() as usize);
+        let written = IconFamily::read(data.as_slice()).unwrap();
+        assert_eq!(written.get_icon_with_type(IconType::RGBA32_64x64)
+                       .unwrap(),
+                   image);
+        // The family itself is left unchanged:
+        assert_eq!(family.elements()[0].data, png);
+    }
+
+    #[test]
+    #[cfg(not(feature = "pngio"))]
+    fn png_compression_requires_pngio() {
+        let family = IconFamily::from_elements(vec![
+            IconElement::new(IconType::RGBA32_64x64.ostype(),
+                             b"\x89PNG\r\n\x1a\n".to_vec()),
+        ]);
+        let options = WriteOptions::new()
+            .png_compression(PngCompression::Best);
+        let error = family.write_with(Vec::new(), &options).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,194 @@
+//! The standard color tables of the classic Mac OS, as used by the indexed
+//! legacy icon types (`icl4`/`ics4`/`ich4` and `icl8`/`ics8`/`ich8`).
+//!
+//! The 8-bit table consists of a 6x6x6 color cube (with each channel taking
+//! the values 0xFF, 0xCC, 0x99, 0x66, 0x33, and 0x00, and with red varying
+//! slowest), minus black; then ten-step ramps of pure red, green, blue, and
+//! gray, each running from 0xEE down to 0x11 (skipping the multiples of
+//! 0x33); and finally black.
+//!
+//! # Example
+//! ```
+//! use icns::palette;
+//! assert_eq!(palette::color_at(&palette::MAC_8BIT, 0), [0xFF, 0xFF, 0xFF]);
+//! assert_eq!(palette::color_at(&palette::MAC_8BIT, 255), [0, 0, 0]);
+//! assert_eq!(palette::index_of(&palette::MAC_4BIT, [0, 0, 0]), Some(15));
+//! assert_eq!(palette::nearest_index(&palette::MAC_4BIT, [250, 240, 10]), 1);
+//! ```
+
+use super::image::{Image, PixelFormat};
+
+/// The standard 16-color Mac OS system palette.
+pub const MAC_4BIT: [[u8; 3]; 16] = [[0xFF, 0xFF, 0xFF],
+                                     [0xFC, 0xF3, 0x05],
+                                     [0xFF, 0x64, 0x02],
+                                     [0xDD, 0x08, 0x06],
+                                     [0xF2, 0x08, 0x84],
+                                     [0x46, 0x00, 0xA5],
+                                     [0x00, 0x00, 0xD4],
+                                     [0x02, 0xAB, 0xEA],
+                                     [0x1F, 0xB7, 0x14],
+                                     [0x00, 0x64, 0x11],
+                                     [0x56, 0x2C, 0x05],
+                                     [0x90, 0x71, 0x3A],
+                                     [0xC0, 0xC0, 0xC0],
+                                     [0x80, 0x80, 0x80],
+                                     [0x40, 0x40, 0x40],
+                                     [0x00, 0x00, 0x00]];
+
+/// The standard 256-color Mac OS system palette.
+pub const MAC_8BIT: [[u8; 3]; 256] = mac_8bit_palette();
+
+/// The values of each channel in the 8-bit palette's ramps, in order:
+const RAMP_VALUES: [u8; 10] =
+    [0xEE, 0xDD, 0xBB, 0xAA, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+/// Builds the 256-color palette described in the module documentation.
+const fn mac_8bit_palette() -> [[u8; 3]; 256] {
+    let mut palette = [[0u8; 3]; 256];
+    let mut index = 0;
+    // The color cube (minus black, which is the last cube entry):
+    while index < 215 {
+        palette[index] = [0xFF - 0x33 * (index / 36) as u8,
+                          0xFF - 0x33 * ((index / 6) % 6) as u8,
+                          0xFF - 0x33 * (index % 6) as u8];
+        index += 1;
+    }
+    // The red, green, blue, and gray ramps:
+    let mut step = 0;
+    while step < RAMP_VALUES.len() {
+        let value = RAMP_VALUES[step];
+        palette[215 + step] = [value, 0, 0];
+        palette[225 + step] = [0, value, 0];
+        palette[235 + step] = [0, 0, value];
+        palette[245 + step] = [value, value, value];
+        step += 1;
+    }
+    // Index 255 is black, which it already is.
+    palette
+}
+
+/// Returns the color at the given index in the given palette.  Panics if the
+/// index is out of range for the palette.
+pub fn color_at(palette: &[[u8; 3]], index: u8) -> [u8; 3] {
+    palette[index as usize]
+}
+
+/// Returns the index of the first entry in the given palette exactly equal
+/// to the given color, or `None` if the palette doesn't contain the color.
+pub fn index_of(palette: &[[u8; 3]], color: [u8; 3]) -> Option<u8> {
+    palette.iter()
+        .position(|&entry| entry == color)
+        .map(|index| index as u8)
+}
+
+/// Returns the index of the entry in the given palette closest to the given
+/// color (by Euclidean distance in RGB space), preferring the lowest index in
+/// case of a tie.  Panics if the palette is empty or has more than 256
+/// entries.
+pub fn nearest_index(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    assert!(!palette.is_empty() && palette.len() <= 256,
+            "palette must have between 1 and 256 entries");
+    let distance = |entry: &[u8; 3]| -> u32 {
+        entry.iter()
+            .zip(color.iter())
+            .map(|(&a, &b)| {
+                let delta = u32::from(a.abs_diff(b));
+                delta * delta
+            })
+            .sum()
+    };
+    let mut best = 0;
+    for (index, entry) in palette.iter().enumerate().skip(1) {
+        if distance(entry) < distance(&palette[best]) {
+            best = index;
+        }
+    }
+    best as u8
+}
+
+/// Maps each pixel of the image to the index of the nearest color in the
+/// given palette (see [`nearest_index`](fn.nearest_index.html)), returning
+/// one index per pixel in row-major order.  If `alpha_cutoff` is given,
+/// pixels whose alpha is below the cutoff are mapped to index 0 (which is
+/// white, the background color, in both Mac system palettes) regardless of
+/// their color; the classic indexed icon types rely on a separate mask for
+/// transparency.  Panics if the palette is empty or has more than 256
+/// entries.
+///
+/// # Examples
+/// ```
+/// use icns::{Image, PixelFormat};
+/// use icns::palette::{self, MAC_4BIT};
+/// let image = Image::from_data(PixelFormat::RGBA, 3, 1,
+///                              vec![0, 0, 200, 255,
+///                                   10, 10, 10, 255,
+///                                   0, 0, 0, 0]).unwrap();
+/// assert_eq!(palette::map_image(&image, &MAC_4BIT, None), vec![6, 15, 15]);
+/// assert_eq!(palette::map_image(&image, &MAC_4BIT, Some(128)),
+///            vec![6, 15, 0]);
+/// ```
+pub fn map_image(image: &Image,
+                 palette: &[[u8; 3]],
+                 alpha_cutoff: Option<u8>)
+                 -> Vec<u8> {
+    let rgba = image.convert_to(PixelFormat::RGBA);
+    rgba.data()
+        .chunks(4)
+        .map(|pixel| match alpha_cutoff {
+            Some(cutoff) if pixel[3] < cutoff => 0,
+            _ => nearest_index(palette, [pixel[0], pixel[1], pixel[2]]),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mac_8bit_palette_entries() {
+        assert_eq!(MAC_8BIT[0], [0xFF, 0xFF, 0xFF]);
+        assert_eq!(MAC_8BIT[1], [0xFF, 0xFF, 0xCC]);
+        assert_eq!(MAC_8BIT[35], [0xFF, 0x00, 0x00]);
+        assert_eq!(MAC_8BIT[214], [0x00, 0x00, 0x33]);
+        assert_eq!(MAC_8BIT[215], [0xEE, 0x00, 0x00]);
+        assert_eq!(MAC_8BIT[234], [0x00, 0x11, 0x00]);
+        assert_eq!(MAC_8BIT[244], [0x00, 0x00, 0x11]);
+        assert_eq!(MAC_8BIT[245], [0xEE, 0xEE, 0xEE]);
+        assert_eq!(MAC_8BIT[255], [0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn palette_colors_are_distinct() {
+        for palette in [&MAC_4BIT[..], &MAC_8BIT[..]].iter() {
+            for (index, &color) in palette.iter().enumerate() {
+                assert_eq!(index_of(palette, color), Some(index as u8));
+                assert_eq!(color_at(palette, index as u8), color);
+            }
+        }
+        assert_eq!(index_of(&MAC_4BIT, [1, 2, 3]), None);
+    }
+
+    #[test]
+    fn nearest_palette_colors() {
+        for (index, &color) in MAC_8BIT.iter().enumerate() {
+            assert_eq!(nearest_index(&MAC_8BIT, color), index as u8);
+        }
+        assert_eq!(nearest_index(&MAC_8BIT, [0xFE, 0x01, 0x02]), 35);
+        assert_eq!(nearest_index(&MAC_4BIT, [0x30, 0x30, 0x30]), 14);
+        // Ties go to the lower index:
+        assert_eq!(nearest_index(&[[0, 0, 0], [2, 2, 2]], [1, 1, 1]), 0);
+    }
+
+    #[test]
+    fn map_gray_image() {
+        let image = Image::from_data(PixelFormat::GrayAlpha,
+                                     2,
+                                     1,
+                                     vec![0xFF, 0x10, 0x80, 0xFF])
+            .unwrap();
+        assert_eq!(map_image(&image, &MAC_4BIT, None), vec![0, 13]);
+        assert_eq!(map_image(&image, &MAC_8BIT, Some(0x20)), vec![0, 249]);
+    }
+}
@@ -0,0 +1,87 @@
+use rayon::prelude::*;
+use std::io;
+
+use super::family::{DuplicatePolicy, IconFamily};
+use super::icontype::IconType;
+use super::image::Image;
+
+impl IconFamily {
+    /// Like [`map_icons`](#method.map_icons), but decodes, transforms, and
+    /// re-encodes the icons in parallel (using rayon's global thread pool).
+    /// The results are the same as for `map_icons`; in particular, if an
+    /// error is returned, the family is left unchanged.
+    ///
+    /// This method is only available when the `rayon` feature is enabled.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 256, 256);
+    /// for &icon_type in &[IconType::RGBA32_256x256,
+    ///                     IconType::RGBA32_128x128_2x] {
+    ///     family.add_icon_with_type(&image, icon_type).unwrap();
+    /// }
+    /// family.par_map_icons(|_, image| image.flatten_onto([255, 255, 255]))
+    ///     .unwrap();
+    /// let icon = family.get_icon_with_type(IconType::RGBA32_256x256)
+    ///     .unwrap();
+    /// assert_eq!(&icon.data()[0..3], &[255, 255, 255]);
+    /// ```
+    pub fn par_map_icons<F>(&mut self, transform: F) -> io::Result<()>
+        where F: Fn(IconType, &Image) -> Image + Sync
+    {
+        let this = &*self;
+        let encoded = this.available_icons()
+            .into_par_iter()
+            .map(|icon_type| {
+                let image = this.get_icon_with_type(icon_type)?;
+                let mut family = this.empty_copy();
+                family.add_icon_with_type(&transform(icon_type, &image),
+                                          icon_type)?;
+                Ok(family.elements)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut updated = self.clone();
+        updated.set_duplicate_policy(DuplicatePolicy::Replace);
+        for elements in encoded {
+            updated.insert_elements(elements)?;
+        }
+        self.elements = updated.elements;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::test_image;
+    use super::super::image::PixelFormat;
+    use super::super::transform::BlendMode;
+
+    #[test]
+    fn par_map_matches_map() {
+        let image = test_image(PixelFormat::RGBA, 64, 64);
+        let mut family = IconFamily::new();
+        for &icon_type in &[IconType::RGB24_48x48,
+                            IconType::RGBA32_32x32_2x,
+                            IconType::RGBA32_16x16] {
+            let size = icon_type.pixel_width();
+            family.add_icon_with_type(&image.resize(size, size), icon_type)
+                .unwrap();
+        }
+        let transform = |_: IconType, image: &Image| {
+            image.tint([10, 200, 30], BlendMode::Screen)
+        };
+        let mut expected = family.clone();
+        expected.map_icons(transform).unwrap();
+        family.par_map_icons(transform).unwrap();
+        assert_eq!(family.elements.len(), expected.elements.len());
+        for (element, expected) in family.elements
+            .iter()
+            .zip(expected.elements.iter()) {
+            assert_eq!(element.ostype, expected.ostype);
+            assert_eq!(element.data, expected.data);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+
+use super::element::{ARGB_MAGIC, IconElement, JPEG_2000_FILE_MAGIC_NUMBER};
+use super::family::ICNS_MAGIC_LITERAL;
+use super::icontype::{Encoding, IconType, OSType};
+use super::pngcheck::PNG_SIGNATURE;
+use super::rle;
+
+/// The format of the data payload of an icon element, as detected by
+/// [`IconElement::payload_format`](
+/// struct.IconElement.html#method.payload_format).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PayloadFormat {
+    /// A PNG file.
+    PNG,
+    /// A JPEG 2000 file.
+    JPEG2000,
+    /// RLE-compressed 24-bit RGB data.
+    RLE24,
+    /// RLE-compressed 32-bit ARGB data, prefixed with `ARGB` (as used by the
+    /// `ic04` and `ic05` element types).
+    ARGB,
+    /// An uncompressed 8-bit alpha mask.
+    Mask8,
+    /// A nested icon family (as used by e.g. `tile` elements).
+    IconFamily,
+    /// Data in some other format (e.g. a `TOC ` or `icnV` element).
+    Unknown,
+}
+
+impl fmt::Display for PayloadFormat {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            PayloadFormat::PNG => "PNG",
+            PayloadFormat::JPEG2000 => "JPEG 2000",
+            PayloadFormat::RLE24 => "RLE",
+            PayloadFormat::ARGB => "ARGB",
+            PayloadFormat::Mask8 => "mask",
+            PayloadFormat::IconFamily => "icon family",
+            PayloadFormat::Unknown => "unknown",
+        };
+        out.write_str(name)
+    }
+}
+
+/// Compression statistics for a single icon element, as reported by
+/// [`IconElement::compression_info`](
+/// struct.IconElement.html#method.compression_info).
+///
+/// # Examples
+/// ```
+/// use icns::{IconElement, IconType, Image, PixelFormat, PayloadFormat};
+/// let image = Image::new(PixelFormat::RGBA, 16, 16);
+/// let element =
+///     IconElement::encode_image_with_type(&image, IconType::RGB24_16x16)
+///         .unwrap();
+/// let info = element.compression_info().unwrap();
+/// assert_eq!(info.format, PayloadFormat::RLE24);
+/// assert_eq!(info.decoded_size, 16 * 16 * 3);
+/// assert_eq!(info.to_string(), "is32: RLE, 12 bytes, 64.0:1");
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CompressionInfo {
+    /// The OSType of the element.
+    pub ostype: OSType,
+    /// The format of the element's data payload.
+    pub format: PayloadFormat,
+    /// The length of the element's data payload, in bytes.
+    pub compressed_size: usize,
+    /// The length of the element's pixel data once decoded, in bytes (three
+    /// bytes per pixel for RGB data, four for RGBA, or one for masks).
+    pub decoded_size: usize,
+}
+
+impl CompressionInfo {
+    /// Returns the compression ratio (the decoded size divided by the
+    /// compressed size).
+    pub fn ratio(&self) -> f64 {
+        self.decoded_size as f64 / self.compressed_size.max(1) as f64
+    }
+}
+
+impl fmt::Display for CompressionInfo {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out,
+               "{}: {}, {} bytes, {:.1}:1",
+               self.ostype,
+               self.format,
+               self.compressed_size,
+               self.ratio())
+    }
+}
+
+impl IconElement {
+    /// Detects the format of this element's data payload, by sniffing the
+    /// start of the data and falling back to the encoding implied by the
+    /// element's OSType.  This doesn't check that the payload is valid.
+    ///
+    /// Note that some files store RLE-compressed RGB or ARGB data in `icp4`
+    /// and `icp5` elements, rather than PNG or JPEG 2000 data; so for those
+    /// two element types, a payload that isn't PNG or JPEG 2000 data is
+    /// detected as `ARGB` if it starts with `ARGB`, or as `RLE24` if it is
+    /// well-formed RLE data of exactly the right size.  Any other payload
+    /// (such as PNG data with a damaged signature) is detected as
+    /// `Unknown`.
+    pub fn payload_format(&self) -> PayloadFormat {
+        if self.data.starts_with(&PNG_SIGNATURE) {
+            return PayloadFormat::PNG;
+        }
+        if self.data.starts_with(&JPEG_2000_FILE_MAGIC_NUMBER) {
+            return PayloadFormat::JPEG2000;
+        }
+        if self.data.starts_with(ICNS_MAGIC_LITERAL) {
+            return PayloadFormat::IconFamily;
+        }
+        let icon_type = self.icon_type();
+        if let Some(icon_type) = icon_type {
+            if icon_type == IconType::RGBA32_16x16 ||
+               icon_type == IconType::RGBA32_32x32 {
+                if self.data.starts_with(ARGB_MAGIC) {
+                    return PayloadFormat::ARGB;
+                }
+                let num_pixels = icon_type.pixel_width() *
+                                 icon_type.pixel_height();
+                if rle::is_well_formed(self.rle_data(),
+                                       3,
+                                       num_pixels as usize) {
+                    return PayloadFormat::RLE24;
+                }
+                return PayloadFormat::Unknown;
+            }
+        }
+        match icon_type.map(|icon_type| icon_type.encoding()) {
+            Some(Encoding::RLE24) => PayloadFormat::RLE24,
+            Some(Encoding::Mask8) => PayloadFormat::Mask8,
+            Some(Encoding::JP2PNG) | None => PayloadFormat::Unknown,
+        }
+    }
+
+    /// Returns compression statistics for this element, without decoding
+    /// it, or `None` if the element doesn't encode a supported icon type
+    /// (or its payload format can't be detected).  The decoded size is
+    /// computed from the element's icon type.
+    pub fn compression_info(&self) -> Option<CompressionInfo> {
+        let icon_type = self.icon_type()?;
+        let format = self.payload_format();
+        let bytes_per_pixel = match format {
+            PayloadFormat::PNG | PayloadFormat::JPEG2000 |
+            PayloadFormat::ARGB => 4,
+            PayloadFormat::RLE24 => 3,
+            PayloadFormat::Mask8 => 1,
+            PayloadFormat::IconFamily | PayloadFormat::Unknown => return None,
+        };
+        let num_pixels = icon_type.pixel_width() * icon_type.pixel_height();
+        Some(CompressionInfo {
+            ostype: self.ostype,
+            format,
+            compressed_size: self.data.len(),
+            decoded_size: num_pixels as usize * bytes_per_pixel,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::image::{Image, PixelFormat};
+
+    #[test]
+    fn payload_formats() {
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        let mask =
+            IconElement::encode_image_with_type(&image, IconType::Mask8_32x32)
+                .unwrap();
+        assert_eq!(mask.payload_format(), PayloadFormat::Mask8);
+        let mut jp2 = JPEG_2000_FILE_MAGIC_NUMBER.to_vec();
+        jp2.extend_from_slice(&[0; 16]);
+        let jp2 = IconElement::new(OSType(*b"ic08"), jp2);
+        assert_eq!(jp2.payload_format(), PayloadFormat::JPEG2000);
+        let argb = IconElement::new(OSType(*b"icp4"), b"ARGB".to_vec());
+        assert_eq!(argb.payload_format(), PayloadFormat::ARGB);
+        let rle = rle::encode(&[0; 3 * 32 * 32], 3, 32 * 32).unwrap();
+        let rle = IconElement::new(OSType(*b"icp5"), rle);
+        assert_eq!(rle.payload_format(), PayloadFormat::RLE24);
+        let junk = IconElement::new(OSType(*b"icp5"), vec![0; 8]);
+        assert_eq!(junk.payload_format(), PayloadFormat::Unknown);
+        let toc = IconElement::new(OSType(*b"TOC "), vec![0; 8]);
+        assert_eq!(toc.payload_format(), PayloadFormat::Unknown);
+        assert_eq!(toc.compression_info(), None);
+    }
+
+    #[test]
+    fn compression_info() {
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        let mask =
+            IconElement::encode_image_with_type(&image, IconType::Mask8_32x32)
+                .unwrap();
+        let info = mask.compression_info().unwrap();
+        assert_eq!(info.compressed_size, 1024);
+        assert_eq!(info.decoded_size, 1024);
+        assert_eq!(info.ratio(), 1.0);
+        let rle =
+            IconElement::encode_image_with_type(&image, IconType::RGB24_32x32)
+                .unwrap();
+        let info = rle.compression_info().unwrap();
+        assert_eq!(info.format, PayloadFormat::RLE24);
+        assert_eq!(info.decoded_size, 3072);
+        assert!(info.ratio() > 1.0);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn png_payloads() {
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        let png =
+            IconElement::encode_image_with_type(&image, IconType::RGBA32_32x32)
+                .unwrap();
+        assert_eq!(png.payload_format(), PayloadFormat::PNG);
+        let info = png.compression_info().unwrap();
+        assert_eq!(info.format, PayloadFormat::PNG);
+        assert_eq!(info.decoded_size, 4096);
+        assert!(info.ratio() > 1.0);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn png_with_damaged_signature() {
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        let mut png =
+            IconElement::encode_image_with_type(&image, IconType::RGBA32_16x16)
+                .unwrap();
+        png.data[1] = b'Q';
+        assert_eq!(png.payload_format(), PayloadFormat::Unknown);
+        let error = png.decode_image().unwrap_err();
+        assert!(error.to_string().contains("PNG"), "{}", error);
+        assert!(!error.to_string().contains("RLE"), "{}", error);
+    }
+}
@@ -0,0 +1,606 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::fmt;
+use std::io::{self, Error, ErrorKind};
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::icontype::OSType;
+
+/// The first eight bytes of a PNG file are always this:
+pub(crate) const PNG_SIGNATURE: [u8; 8] =
+    [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// The length of the data in a PNG `IHDR` chunk:
+pub(crate) const IHDR_LENGTH: usize = 13;
+
+/// The length of the data in a PNG `pHYs` chunk:
+#[cfg(feature = "pngio")]
+const PHYS_LENGTH: usize = 9;
+
+/// The `pHYs` unit specifier for pixels per meter:
+#[cfg(feature = "pngio")]
+const PHYS_UNIT_METER: u8 = 1;
+
+/// The resolution of a 1x icon, in dots per inch:
+#[cfg(feature = "pngio")]
+const BASE_DPI: u32 = 72;
+
+/// Types of the ancillary PNG chunks that describe an image's color space or
+/// carry metadata (rather than depending on how its pixels are encoded), and
+/// so still apply to a re-encoding of the same image:
+#[cfg(feature = "pngio")]
+const PORTABLE_CHUNK_TYPES: &[&[u8; 4]] = &[b"cHRM", b"cICP", b"eXIf",
+                                             b"gAMA", b"iCCP", b"iTXt",
+                                             b"pHYs", b"sRGB", b"tEXt",
+                                             b"tIME", b"zTXt"];
+
+/// The color type of a PNG image, as stored in its `IHDR` chunk.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PngColorType {
+    /// Grayscale, without alpha.
+    Gray,
+    /// RGB color, without alpha.
+    RGB,
+    /// Palette-indexed color.
+    Indexed,
+    /// Grayscale, with alpha.
+    GrayAlpha,
+    /// RGB color, with alpha.
+    RGBA,
+}
+
+/// The basic properties of a PNG image, read from its `IHDR` chunk without
+/// decoding the image data.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "pngio")] {
+/// use icns::{IconElement, IconType, Image, PixelFormat, PngColorType};
+/// let image = Image::new(PixelFormat::RGBA, 32, 32);
+/// let element =
+///     IconElement::encode_image_with_type(&image, IconType::RGBA32_16x16_2x)
+///         .unwrap();
+/// let header = element.png_header().unwrap();
+/// assert_eq!((header.width, header.height), (32, 32));
+/// assert_eq!(header.color_type, PngColorType::RGBA);
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PngHeader {
+    /// The width of the image, in pixels.
+    pub width: u32,
+    /// The height of the image, in pixels.
+    pub height: u32,
+    /// The number of bits per sample (or per palette index).
+    pub bit_depth: u8,
+    /// The color type of the image.
+    pub color_type: PngColorType,
+}
+
+impl PngHeader {
+    /// Reads the header of the PNG file at the start of the given data,
+    /// looking only at the PNG signature and the `IHDR` chunk that must
+    /// follow it.  Returns an error if the data doesn't start with a PNG
+    /// signature and a well-formed `IHDR` chunk.
+    pub fn parse(data: &[u8]) -> io::Result<PngHeader> {
+        if !data.starts_with(&PNG_SIGNATURE) {
+            let msg = "not PNG data (wrong signature)";
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let chunk = &data[PNG_SIGNATURE.len()..];
+        if chunk.len() < 8 + IHDR_LENGTH ||
+           BigEndian::read_u32(chunk) as usize != IHDR_LENGTH ||
+           &chunk[4..8] != b"IHDR" {
+            let msg = "PNG data does not start with a valid IHDR chunk";
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let ihdr = &chunk[8..];
+        let color_type = match ihdr[9] {
+            0 => PngColorType::Gray,
+            2 => PngColorType::RGB,
+            3 => PngColorType::Indexed,
+            4 => PngColorType::GrayAlpha,
+            6 => PngColorType::RGBA,
+            other => {
+                let msg = format!("invalid PNG color type: {}", other);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+        };
+        Ok(PngHeader {
+            width: BigEndian::read_u32(ihdr),
+            height: BigEndian::read_u32(&ihdr[4..]),
+            bit_depth: ihdr[8],
+            color_type,
+        })
+    }
+}
+
+/// A structural problem found in the PNG data payload of an icon element, as
+/// reported by [`IconElement::check_png_chunks`](
+/// struct.IconElement.html#method.check_png_chunks).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PngProblem {
+    /// The chunk starting at the given byte offset within the payload has a
+    /// CRC that doesn't match its contents.
+    BadCrc {
+        /// The four-byte type of the damaged chunk (e.g. `IDAT`).
+        chunk_type: [u8; 4],
+        /// The byte offset of the start of the chunk within the payload.
+        offset: usize,
+    },
+    /// The payload ends partway through the chunk starting at the given byte
+    /// offset, or (if the offset is the length of the payload) ends without
+    /// an `IEND` chunk.
+    Truncated {
+        /// The byte offset of the start of the incomplete chunk within the
+        /// payload.
+        offset: usize,
+    },
+}
+
+impl fmt::Display for PngProblem {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PngProblem::BadCrc { chunk_type, offset } => {
+                write!(out,
+                       "bad CRC in {} chunk at offset {}",
+                       String::from_utf8_lossy(&chunk_type),
+                       offset)
+            }
+            PngProblem::Truncated { offset } => {
+                write!(out, "PNG data truncated at offset {}", offset)
+            }
+        }
+    }
+}
+
+/// A problem with the PNG payload of one element in an icon family, as
+/// reported by [`IconFamily::png_diagnostics`](
+/// struct.IconFamily.html#method.png_diagnostics).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PngDiagnostic {
+    /// The index of the damaged element within the family.
+    pub index: usize,
+    /// The OSType of the damaged element.
+    pub ostype: OSType,
+    /// The problem found in the element's payload.
+    pub problem: PngProblem,
+}
+
+impl fmt::Display for PngDiagnostic {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out,
+               "element {} ('{}'): {}",
+               self.index,
+               self.ostype,
+               self.problem)
+    }
+}
+
+impl IconElement {
+    /// Reads the header of this element's data payload, if it is PNG data,
+    /// without decoding the image.  Returns `None` if the payload isn't PNG
+    /// data, or doesn't start with a valid PNG header.
+    pub fn png_header(&self) -> Option<PngHeader> {
+        PngHeader::parse(&self.data).ok()
+    }
+
+    /// Checks the chunk structure of this element's data payload, if it is
+    /// PNG data, without decoding the image.  Returns the first problem
+    /// found (a chunk with a bad CRC, or a truncated payload), or `None` if
+    /// the chunks are intact or the payload isn't PNG data at all.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconElement, IconType, Image, PixelFormat};
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// let mut element =
+    ///     IconElement::encode_image_with_type(&image, IconType::RGBA32_32x32)
+    ///         .unwrap();
+    /// assert_eq!(element.check_png_chunks(), None);
+    /// let length = element.data.len();
+    /// element.data.truncate(length - 1);
+    /// assert!(element.check_png_chunks().is_some());
+    /// # }
+    /// ```
+    pub fn check_png_chunks(&self) -> Option<PngProblem> {
+        if !self.data.starts_with(&PNG_SIGNATURE) {
+            return None;
+        }
+        let mut offset = PNG_SIGNATURE.len();
+        loop {
+            let chunk = &self.data[offset..];
+            if chunk.len() < 12 {
+                return Some(PngProblem::Truncated { offset });
+            }
+            let length = BigEndian::read_u32(chunk) as usize;
+            if chunk.len() - 12 < length {
+                return Some(PngProblem::Truncated { offset });
+            }
+            let mut chunk_type = [0u8; 4];
+            chunk_type.copy_from_slice(&chunk[4..8]);
+            let crc = BigEndian::read_u32(&chunk[(8 + length)..]);
+            if crc != crc32(&chunk[4..(8 + length)]) {
+                return Some(PngProblem::BadCrc { chunk_type, offset });
+            }
+            if &chunk_type == b"IEND" {
+                return None;
+            }
+            offset += 12 + length;
+        }
+    }
+}
+
+impl IconFamily {
+    /// Checks the chunk structure of every PNG-encoded element in the
+    /// family (as with [`IconElement::check_png_chunks`](
+    /// struct.IconElement.html#method.check_png_chunks)), and returns a
+    /// diagnostic for each damaged element.  Unlike decoding the icons, this
+    /// doesn't stop at the first damaged element, which makes it useful for
+    /// triaging damaged icon files.
+    pub fn png_diagnostics(&self) -> Vec<PngDiagnostic> {
+        self.elements
+            .iter()
+            .enumerate()
+            .filter_map(|(index, element)| {
+                element.check_png_chunks().map(|problem| {
+                    PngDiagnostic {
+                        index,
+                        ostype: element.ostype,
+                        problem,
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns the length of the PNG data at the start of the given bytes (that
+/// is, the offset just past the end of its `IEND` chunk), or `None` if the
+/// bytes don't start with complete PNG data.  Chunk CRCs are not checked.
+pub(crate) fn png_data_length(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+    let mut offset = PNG_SIGNATURE.len();
+    while data.len() - offset >= 12 {
+        let length = BigEndian::read_u32(&data[offset..]) as usize;
+        if data.len() - offset - 12 < length {
+            return None;
+        }
+        let chunk_type = &data[(offset + 4)..(offset + 8)];
+        offset += 12 + length;
+        if chunk_type == b"IEND" {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// Returns a copy of the given PNG data with a `pHYs` chunk declaring a
+/// resolution of 72 DPI times `scale` (e.g. 144 DPI for 2x "retina" icons),
+/// so that design tools display the image at the right size.  Any existing
+/// `pHYs` chunk is replaced.  Returns `None` if the data isn't complete PNG
+/// data starting with an `IHDR` chunk.
+#[cfg(feature = "pngio")]
+pub(crate) fn png_with_scale(data: &[u8], scale: u32) -> Option<Vec<u8>> {
+    let length = png_data_length(data)?;
+    let ihdr_end = PNG_SIGNATURE.len() + 12 + IHDR_LENGTH;
+    if data.get((PNG_SIGNATURE.len() + 4)..(PNG_SIGNATURE.len() + 8)) !=
+       Some(b"IHDR") || length < ihdr_end {
+        return None;
+    }
+    let pixels_per_meter =
+        (u64::from(BASE_DPI) * u64::from(scale) * 10_000 + 127) / 254;
+    let pixels_per_meter = pixels_per_meter.min(u64::from(u32::MAX));
+    let mut phys = [0u8; PHYS_LENGTH];
+    BigEndian::write_u32(&mut phys[0..4], pixels_per_meter as u32);
+    BigEndian::write_u32(&mut phys[4..8], pixels_per_meter as u32);
+    phys[8] = PHYS_UNIT_METER;
+    let mut output = Vec::with_capacity(length + 12 + PHYS_LENGTH);
+    output.extend_from_slice(&data[..ihdr_end]);
+    output.extend_from_slice(&(PHYS_LENGTH as u32).to_be_bytes());
+    let crc_start = output.len();
+    output.extend_from_slice(b"pHYs");
+    output.extend_from_slice(&phys);
+    let crc = crc32(&output[crc_start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+    let mut offset = ihdr_end;
+    while offset < length {
+        let chunk_length = BigEndian::read_u32(&data[offset..]) as usize;
+        let chunk_end = offset + 12 + chunk_length;
+        if &data[(offset + 4)..(offset + 8)] != b"pHYs" {
+            output.extend_from_slice(&data[offset..chunk_end]);
+        }
+        offset = chunk_end;
+    }
+    Some(output)
+}
+
+/// Returns a copy of the `reencoded` PNG data (a re-encoding of the same
+/// image as the `original` PNG data) with the original's color space and
+/// metadata chunks, such as an `iCCP` color profile or a `pHYs` resolution,
+/// inserted just after its `IHDR` chunk in place of any of its own.  Returns
+/// `None` if either isn't complete PNG data starting with an `IHDR` chunk.
+#[cfg(feature = "pngio")]
+pub(crate) fn png_with_chunks_from(original: &[u8],
+                                   reencoded: &[u8])
+                                   -> Option<Vec<u8>> {
+    let is_portable = |chunk: &[u8]| {
+        PORTABLE_CHUNK_TYPES.iter().any(|&chunk_type| {
+            &chunk[4..8] == chunk_type
+        })
+    };
+    let original = png_chunks(original)?;
+    let mut reencoded = png_chunks(reencoded)?.into_iter();
+    let mut output = PNG_SIGNATURE.to_vec();
+    output.extend_from_slice(reencoded.next()?);
+    for chunk in original.into_iter().filter(|chunk| is_portable(chunk)) {
+        output.extend_from_slice(chunk);
+    }
+    for chunk in reencoded.filter(|chunk| !is_portable(chunk)) {
+        output.extend_from_slice(chunk);
+    }
+    Some(output)
+}
+
+/// Splits complete PNG data into its chunks (each including its length,
+/// type, and CRC), or returns `None` if the data isn't complete PNG data
+/// starting with an `IHDR` chunk.
+#[cfg(feature = "pngio")]
+fn png_chunks(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let length = png_data_length(data)?;
+    if data.get((PNG_SIGNATURE.len() + 4)..(PNG_SIGNATURE.len() + 8)) !=
+       Some(b"IHDR") {
+        return None;
+    }
+    let mut chunks = Vec::new();
+    let mut offset = PNG_SIGNATURE.len();
+    while offset < length {
+        let chunk_length = BigEndian::read_u32(&data[offset..]) as usize;
+        let chunk_end = offset + 12 + chunk_length;
+        chunks.push(&data[offset..chunk_end]);
+        offset = chunk_end;
+    }
+    Some(chunks)
+}
+
+/// Returns the scale factor implied by the given PNG data's `pHYs` chunk
+/// (its resolution divided by 72 DPI, rounded to the nearest whole number),
+/// if it has one that specifies square pixels in physical units.
+#[cfg(feature = "pngio")]
+pub(crate) fn png_scale(data: &[u8]) -> Option<u32> {
+    let length = png_data_length(data)?;
+    let mut offset = PNG_SIGNATURE.len();
+    while offset < length {
+        let chunk_length = BigEndian::read_u32(&data[offset..]) as usize;
+        let chunk_type = &data[(offset + 4)..(offset + 8)];
+        if chunk_type == b"IDAT" {
+            break;
+        }
+        if chunk_type == b"pHYs" && chunk_length == PHYS_LENGTH {
+            let phys = &data[(offset + 8)..(offset + 8 + PHYS_LENGTH)];
+            let x = BigEndian::read_u32(&phys[0..4]);
+            let y = BigEndian::read_u32(&phys[4..8]);
+            if x != y || phys[8] != PHYS_UNIT_METER {
+                return None;
+            }
+            let base = u64::from(BASE_DPI) * 10_000;
+            let scale = (u64::from(x) * 254 + base / 2) / base;
+            return if scale == 0 { None } else { Some(scale as u32) };
+        }
+        offset += 12 + chunk_length;
+    }
+    None
+}
+
+/// Lookup table for `crc32`, indexed by the low byte of the running CRC.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// Computes the CRC-32 (as used by PNG) of the given bytes.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^
+              (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![0u8; 4];
+        BigEndian::write_u32(&mut chunk, data.len() as u32);
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(data);
+        let crc = crc32(&chunk[4..]);
+        chunk.extend_from_slice(&[0u8; 4]);
+        let length = chunk.len();
+        BigEndian::write_u32(&mut chunk[(length - 4)..], crc);
+        chunk
+    }
+
+    fn png_element(chunks: &[Vec<u8>]) -> IconElement {
+        let mut data = PNG_SIGNATURE.to_vec();
+        for chunk in chunks {
+            data.extend_from_slice(chunk);
+        }
+        IconElement::new(OSType(*b"ic07"), data)
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn png_scale_round_trip() {
+        let ihdr = chunk(b"IHDR", &[0, 0, 0, 32, 0, 0, 0, 32, 8, 6, 0, 0, 0]);
+        let idat = chunk(b"IDAT", &[]);
+        let iend = chunk(b"IEND", &[]);
+        let png = png_element(&[ihdr.clone(), idat.clone(), iend.clone()])
+            .data;
+        assert_eq!(png_scale(&png), None);
+        let retina = png_with_scale(&png, 2).unwrap();
+        assert_eq!(png_scale(&retina), Some(2));
+        assert_eq!(retina.len(), png.len() + 12 + PHYS_LENGTH);
+        assert_eq!(BigEndian::read_u32(&retina[41..]), 5669);
+        let element = IconElement::new(OSType(*b"ic11"), retina.clone());
+        assert_eq!(element.check_png_chunks(), None);
+        let normal = png_with_scale(&retina, 1).unwrap();
+        assert_eq!(normal.len(), retina.len());
+        assert_eq!(png_scale(&normal), Some(1));
+        assert_eq!(png_with_scale(b"not a png", 2), None);
+        let huge = png_with_scale(&png, u32::MAX).unwrap();
+        assert_eq!(BigEndian::read_u32(&huge[41..]), u32::MAX);
+        let unitless = png_element(&[ihdr,
+                                     chunk(b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 1,
+                                                      0]),
+                                     idat,
+                                     iend])
+            .data;
+        assert_eq!(png_scale(&unitless), None);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn png_chunks_carried_over() {
+        let ihdr = chunk(b"IHDR", &[0, 0, 0, 32, 0, 0, 0, 32, 8, 6, 0, 0, 0]);
+        let srgb = chunk(b"sRGB", &[0]);
+        let bkgd = chunk(b"bKGD", &[0, 0]);
+        let idat = chunk(b"IDAT", &[]);
+        let text = chunk(b"tEXt", b"Title\0Icon");
+        let iend = chunk(b"IEND", &[]);
+        let original = png_element(&[ihdr.clone(),
+                                     srgb.clone(),
+                                     bkgd,
+                                     idat.clone(),
+                                     text.clone(),
+                                     iend.clone()])
+            .data;
+        // The re-encoding's own gAMA chunk gives way to the original's
+        // color space chunks, but the bKGD chunk (whose format depends on
+        // the color type) isn't carried over:
+        let gama = chunk(b"gAMA", &[0, 0, 0xb1, 0x8f]);
+        let reencoded = png_element(&[ihdr.clone(),
+                                      gama,
+                                      idat.clone(),
+                                      iend.clone()])
+            .data;
+        let expected = png_element(&[ihdr, srgb, text, idat, iend]).data;
+        assert_eq!(png_with_chunks_from(&original, &reencoded),
+                   Some(expected));
+        assert_eq!(png_with_chunks_from(b"not a png", &reencoded), None);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn parse_png_header() {
+        let mut ihdr = [0u8; 13];
+        BigEndian::write_u32(&mut ihdr, 48);
+        BigEndian::write_u32(&mut ihdr[4..], 64);
+        ihdr[8] = 8;
+        ihdr[9] = 4;
+        let element = png_element(&[chunk(b"IHDR", &ihdr)]);
+        assert_eq!(element.png_header(),
+                   Some(PngHeader {
+                       width: 48,
+                       height: 64,
+                       bit_depth: 8,
+                       color_type: PngColorType::GrayAlpha,
+                   }));
+        ihdr[9] = 5;
+        let element = png_element(&[chunk(b"IHDR", &ihdr)]);
+        assert_eq!(element.png_header(), None);
+        let element = png_element(&[chunk(b"IEND", &[])]);
+        assert_eq!(element.png_header(), None);
+        assert!(PngHeader::parse(b"not a PNG").is_err());
+    }
+
+    #[test]
+    fn png_data_length() {
+        let mut data = png_element(&[chunk(b"IHDR", &[0; 13]),
+                                     chunk(b"IEND", &[])])
+            .data;
+        assert_eq!(super::png_data_length(&data), Some(45));
+        data.extend_from_slice(b"trailing");
+        assert_eq!(super::png_data_length(&data), Some(45));
+        assert_eq!(super::png_data_length(&data[..44]), None);
+        assert_eq!(super::png_data_length(b"not a PNG"), None);
+    }
+
+    #[test]
+    fn check_png_chunks() {
+        let good = png_element(&[chunk(b"IHDR", &[0; 13]),
+                                 chunk(b"IEND", &[])]);
+        assert_eq!(good.check_png_chunks(), None);
+        let mut bad = good.clone();
+        bad.data[8 + 8 + 5] ^= 1;
+        assert_eq!(bad.check_png_chunks(),
+                   Some(PngProblem::BadCrc {
+                       chunk_type: *b"IHDR",
+                       offset: 8,
+                   }));
+        let short = png_element(&[chunk(b"IHDR", &[0; 13])]);
+        assert_eq!(short.check_png_chunks(),
+                   Some(PngProblem::Truncated { offset: 33 }));
+        let mut cut = good.clone();
+        cut.data.truncate(20);
+        assert_eq!(cut.check_png_chunks(),
+                   Some(PngProblem::Truncated { offset: 8 }));
+        let not_png = IconElement::new(OSType(*b"ic07"), vec![1, 2, 3]);
+        assert_eq!(not_png.check_png_chunks(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn decode_damaged_png() {
+        let mut element = png_element(&[chunk(b"IHDR", &[0; 13]),
+                                        chunk(b"IEND", &[])]);
+        element.data[8 + 8 + 5] ^= 1;
+        let error = element.decode_image().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(),
+                   "'ic07' element contains damaged PNG data: bad CRC in \
+                    IHDR chunk at offset 8");
+    }
+
+    #[test]
+    fn png_diagnostics() {
+        let good = png_element(&[chunk(b"IEND", &[])]);
+        let mut bad = good.clone();
+        bad.ostype = OSType(*b"ic08");
+        bad.data.pop();
+        let family = IconFamily::from_elements(vec![good, bad.clone(), bad]);
+        let diagnostics = family.png_diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].to_string(),
+                   "element 1 ('ic08'): PNG data truncated at offset 8");
+        assert_eq!(diagnostics[1].index, 2);
+    }
+}
@@ -1,6 +1,98 @@
 use png;
+use std::fs;
 use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use family::{DuplicatePolicy, IconFamily};
 use image::{Image, PixelFormat};
+use options::PngCompression;
+use pngcheck::{png_scale, png_with_scale};
+
+impl IconFamily {
+    /// Creates a complete icon family from a single square master PNG file
+    /// that is at least 1024x1024 pixels.  See the
+    /// [`from_master_image`](#method.from_master_image) method for details.
+    pub fn from_master_png<R: Read>(input: R) -> io::Result<IconFamily> {
+        IconFamily::from_master_image(&Image::read_png(input)?)
+    }
+
+    /// Creates an icon family from all the PNG files in a directory, as
+    /// with [`add_png_directory`](#method.add_png_directory) on a new
+    /// family.
+    ///
+    /// Returns the icon family, along with the paths of any PNG files that
+    /// couldn't be placed in the family.  Returns an error only if the
+    /// directory itself can't be read.
+    pub fn from_png_directory<P>(dir: P)
+                                 -> io::Result<(IconFamily, Vec<PathBuf>)>
+        where P: AsRef<Path>
+    {
+        let mut family = IconFamily::new();
+        let skipped = family.add_png_directory(dir)?;
+        Ok((family, skipped))
+    }
+
+    /// Adds all the PNG files in a directory to the family, choosing an icon
+    /// type for each one based on its dimensions and the family's
+    /// [type preference](#method.set_type_preference) (as with
+    /// [`add_icon`](#method.add_icon)).  Files whose names end in `@2x.png`,
+    /// or whose `pHYs` chunk declares a resolution of 144 DPI (as written by
+    /// [`Image::write_png_with_scale`](
+    /// struct.Image.html#method.write_png_with_scale)), are treated as 2x
+    /// "retina" icons.  Files are processed in order of name, and files
+    /// without a `.png` extension are ignored.
+    ///
+    /// Returns the paths of any PNG files that couldn't be placed in the
+    /// family (because they couldn't be decoded, because no icon type has
+    /// their dimensions, or because the family or an earlier file already
+    /// used the same icon type).  Returns an error only if the directory
+    /// itself can't be read, in which case the family is left unchanged.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use icns::{IconFamily, TypePreference};
+    /// let mut family = IconFamily::new();
+    /// family.set_type_preference(TypePreference::ModernFirst);
+    /// let skipped = family.add_png_directory("AppIcon.iconset").unwrap();
+    /// assert!(skipped.is_empty());
+    /// ```
+    pub fn add_png_directory<P>(&mut self, dir: P) -> io::Result<Vec<PathBuf>>
+        where P: AsRef<Path>
+    {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_png = path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+            if is_png && path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        let policy = self.duplicate_policy();
+        self.set_duplicate_policy(DuplicatePolicy::Error);
+        let mut skipped = Vec::new();
+        for path in paths {
+            let named_retina = path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| stem.ends_with("@2x"));
+            let result = fs::read(&path)
+                .and_then(|data| {
+                    let retina = named_retina || png_scale(&data) == Some(2);
+                    Ok((Image::read_png(data.as_slice())?, retina))
+                })
+                .and_then(|(image, retina)| if retina {
+                    self.add_icon_with_density(&image, 2)
+                } else {
+                    self.add_icon(&image)
+                });
+            if result.is_err() {
+                skipped.push(path);
+            }
+        }
+        self.set_duplicate_policy(policy);
+        Ok(skipped)
+    }
+}
 
 impl Image {
 
@@ -37,6 +129,45 @@ impl Image {
 
     /// Writes the image to a PNG file.
     pub fn write_png<W: Write>(&self, output: W) -> io::Result<()> {
+        self.write_png_with_compression(output, PngCompression::Fast)
+    }
+
+    /// Writes the image to a PNG file, with a `pHYs` chunk declaring a
+    /// resolution of 72 DPI times `scale` (e.g. 144 DPI for a 2x "retina"
+    /// icon), so that design tools display the image at its size in points.
+    /// Returns an error if `scale` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let image = Image::new(PixelFormat::RGBA, 64, 64);
+    /// let mut data = Vec::new();
+    /// image.write_png_with_scale(&mut data, 2).unwrap();
+    /// let windows = data.windows(4).collect::<Vec<_>>();
+    /// assert!(windows.contains(&&b"pHYs"[..]));
+    /// ```
+    pub fn write_png_with_scale<W: Write>(&self,
+                                          mut output: W,
+                                          scale: u32)
+                                          -> io::Result<()> {
+        if scale == 0 {
+            let msg = "PNG scale factor must be at least 1";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let mut data = Vec::new();
+        self.write_png(&mut data)?;
+        let data = png_with_scale(&data, scale).ok_or_else(|| {
+            let msg = "failed to parse PNG data that was just written";
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        })?;
+        output.write_all(&data)
+    }
+
+    /// Writes the image to a PNG file, using the given compression level.
+    pub fn write_png_with_compression<W: Write>(&self,
+                                                output: W,
+                                                compression: PngCompression)
+                                                -> io::Result<()> {
         let color_type = match self.format {
             PixelFormat::RGBA => png::ColorType::RGBA,
             PixelFormat::RGB => png::ColorType::RGB,
@@ -44,12 +175,17 @@ impl Image {
             PixelFormat::Gray => png::ColorType::Grayscale,
             PixelFormat::Alpha => {
                 return self.convert_to(PixelFormat::GrayAlpha)
-                    .write_png(output);
+                    .write_png_with_compression(output, compression);
             }
         };
         let mut encoder = png::Encoder::new(output, self.width, self.height);
         encoder.set_color(color_type);
         encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(match compression {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Balanced => png::Compression::Default,
+            PngCompression::Best => png::Compression::Best,
+        });
         let mut writer = encoder.write_header()?;
         writer.write_image_data(&self.data).map_err(|err| match err {
             png::EncodingError::IoError(err) => err,
@@ -59,3 +195,66 @@ impl Image {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use family::TypePreference;
+    use fixtures::TempDir;
+    use icontype::IconType;
+    use std::fs::File;
+
+    fn write_png(dir: &Path, name: &str, size: u32) {
+        let image = Image::new(PixelFormat::RGBA, size, size);
+        image.write_png(File::create(dir.join(name)).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn icon_family_from_png_directory() {
+        let temp = TempDir::new("png-directory");
+        let dir = temp.path();
+        write_png(dir, "a.png", 16);
+        write_png(dir, "b@2x.png", 32);
+        write_png(dir, "c.PNG", 32);
+        write_png(dir, "d.png", 16);
+        write_png(dir, "e.png", 20);
+        let image = Image::new(PixelFormat::RGBA, 64, 64);
+        image.write_png_with_scale(File::create(dir.join("g.png")).unwrap(),
+                                   2)
+            .unwrap();
+        let error = image.write_png_with_scale(io::sink(), 0).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        fs::write(dir.join("f.png"), b"not a png").unwrap();
+        fs::write(dir.join("readme.txt"), b"hello").unwrap();
+        let (family, skipped) = IconFamily::from_png_directory(dir).unwrap();
+        assert_eq!(family.available_icons(),
+                   vec![IconType::RGB24_16x16,
+                        IconType::RGBA32_16x16_2x,
+                        IconType::RGB24_32x32,
+                        IconType::RGBA32_32x32_2x]);
+        assert_eq!(family.duplicate_policy(), DuplicatePolicy::Append);
+        assert_eq!(skipped,
+                   vec![dir.join("d.png"),
+                        dir.join("e.png"),
+                        dir.join("f.png")]);
+    }
+
+    #[test]
+    fn add_png_directory_uses_type_preference() {
+        let temp = TempDir::new("add-png-directory");
+        let dir = temp.path();
+        write_png(dir, "icon_16x16.png", 16);
+        write_png(dir, "icon_16x16@2x.png", 32);
+        write_png(dir, "icon_32x32.png", 32);
+        let mut family = IconFamily::new();
+        family.set_type_preference(TypePreference::ModernFirst);
+        family.set_duplicate_policy(DuplicatePolicy::Replace);
+        let skipped = family.add_png_directory(dir).unwrap();
+        assert!(skipped.is_empty());
+        assert_eq!(family.available_icons(),
+                   vec![IconType::RGBA32_16x16,
+                        IconType::RGBA32_16x16_2x,
+                        IconType::RGBA32_32x32]);
+        assert_eq!(family.duplicate_policy(), DuplicatePolicy::Replace);
+    }
+}
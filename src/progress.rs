@@ -0,0 +1,30 @@
+use super::icontype::OSType;
+
+/// A progress notification from a potentially long-running operation on an
+/// icon family, such as
+/// [`IconFamily::read_with_progress`](
+/// struct.IconFamily.html#method.read_with_progress).  Each element that is
+/// processed produces an `ElementStarted` notification followed (if
+/// successful) by an `ElementFinished` notification.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Progress {
+    /// Work on an element has started.
+    ElementStarted {
+        /// The index of the element within the family.
+        index: usize,
+        /// The total number of elements to be processed, if known in advance.
+        elements_total: Option<usize>,
+    },
+    /// Work on an element has finished.
+    ElementFinished {
+        /// The index of the element within the family.
+        index: usize,
+        /// The OSType of the element.
+        ostype: OSType,
+        /// The total number of bytes read, written, or encoded so far
+        /// (including file and element headers).
+        bytes_processed: u64,
+        /// The total number of bytes to be processed, if known in advance.
+        bytes_total: Option<u64>,
+    },
+}
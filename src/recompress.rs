@@ -0,0 +1,136 @@
+use std::mem;
+
+use super::family::IconFamily;
+use super::icontype::IconType;
+use super::payload::PayloadFormat;
+use super::pngcheck::PngHeader;
+
+impl IconFamily {
+    /// Passes the PNG data of each PNG-encoded icon element in the family to
+    /// the `compressor` callback (e.g. one that runs an external PNG
+    /// optimizer), and replaces the element's data with the returned bytes
+    /// if they are smaller.  The callback can return `None` to leave an
+    /// element unchanged.  Returns the total number of bytes saved.
+    ///
+    /// To guard against a misbehaving compressor, a result is only used if
+    /// it is well-formed PNG data (with intact chunk checksums) with the
+    /// same dimensions as the original.  Since element and file lengths are
+    /// computed when the family is written, they remain consistent.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::IconFamily;
+    /// let mut family = IconFamily::new();
+    /// // ...
+    /// let saved = family.recompress_with(|icon_type, png_data| {
+    ///     println!("{:?}: {} bytes", icon_type, png_data.len());
+    ///     None  // e.g. run zopflipng on `png_data` here instead
+    /// });
+    /// assert_eq!(saved, 0);
+    /// ```
+    pub fn recompress_with<F>(&mut self, mut compressor: F) -> u64
+        where F: FnMut(IconType, &[u8]) -> Option<Vec<u8>>
+    {
+        let mut saved = 0;
+        for element in self.elements.iter_mut() {
+            let icon_type = match element.icon_type() {
+                Some(icon_type) => icon_type,
+                None => continue,
+            };
+            if element.payload_format() != PayloadFormat::PNG {
+                continue;
+            }
+            let original = match element.png_header() {
+                Some(header) => header,
+                None => continue,
+            };
+            let compressed = match compressor(icon_type, &element.data) {
+                Some(data) => data,
+                None => continue,
+            };
+            if compressed.len() >= element.data.len() {
+                continue;
+            }
+            match PngHeader::parse(&compressed) {
+                Ok(ref header) if header.width == original.width &&
+                                  header.height == original.height => {}
+                _ => continue,
+            }
+            let old_data = mem::replace(&mut element.data, compressed);
+            if element.check_png_chunks().is_some() {
+                element.data = old_data;
+                continue;
+            }
+            saved += (old_data.len() - element.data.len()) as u64;
+        }
+        saved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::FixtureBuilder;
+    use super::super::icontype::OSType;
+    use super::super::pngcheck::{PNG_SIGNATURE, crc32};
+
+    /// Returns a minimal PNG-like payload (signature, IHDR, and IEND) of the
+    /// given dimensions, padded with an unknown chunk of the given length.
+    fn png_data(size: u32, padding: usize) -> Vec<u8> {
+        let mut ihdr = size.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&size.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut data = PNG_SIGNATURE.to_vec();
+        for &(chunk_type, ref body) in &[(b"IHDR", ihdr),
+                                        (b"zzZz", vec![0; padding]),
+                                        (b"IEND", vec![])] {
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            let start = data.len();
+            data.extend_from_slice(chunk_type);
+            data.extend_from_slice(body);
+            let crc = crc32(&data[start..]);
+            data.extend_from_slice(&crc.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn recompress_png_elements() {
+        let mut family = FixtureBuilder::new()
+            .element(OSType(*b"ic11"), png_data(32, 100))
+            .element(OSType(*b"ic12"), png_data(64, 100))
+            .version_element()
+            .family();
+        let mut seen = Vec::new();
+        let saved = family.recompress_with(|icon_type, data| {
+            seen.push(icon_type);
+            match icon_type {
+                // A smaller, valid result is used:
+                IconType::RGBA32_16x16_2x => Some(png_data(32, 10)),
+                // A result with the wrong dimensions is ignored:
+                _ => Some(png_data(16, 0)),
+            }
+            .filter(|new_data| new_data.len() < data.len())
+        });
+        assert_eq!(seen,
+                   vec![IconType::RGBA32_16x16_2x, IconType::RGBA32_32x32_2x]);
+        assert_eq!(saved, 90);
+        assert_eq!(family.elements[0].data, png_data(32, 10));
+        assert_eq!(family.elements[1].data, png_data(64, 100));
+    }
+
+    #[test]
+    fn recompress_rejects_damaged_png() {
+        let mut family = FixtureBuilder::new()
+            .element(OSType(*b"ic11"), png_data(32, 100))
+            .family();
+        let saved = family.recompress_with(|_, _| {
+            let mut data = png_data(32, 10);
+            let last = data.len() - 1;
+            data[last] ^= 1;
+            Some(data)
+        });
+        assert_eq!(saved, 0);
+        assert_eq!(family.elements[0].data, png_data(32, 100));
+    }
+}
@@ -0,0 +1,153 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::io::{self, Error, ErrorKind, Read};
+
+use super::element::IconElement;
+use super::family::{ICNS_MAGIC_LITERAL, ICON_FAMILY_HEADER_LENGTH,
+                    IconFamily};
+use super::icontype::{ALL_ICON_TYPES, OSType};
+use super::kind::is_known_ostype;
+use super::pngcheck::png_data_length;
+
+/// The length of an icon element header, in bytes:
+pub(crate) const ELEMENT_HEADER_LENGTH: usize = 8;
+
+impl IconFamily {
+    /// Reads an ICNS file whose length fields can't be trusted (such as
+    /// those produced by buggy tools that write off-by-N element lengths),
+    /// re-deriving the boundaries of each element from its contents.  The
+    /// file length in the header is ignored; PNG payloads are measured by
+    /// walking their chunks; and when an element's stated length doesn't
+    /// lead to the start of another element (or to the end of the file),
+    /// the nearest position that looks like the start of an element is
+    /// used instead.  Writing out the resulting family produces a
+    /// well-formed ICNS file.  Returns an error if the data doesn't start
+    /// with an ICNS header at all.
+    pub fn repair<R: Read>(mut reader: R) -> io::Result<IconFamily> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if !data.starts_with(ICNS_MAGIC_LITERAL) ||
+           data.len() < ICON_FAMILY_HEADER_LENGTH as usize {
+            let msg = "not an icns file (wrong magic literal)";
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let mut family = IconFamily::new();
+        let mut position = ICON_FAMILY_HEADER_LENGTH as usize;
+        while data.len() - position >= ELEMENT_HEADER_LENGTH {
+            let mut raw_ostype = [0u8; 4];
+            raw_ostype.copy_from_slice(&data[position..(position + 4)]);
+            let stated_length =
+                BigEndian::read_u32(&data[(position + 4)..]) as usize;
+            let start = position + ELEMENT_HEADER_LENGTH;
+            let end = element_end(&data, start, position + stated_length);
+            family.elements
+                .push(IconElement::new(OSType(raw_ostype),
+                                       data[start..end].to_vec()));
+            position = end;
+        }
+        Ok(family)
+    }
+}
+
+/// Returns the most likely end position of the element whose payload begins
+/// at `start`, given the end position implied by its length field.
+fn element_end(data: &[u8], start: usize, stated_end: usize) -> usize {
+    let is_boundary = |end: usize| {
+        end == data.len() || is_element_header(data, end)
+    };
+    if let Some(length) = png_data_length(&data[start..]) {
+        if is_boundary(start + length) {
+            return start + length;
+        }
+    }
+    if stated_end >= start && stated_end <= data.len() &&
+       is_boundary(stated_end) {
+        return stated_end;
+    }
+    (start..data.len())
+        .filter(|&position| is_element_header(data, position))
+        .min_by_key(|&position| position.abs_diff(stated_end))
+        .unwrap_or(data.len())
+}
+
+/// Returns true if the bytes at the given position look like the header of
+/// an icon element: a known OSType, followed by a plausible length.
+fn is_element_header(data: &[u8], position: usize) -> bool {
+    if data.len() - position < ELEMENT_HEADER_LENGTH {
+        return false;
+    }
+    let raw_ostype = &data[position..(position + 4)];
+    let is_icon_type = ALL_ICON_TYPES.iter()
+        .any(|icon_type| &icon_type.ostype().0[..] == raw_ostype);
+    let is_other_type = is_known_ostype(OSType([raw_ostype[0],
+                                                raw_ostype[1],
+                                                raw_ostype[2],
+                                                raw_ostype[3]]));
+    let length = BigEndian::read_u32(&data[(position + 4)..]) as usize;
+    (is_icon_type || is_other_type) && length >= ELEMENT_HEADER_LENGTH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::FixtureBuilder;
+    use super::super::icontype::IconType;
+    use std::io::Cursor;
+
+    fn sample_family() -> IconFamily {
+        let builder = FixtureBuilder::new().icon(IconType::RGB24_32x32);
+        #[cfg(feature = "pngio")]
+        let builder = builder.icon(IconType::RGBA32_16x16_2x);
+        builder.element(OSType(*b"icnV"), vec![0, 0, 0, 1]).family()
+    }
+
+    fn element_offsets(family: &IconFamily) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let mut position = ICON_FAMILY_HEADER_LENGTH as usize;
+        for element in &family.elements {
+            offsets.push(position);
+            position += element.total_length() as usize;
+        }
+        offsets
+    }
+
+    #[test]
+    fn repair_intact_file() {
+        let family = sample_family();
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        let repaired = IconFamily::repair(Cursor::new(&data)).unwrap();
+        let mut rewritten = Vec::new();
+        repaired.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, data);
+    }
+
+    #[test]
+    fn repair_off_by_n_lengths() {
+        let family = sample_family();
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        let offsets = element_offsets(&family);
+        // Corrupt the file length, and every element length.
+        BigEndian::write_u32(&mut data[4..], 12345);
+        for (&offset, delta) in offsets.iter().zip([3i64, -2, 1].iter()) {
+            let length = BigEndian::read_u32(&data[(offset + 4)..]) as i64;
+            BigEndian::write_u32(&mut data[(offset + 4)..],
+                                 (length + delta) as u32);
+        }
+        assert!(IconFamily::read(Cursor::new(&data)).is_err());
+        let repaired = IconFamily::repair(Cursor::new(&data)).unwrap();
+        assert_eq!(repaired.elements.len(), family.elements.len());
+        for (repaired, original) in repaired.elements
+            .iter()
+            .zip(family.elements.iter()) {
+            assert_eq!(repaired.ostype, original.ostype);
+            assert_eq!(repaired.data, original.data);
+        }
+    }
+
+    #[test]
+    fn repair_non_icns_data() {
+        let error = IconFamily::repair(Cursor::new(b"PNG data")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,216 @@
+use super::image::{Image, PixelFormat};
+
+impl Image {
+    /// Creates a copy of this image resampled to the given dimensions, in the
+    /// same pixel format.  Resampling uses a tent filter whose width grows
+    /// with the reduction factor, so that when shrinking an image every
+    /// source pixel contributes to the result; color channels are weighted by
+    /// alpha, so that fully transparent pixels don't bleed their (invisible)
+    /// color into their neighbors.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let image = Image::new(PixelFormat::RGBA, 1024, 1024);
+    /// let small = image.resize(32, 32);
+    /// assert_eq!(small.width(), 32);
+    /// assert_eq!(small.height(), 32);
+    /// assert_eq!(small.pixel_format(), PixelFormat::RGBA);
+    /// ```
+    pub fn resize(&self, width: u32, height: u32) -> Image {
+        if width == self.width && height == self.height {
+            return self.clone();
+        }
+        let mut output = Image::new(self.format, width, height);
+        if self.width == 0 || self.height == 0 || width == 0 || height == 0 {
+            return output;
+        }
+        let channels = (self.format.bits_per_pixel() / 8) as usize;
+        let pixels: Vec<f32> =
+            self.data.iter().map(|&value| f32::from(value)).collect();
+        let resampled = resample(pixels,
+                                 channels,
+                                 alpha_channel(self.format),
+                                 255.0,
+                                 (self.width as usize, self.height as usize),
+                                 (width as usize, height as usize));
+        for (byte, &value) in output.data.iter_mut().zip(resampled.iter()) {
+            *byte = value.round().clamp(0.0, 255.0) as u8;
+        }
+        output
+    }
+}
+
+/// Resamples pixel data with `channels` channels per pixel (including, if
+/// `alpha` is given, an alpha channel at that index, whose fully opaque value
+/// is `opaque`) from `src` to `dst` dimensions, as described for
+/// [`Image::resize`](struct.Image.html#method.resize).  Neither dimension of
+/// either size may be zero.
+pub(crate) fn resample(mut pixels: Vec<f32>,
+                       channels: usize,
+                       alpha: Option<usize>,
+                       opaque: f32,
+                       src: (usize, usize),
+                       dst: (usize, usize))
+                       -> Vec<f32> {
+    if let Some(alpha) = alpha {
+        premultiply(&mut pixels, channels, alpha, opaque);
+    }
+    let (src_width, src_height) = src;
+    let (dst_width, dst_height) = dst;
+    // Resample horizontally, then vertically.
+    let mut horizontal = vec![0f32; dst_width * src_height * channels];
+    let weights = filter_weights(src_width, dst_width);
+    for row in 0..src_height {
+        for (col, &(start, ref taps)) in weights.iter().enumerate() {
+            let out = (row * dst_width + col) * channels;
+            for (tap, &weight) in taps.iter().enumerate() {
+                let src = (row * src_width + start + tap) * channels;
+                for channel in 0..channels {
+                    horizontal[out + channel] +=
+                        weight * pixels[src + channel];
+                }
+            }
+        }
+    }
+    let mut vertical = vec![0f32; dst_width * dst_height * channels];
+    let weights = filter_weights(src_height, dst_height);
+    for (row, &(start, ref taps)) in weights.iter().enumerate() {
+        for col in 0..dst_width {
+            let out = (row * dst_width + col) * channels;
+            for (tap, &weight) in taps.iter().enumerate() {
+                let src = ((start + tap) * dst_width + col) * channels;
+                for channel in 0..channels {
+                    vertical[out + channel] +=
+                        weight * horizontal[src + channel];
+                }
+            }
+        }
+    }
+    if let Some(alpha) = alpha {
+        unpremultiply(&mut vertical, channels, alpha, opaque);
+    }
+    vertical
+}
+
+/// Returns the index of the alpha channel within each pixel of the given
+/// format, if the format has color channels as well as alpha.
+fn alpha_channel(format: PixelFormat) -> Option<usize> {
+    match format {
+        PixelFormat::RGBA => Some(3),
+        PixelFormat::GrayAlpha => Some(1),
+        PixelFormat::RGB | PixelFormat::Gray | PixelFormat::Alpha => None,
+    }
+}
+
+/// Multiplies the color channels of each pixel by the pixel's alpha (as a
+/// fraction of the fully opaque value).
+fn premultiply(pixels: &mut [f32],
+               channels: usize,
+               alpha: usize,
+               opaque: f32) {
+    for pixel in pixels.chunks_mut(channels) {
+        let factor = pixel[alpha] / opaque;
+        for (channel, value) in pixel.iter_mut().enumerate() {
+            if channel != alpha {
+                *value *= factor;
+            }
+        }
+    }
+}
+
+/// Divides the color channels of each pixel by the pixel's alpha (as a
+/// fraction of the fully opaque value).
+fn unpremultiply(pixels: &mut [f32],
+                 channels: usize,
+                 alpha: usize,
+                 opaque: f32) {
+    for pixel in pixels.chunks_mut(channels) {
+        let factor = if pixel[alpha] > 0.0 {
+            opaque / pixel[alpha]
+        } else {
+            0.0
+        };
+        for (channel, value) in pixel.iter_mut().enumerate() {
+            if channel != alpha {
+                *value *= factor;
+            }
+        }
+    }
+}
+
+/// Computes, for each destination pixel along one axis, the index of the
+/// first contributing source pixel and the (normalized) weights of each
+/// contributing source pixel.
+fn filter_weights(src_len: usize, dst_len: usize) -> Vec<(usize, Vec<f32>)> {
+    let scale = src_len as f32 / dst_len as f32;
+    let support = scale.max(1.0);
+    (0..dst_len)
+        .map(|index| {
+            let center = (index as f32 + 0.5) * scale - 0.5;
+            let first = ((center - support).ceil().max(0.0)) as usize;
+            let last = ((center + support).floor() as usize).min(src_len - 1);
+            let first = first.min(last);
+            let mut taps: Vec<f32> = (first..(last + 1))
+                .map(|src| {
+                    let distance = (src as f32 - center).abs() / support;
+                    (1.0 - distance).max(0.0)
+                })
+                .collect();
+            let total: f32 = taps.iter().sum();
+            if total > 0.0 {
+                for tap in taps.iter_mut() {
+                    *tap /= total;
+                }
+            } else {
+                let nearest = (center.round().max(0.0) as usize)
+                    .clamp(first, last);
+                for (offset, tap) in taps.iter_mut().enumerate() {
+                    *tap = if first + offset == nearest { 1.0 } else { 0.0 };
+                }
+            }
+            (first, taps)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::image::{Image, PixelFormat};
+
+    #[test]
+    fn resize_same_size() {
+        let data: Vec<u8> = vec![1, 2, 3, 4];
+        let image = Image::from_data(PixelFormat::Gray, 2, 2, data.clone())
+            .unwrap();
+        assert_eq!(image.resize(2, 2).data(), &data as &[u8]);
+    }
+
+    #[test]
+    fn downscale_averages_pixels() {
+        let data: Vec<u8> = vec![10, 30, 50, 70];
+        let image = Image::from_data(PixelFormat::Gray, 2, 2, data).unwrap();
+        let small = image.resize(1, 1);
+        assert_eq!(small.data(), &[40]);
+    }
+
+    #[test]
+    fn downscale_ignores_transparent_color() {
+        let data: Vec<u8> = vec![255, 0, 0, 255, 0, 255, 0, 0];
+        let image = Image::from_data(PixelFormat::RGBA, 2, 1, data).unwrap();
+        let small = image.resize(1, 1);
+        assert_eq!(small.data(), &[255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn upscale_solid_color() {
+        let data: Vec<u8> = vec![10, 20, 30, 10, 20, 30];
+        let image = Image::from_data(PixelFormat::RGB, 2, 1, data).unwrap();
+        let large = image.resize(5, 3);
+        assert_eq!(large.width(), 5);
+        assert_eq!(large.height(), 3);
+        for pixel in large.data().chunks(3) {
+            assert_eq!(pixel, &[10, 20, 30]);
+        }
+    }
+}
@@ -0,0 +1,533 @@
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+use super::family::IconFamily;
+
+/// The magic number at the start of an AppleSingle file:
+const APPLE_SINGLE_MAGIC: u32 = 0x0005_1600;
+
+/// The magic number at the start of an AppleDouble (`._`) file:
+const APPLE_DOUBLE_MAGIC: u32 = 0x0005_1607;
+
+/// The length of an AppleSingle/AppleDouble header, not including the entry
+/// descriptors:
+const APPLE_DOUBLE_HEADER_LENGTH: usize = 26;
+
+/// The length of a single AppleSingle/AppleDouble entry descriptor:
+const APPLE_DOUBLE_ENTRY_LENGTH: usize = 12;
+
+/// The AppleSingle/AppleDouble entry ID for the resource fork:
+const RESOURCE_FORK_ENTRY_ID: u32 = 2;
+
+/// The AppleSingle/AppleDouble entry ID for the Finder info:
+const FINDER_INFO_ENTRY_ID: u32 = 9;
+
+/// The length of the Finder info entry in an AppleDouble file:
+const FINDER_INFO_LENGTH: usize = 32;
+
+/// The Finder flag indicating that a file or folder has a custom icon:
+const HAS_CUSTOM_ICON_FLAG: u16 = 0x0400;
+
+/// The Finder flag indicating that a file is hidden:
+const IS_INVISIBLE_FLAG: u16 = 0x4000;
+
+/// The offset of the resource data within a resource fork written by this
+/// library (following the fork header and the reserved system area):
+const RESOURCE_DATA_OFFSET: usize = 256;
+
+/// The resource type under which icon families are stored:
+const ICNS_RESOURCE_TYPE: &[u8; 4] = b"icns";
+
+/// The resource ID used by the Finder for custom file and folder icons:
+const CUSTOM_ICON_RESOURCE_ID: i16 = -16455;
+
+/// The name of the (invisible) file whose resource fork holds a folder's
+/// custom icon:
+const FOLDER_ICON_FILE_NAME: &str = "Icon\r";
+
+impl IconFamily {
+    /// Reads an icon family from an AppleDouble file (such as the `._foo`
+    /// files that macOS creates on non-HFS filesystems) or an AppleSingle
+    /// file.  The icon family is taken from the `icns` resource within the
+    /// file's resource fork entry (preferring the Finder's custom icon
+    /// resource, if there is more than one).  Returns an error if the file
+    /// is malformed or contains no `icns` resource.
+    pub fn read_apple_double<R: Read>(mut reader: R)
+                                      -> io::Result<IconFamily> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let fork = apple_double_resource_fork(&data)?;
+        icns_from_resource_fork(fork)
+    }
+
+    /// Reads an icon family from the `icns` resource within a raw resource
+    /// fork (preferring the Finder's custom icon resource, if there is more
+    /// than one).  Returns an error if the resource fork is malformed or
+    /// contains no `icns` resource.
+    pub fn read_resource_fork<R: Read>(mut reader: R)
+                                       -> io::Result<IconFamily> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        icns_from_resource_fork(&data)
+    }
+
+    /// Reads the custom Finder icon of a folder, which is stored in the
+    /// resource fork of an invisible `Icon\r` file within the folder.  On
+    /// filesystems without resource forks (or in copies made by backup and
+    /// sync tools), the resource fork is instead stored in an AppleDouble
+    /// file named `._Icon\r`, which is tried first; otherwise, the resource
+    /// fork is read directly (which only works on macOS).  Returns an error
+    /// of kind `NotFound` if the folder has no custom icon.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use icns::IconFamily;
+    /// let family = IconFamily::read_folder_icon("/Volumes/Backup/Photos")
+    ///     .unwrap();
+    /// println!("Custom icon has {} elements",
+    ///          family.elements().len());
+    /// ```
+    pub fn read_folder_icon<P: AsRef<Path>>(folder: P)
+                                            -> io::Result<IconFamily> {
+        let folder = folder.as_ref();
+        let apple_double_path =
+            folder.join(format!("._{}", FOLDER_ICON_FILE_NAME));
+        if apple_double_path.is_file() {
+            let file = BufReader::new(File::open(apple_double_path)?);
+            return IconFamily::read_apple_double(file);
+        }
+        let icon_path = folder.join(FOLDER_ICON_FILE_NAME);
+        if !icon_path.is_file() {
+            let msg = format!("{} has no custom folder icon",
+                              folder.display());
+            return Err(Error::new(ErrorKind::NotFound, msg));
+        }
+        let fork_path = icon_path.join("..namedfork").join("rsrc");
+        let file = BufReader::new(File::open(fork_path)?);
+        IconFamily::read_resource_fork(file)
+    }
+
+    /// Writes the icon family as a raw resource fork, containing a single
+    /// `icns` resource with the ID that the Finder uses for custom icons.
+    pub fn write_resource_fork<W: Write>(&self,
+                                         mut writer: W)
+                                         -> io::Result<()> {
+        writer.write_all(&self.resource_fork()?)
+    }
+
+    /// Writes an AppleDouble file whose resource fork holds the icon family
+    /// (see [`write_resource_fork`](#method.write_resource_fork)), and whose
+    /// Finder info marks the file as having a custom icon.  Writing this to
+    /// `._foo` alongside a file named `foo` on a filesystem without resource
+    /// forks gives that file a custom icon when it is copied to a Mac.
+    pub fn write_apple_double<W: Write>(&self,
+                                        mut writer: W)
+                                        -> io::Result<()> {
+        let finder_info = finder_info(&[0; 8], HAS_CUSTOM_ICON_FLAG);
+        let fork = self.resource_fork()?;
+        writer.write_all(&apple_double(&finder_info, Some(&fork)))
+    }
+
+    /// Gives a folder a custom Finder icon, in the AppleDouble form that
+    /// macOS uses on filesystems without resource forks (and that
+    /// [`read_folder_icon`](#method.read_folder_icon) reads).  This writes
+    /// three files: an empty `Icon\r` file within the folder; a `._Icon\r`
+    /// AppleDouble file holding its resource fork (which contains the icon
+    /// family) and marking it as invisible; and a `._<name>` AppleDouble file
+    /// alongside the folder, marking the folder as having a custom icon.
+    /// Any existing files with those names are replaced.
+    ///
+    /// Returns an error of kind `InvalidInput` if the path has no parent
+    /// directory or final name (e.g. if it is `/` or ends in `..`).
+    pub fn write_folder_icon<P: AsRef<Path>>(&self,
+                                             folder: P)
+                                             -> io::Result<()> {
+        let folder = folder.as_ref();
+        let (parent, name) = match (folder.parent(), folder.file_name()) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => {
+                let msg = format!("cannot set a custom icon for {}",
+                                  folder.display());
+                return Err(Error::new(ErrorKind::InvalidInput, msg));
+            }
+        };
+        let fork = self.resource_fork()?;
+        let icon_info = finder_info(b"iconMACS", IS_INVISIBLE_FLAG);
+        fs::write(folder.join(FOLDER_ICON_FILE_NAME), b"")?;
+        fs::write(folder.join(format!("._{}", FOLDER_ICON_FILE_NAME)),
+                  apple_double(&icon_info, Some(&fork)))?;
+        let mut apple_double_name = OsString::from("._");
+        apple_double_name.push(name);
+        let folder_info = finder_info(&[0; 8], HAS_CUSTOM_ICON_FLAG);
+        fs::write(parent.join(apple_double_name),
+                  apple_double(&folder_info, None))
+    }
+
+    /// Private helper method.
+    fn resource_fork(&self) -> io::Result<Vec<u8>> {
+        let mut icns = Vec::new();
+        self.write(&mut icns)?;
+        let mut data = Vec::with_capacity(icns.len() + 4);
+        data.write_u32::<BigEndian>(icns.len() as u32)?;
+        data.write_all(&icns)?;
+        let mut map = Vec::new();
+        map.write_all(&[0; 24])?; // header copy, next map, refnum, attrs
+        map.write_u16::<BigEndian>(28)?; // type list offset
+        map.write_u16::<BigEndian>(50)?; // name list offset (empty)
+        map.write_u16::<BigEndian>(0)?; // number of types - 1
+        map.write_all(ICNS_RESOURCE_TYPE)?;
+        map.write_u16::<BigEndian>(0)?; // number of resources - 1
+        map.write_u16::<BigEndian>(10)?; // reference list offset
+        map.write_i16::<BigEndian>(CUSTOM_ICON_RESOURCE_ID)?;
+        map.write_u16::<BigEndian>(0xffff)?; // no name
+        map.write_u32::<BigEndian>(0)?; // attributes and data offset
+        map.write_u32::<BigEndian>(0)?; // reserved handle
+        let mut header = Vec::with_capacity(16);
+        header.write_u32::<BigEndian>(RESOURCE_DATA_OFFSET as u32)?;
+        header.write_u32::<BigEndian>((RESOURCE_DATA_OFFSET + data.len()) as
+                                      u32)?;
+        header.write_u32::<BigEndian>(data.len() as u32)?;
+        header.write_u32::<BigEndian>(map.len() as u32)?;
+        map[0..16].copy_from_slice(&header);
+        let mut fork = header;
+        fork.resize(RESOURCE_DATA_OFFSET, 0);
+        fork.extend_from_slice(&data);
+        fork.extend_from_slice(&map);
+        Ok(fork)
+    }
+}
+
+/// Returns a Finder info record with the given file type and creator code
+/// (or folder window position, for folders) and Finder flags.
+fn finder_info(type_and_creator: &[u8; 8],
+               flags: u16)
+               -> [u8; FINDER_INFO_LENGTH] {
+    let mut info = [0; FINDER_INFO_LENGTH];
+    info[0..8].copy_from_slice(type_and_creator);
+    BigEndian::write_u16(&mut info[8..10], flags);
+    info
+}
+
+/// Returns the contents of an AppleDouble file with the given Finder info
+/// and (optionally) resource fork entries.
+fn apple_double(finder_info: &[u8; FINDER_INFO_LENGTH],
+                fork: Option<&[u8]>)
+                -> Vec<u8> {
+    let num_entries = if fork.is_some() { 2 } else { 1 };
+    let mut offset = APPLE_DOUBLE_HEADER_LENGTH +
+                     num_entries * APPLE_DOUBLE_ENTRY_LENGTH;
+    let mut data = Vec::new();
+    data.extend_from_slice(&APPLE_DOUBLE_MAGIC.to_be_bytes());
+    data.extend_from_slice(&0x0002_0000u32.to_be_bytes());
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&(num_entries as u16).to_be_bytes());
+    let mut entries = vec![(FINDER_INFO_ENTRY_ID, &finder_info[..])];
+    if let Some(fork) = fork {
+        entries.push((RESOURCE_FORK_ENTRY_ID, fork));
+    }
+    for &(id, entry) in entries.iter() {
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(&(offset as u32).to_be_bytes());
+        data.extend_from_slice(&(entry.len() as u32).to_be_bytes());
+        offset += entry.len();
+    }
+    for &(_, entry) in entries.iter() {
+        data.extend_from_slice(entry);
+    }
+    data
+}
+
+/// Returns the resource fork entry within AppleSingle/AppleDouble data.
+fn apple_double_resource_fork(data: &[u8]) -> io::Result<&[u8]> {
+    if data.len() < APPLE_DOUBLE_HEADER_LENGTH {
+        return Err(truncated_error());
+    }
+    let magic = BigEndian::read_u32(&data[0..4]);
+    if magic != APPLE_DOUBLE_MAGIC && magic != APPLE_SINGLE_MAGIC {
+        let msg = "not an AppleDouble or AppleSingle file (wrong magic \
+                   number)";
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    let num_entries = BigEndian::read_u16(&data[24..26]) as usize;
+    for index in 0..num_entries {
+        let start = APPLE_DOUBLE_HEADER_LENGTH +
+                    index * APPLE_DOUBLE_ENTRY_LENGTH;
+        let entry = data.get(start..(start + APPLE_DOUBLE_ENTRY_LENGTH))
+            .ok_or_else(truncated_error)?;
+        if BigEndian::read_u32(&entry[0..4]) == RESOURCE_FORK_ENTRY_ID {
+            let offset = BigEndian::read_u32(&entry[4..8]) as usize;
+            let length = BigEndian::read_u32(&entry[8..12]) as usize;
+            return slice(data, offset, length);
+        }
+    }
+    let msg = "AppleDouble file has no resource fork entry";
+    Err(Error::new(ErrorKind::NotFound, msg))
+}
+
+/// Finds and parses the `icns` resource within a resource fork.
+fn icns_from_resource_fork(fork: &[u8]) -> io::Result<IconFamily> {
+    if fork.len() < 16 {
+        return Err(truncated_error());
+    }
+    let data_offset = BigEndian::read_u32(&fork[0..4]) as usize;
+    let map_offset = BigEndian::read_u32(&fork[4..8]) as usize;
+    let data_length = BigEndian::read_u32(&fork[8..12]) as usize;
+    let map_length = BigEndian::read_u32(&fork[12..16]) as usize;
+    let resource_data = slice(fork, data_offset, data_length)?;
+    let map = slice(fork, map_offset, map_length)?;
+    if map.len() < 28 {
+        return Err(truncated_error());
+    }
+    let type_list_offset = BigEndian::read_u16(&map[24..26]) as usize;
+    let type_list = map.get(type_list_offset..).ok_or_else(truncated_error)?;
+    if type_list.len() < 2 {
+        return Err(truncated_error());
+    }
+    let num_types = BigEndian::read_u16(&type_list[0..2]).wrapping_add(1);
+    let mut best_offset: Option<usize> = None;
+    for type_index in 0..(num_types as usize) {
+        let type_entry = slice(type_list, 2 + 8 * type_index, 8)?;
+        if &type_entry[0..4] != ICNS_RESOURCE_TYPE {
+            continue;
+        }
+        let num_resources = BigEndian::read_u16(&type_entry[4..6]) as usize +
+                            1;
+        let ref_list_offset = BigEndian::read_u16(&type_entry[6..8]) as usize;
+        for resource_index in 0..num_resources {
+            let reference =
+                slice(type_list, ref_list_offset + 12 * resource_index, 12)?;
+            let id = BigEndian::read_i16(&reference[0..2]);
+            let offset = (BigEndian::read_u32(&reference[4..8]) &
+                          0x00ff_ffff) as usize;
+            if best_offset.is_none() || id == CUSTOM_ICON_RESOURCE_ID {
+                best_offset = Some(offset);
+            }
+        }
+    }
+    let offset = best_offset.ok_or_else(|| {
+        Error::new(ErrorKind::NotFound,
+                   "resource fork contains no 'icns' resource")
+    })?;
+    let header = slice(resource_data, offset, 4)?;
+    let length = BigEndian::read_u32(header) as usize;
+    let icns = slice(resource_data, offset + 4, length)?;
+    IconFamily::read(Cursor::new(icns))
+}
+
+/// Returns the given subslice of `data`, or an error if it is out of bounds.
+fn slice(data: &[u8], offset: usize, length: usize) -> io::Result<&[u8]> {
+    offset.checked_add(length)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(truncated_error)
+}
+
+fn truncated_error() -> Error {
+    Error::new(ErrorKind::InvalidData, "resource data is truncated")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::TempDir;
+
+    const ICNS_DATA: &[u8] = b"icns\0\0\0\x11quux\0\0\0\x09#";
+
+    fn resource_fork(icns: &[u8], id: i16) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u32::<BigEndian>(icns.len() as u32).unwrap();
+        data.write_all(icns).unwrap();
+        let mut map = vec![0u8; 24];
+        map.write_u16::<BigEndian>(28).unwrap(); // type list offset
+        map.write_u16::<BigEndian>(50).unwrap(); // name list offset
+        map.write_u16::<BigEndian>(0).unwrap(); // number of types - 1
+        map.write_all(b"icns").unwrap();
+        map.write_u16::<BigEndian>(0).unwrap(); // number of resources - 1
+        map.write_u16::<BigEndian>(10).unwrap(); // reference list offset
+        map.write_i16::<BigEndian>(id).unwrap();
+        map.write_u16::<BigEndian>(0xffff).unwrap(); // no name
+        map.write_u32::<BigEndian>(0).unwrap(); // attributes and offset
+        map.write_u32::<BigEndian>(0).unwrap(); // reserved handle
+        let mut fork = Vec::new();
+        fork.write_u32::<BigEndian>(256).unwrap();
+        fork.write_u32::<BigEndian>(256 + data.len() as u32).unwrap();
+        fork.write_u32::<BigEndian>(data.len() as u32).unwrap();
+        fork.write_u32::<BigEndian>(map.len() as u32).unwrap();
+        fork.resize(256, 0);
+        fork.write_all(&data).unwrap();
+        fork.write_all(&map).unwrap();
+        fork
+    }
+
+    /// Returns a hand-assembled resource fork (written out byte by byte, so
+    /// that it doesn't share any layout code with `resource_fork` above or
+    /// with the library's writer), holding a `TEXT` resource and two `icns`
+    /// resources, only the second of which (ID -16455) is a custom icon.
+    fn finder_fork() -> Vec<u8> {
+        let header: &[u8] = &[0x00, 0x00, 0x01, 0x00, // data offset
+                              0x00, 0x00, 0x01, 0x1d, // map offset
+                              0x00, 0x00, 0x00, 0x1d, // data length
+                              0x00, 0x00, 0x00, 0x52]; // map length
+        let data: &[u8] = b"\0\0\0\x04\xde\xad\xbe\xef\
+                            \0\0\0\x11icns\0\0\0\x11quux\0\0\0\x09#";
+        let map: &[u8] = &[// Copy of the header, then the next map handle,
+                           // file reference number, and attributes:
+                           0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x1d,
+                           0x00, 0x00, 0x00, 0x1d, 0x00, 0x00, 0x00, 0x52,
+                           0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                           // Type list offset and name list offset:
+                           0x00, 0x1c, 0x00, 0x52,
+                           // Type list (2 types):
+                           0x00, 0x01,
+                           b'T', b'E', b'X', b'T', 0x00, 0x00, 0x00, 0x12,
+                           b'i', b'c', b'n', b's', 0x00, 0x01, 0x00, 0x1e,
+                           // TEXT 128 at data offset 0:
+                           0x00, 0x80, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+                           0x00, 0x00, 0x00, 0x00,
+                           // icns 128, also at data offset 0 (and so not
+                           // valid ICNS data):
+                           0x00, 0x80, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+                           0x00, 0x00, 0x00, 0x00,
+                           // icns -16455 (purgeable) at data offset 8:
+                           0xbf, 0xb9, 0xff, 0xff, 0x20, 0x00, 0x00, 0x08,
+                           0x00, 0x00, 0x00, 0x00];
+        let mut fork = header.to_vec();
+        fork.resize(256, 0);
+        fork.extend_from_slice(data);
+        fork.extend_from_slice(map);
+        fork
+    }
+
+    #[test]
+    fn read_icns_from_finder_fork() {
+        let family = IconFamily::read_resource_fork(Cursor::new(finder_fork()))
+            .expect("failed to read resource fork");
+        assert_eq!(1, family.elements.len());
+        assert_eq!(b"quux", &family.elements[0].ostype.0);
+        assert_eq!(b"#", &family.elements[0].data as &[u8]);
+    }
+
+    #[test]
+    fn write_resource_fork_layout() {
+        let family = IconFamily::read(Cursor::new(ICNS_DATA)).unwrap();
+        let mut fork = Vec::new();
+        family.write_resource_fork(&mut fork).unwrap();
+        let header: &[u8] = &[0x00, 0x00, 0x01, 0x00, // data offset
+                              0x00, 0x00, 0x01, 0x15, // map offset
+                              0x00, 0x00, 0x00, 0x15, // data length
+                              0x00, 0x00, 0x00, 0x32]; // map length
+        let mut expected = header.to_vec();
+        expected.resize(256, 0);
+        expected.extend_from_slice(b"\0\0\0\x11");
+        expected.extend_from_slice(ICNS_DATA);
+        expected.extend_from_slice(header);
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x1c, 0x00, 0x32,
+                                     0x00, 0x00,
+                                     b'i', b'c', b'n', b's',
+                                     0x00, 0x00, 0x00, 0x0a,
+                                     0xbf, 0xb9, 0xff, 0xff,
+                                     0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(fork, expected);
+    }
+
+    #[test]
+    fn read_icns_from_resource_fork() {
+        let fork = resource_fork(ICNS_DATA, CUSTOM_ICON_RESOURCE_ID);
+        let family = IconFamily::read_resource_fork(Cursor::new(fork))
+            .expect("failed to read resource fork");
+        assert_eq!(1, family.elements.len());
+        assert_eq!(b"#", &family.elements[0].data as &[u8]);
+    }
+
+    fn apple_double(fork: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.write_u32::<BigEndian>(APPLE_DOUBLE_MAGIC).unwrap();
+        file.write_u32::<BigEndian>(0x0002_0000).unwrap();
+        file.write_all(&[0u8; 16]).unwrap();
+        file.write_u16::<BigEndian>(1).unwrap();
+        file.write_u32::<BigEndian>(RESOURCE_FORK_ENTRY_ID).unwrap();
+        file.write_u32::<BigEndian>(38).unwrap();
+        file.write_u32::<BigEndian>(fork.len() as u32).unwrap();
+        file.write_all(fork).unwrap();
+        file
+    }
+
+    #[test]
+    fn read_icns_from_apple_double() {
+        let file = apple_double(&resource_fork(ICNS_DATA, 128));
+        let family = IconFamily::read_apple_double(Cursor::new(file))
+            .expect("failed to read AppleDouble file");
+        assert_eq!(1, family.elements.len());
+        assert_eq!(b"quux", &family.elements[0].ostype.0);
+    }
+
+    #[test]
+    fn apple_double_without_resource_fork() {
+        let mut file = Vec::new();
+        file.write_u32::<BigEndian>(APPLE_DOUBLE_MAGIC).unwrap();
+        file.write_u32::<BigEndian>(0x0002_0000).unwrap();
+        file.write_all(&[0u8; 16]).unwrap();
+        file.write_u16::<BigEndian>(0).unwrap();
+        let result = IconFamily::read_apple_double(Cursor::new(file));
+        assert_eq!(result.err().map(|err| err.kind()),
+                   Some(ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn read_folder_icon_from_apple_double() {
+        let temp = TempDir::new("folder-icon");
+        let folder = temp.path();
+        let icon_path = folder.join("._Icon\r");
+        let error = IconFamily::read_folder_icon(folder).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        let fork = resource_fork(ICNS_DATA, CUSTOM_ICON_RESOURCE_ID);
+        fs::write(&icon_path, apple_double(&fork)).unwrap();
+        let family = IconFamily::read_folder_icon(folder).unwrap();
+        assert_eq!(1, family.elements.len());
+        assert_eq!(b"quux", &family.elements[0].ostype.0);
+    }
+
+    #[test]
+    fn write_and_read_resource_fork() {
+        let family = IconFamily::read(Cursor::new(ICNS_DATA)).unwrap();
+        let mut fork = Vec::new();
+        family.write_resource_fork(&mut fork).unwrap();
+        let read = IconFamily::read_resource_fork(Cursor::new(&fork))
+            .unwrap();
+        assert_eq!(read.elements[0].data, family.elements[0].data);
+        // The resource map begins with a copy of the fork header:
+        let map_offset = BigEndian::read_u32(&fork[4..8]) as usize;
+        assert_eq!(fork[map_offset..(map_offset + 16)], fork[0..16]);
+        let mut file = Vec::new();
+        family.write_apple_double(&mut file).unwrap();
+        let read = IconFamily::read_apple_double(Cursor::new(file)).unwrap();
+        assert_eq!(read.elements.len(), 1);
+        assert_eq!(read.elements[0].data, family.elements[0].data);
+    }
+
+    #[test]
+    fn write_and_read_folder_icon() {
+        // The folder's own AppleDouble file is written next to it, so put
+        // the folder inside a temporary directory of its own:
+        let temp = TempDir::new("write-folder-icon");
+        let folder = temp.path().join("Folder");
+        fs::create_dir(&folder).unwrap();
+        let family = IconFamily::read(Cursor::new(ICNS_DATA)).unwrap();
+        family.write_folder_icon(&folder).unwrap();
+        assert_eq!(fs::read(folder.join("Icon\r")).unwrap().len(), 0);
+        let read = IconFamily::read_folder_icon(&folder).unwrap();
+        assert_eq!(read.elements.len(), 1);
+        assert_eq!(b"quux", &read.elements[0].ostype.0);
+        let folder_info = fs::read(temp.path().join("._Folder")).unwrap();
+        assert_eq!(folder_info.len(), 70);
+        assert_eq!(BigEndian::read_u16(&folder_info[46..48]),
+                   HAS_CUSTOM_ICON_FLAG);
+        let error = family.write_folder_icon("/").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,295 @@
+//! The PackBits-style run-length encoding used by ICNS files (and by classic
+//! Mac OS resources more generally) for 24-bit color icon data.
+//!
+//! Pixel data is compressed one channel at a time: all of the first channel's
+//! values, then all of the second channel's, and so on, with each channel
+//! compressed separately into a sequence of packets.  A header byte `n` less
+//! than 128 is followed by `n + 1` literal values; a header byte `n` of 128
+//! or more is followed by a single value that is repeated `n - 125` times.
+//! Packets never span two channels.
+//!
+//! Note that the 128x128 RLE icon type (`it32`) prefixes its compressed data
+//! with four zero bytes, which are not part of the encoding itself, and which
+//! are not handled by this module.
+//!
+//! # Example
+//! ```
+//! use icns::rle;
+//! let pixels: Vec<u8> = vec![1, 2, 3, 1, 2, 3, 1, 2, 3];
+//! let encoded = rle::encode(&pixels, 3, 3).unwrap();
+//! assert_eq!(encoded, vec![128, 1, 128, 2, 128, 3]);
+//! let decoded = rle::decode(&encoded, 3, 3).unwrap();
+//! assert_eq!(decoded, pixels);
+//! ```
+
+use std::cmp;
+use std::io::{self, Error, ErrorKind};
+
+/// The maximum number of values in a single literal packet:
+const MAX_LITERAL_LENGTH: usize = 128;
+
+/// The minimum and maximum number of values in a single run packet:
+const MIN_RUN_LENGTH: usize = 3;
+const MAX_RUN_LENGTH: usize = 130;
+
+/// Compresses `num_pixels` pixels of interleaved pixel data, each
+/// `num_channels` bytes long.  Returns an error if `num_channels` is zero, or
+/// if the input is not exactly `num_channels * num_pixels` bytes long.
+pub fn encode(input: &[u8],
+              num_channels: usize,
+              num_pixels: usize)
+              -> io::Result<Vec<u8>> {
+    if num_channels.checked_mul(num_pixels) != Some(input.len()) {
+        let msg = format!("RLE input has wrong length ({} bytes, but {} \
+                           pixels of {} channels were expected)",
+                          input.len(),
+                          num_pixels,
+                          num_channels);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    encode_strided(input, num_channels, num_channels)
+}
+
+/// Compresses the first `num_channels` channels of interleaved pixel data
+/// whose pixels are each `stride` bytes long, ignoring any remaining channels
+/// (for example, this can compress just the color channels of RGBA data, by
+/// passing a `num_channels` of 3 and a `stride` of 4).  Returns an error if
+/// `num_channels` is zero or greater than `stride`, or if the input length is
+/// not a multiple of `stride`.
+pub fn encode_strided(input: &[u8],
+                      num_channels: usize,
+                      stride: usize)
+                      -> io::Result<Vec<u8>> {
+    check_stride(input.len(), num_channels, stride)?;
+    let num_pixels = input.len() / stride;
+    let mut output = Vec::new();
+    for channel in 0..num_channels {
+        let value_at = |pixel: usize| input[stride * pixel + channel];
+        let mut pixel: usize = 0;
+        let mut literal_start: usize = 0;
+        while pixel < num_pixels {
+            let value = value_at(pixel);
+            let mut run_length = 1;
+            while pixel + run_length < num_pixels &&
+                  value_at(pixel + run_length) == value &&
+                  run_length < MAX_RUN_LENGTH {
+                run_length += 1;
+            }
+            if run_length >= MIN_RUN_LENGTH {
+                while literal_start < pixel {
+                    let literal_length = cmp::min(MAX_LITERAL_LENGTH,
+                                                  pixel - literal_start);
+                    output.push((literal_length - 1) as u8);
+                    for i in 0..literal_length {
+                        output.push(value_at(literal_start + i));
+                    }
+                    literal_start += literal_length;
+                }
+                output.push((run_length + 125) as u8);
+                output.push(value);
+                pixel += run_length;
+                literal_start = pixel;
+            } else {
+                pixel += run_length;
+            }
+        }
+        while literal_start < pixel {
+            let literal_length = cmp::min(MAX_LITERAL_LENGTH,
+                                          pixel - literal_start);
+            output.push((literal_length - 1) as u8);
+            for i in 0..literal_length {
+                output.push(value_at(literal_start + i));
+            }
+            literal_start += literal_length;
+        }
+    }
+    Ok(output)
+}
+
+/// Decompresses data into `num_pixels` pixels of interleaved pixel data, each
+/// `num_channels` bytes long.  Returns an error if `num_channels` is zero, or
+/// if the compressed data is malformed or doesn't decompress to exactly the
+/// expected number of pixels.
+pub fn decode(input: &[u8],
+              num_channels: usize,
+              num_pixels: usize)
+              -> io::Result<Vec<u8>> {
+    let length = num_channels.checked_mul(num_pixels).ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, "RLE output is too large")
+    })?;
+    let mut output = vec![0u8; length];
+    decode_strided(input, num_channels, &mut output, num_channels)?;
+    Ok(output)
+}
+
+/// Decompresses data into the first `num_channels` channels of interleaved
+/// pixel data whose pixels are each `stride` bytes long, leaving any
+/// remaining channels untouched (for example, this can decompress color data
+/// into an RGBA buffer, by passing a `num_channels` of 3 and a `stride` of
+/// 4).  Returns an error if `num_channels` is zero or greater than `stride`,
+/// if the output length is not a multiple of `stride`, or if the compressed
+/// data is malformed or doesn't decompress to exactly fill the output.
+pub fn decode_strided(input: &[u8],
+                      num_channels: usize,
+                      output: &mut [u8],
+                      stride: usize)
+                      -> io::Result<()> {
+    check_stride(output.len(), num_channels, stride)?;
+    let num_pixels = output.len() / stride;
+    let mut iter = input.iter();
+    let mut remaining: usize = 0;
+    let mut within_run = false;
+    let mut run_value: u8 = 0;
+    for channel in 0..num_channels {
+        for pixel in 0..num_pixels {
+            if remaining == 0 {
+                let next: u8 = *iter.next().ok_or_else(rle_error)?;
+                if next < 128 {
+                    remaining = (next as usize) + 1;
+                    within_run = false;
+                } else {
+                    remaining = (next as usize) - 125;
+                    within_run = true;
+                    run_value = *iter.next().ok_or_else(rle_error)?;
+                }
+            }
+            output[stride * pixel + channel] = if within_run {
+                run_value
+            } else {
+                *iter.next().ok_or_else(rle_error)?
+            };
+            remaining -= 1;
+        }
+        if remaining != 0 {
+            return Err(rle_error());
+        }
+    }
+    if iter.next().is_some() {
+        Err(rle_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns true if the compressed data is well-formed and would decompress
+/// to exactly `num_pixels` pixels of `num_channels` channels each (as
+/// required by `decode`), without actually decompressing it.
+pub(crate) fn is_well_formed(input: &[u8],
+                             num_channels: usize,
+                             num_pixels: usize)
+                             -> bool {
+    let mut offset: usize = 0;
+    for _ in 0..num_channels {
+        let mut pixels: usize = 0;
+        while pixels < num_pixels {
+            let header = match input.get(offset) {
+                Some(&header) => header as usize,
+                None => return false,
+            };
+            if header < 128 {
+                pixels += header + 1;
+                offset += header + 2;
+            } else {
+                pixels += header - 125;
+                offset += 2;
+            }
+        }
+        if pixels != num_pixels || offset > input.len() {
+            return false;
+        }
+    }
+    offset == input.len()
+}
+
+/// Checks the channel count and stride arguments to a strided function.
+fn check_stride(length: usize,
+                num_channels: usize,
+                stride: usize)
+                -> io::Result<()> {
+    if num_channels == 0 || num_channels > stride {
+        let msg = format!("invalid RLE channel count ({} channels with a \
+                           stride of {})",
+                          num_channels,
+                          stride);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    if !length.is_multiple_of(stride) {
+        let msg = format!("RLE pixel data length ({}) is not a multiple of \
+                           the stride ({})",
+                          length,
+                          stride);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(())
+}
+
+fn rle_error() -> Error {
+    Error::new(ErrorKind::InvalidData, "invalid RLE-compressed data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_literals_and_runs() {
+        let input: Vec<u8> = vec![44, 55, 66, 66, 66];
+        let encoded = encode(&input, 1, 5).unwrap();
+        assert_eq!(encoded, vec![1, 44, 55, 128, 66]);
+        assert_eq!(decode(&encoded, 1, 5).unwrap(), input);
+    }
+
+    #[test]
+    fn encode_long_runs_and_literals() {
+        let mut input: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        input.extend_from_slice(&[7; 300]);
+        let encoded = encode(&input, 1, 500).unwrap();
+        assert_eq!(encoded.len(), (1 + 128) + (1 + 72) + 3 * 2);
+        assert_eq!(decode(&encoded, 1, 500).unwrap(), input);
+    }
+
+    #[test]
+    fn strided_round_trip() {
+        let rgba: Vec<u8> = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let encoded = encode_strided(&rgba, 3, 4).unwrap();
+        assert_eq!(encoded, vec![1, 1, 5, 1, 2, 6, 1, 3, 7]);
+        let mut output = vec![9u8; 8];
+        decode_strided(&encoded, 3, &mut output, 4).unwrap();
+        assert_eq!(output, vec![1, 2, 3, 9, 5, 6, 7, 9]);
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let error = encode(&[1, 2, 3], 2, 2).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = encode_strided(&[1, 2, 3], 0, 1).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = decode_strided(&[], 4, &mut [0; 6], 3).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn well_formed_data() {
+        let encoded = encode(&[1, 2, 3, 1, 2, 3, 1, 2, 4], 3, 3).unwrap();
+        assert!(is_well_formed(&encoded, 3, 3));
+        assert!(!is_well_formed(&encoded, 3, 4));
+        assert!(!is_well_formed(&encoded, 3, 2));
+        assert!(!is_well_formed(&encoded[..(encoded.len() - 1)], 3, 3));
+        let mut extra = encoded.clone();
+        extra.push(0);
+        assert!(!is_well_formed(&extra, 3, 3));
+        // A packet may not span two channels:
+        assert!(!is_well_formed(&[129, 7, 129, 8], 2, 3));
+        assert!(decode(&[129, 7, 129, 8], 2, 3).is_err());
+    }
+
+    #[test]
+    fn malformed_data() {
+        // Truncated literal:
+        assert!(decode(&[2, 1, 2], 1, 3).is_err());
+        // Run spanning two channels:
+        assert!(decode(&[128, 1], 2, 2).is_err());
+        // Trailing garbage:
+        assert!(decode(&[128, 1, 0], 1, 3).is_err());
+    }
+}
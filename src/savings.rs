@@ -0,0 +1,247 @@
+use std::fmt;
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::icontype::{Encoding, IconType, OSType};
+#[cfg(feature = "pngio")]
+use super::options::PngCompression;
+#[cfg(feature = "pngio")]
+use super::payload::PayloadFormat;
+
+/// The kind of potential saving found by
+/// [`IconFamily::compression_report`](
+/// struct.IconFamily.html#method.compression_report).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FindingKind {
+    /// The element's PNG data could be made smaller by re-encoding it at
+    /// the best compression level (which also drops ancillary chunks, such
+    /// as embedded metadata).
+    PoorPngCompression,
+    /// The element's data payload is identical to that of an earlier element
+    /// with the given OSType.
+    DuplicateContent {
+        /// The OSType of the earlier element.
+        duplicate_of: OSType,
+    },
+    /// The element is a legacy RLE-compressed icon (or its mask) of the same
+    /// size as the given modern icon type, which macOS uses instead.
+    ShadowedLegacy {
+        /// The modern icon type of the same size.
+        shadowed_by: IconType,
+    },
+}
+
+/// A single potential saving found by
+/// [`IconFamily::compression_report`](
+/// struct.IconFamily.html#method.compression_report).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct CompressionFinding {
+    /// The OSType of the element concerned.
+    pub ostype: OSType,
+    /// What could be done about the element.
+    pub kind: FindingKind,
+    /// The estimated number of bytes that could be saved.
+    pub estimated_savings: u64,
+}
+
+impl fmt::Display for CompressionFinding {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}: ", self.ostype)?;
+        match self.kind {
+            FindingKind::PoorPngCompression => {
+                write!(out, "PNG data could be recompressed")?
+            }
+            FindingKind::DuplicateContent { duplicate_of } => {
+                write!(out, "duplicates '{}' element", duplicate_of)?
+            }
+            FindingKind::ShadowedLegacy { shadowed_by } => {
+                write!(out,
+                       "legacy icon shadowed by '{}' element",
+                       shadowed_by.ostype())?
+            }
+        }
+        write!(out, " (saves ~{} bytes)", self.estimated_savings)
+    }
+}
+
+/// A report of ways that an icon family could be made smaller, as returned
+/// by [`IconFamily::compression_report`](
+/// struct.IconFamily.html#method.compression_report).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CompressionReport {
+    /// The potential savings found, in the order of the elements concerned.
+    /// Each element has at most one finding.
+    pub findings: Vec<CompressionFinding>,
+}
+
+impl CompressionReport {
+    /// Returns the total estimated savings of all findings, in bytes.
+    pub fn total_savings(&self) -> u64 {
+        self.findings.iter().map(|finding| finding.estimated_savings).sum()
+    }
+}
+
+impl IconFamily {
+    /// Analyzes the family for ways it could be made smaller, without
+    /// modifying it, and estimates the number of bytes each would save.
+    /// This flags:
+    ///
+    /// * Elements whose payload is identical to that of an earlier element
+    ///   (saving the whole element if it's dropped or shared).
+    /// * Legacy RLE-compressed icons, and their masks, for which the family
+    ///   also has a modern icon type of the same size (see also
+    ///   [`pruned_for`](#method.pruned_for)).
+    /// * PNG payloads that get smaller when re-encoded at the best
+    ///   compression level (see also
+    ///   [`recompress_with`](#method.recompress_with)).  This requires the
+    ///   `pngio` feature, and decodes and re-encodes every PNG payload, so
+    ///   it can take a while for large families.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{FindingKind, IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_16x16).unwrap();
+    /// let report = family.compression_report();
+    /// let shadowed = FindingKind::ShadowedLegacy {
+    ///     shadowed_by: IconType::RGBA32_16x16,
+    /// };
+    /// // Both the 'is32' element and its 's8mk' mask are redundant:
+    /// assert_eq!(report.findings[0].kind, shadowed);
+    /// assert_eq!(report.findings[1].kind, shadowed);
+    /// assert!(report.total_savings() > 0);
+    /// # }
+    /// ```
+    pub fn compression_report(&self) -> CompressionReport {
+        let modern: Vec<IconType> = self.available_icons()
+            .into_iter()
+            .filter(|icon_type| icon_type.encoding() == Encoding::JP2PNG)
+            .collect();
+        let mut findings = Vec::new();
+        for (index, element) in self.elements.iter().enumerate() {
+            let duplicate = self.elements[..index]
+                .iter()
+                .find(|other| other.data == element.data);
+            let kind = if let Some(other) = duplicate {
+                FindingKind::DuplicateContent { duplicate_of: other.ostype }
+            } else if let Some(shadowed_by) = shadowing_type(element,
+                                                              &modern) {
+                FindingKind::ShadowedLegacy { shadowed_by }
+            } else if let Some(savings) = png_savings(element) {
+                findings.push(CompressionFinding {
+                    ostype: element.ostype,
+                    kind: FindingKind::PoorPngCompression,
+                    estimated_savings: savings,
+                });
+                continue;
+            } else {
+                continue;
+            };
+            findings.push(CompressionFinding {
+                ostype: element.ostype,
+                kind,
+                estimated_savings: u64::from(element.total_length()),
+            });
+        }
+        CompressionReport { findings }
+    }
+}
+
+/// Returns the modern icon type (from among `modern`) that makes the given
+/// element redundant, if it is a legacy RLE-compressed icon or mask.
+fn shadowing_type(element: &IconElement,
+                  modern: &[IconType])
+                  -> Option<IconType> {
+    let icon_type = element.icon_type()?;
+    if icon_type.encoding() == Encoding::JP2PNG {
+        return None;
+    }
+    modern.iter().cloned().find(|other| other.size() == icon_type.size())
+}
+
+/// Returns the number of bytes saved by re-encoding the element's PNG data
+/// at the best compression level, if any.
+#[cfg(feature = "pngio")]
+fn png_savings(element: &IconElement) -> Option<u64> {
+    if element.payload_format() != PayloadFormat::PNG {
+        return None;
+    }
+    let image = element.decode_image().ok()?;
+    let mut data = Vec::new();
+    image.write_png_with_compression(&mut data, PngCompression::Best).ok()?;
+    let savings = element.data.len().checked_sub(data.len())?;
+    if savings > 0 { Some(savings as u64) } else { None }
+}
+
+/// Returns the number of bytes saved by re-encoding the element's PNG data
+/// (which can't be determined without the `pngio` feature).
+#[cfg(not(feature = "pngio"))]
+fn png_savings(_element: &IconElement) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::image::{Image, PixelFormat};
+
+    #[test]
+    fn duplicate_and_shadowed_elements() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        family.elements.push(IconElement::new(OSType(*b"icnV"), vec![1]));
+        family.elements.push(IconElement::new(OSType(*b"quux"), vec![1]));
+        let report = family.compression_report();
+        assert_eq!(report.findings,
+                   vec![CompressionFinding {
+                            ostype: OSType(*b"quux"),
+                            kind: FindingKind::DuplicateContent {
+                                duplicate_of: OSType(*b"icnV"),
+                            },
+                            estimated_savings: 9,
+                        }]);
+        assert_eq!(report.findings[0].to_string(),
+                   "quux: duplicates 'icnV' element (saves ~9 bytes)");
+        let modern = IconType::RGBA32_16x16.ostype();
+        family.elements.push(IconElement::new(modern, vec![0; 4]));
+        let report = family.compression_report();
+        let shadowed = FindingKind::ShadowedLegacy {
+            shadowed_by: IconType::RGBA32_16x16,
+        };
+        assert_eq!(report.findings[0].ostype, OSType(*b"is32"));
+        assert_eq!(report.findings[0].kind, shadowed);
+        assert_eq!(report.findings[1].ostype, OSType(*b"s8mk"));
+        assert_eq!(report.findings[1].kind, shadowed);
+        assert_eq!(report.findings[1].estimated_savings,
+                   u64::from(family.elements[1].total_length()));
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn poorly_compressed_png() {
+        use super::super::pngcheck::crc32;
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        let mut data = Vec::new();
+        image.write_png(&mut data).unwrap();
+        // Insert a bulky ancillary chunk before the IEND chunk:
+        let iend = data.split_off(data.len() - 12);
+        data.extend_from_slice(&1000u32.to_be_bytes());
+        let start = data.len();
+        data.extend_from_slice(b"zzZz");
+        data.extend_from_slice(&[0; 1000]);
+        let crc = crc32(&data[start..]);
+        data.extend_from_slice(&crc.to_be_bytes());
+        data.extend_from_slice(&iend);
+        let mut family = IconFamily::new();
+        let ostype = IconType::RGBA32_32x32.ostype();
+        family.elements.push(IconElement::new(ostype, data));
+        let report = family.compression_report();
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].kind, FindingKind::PoorPngCompression);
+        assert!(report.findings[0].estimated_savings >= 1012);
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::{self, Error, ErrorKind, Read};
+
+use super::element::ICON_ELEMENT_HEADER_LENGTH;
+use super::extract::{read_element_header, read_family_header};
+use super::family::{ICON_FAMILY_HEADER_LENGTH, IconFamily};
+use super::icontype::OSType;
+
+/// The header of an icon element, as passed to the visitor callback of
+/// [`IconFamily::scan`](struct.IconFamily.html#method.scan).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ElementHeader {
+    /// The element's OSType.
+    pub ostype: OSType,
+    /// The offset of the start of the element (that is, of its header)
+    /// within the ICNS file, in bytes.
+    pub offset: u32,
+    /// The length of the element's data payload, in bytes (not including
+    /// the eight-byte element header).
+    pub data_length: u32,
+}
+
+impl IconFamily {
+    /// Reads an ICNS file from a stream, one element at a time, without
+    /// ever holding more than one element's header in memory.  For each
+    /// element, the `visitor` callback is given the element's header and a
+    /// reader for its data payload.  The callback may read as much or as
+    /// little of the payload as it likes; whatever it leaves unread is
+    /// skipped.  Unlike [`extract_icon_from_reader`](
+    /// #method.extract_icon_from_reader), this doesn't require `Seek`, so it
+    /// works on e.g. network streams.
+    ///
+    /// Returns the number of elements visited.  Returns an error if the data
+    /// isn't an ICNS file, if the stream ends partway through an element, or
+    /// if the callback returns an error (in which case scanning stops
+    /// immediately).
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// use std::io::Read;
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// let mut data = Vec::new();
+    /// family.write(&mut data).unwrap();
+    ///
+    /// let mut log = Vec::new();
+    /// let count = IconFamily::scan(data.as_slice(), |header, payload| {
+    ///     let mut first_byte = [0u8; 1];
+    ///     payload.read_exact(&mut first_byte)?;
+    ///     log.push((header.ostype.to_string(), first_byte[0]));
+    ///     Ok(())
+    /// }).unwrap();
+    /// assert_eq!(count, 2);
+    /// assert_eq!(log[0].0, "is32");
+    /// assert_eq!(log[1], ("s8mk".to_string(), 0));
+    /// ```
+    pub fn scan<R, F>(mut reader: R, mut visitor: F) -> io::Result<usize>
+        where R: Read,
+              F: FnMut(ElementHeader, &mut dyn Read) -> io::Result<()>
+    {
+        let file_length = read_family_header(reader.by_ref())?;
+        let mut offset = ICON_FAMILY_HEADER_LENGTH;
+        let mut count = 0;
+        while offset < file_length {
+            let (ostype, data_length) = read_element_header(reader.by_ref())?;
+            let header = ElementHeader {
+                ostype,
+                offset,
+                data_length,
+            };
+            let mut payload = reader.by_ref().take(u64::from(data_length));
+            visitor(header, &mut payload)?;
+            io::copy(&mut payload, &mut io::sink())?;
+            if payload.limit() > 0 {
+                let msg = format!("stream ended partway through '{}' \
+                                   element",
+                                  ostype);
+                return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+            }
+            offset = offset.saturating_add(ICON_ELEMENT_HEADER_LENGTH)
+                .saturating_add(data_length);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::FixtureBuilder;
+
+    #[test]
+    fn scan_skips_unread_payloads() {
+        let data = FixtureBuilder::new()
+            .element(OSType(*b"quux"), vec![1, 2, 3])
+            .element(OSType(*b"icnV"), vec![4, 5, 6, 7])
+            .build();
+        let mut headers = Vec::new();
+        let mut payloads = Vec::new();
+        let count = IconFamily::scan(data.as_slice(), |header, payload| {
+            headers.push(header);
+            if header.ostype == OSType(*b"icnV") {
+                let mut bytes = Vec::new();
+                payload.read_to_end(&mut bytes)?;
+                payloads.push(bytes);
+            }
+            Ok(())
+        })
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(headers,
+                   vec![ElementHeader {
+                            ostype: OSType(*b"quux"),
+                            offset: 8,
+                            data_length: 3,
+                        },
+                        ElementHeader {
+                            ostype: OSType(*b"icnV"),
+                            offset: 19,
+                            data_length: 4,
+                        }]);
+        assert_eq!(payloads, vec![vec![4, 5, 6, 7]]);
+    }
+
+    #[test]
+    fn scan_errors() {
+        let builder = FixtureBuilder::new()
+            .element(OSType(*b"quux"), vec![1, 2, 3]);
+        let data = builder.clone().truncate(1).build();
+        let error = IconFamily::scan(data.as_slice(), |_, _| Ok(()))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let data = builder.build();
+        let error = IconFamily::scan(data.as_slice(), |_, _| {
+                Err(Error::new(ErrorKind::Interrupted, "stop"))
+            })
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        let error = IconFamily::scan(&b"icnx\0\0\0\x08"[..], |_, _| Ok(()))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+
+use super::family::IconFamily;
+use super::icontype::{ALL_ICON_TYPES, IconType};
+
+/// The logical size of an icon, independent of how it is stored: a size in
+/// points (i.e. its screen width and height), and a scale factor (the pixel
+/// density).  For example, the 1024x1024-pixel `RGBA32_512x512_2x` icon type
+/// has a size of 512 points at a scale of 2.
+///
+/// Sizes are ordered by point size first, then by scale.
+///
+/// # Examples
+/// ```
+/// use icns::{IconSize, IconType};
+/// let size = IconSize::new(128, 2);
+/// assert_eq!(size.pixel_size(), 256);
+/// assert_eq!(size.to_string(), "128x128@2x");
+/// assert_eq!(size.icon_types(), vec![IconType::RGBA32_128x128_2x]);
+/// assert_eq!(IconType::RGB24_128x128.size(), IconSize::new(128, 1));
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct IconSize {
+    /// The width and height of the icon, in points.
+    pub points: u32,
+    /// The number of pixels per point along each axis (1 for normal icons,
+    /// or 2 for "retina" icons).
+    pub scale: u32,
+}
+
+impl IconSize {
+    /// Creates a new icon size.
+    pub fn new(points: u32, scale: u32) -> IconSize {
+        IconSize { points, scale }
+    }
+
+    /// Returns the width and height of an icon of this size, in pixels.
+    pub fn pixel_size(self) -> u32 {
+        self.points * self.scale
+    }
+
+    /// Returns all (non-mask) icon types with this size, in the order they
+    /// are declared in [`IconType`](enum.IconType.html) (so legacy types come
+    /// before PNG/JPEG 2000 types).
+    pub fn icon_types(self) -> Vec<IconType> {
+        ALL_ICON_TYPES.iter()
+            .cloned()
+            .filter(|icon_type| {
+                !icon_type.is_mask() && icon_type.size() == self
+            })
+            .collect()
+    }
+
+    /// Returns the icon type that would be chosen for an icon of this size
+    /// by [`IconType::from_pixel_size_and_density`](
+    /// enum.IconType.html#method.from_pixel_size_and_density), if any.
+    pub fn icon_type(self) -> Option<IconType> {
+        let pixels = self.pixel_size();
+        IconType::from_pixel_size_and_density(pixels, pixels, self.scale)
+    }
+}
+
+impl fmt::Display for IconSize {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}x{}", self.points, self.points)?;
+        if self.scale != 1 {
+            write!(out, "@{}x", self.scale)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<IconType> for IconSize {
+    fn from(icon_type: IconType) -> IconSize {
+        icon_type.size()
+    }
+}
+
+impl IconType {
+    /// Returns the logical size of this icon type.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconSize, IconType};
+    /// assert_eq!(IconType::RGBA32_16x16_2x.size(), IconSize::new(16, 2));
+    /// assert_eq!(IconType::Mask8_48x48.size(), IconSize::new(48, 1));
+    /// ```
+    pub fn size(self) -> IconSize {
+        IconSize::new(self.screen_width(), self.pixel_density())
+    }
+
+    /// Returns the logical width and height of this icon type, in points
+    /// (the same as its [`screen_width`](#method.screen_width)).  Compare
+    /// with [`pixel_size`](#method.pixel_size).
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::IconType;
+    /// assert_eq!(IconType::RGBA32_32x32.point_size(), 32);
+    /// assert_eq!(IconType::RGBA32_32x32_2x.point_size(), 32);
+    /// ```
+    pub fn point_size(self) -> u32 {
+        self.screen_width()
+    }
+
+    /// Returns the width and height of the image data stored for this icon
+    /// type, in pixels (the same as its [`pixel_width`](#method.pixel_width)).
+    /// This is the point size multiplied by the pixel density.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::IconType;
+    /// assert_eq!(IconType::RGBA32_32x32.pixel_size(), 32);
+    /// assert_eq!(IconType::RGBA32_32x32_2x.pixel_size(), 64);
+    /// ```
+    pub fn pixel_size(self) -> u32 {
+        self.pixel_width()
+    }
+}
+
+impl IconFamily {
+    /// Returns the logical sizes of the icons available in the family (see
+    /// [`available_icons`](#method.available_icons)), in order and without
+    /// duplicates.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconSize, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x).unwrap();
+    /// assert_eq!(family.available_sizes(),
+    ///            vec![IconSize::new(16, 2), IconSize::new(32, 1)]);
+    /// # }
+    /// ```
+    pub fn available_sizes(&self) -> Vec<IconSize> {
+        let mut sizes: Vec<IconSize> = self.available_icons()
+            .into_iter()
+            .map(IconType::size)
+            .collect();
+        sizes.sort();
+        sizes.dedup();
+        sizes
+    }
+
+    /// Returns the icons available in the family (see
+    /// [`available_icons`](#method.available_icons)) with the given scale
+    /// factor (pixel density), such as 2 for "retina" icons.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 64, 64);
+    /// family.add_icon_with_type(&image, IconType::RGBA32_64x64).unwrap();
+    /// family.add_icon_with_type(&image, IconType::RGBA32_32x32_2x).unwrap();
+    /// assert_eq!(family.available_icons_with_scale(2),
+    ///            vec![IconType::RGBA32_32x32_2x]);
+    /// # }
+    /// ```
+    pub fn available_icons_with_scale(&self, scale: u32) -> Vec<IconType> {
+        self.available_icons()
+            .into_iter()
+            .filter(|icon_type| icon_type.pixel_density() == scale)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icon_size_round_trip() {
+        for &icon_type in ALL_ICON_TYPES.iter() {
+            let size = icon_type.size();
+            if !icon_type.is_mask() {
+                assert!(size.icon_types().contains(&icon_type));
+            }
+            let chosen = size.icon_type().unwrap();
+            assert_eq!(chosen.size(), size);
+        }
+    }
+
+    #[test]
+    fn point_and_pixel_sizes() {
+        for &icon_type in ALL_ICON_TYPES.iter() {
+            let size = icon_type.size();
+            assert_eq!(icon_type.point_size(), size.points);
+            assert_eq!(icon_type.pixel_size(), size.pixel_size());
+            assert_eq!(icon_type.pixel_size(),
+                       icon_type.point_size() * icon_type.pixel_density());
+        }
+    }
+
+    #[test]
+    fn icon_size_order() {
+        let mut sizes = vec![IconSize::new(32, 1),
+                             IconSize::new(16, 2),
+                             IconSize::new(16, 1)];
+        sizes.sort();
+        assert_eq!(sizes,
+                   vec![IconSize::new(16, 1),
+                        IconSize::new(16, 2),
+                        IconSize::new(32, 1)]);
+        assert_eq!(IconSize::new(64, 2).icon_types(), vec![]);
+        assert_eq!(IconSize::new(64, 2).icon_type(), None);
+    }
+}
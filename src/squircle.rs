@@ -0,0 +1,245 @@
+use std::io::{self, Error, ErrorKind};
+
+use super::family::{IconFamily, MASTER_ICON_TYPES};
+use super::image::{Image, PixelFormat};
+
+// The following dimensions are taken from Apple's macOS 11 app icon
+// template, and are given in pixels on a 1024x1024 canvas.
+
+/// The width and height of the rounded-rect icon body:
+const BODY_SIZE: f32 = 824.0;
+
+/// The standard deviation (roughly) of the drop shadow's blur:
+const SHADOW_BLUR: f32 = 10.0;
+
+/// How far the drop shadow is offset downwards from the icon body:
+const SHADOW_OFFSET: f32 = 10.0;
+
+/// The opacity of the drop shadow, from 0.0 to 1.0:
+const SHADOW_OPACITY: f32 = 0.3;
+
+/// The exponent of the superellipse that approximates the continuous-corner
+/// rounded rect ("squircle") used for macOS icons:
+const SUPERELLIPSE_EXPONENT: f32 = 5.0;
+
+/// The number of subsamples per pixel row used when antialiasing the edges
+/// of the squircle:
+const SUBSAMPLES: usize = 4;
+
+impl Image {
+    /// Renders a `size` by `size` macOS 11-style app icon from this (square)
+    /// artwork: the artwork is scaled down to fit the standard icon grid,
+    /// clipped to the rounded-rect "squircle" shape, and given the standard
+    /// drop shadow.  The result is always in RGBA format.  Returns an error
+    /// if `size` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let artwork = Image::new(PixelFormat::RGB, 100, 100);
+    /// let icon = artwork.to_squircle_icon(64).unwrap();
+    /// assert_eq!(icon.pixel_format(), PixelFormat::RGBA);
+    /// // The corners lie outside the squircle, and so are transparent.
+    /// assert_eq!(icon.data()[3], 0);
+    /// // The center is covered by the (opaque) artwork.
+    /// assert_eq!(icon.data()[(32 * 64 + 32) * 4 + 3], 255);
+    /// ```
+    pub fn to_squircle_icon(&self, size: u32) -> io::Result<Image> {
+        if size == 0 {
+            let msg = "squircle icon size must be nonzero";
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let scale = size as f32 / 1024.0;
+        let body_size = ((BODY_SIZE * scale).round() as u32).clamp(1, size);
+        let body_left = (size - body_size) / 2;
+        let coverage = squircle_coverage(size, body_left, body_size);
+        let shadow = shadow_alpha(&coverage,
+                                  size,
+                                  SHADOW_BLUR * scale,
+                                  (SHADOW_OFFSET * scale).round() as usize);
+        let mut artwork = Image::new(PixelFormat::RGBA, size, size);
+        artwork.paste(&self.convert_to(PixelFormat::RGBA)
+                          .resize(body_size, body_size),
+                      body_left,
+                      body_left);
+        let mut output = Image::new(PixelFormat::RGBA, size, size);
+        for (index, (out, pixel)) in output.data
+            .chunks_mut(4)
+            .zip(artwork.data.chunks(4))
+            .enumerate() {
+            let body_alpha = f32::from(pixel[3]) / 255.0 * coverage[index];
+            // Composite the body over the (black) shadow.
+            let alpha = body_alpha + shadow[index] * (1.0 - body_alpha);
+            if alpha <= 0.0 {
+                continue;
+            }
+            for channel in 0..3 {
+                let value = f32::from(pixel[channel]) * body_alpha / alpha;
+                out[channel] = value.round() as u8;
+            }
+            out[3] = (alpha * 255.0).round() as u8;
+        }
+        Ok(output)
+    }
+}
+
+impl IconFamily {
+    /// Creates a complete macOS 11-style icon family from square artwork, by
+    /// rendering it with [`Image::to_squircle_icon`](
+    /// struct.Image.html#method.to_squircle_icon) at each of the sizes
+    /// generated by [`from_master_image`](#method.from_master_image).  Each
+    /// size is rendered separately (rather than scaled down from the largest
+    /// one), so that the squircle edges and shadow stay crisp.  Returns an
+    /// error if the artwork is not square, or is empty.
+    pub fn from_squircle_artwork(artwork: &Image) -> io::Result<IconFamily> {
+        if artwork.width() != artwork.height() || artwork.width() == 0 {
+            let msg = format!("artwork must be square and non-empty (was \
+                               {}x{})",
+                              artwork.width(),
+                              artwork.height());
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let mut family = IconFamily::new();
+        let mut rendered: Vec<Image> = Vec::new();
+        for &icon_type in MASTER_ICON_TYPES {
+            let size = icon_type.pixel_width();
+            let position = rendered.iter().position(|i| i.width() == size);
+            let index = match position {
+                Some(index) => index,
+                None => {
+                    rendered.push(artwork.to_squircle_icon(size)?);
+                    rendered.len() - 1
+                }
+            };
+            family.add_icon_with_type(&rendered[index], icon_type)?;
+        }
+        Ok(family)
+    }
+}
+
+/// Returns the fraction of each pixel (from 0.0 to 1.0) of a `size` by `size`
+/// image that is covered by a squircle whose bounding square has its top-left
+/// corner at (`left`, `left`) and is `body_size` pixels wide.
+fn squircle_coverage(size: u32, left: u32, body_size: u32) -> Vec<f32> {
+    let size = size as usize;
+    let radius = body_size as f32 / 2.0;
+    let center = left as f32 + radius;
+    let mut coverage = vec![0f32; size * size];
+    for row in 0..size {
+        for subsample in 0..SUBSAMPLES {
+            let y = row as f32 + (subsample as f32 + 0.5) / SUBSAMPLES as f32;
+            let dy = ((y - center) / radius).abs();
+            if dy >= 1.0 {
+                continue;
+            }
+            // The horizontal extent of the squircle along this subsample.
+            let half_width = radius *
+                             (1.0 - dy.powf(SUPERELLIPSE_EXPONENT))
+                .powf(1.0 / SUPERELLIPSE_EXPONENT);
+            let start = center - half_width;
+            let end = center + half_width;
+            for col in 0..size {
+                let overlap = (end.min(col as f32 + 1.0) -
+                               start.max(col as f32))
+                    .max(0.0);
+                coverage[row * size + col] += overlap / SUBSAMPLES as f32;
+            }
+        }
+    }
+    coverage
+}
+
+/// Returns the alpha (from 0.0 to 1.0) of the drop shadow cast by a shape
+/// with the given coverage, shifted down by `offset` pixels and blurred.
+fn shadow_alpha(coverage: &[f32],
+                size: u32,
+                blur: f32,
+                offset: usize)
+                -> Vec<f32> {
+    let size = size as usize;
+    let mut shadow = vec![0f32; size * size];
+    for row in offset..size {
+        let src = (row - offset) * size;
+        let dst = row * size;
+        for col in 0..size {
+            shadow[dst + col] = coverage[src + col] * SHADOW_OPACITY;
+        }
+    }
+    // Three passes of a box blur approximate a Gaussian blur.
+    let box_radius = (blur / 3f32.sqrt()).round() as usize;
+    if box_radius > 0 {
+        for _ in 0..3 {
+            box_blur(&mut shadow, size, box_radius, 1, size);
+            box_blur(&mut shadow, size, box_radius, size, 1);
+        }
+    }
+    shadow
+}
+
+/// Blurs each line of a `size` by `size` buffer with a box filter, where
+/// consecutive values in a line are `step` apart, and consecutive lines start
+/// `line_step` apart.
+fn box_blur(values: &mut [f32],
+            size: usize,
+            radius: usize,
+            step: usize,
+            line_step: usize) {
+    let width = (2 * radius + 1) as f32;
+    let mut line = vec![0f32; size];
+    for start in (0..size).map(|index| index * line_step) {
+        for (position, value) in line.iter_mut().enumerate() {
+            *value = values[start + position * step];
+        }
+        let mut sum: f32 = line[..radius.min(size)].iter().sum();
+        for position in 0..size {
+            if position + radius < size {
+                sum += line[position + radius];
+            }
+            if position > radius {
+                sum -= line[position - radius - 1];
+            }
+            values[start + position * step] = sum / width;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squircle_icon_shape() {
+        let mut artwork = Image::new(PixelFormat::RGB, 64, 64);
+        for value in artwork.data_mut() {
+            *value = 200;
+        }
+        let icon = artwork.to_squircle_icon(256).unwrap();
+        let alpha = |x: usize, y: usize| icon.data()[(y * 256 + x) * 4 + 3];
+        // Outside the squircle: transparent, apart from the shadow.
+        assert_eq!(alpha(0, 0), 0);
+        assert_eq!(alpha(128, 0), 0);
+        assert!(alpha(128, 233) > 0 && alpha(128, 233) < 128);
+        assert_eq!(&icon.data()[(233 * 256 + 128) * 4..][..3], &[0, 0, 0]);
+        // The corner of the body square lies outside the squircle, but its
+        // edges don't.
+        assert!(alpha(27, 27) < 255);
+        assert_eq!(alpha(128, 27), 255);
+        assert_eq!(&icon.data()[(128 * 256 + 128) * 4..][..4],
+                   &[200, 200, 200, 255]);
+        let error = artwork.to_squircle_icon(0).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let tiny = artwork.to_squircle_icon(1).unwrap();
+        assert_eq!(tiny.width(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn squircle_icon_family() {
+        let artwork = Image::new(PixelFormat::RGBA, 32, 32);
+        let family = IconFamily::from_squircle_artwork(&artwork).unwrap();
+        assert_eq!(family.available_icons(), MASTER_ICON_TYPES.to_vec());
+        let artwork = Image::new(PixelFormat::RGBA, 32, 16);
+        let error = IconFamily::from_squircle_artwork(&artwork).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,186 @@
+//! [Proptest](https://docs.rs/proptest) strategies for generating images,
+//! icon types, and icon families.
+//!
+//! These strategies are used by this crate's own property tests, and are
+//! exported (when the `proptest` feature is enabled) so that downstream
+//! crates can property-test their own ICNS handling.
+
+use proptest::collection;
+use proptest::prelude::*;
+
+use super::family::IconFamily;
+use super::icontype::IconType;
+use super::image::{Image, PixelFormat};
+
+/// All non-mask icon types supported by this library.
+const ICON_TYPES: &[IconType] = &[IconType::RGB24_16x16,
+                                  IconType::RGB24_32x32,
+                                  IconType::RGB24_48x48,
+                                  IconType::RGB24_128x128,
+                                  IconType::RGBA32_16x16,
+                                  IconType::RGBA32_16x16_2x,
+                                  IconType::RGBA32_32x32,
+                                  IconType::RGBA32_32x32_2x,
+                                  IconType::RGBA32_64x64,
+                                  IconType::RGBA32_128x128,
+                                  IconType::RGBA32_128x128_2x,
+                                  IconType::RGBA32_256x256,
+                                  IconType::RGBA32_256x256_2x,
+                                  IconType::RGBA32_512x512,
+                                  IconType::RGBA32_512x512_2x];
+
+/// Generates any pixel format.
+pub fn pixel_format() -> impl Strategy<Value = PixelFormat> {
+    prop_oneof![Just(PixelFormat::RGBA),
+                Just(PixelFormat::RGB),
+                Just(PixelFormat::GrayAlpha),
+                Just(PixelFormat::Gray),
+                Just(PixelFormat::Alpha)]
+}
+
+/// Generates images with the given format and dimensions, and with
+/// arbitrary pixel data.
+pub fn image(format: PixelFormat,
+             width: u32,
+             height: u32)
+             -> impl Strategy<Value = Image> {
+    let num_bytes = (format.bits_per_pixel() / 8 * width * height) as usize;
+    collection::vec(any::<u8>(), num_bytes).prop_map(move |data| {
+        Image::from_data(format, width, height, data).unwrap()
+    })
+}
+
+/// Generates images with an arbitrary format, arbitrary pixel data, and a
+/// width and height of at most `max_size`.
+pub fn any_image(max_size: u32) -> impl Strategy<Value = Image> {
+    (pixel_format(), 0..(max_size + 1), 0..(max_size + 1))
+        .prop_flat_map(|(format, width, height)| image(format, width, height))
+}
+
+/// Generates non-mask icon types whose pixel width is at most `max_size`.
+/// Since encoding large PNG icons is relatively slow, it is often a good
+/// idea to limit the size of the icon types being tested.
+pub fn icon_type(max_size: u32) -> impl Strategy<Value = IconType> {
+    let types: Vec<IconType> = ICON_TYPES.iter()
+        .cloned()
+        .filter(|icon_type| icon_type.pixel_width() <= max_size)
+        .collect();
+    proptest::sample::select(types)
+}
+
+/// Generates RGBA images with arbitrary pixel data, sized to match the given
+/// icon type.
+pub fn image_for_icon_type(icon_type: IconType)
+                           -> impl Strategy<Value = Image> {
+    image(PixelFormat::RGBA,
+          icon_type.pixel_width(),
+          icon_type.pixel_height())
+}
+
+/// Generates icon families containing complete icons (with arbitrary pixel
+/// data) for a subset of the non-mask icon types whose pixel width is at
+/// most `max_size`, with the family's elements in an arbitrary order.
+pub fn icon_family(max_size: u32) -> impl Strategy<Value = IconFamily> {
+    collection::btree_set(icon_type_index(max_size), 0..4)
+        .prop_flat_map(|indices| {
+            let images: Vec<_> = indices.into_iter()
+                .map(|index| {
+                    let icon_type = ICON_TYPES[index];
+                    (Just(icon_type), image_for_icon_type(icon_type))
+                })
+                .collect();
+            images
+        })
+        .prop_map(|icons| {
+            let mut family = IconFamily::new();
+            for (icon_type, image) in icons {
+                family.add_icon_with_type(&image, icon_type).unwrap();
+            }
+            family.elements
+        })
+        .prop_shuffle()
+        .prop_map(IconFamily::from_elements)
+}
+
+/// Generates indices into `ICON_TYPES` for types no wider than `max_size`.
+fn icon_type_index(max_size: u32) -> impl Strategy<Value = usize> {
+    let indices: Vec<usize> = (0..ICON_TYPES.len())
+        .filter(|&index| ICON_TYPES[index].pixel_width() <= max_size)
+        .collect();
+    proptest::sample::select(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::element::IconElement;
+    #[cfg(feature = "pngio")]
+    use std::io::Cursor;
+
+    fn config() -> ProptestConfig {
+        ProptestConfig::with_cases(32)
+    }
+
+    proptest! {
+        #![proptest_config(config())]
+
+        #[test]
+        fn rle_round_trip(image in image(PixelFormat::RGB, 16, 16)) {
+            let element =
+                IconElement::encode_image_with_type(&image,
+                                                    IconType::RGB24_16x16)
+                    .unwrap();
+            let decoded = element.decode_image().unwrap();
+            prop_assert_eq!(decoded.data(), image.data());
+        }
+
+        #[test]
+        fn mask_round_trip(image in image(PixelFormat::Alpha, 32, 32)) {
+            let element =
+                IconElement::encode_image_with_type(&image,
+                                                    IconType::Mask8_32x32)
+                    .unwrap();
+            let decoded = element.decode_image().unwrap();
+            prop_assert_eq!(decoded.data(), image.data());
+        }
+
+        #[test]
+        fn convert_to_rgba_and_back(image in any_image(8)) {
+            // Converting to RGBA is lossless for color formats.
+            let rgba = image.convert_to(PixelFormat::RGBA);
+            let back = rgba.convert_to(image.pixel_format());
+            prop_assert_eq!(back.width(), image.width());
+            prop_assert_eq!(back.height(), image.height());
+            if matches!(image.pixel_format(),
+                        PixelFormat::RGBA | PixelFormat::RGB) {
+                prop_assert_eq!(back.data(), image.data());
+            }
+        }
+
+        #[test]
+        #[cfg(feature = "pngio")]
+        fn icon_round_trip((icon_type, image) in icon_type(32)
+                .prop_flat_map(|t| (Just(t), image_for_icon_type(t)))) {
+            let mut family = IconFamily::new();
+            family.add_icon_with_type(&image, icon_type).unwrap();
+            let decoded = family.get_icon_with_type(icon_type).unwrap();
+            let expected = image.convert_to(decoded.pixel_format());
+            prop_assert_eq!(decoded.data(), expected.data());
+        }
+
+        #[test]
+        #[cfg(feature = "pngio")]
+        fn family_write_read_round_trip(family in icon_family(32)) {
+            let mut data = Vec::new();
+            family.write(&mut data).unwrap();
+            let read = IconFamily::read(Cursor::new(&data)).unwrap();
+            prop_assert_eq!(read.available_icons().len(),
+                            family.available_icons().len());
+            for icon_type in family.available_icons() {
+                let original = family.get_icon_with_type(icon_type).unwrap();
+                let decoded = read.get_icon_with_type(icon_type).unwrap();
+                prop_assert_eq!(original.data(), decoded.data());
+            }
+        }
+    }
+}
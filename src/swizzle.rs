@@ -0,0 +1,127 @@
+use std::io::{self, Error, ErrorKind};
+
+use super::image::{Image, PixelFormat};
+
+/// An order in which the four channels of an RGBA pixel can be stored, for
+/// use with [`Image::swizzle`](struct.Image.html#method.swizzle).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChannelOrder {
+    /// Red, green, blue, alpha (the order used by `PixelFormat::RGBA`).
+    RGBA,
+    /// Blue, green, red, alpha (as used by e.g. Direct3D and Metal's
+    /// preferred `BGRA8Unorm` texture formats).
+    BGRA,
+    /// Alpha, red, green, blue (as used by e.g. the `ic04` element's ARGB
+    /// data, and big-endian 32-bit ARGB pixels).
+    ARGB,
+    /// Alpha, blue, green, red (as used by e.g. little-endian 32-bit RGBA
+    /// pixels).
+    ABGR,
+}
+
+impl ChannelOrder {
+    /// Returns, for each channel position in this order, the index of the
+    /// corresponding channel in an RGBA pixel.
+    fn rgba_indices(self) -> [usize; 4] {
+        match self {
+            ChannelOrder::RGBA => [0, 1, 2, 3],
+            ChannelOrder::BGRA => [2, 1, 0, 3],
+            ChannelOrder::ARGB => [3, 0, 1, 2],
+            ChannelOrder::ABGR => [3, 2, 1, 0],
+        }
+    }
+}
+
+impl Image {
+    /// Reorders the channels of each pixel of this RGBA image, in place,
+    /// into the given order, so that the pixel data can be passed directly
+    /// to an API (such as a GPU texture upload) that expects that order.
+    /// The image's pixel format remains `RGBA`, so until the channels are
+    /// put back with [`unswizzle`](#method.unswizzle), other methods will
+    /// misinterpret them.  Returns an error if the image isn't in `RGBA`
+    /// format.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{ChannelOrder, Image, PixelFormat};
+    /// let mut image = Image::from_data(PixelFormat::RGBA, 1, 1,
+    ///                                  vec![1, 2, 3, 4]).unwrap();
+    /// image.swizzle(ChannelOrder::BGRA).unwrap();
+    /// assert_eq!(image.data(), &[3, 2, 1, 4]);
+    /// image.unswizzle(ChannelOrder::BGRA).unwrap();
+    /// image.swizzle(ChannelOrder::ARGB).unwrap();
+    /// assert_eq!(image.data(), &[4, 1, 2, 3]);
+    /// ```
+    pub fn swizzle(&mut self, order: ChannelOrder) -> io::Result<()> {
+        let indices = order.rgba_indices();
+        self.permute_channels(|pixel| {
+            [pixel[indices[0]],
+             pixel[indices[1]],
+             pixel[indices[2]],
+             pixel[indices[3]]]
+        })
+    }
+
+    /// Reverses [`swizzle`](#method.swizzle), reordering the channels of each
+    /// pixel, in place, from the given order back into RGBA order (for
+    /// example, after reading pixel data back from a GPU texture into an
+    /// RGBA image).  Returns an error if the image isn't in `RGBA` format.
+    pub fn unswizzle(&mut self, order: ChannelOrder) -> io::Result<()> {
+        let indices = order.rgba_indices();
+        self.permute_channels(|pixel| {
+            let mut rgba = [0u8; 4];
+            for (position, &index) in indices.iter().enumerate() {
+                rgba[index] = pixel[position];
+            }
+            rgba
+        })
+    }
+
+    /// Private helper method: replaces each pixel of this RGBA image with
+    /// the result of `permute`.
+    fn permute_channels<F>(&mut self, permute: F) -> io::Result<()>
+        where F: Fn(&[u8]) -> [u8; 4]
+    {
+        if self.format != PixelFormat::RGBA {
+            let msg = format!("can only swizzle RGBA images, not {:?}",
+                              self.format);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        for pixel in self.data.chunks_mut(4) {
+            let permuted = permute(pixel);
+            pixel.copy_from_slice(&permuted);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swizzle_round_trips() {
+        let data = vec![10, 20, 30, 40, 50, 60, 70, 80];
+        let original = Image::from_data(PixelFormat::RGBA, 2, 1, data)
+            .unwrap();
+        let expected: [(ChannelOrder, [u8; 4]); 4] =
+            [(ChannelOrder::RGBA, [10, 20, 30, 40]),
+             (ChannelOrder::BGRA, [30, 20, 10, 40]),
+             (ChannelOrder::ARGB, [40, 10, 20, 30]),
+             (ChannelOrder::ABGR, [40, 30, 20, 10])];
+        for &(order, first_pixel) in expected.iter() {
+            let mut image = original.clone();
+            image.swizzle(order).unwrap();
+            assert_eq!(&image.data()[0..4], &first_pixel);
+            image.unswizzle(order).unwrap();
+            assert_eq!(image, original);
+        }
+    }
+
+    #[test]
+    fn swizzle_requires_rgba() {
+        let mut image = Image::new(PixelFormat::RGB, 2, 2);
+        let error = image.swizzle(ChannelOrder::BGRA).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,206 @@
+use std::io;
+
+use super::family::IconFamily;
+use super::size::IconSize;
+
+/// A place where an application icon is displayed (or submitted), used to
+/// decide which icon sizes a family needs to contain, for use with
+/// [`IconFamily::missing_recommended_types`](
+/// struct.IconFamily.html#method.missing_recommended_types).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IconTarget {
+    /// The Dock, which shows icons at up to 128 points (or more, with
+    /// magnification), and so needs 128x128, 256x256, and 512x512 icons at
+    /// both 1x and 2x density.
+    Dock,
+    /// Finder, which shows icons at every size from 16 points (in list view)
+    /// up to 256 points (in icon view), and so needs 16x16, 32x32, 128x128,
+    /// and 256x256 icons at both 1x and 2x density.
+    Finder,
+    /// Submission to the Mac App Store, which requires the complete set
+    /// produced by `iconutil`: 16x16, 32x32, 128x128, 256x256, and 512x512,
+    /// each at both 1x and 2x density.
+    AppStore,
+}
+
+impl IconTarget {
+    /// Returns the icon sizes that this target calls for, in order.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconSize, IconTarget};
+    /// assert_eq!(IconTarget::AppStore.recommended_sizes().len(), 10);
+    /// assert_eq!(IconTarget::Dock.recommended_sizes()[0],
+    ///            IconSize::new(128, 1));
+    /// ```
+    pub fn recommended_sizes(self) -> Vec<IconSize> {
+        let points: &[u32] = match self {
+            IconTarget::Dock => &[128, 256, 512],
+            IconTarget::Finder => &[16, 32, 128, 256],
+            IconTarget::AppStore => &[16, 32, 128, 256, 512],
+        };
+        points.iter()
+            .flat_map(|&points| {
+                vec![IconSize::new(points, 1), IconSize::new(points, 2)]
+            })
+            .collect()
+    }
+}
+
+impl IconFamily {
+    /// Returns the icon sizes recommended for the given target that the
+    /// family does not contain a complete icon for (in any encoding), in
+    /// order.  An empty result means the family is complete for that target.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconSize, IconTarget, IconType, Image,
+    ///            PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 1024, 1024);
+    /// family.add_icon_with_type(&image, IconType::RGBA32_512x512_2x)
+    ///     .unwrap();
+    /// assert_eq!(family.missing_recommended_types(IconTarget::Dock),
+    ///            vec![IconSize::new(128, 1),
+    ///                 IconSize::new(128, 2),
+    ///                 IconSize::new(256, 1),
+    ///                 IconSize::new(256, 2),
+    ///                 IconSize::new(512, 1)]);
+    /// # }
+    /// ```
+    pub fn missing_recommended_types(&self,
+                                     target: IconTarget)
+                                     -> Vec<IconSize> {
+        let available = self.available_sizes();
+        target.recommended_sizes()
+            .into_iter()
+            .filter(|size| !available.contains(size))
+            .collect()
+    }
+
+    /// Fills in the icon sizes [missing](#method.missing_recommended_types)
+    /// for the given target by downscaling the nearest larger icon in the
+    /// family (that is, the one with the fewest pixels that still has at
+    /// least as many as the missing size).  Sizes with no larger source are
+    /// left missing, unless `allow_upscale` is true, in which case they are
+    /// scaled up from the largest icon in the family.  Returns the sizes that
+    /// were added, in order.  If any source icon fails to decode, returns an
+    /// error and leaves the family unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, IconSize, IconTarget, IconType, Image,
+    ///            PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 512, 512);
+    /// family.add_icon_with_type(&image, IconType::RGBA32_512x512).unwrap();
+    /// let target = IconTarget::AppStore;
+    /// let added = family.generate_missing_sizes(target, false).unwrap();
+    /// assert_eq!(added.len(), 8);
+    /// assert_eq!(family.missing_recommended_types(target),
+    ///            vec![IconSize::new(512, 2)]);
+    /// # }
+    /// ```
+    pub fn generate_missing_sizes(&mut self,
+                                  target: IconTarget,
+                                  allow_upscale: bool)
+                                  -> io::Result<Vec<IconSize>> {
+        let mut updated = self.clone();
+        let mut added = Vec::new();
+        for size in self.missing_recommended_types(target) {
+            let icon_type = match size.icon_type() {
+                Some(icon_type) => icon_type,
+                None => continue,
+            };
+            let pixels = size.pixel_size();
+            let source = match self.best_source_for_size(pixels,
+                                                         allow_upscale) {
+                Some(source) => source,
+                None => continue,
+            };
+            let image = self.get_icon_with_type(source)?;
+            updated.add_icon_with_type(&image.resize(pixels, pixels),
+                                       icon_type)?;
+            added.push(size);
+        }
+        self.elements = updated.elements;
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "pngio")]
+    use super::super::icontype::IconType;
+    #[cfg(feature = "pngio")]
+    use super::super::image::{Image, PixelFormat};
+    #[cfg(feature = "pngio")]
+    use super::super::typeset::TypeSet;
+
+    #[test]
+    fn recommended_sizes_have_icon_types() {
+        for &target in &[IconTarget::Dock,
+                         IconTarget::Finder,
+                         IconTarget::AppStore] {
+            for size in target.recommended_sizes() {
+                assert!(size.icon_type().is_some(), "{}", size);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn apple_recommended_family_is_complete() {
+        let image = Image::new(PixelFormat::RGBA, 1024, 1024);
+        let mut family = IconFamily::new();
+        assert_eq!(family.missing_recommended_types(IconTarget::Finder).len(),
+                   8);
+        family.add_icons_for_types(&image, TypeSet::APPLE_RECOMMENDED)
+            .unwrap();
+        for &target in &[IconTarget::Dock,
+                         IconTarget::Finder,
+                         IconTarget::AppStore] {
+            assert!(family.missing_recommended_types(target).is_empty());
+        }
+        // An incomplete RLE icon (without its mask) doesn't count:
+        family.elements.retain(|element| element.ostype.0 != *b"s8mk");
+        assert_eq!(family.missing_recommended_types(IconTarget::AppStore),
+                   vec![IconSize::new(16, 1)]);
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn generate_missing_sizes_from_nearest_larger() {
+        let mut family = IconFamily::new();
+        let mut image = Image::new(PixelFormat::RGBA, 64, 64);
+        for pixel in image.data_mut().chunks_mut(4) {
+            pixel.copy_from_slice(&[200, 100, 50, 255]);
+        }
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32_2x).unwrap();
+        let added = family.generate_missing_sizes(IconTarget::Finder, false)
+            .unwrap();
+        assert_eq!(added,
+                   vec![IconSize::new(16, 1),
+                        IconSize::new(16, 2),
+                        IconSize::new(32, 1)]);
+        let icon = family.get_icon_with_type(IconType::RGB24_16x16).unwrap();
+        assert_eq!(&icon.data()[..4], &[200, 100, 50, 255]);
+        let added = family.generate_missing_sizes(IconTarget::Finder, true)
+            .unwrap();
+        assert_eq!(added.len(), 4);
+        assert!(family.missing_recommended_types(IconTarget::Finder)
+            .is_empty());
+    }
+
+    #[test]
+    fn generate_missing_sizes_in_empty_family() {
+        let mut family = IconFamily::new();
+        assert!(family.generate_missing_sizes(IconTarget::Dock, true)
+            .unwrap()
+            .is_empty());
+        assert!(family.is_empty());
+    }
+}
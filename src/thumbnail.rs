@@ -0,0 +1,162 @@
+use rayon::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::family::IconFamily;
+use super::image::Image;
+
+/// The icon extracted from a single file by
+/// [`IconFamily::thumbnails_in_dir`](
+/// struct.IconFamily.html#method.thumbnails_in_dir) or
+/// [`IconFamily::thumbnails_for_paths`](
+/// struct.IconFamily.html#method.thumbnails_for_paths).
+#[derive(Debug)]
+pub struct Thumbnail {
+    /// The path of the ICNS file (or application bundle).
+    pub path: PathBuf,
+    /// The icon, scaled to the requested size, or the error that occurred
+    /// while reading or decoding it.
+    pub image: io::Result<Image>,
+}
+
+impl IconFamily {
+    /// Walks the directory tree under `dir` and extracts, in parallel, the
+    /// icon best suited for display at `size` by `size` pixels (as
+    /// [`preview_at`](#method.preview_at) would) from every ICNS file found.
+    /// When the `bundle` feature is enabled, the icons of application
+    /// bundles (`.app` directories, which aren't descended into) are
+    /// extracted too.  Symbolic links to directories aren't followed.
+    ///
+    /// The results are sorted by path.  An error reading or decoding any one
+    /// file (or listing any subdirectory) is reported in that file's
+    /// [`Thumbnail`](struct.Thumbnail.html), and doesn't stop the others
+    /// from being processed; this method only returns an error if `dir`
+    /// itself can't be read.
+    ///
+    /// This method is only available when the `rayon` feature is enabled.
+    pub fn thumbnails_in_dir<P: AsRef<Path>>(dir: P,
+                                             size: u32)
+                                             -> io::Result<Vec<Thumbnail>> {
+        let mut paths = Vec::new();
+        let mut failures = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            collect_icon_paths(entry?.path(), &mut paths, &mut failures);
+        }
+        let mut thumbnails = IconFamily::thumbnails_for_paths(&paths, size);
+        thumbnails.extend(failures.into_iter().map(|(path, error)| {
+            Thumbnail {
+                path,
+                image: Err(error),
+            }
+        }));
+        thumbnails.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(thumbnails)
+    }
+
+    /// Extracts, in parallel, the icon best suited for display at `size` by
+    /// `size` pixels from each of the given ICNS files (or, when the
+    /// `bundle` feature is enabled, application bundles), returning the
+    /// results in the same order as the paths.  As for
+    /// [`thumbnails_in_dir`](#method.thumbnails_in_dir), an error for one
+    /// file doesn't affect the others.
+    ///
+    /// This method is only available when the `rayon` feature is enabled.
+    pub fn thumbnails_for_paths<P>(paths: &[P], size: u32) -> Vec<Thumbnail>
+        where P: AsRef<Path> + Sync
+    {
+        paths.par_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                Thumbnail {
+                    path: path.to_path_buf(),
+                    image: read_icon_file(path)
+                        .and_then(|family| family.preview_at(size)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Adds `path` to `paths` if it's an ICNS file or application bundle, or
+/// else recurses into it if it's a directory.  Errors listing directories
+/// are added to `failures`.
+fn collect_icon_paths(path: PathBuf,
+                      paths: &mut Vec<PathBuf>,
+                      failures: &mut Vec<(PathBuf, io::Error)>) {
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(error) => return failures.push((path, error)),
+    };
+    if is_bundle(&path) && metadata.is_dir() {
+        paths.push(path);
+    } else if metadata.is_dir() {
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(error) => return failures.push((path, error)),
+        };
+        for entry in entries {
+            match entry {
+                Ok(entry) => collect_icon_paths(entry.path(), paths, failures),
+                Err(error) => failures.push((path.clone(), error)),
+            }
+        }
+    } else if has_extension(&path, "icns") {
+        paths.push(path);
+    }
+}
+
+/// Returns true if the path has the given extension (ignoring case).
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// Returns true if the path names an application bundle that can be read.
+fn is_bundle(path: &Path) -> bool {
+    cfg!(feature = "bundle") && has_extension(path, "app")
+}
+
+/// Reads the icon family from an ICNS file or application bundle.
+fn read_icon_file(path: &Path) -> io::Result<IconFamily> {
+    #[cfg(feature = "bundle")]
+    {
+        if path.is_dir() {
+            return IconFamily::read_app_bundle(path);
+        }
+    }
+    IconFamily::read(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::TempDir;
+    use super::super::icontype::IconType;
+    use super::super::image::PixelFormat;
+
+    #[test]
+    fn thumbnails_in_directory_tree() {
+        let temp = TempDir::new("thumbnails");
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        fs::write(dir.join("nested").join("good.ICNS"), &data).unwrap();
+        fs::write(dir.join("bad.icns"), b"not an icns file").unwrap();
+        fs::write(dir.join("readme.txt"), b"ignored").unwrap();
+        let thumbnails = IconFamily::thumbnails_in_dir(dir, 16).unwrap();
+        assert_eq!(thumbnails.len(), 2);
+        assert_eq!(thumbnails[0].path, dir.join("bad.icns"));
+        assert!(thumbnails[0].image.is_err());
+        assert_eq!(thumbnails[1].path, dir.join("nested").join("good.ICNS"));
+        let thumbnail = thumbnails[1].image.as_ref().unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (16, 16));
+        let missing = IconFamily::thumbnails_in_dir(dir.join("missing"), 16);
+        assert!(missing.is_err());
+    }
+}
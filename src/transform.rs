@@ -0,0 +1,418 @@
+use std::io;
+
+use super::family::{DuplicatePolicy, IconFamily};
+use super::icontype::IconType;
+use super::image::{Image, PixelFormat};
+
+/// How [`Image::tint`](struct.Image.html#method.tint) combines a tint color
+/// with each pixel of an image.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BlendMode {
+    /// Multiplies each color channel by the tint color, so that white
+    /// becomes the tint color and black stays black.  This is the usual
+    /// choice for recoloring a light, neutral master icon.
+    Multiply,
+    /// The inverse of `Multiply`: black becomes the tint color and white
+    /// stays white.
+    Screen,
+    /// Replaces the color of every pixel with the tint color, keeping only
+    /// the alpha channel.  This is useful for turning a glyph or silhouette
+    /// into a solid color.
+    Replace,
+}
+
+impl BlendMode {
+    /// Blends a single color channel value with the tint value, both in the
+    /// range 0.0 to 1.0.
+    pub(crate) fn blend(self, value: f32, tint: f32) -> f32 {
+        match self {
+            BlendMode::Multiply => value * tint,
+            BlendMode::Screen => 1.0 - (1.0 - value) * (1.0 - tint),
+            BlendMode::Replace => tint,
+        }
+    }
+}
+
+impl Image {
+    /// Creates a copy of this image recolored with the given RGB tint color,
+    /// blended with each pixel according to the given blend mode.  The
+    /// result is always in RGBA format, with the same alpha channel as the
+    /// original image.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{BlendMode, Image, PixelFormat};
+    /// let image = Image::from_data(PixelFormat::GrayAlpha, 1, 1,
+    ///                              vec![255, 128]).unwrap();
+    /// let tinted = image.tint([255, 0, 64], BlendMode::Multiply);
+    /// assert_eq!(tinted.data(), &[255, 0, 64, 128]);
+    /// ```
+    pub fn tint(&self, color: [u8; 3], mode: BlendMode) -> Image {
+        self.to_float()
+            .tint(unit_color(color), mode)
+            .to_image(PixelFormat::RGBA)
+    }
+
+    /// Creates an opaque copy of this image by alpha-compositing it over a
+    /// solid background of the given RGB color.  The result is always in RGB
+    /// format.  This is preferable to simply converting an image with
+    /// transparency to RGB (which discards the alpha channel, leaving the
+    /// colors of transparent and partially transparent pixels exposed).
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let image = Image::from_data(PixelFormat::RGBA, 2, 1,
+    ///                              vec![0, 0, 0, 0, 0, 0, 0, 255]).unwrap();
+    /// let flat = image.flatten_onto([255, 255, 255]);
+    /// assert_eq!(flat.pixel_format(), PixelFormat::RGB);
+    /// assert_eq!(flat.data(), &[255, 255, 255, 0, 0, 0]);
+    /// ```
+    pub fn flatten_onto(&self, color: [u8; 3]) -> Image {
+        self.to_float()
+            .flatten_onto(unit_color(color))
+            .to_image(PixelFormat::RGB)
+    }
+
+    /// Creates a copy of this image cropped to the smallest rectangle that
+    /// contains every pixel that isn't fully transparent.  Images in formats
+    /// without an alpha channel are returned unchanged, and a completely
+    /// transparent image is cropped down to 0x0.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let mut image = Image::new(PixelFormat::GrayAlpha, 4, 4);
+    /// image.data_mut()[2 * (4 + 1) + 1] = 255; // pixel (1, 1) is opaque
+    /// let trimmed = image.trim_transparent_border();
+    /// assert_eq!((trimmed.width(), trimmed.height()), (1, 1));
+    /// ```
+    pub fn trim_transparent_border(&self) -> Image {
+        let alpha = self.convert_to(PixelFormat::Alpha);
+        let width = self.width as usize;
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for (index, &value) in alpha.data().iter().enumerate() {
+            if value == 0 {
+                continue;
+            }
+            let x = (index % width) as u32;
+            let y = (index / width) as u32;
+            bounds = Some(match bounds {
+                None => (x, y, x, y),
+                Some((left, top, right, bottom)) => {
+                    (left.min(x), top.min(y), right.max(x), bottom.max(y))
+                }
+            });
+        }
+        match bounds {
+            None => Image::new(self.format, 0, 0),
+            Some((left, top, right, bottom)) => {
+                self.crop(left, top, right - left + 1, bottom - top + 1)
+            }
+        }
+    }
+
+    /// Trims the transparent border from this image (as with
+    /// [`trim_transparent_border`](#method.trim_transparent_border)), then
+    /// scales what remains (preserving its aspect ratio) to fit within a
+    /// `width` by `height` image, leaving at least `margin` pixels of empty
+    /// space on each side, and centers it there.  The padding is zeroed,
+    /// which is transparent for pixel formats with an alpha channel.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{Image, PixelFormat};
+    /// let mut image = Image::new(PixelFormat::RGBA, 100, 100);
+    /// for pixel in image.data_mut().chunks_mut(4).take(10) {
+    ///     pixel[3] = 255; // the top-left 10x1 pixels are opaque
+    /// }
+    /// let icon = image.fit_with_margin(64, 64, 2);
+    /// assert_eq!((icon.width(), icon.height()), (64, 64));
+    /// ```
+    pub fn fit_with_margin(&self,
+                           width: u32,
+                           height: u32,
+                           margin: u32)
+                           -> Image {
+        let mut output = Image::new(self.format, width, height);
+        let trimmed = self.trim_transparent_border();
+        let max_width = width.saturating_sub(margin.saturating_mul(2));
+        let max_height = height.saturating_sub(margin.saturating_mul(2));
+        if trimmed.width == 0 || trimmed.height == 0 || max_width == 0 ||
+           max_height == 0 {
+            return output;
+        }
+        let scale = (max_width as f64 / trimmed.width as f64)
+            .min(max_height as f64 / trimmed.height as f64);
+        let scaled_width = ((trimmed.width as f64 * scale).round() as u32)
+            .clamp(1, max_width);
+        let scaled_height = ((trimmed.height as f64 * scale).round() as u32)
+            .clamp(1, max_height);
+        let scaled = trimmed.resize(scaled_width, scaled_height);
+        output.paste(&scaled,
+                     (width - scaled_width) / 2,
+                     (height - scaled_height) / 2);
+        output
+    }
+
+    /// Returns the `width` by `height` region of this image whose top-left
+    /// corner is at (`left`, `top`).  The region must lie within the image.
+    pub(crate) fn crop(&self,
+                       left: u32,
+                       top: u32,
+                       width: u32,
+                       height: u32)
+                       -> Image {
+        let bytes_per_pixel = (self.format.bits_per_pixel() / 8) as usize;
+        let src_stride = self.width as usize * bytes_per_pixel;
+        let row_len = width as usize * bytes_per_pixel;
+        let mut output = Image::new(self.format, width, height);
+        for (row, out) in output.data.chunks_mut(row_len).enumerate() {
+            let start = (top as usize + row) * src_stride +
+                        left as usize * bytes_per_pixel;
+            out.copy_from_slice(&self.data[start..(start + row_len)]);
+        }
+        output
+    }
+
+    /// Copies `other` (which must have the same pixel format, and fit within
+    /// this image) into this image, with its top-left corner at (`left`,
+    /// `top`).
+    pub(crate) fn paste(&mut self, other: &Image, left: u32, top: u32) {
+        debug_assert_eq!(self.format, other.format);
+        let bytes_per_pixel = (self.format.bits_per_pixel() / 8) as usize;
+        let dst_stride = self.width as usize * bytes_per_pixel;
+        let row_len = other.width as usize * bytes_per_pixel;
+        if row_len == 0 {
+            return;
+        }
+        for (row, src) in other.data.chunks(row_len).enumerate() {
+            let start = (top as usize + row) * dst_stride +
+                        left as usize * bytes_per_pixel;
+            self.data[start..(start + row_len)].copy_from_slice(src);
+        }
+    }
+}
+
+impl IconFamily {
+    /// Recolors every (complete) icon in the family with
+    /// [`Image::tint`](struct.Image.html#method.tint), re-encoding each one
+    /// with its original icon type.  Returns an error if any icon can't be
+    /// decoded or re-encoded, in which case the family is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{BlendMode, IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let mut image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// for value in image.data_mut() {
+    ///     *value = 255;
+    /// }
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// family.tint_icons([0, 128, 255], BlendMode::Multiply).unwrap();
+    /// let icon = family.get_icon_with_type(IconType::RGB24_16x16).unwrap();
+    /// assert_eq!(&icon.data()[0..4], &[0, 128, 255, 255]);
+    /// ```
+    pub fn tint_icons(&mut self,
+                      color: [u8; 3],
+                      mode: BlendMode)
+                      -> io::Result<()> {
+        self.map_icons(|_, image| image.tint(color, mode))
+    }
+
+    /// Makes every (complete) icon in the family opaque with
+    /// [`Image::flatten_onto`](struct.Image.html#method.flatten_onto),
+    /// re-encoding each one with its original icon type (so any separate
+    /// mask elements become fully opaque).  Returns an error if any icon
+    /// can't be decoded or re-encoded, in which case the family is left
+    /// unchanged.
+    pub fn flatten_icons_onto(&mut self, color: [u8; 3]) -> io::Result<()> {
+        self.map_icons(|_, image| image.flatten_onto(color))
+    }
+
+    /// Replaces every (complete) icon in the family with the result of
+    /// applying `transform` to its decoded image, re-encoding each result
+    /// with the same icon type (so that the color and mask elements of the
+    /// legacy types are both updated).  The callback is given each icon's
+    /// type along with its image.  Returns an error if any icon can't be
+    /// decoded or re-encoded (e.g. if the callback changes the image's
+    /// dimensions), in which case the family is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 32, 32);
+    /// family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// // Draw a watermark pixel in the corner of each icon:
+    /// family.map_icons(|_icon_type, image| {
+    ///     let mut image = image.convert_to(PixelFormat::RGBA);
+    ///     image.data_mut()[0..4].copy_from_slice(&[255, 0, 0, 255]);
+    ///     image
+    /// }).unwrap();
+    /// for icon_type in family.available_icons() {
+    ///     let icon = family.get_icon_with_type(icon_type).unwrap();
+    ///     assert_eq!(&icon.data()[0..4], &[255, 0, 0, 255]);
+    /// }
+    /// ```
+    pub fn map_icons<F>(&mut self, mut transform: F) -> io::Result<()>
+        where F: FnMut(IconType, &Image) -> Image
+    {
+        let mut updated = self.clone();
+        updated.set_duplicate_policy(DuplicatePolicy::Replace);
+        for icon_type in self.available_icons() {
+            let image = self.get_icon_with_type(icon_type)?;
+            updated.add_icon_with_type(&transform(icon_type, &image),
+                                       icon_type)?;
+        }
+        self.elements = updated.elements;
+        Ok(())
+    }
+}
+
+/// Maps an 8-bit RGB color to the range 0.0 to 1.0.
+fn unit_color(color: [u8; 3]) -> [f32; 3] {
+    [f32::from(color[0]) / 255.0,
+     f32::from(color[1]) / 255.0,
+     f32::from(color[2]) / 255.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tint_blend_modes() {
+        let image = Image::from_data(PixelFormat::RGBA,
+                                     2,
+                                     1,
+                                     vec![0, 0, 0, 255, 255, 255, 255, 64])
+            .unwrap();
+        let color = [200, 100, 0];
+        assert_eq!(image.tint(color, BlendMode::Multiply).data(),
+                   &[0, 0, 0, 255, 200, 100, 0, 64]);
+        assert_eq!(image.tint(color, BlendMode::Screen).data(),
+                   &[200, 100, 0, 255, 255, 255, 255, 64]);
+        assert_eq!(image.tint(color, BlendMode::Replace).data(),
+                   &[200, 100, 0, 255, 200, 100, 0, 64]);
+    }
+
+    #[test]
+    fn flatten_onto_background() {
+        let image = Image::from_data(PixelFormat::GrayAlpha,
+                                     3,
+                                     1,
+                                     vec![0, 0, 0, 255, 255, 128])
+            .unwrap();
+        let flat = image.flatten_onto([200, 100, 0]);
+        assert_eq!(flat.pixel_format(), PixelFormat::RGB);
+        assert_eq!(flat.data(), &[200, 100, 0, 0, 0, 0, 228, 178, 128]);
+    }
+
+    #[test]
+    fn flatten_icon_family() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        family.flatten_icons_onto([255, 0, 0]).unwrap();
+        let mask = family.get_mask_with_type(IconType::RGB24_16x16).unwrap();
+        assert!(mask.data().iter().all(|&alpha| alpha == 255));
+        let icon = family.get_icon_with_type(IconType::RGB24_16x16).unwrap();
+        assert!(icon.data().chunks(4).all(|pixel| {
+            pixel == [255, 0, 0, 255]
+        }));
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn tint_icon_family() {
+        let mut family = IconFamily::new();
+        let image = Image::from_data(PixelFormat::GrayAlpha,
+                                     16,
+                                     16,
+                                     vec![255; 512])
+            .unwrap();
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        family.add_icon_with_type(&image.resize(32, 32),
+                                  IconType::RGBA32_16x16_2x)
+            .unwrap();
+        let num_elements = family.elements.len();
+        family.tint_icons([10, 20, 30], BlendMode::Replace).unwrap();
+        assert_eq!(family.elements.len(), num_elements);
+        for icon_type in family.available_icons() {
+            let icon = family.get_icon_with_type(icon_type).unwrap();
+            assert!(icon.data().chunks(4).all(|pixel| {
+                pixel == [10, 20, 30, 255]
+            }));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn map_icons_failure_leaves_family_unchanged() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_16x16_2x).unwrap();
+        let original: Vec<Vec<u8>> =
+            family.elements.iter().map(|el| el.data.clone()).collect();
+        let mut seen = Vec::new();
+        let result = family.map_icons(|icon_type, image| {
+            seen.push(icon_type);
+            if icon_type == IconType::RGBA32_16x16_2x {
+                Image::new(PixelFormat::RGBA, 16, 16)
+            } else {
+                image.tint([255, 0, 0], BlendMode::Replace)
+            }
+        });
+        assert!(result.is_err());
+        assert_eq!(seen.len(), 2);
+        let data: Vec<Vec<u8>> =
+            family.elements.iter().map(|el| el.data.clone()).collect();
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn trim_transparent_border() {
+        let mut image = Image::new(PixelFormat::RGBA, 5, 4);
+        for &(x, y) in &[(1, 1), (3, 2)] {
+            let index = (y * 5 + x) * 4;
+            image.data_mut()[index..(index + 4)]
+                .copy_from_slice(&[10, 20, 30, 255]);
+        }
+        let trimmed = image.trim_transparent_border();
+        assert_eq!((trimmed.width(), trimmed.height()), (3, 2));
+        assert_eq!(&trimmed.data()[0..4], &[10, 20, 30, 255]);
+        assert_eq!(&trimmed.data()[20..24], &[10, 20, 30, 255]);
+        let blank = Image::new(PixelFormat::RGBA, 5, 4);
+        let trimmed = blank.trim_transparent_border();
+        assert_eq!((trimmed.width(), trimmed.height()), (0, 0));
+        let opaque = Image::new(PixelFormat::RGB, 5, 4);
+        let trimmed = opaque.trim_transparent_border();
+        assert_eq!((trimmed.width(), trimmed.height()), (5, 4));
+    }
+
+    #[test]
+    fn fit_with_margin() {
+        let mut image = Image::new(PixelFormat::GrayAlpha, 20, 20);
+        // An opaque 4x2 rectangle, off-center.
+        for y in 2..4 {
+            for x in 10..14 {
+                image.data_mut()[(y * 20 + x) * 2 + 1] = 255;
+            }
+        }
+        let icon = image.fit_with_margin(16, 16, 2);
+        assert_eq!((icon.width(), icon.height()), (16, 16));
+        let trimmed = icon.trim_transparent_border();
+        assert_eq!((trimmed.width(), trimmed.height()), (12, 6));
+        assert!(trimmed.data().chunks(2).all(|pixel| pixel[1] == 255));
+        let blank = Image::new(PixelFormat::RGBA, 8, 8);
+        let icon = blank.fit_with_margin(16, 16, 2);
+        assert_eq!(icon.data(), Image::new(PixelFormat::RGBA, 16, 16).data());
+        // A huge margin leaves no room, rather than overflowing:
+        let icon = image.fit_with_margin(16, 16, u32::MAX);
+        assert!(icon.data().chunks(2).all(|pixel| pixel[1] == 0));
+    }
+}
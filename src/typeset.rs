@@ -0,0 +1,167 @@
+use std::io::{self, Error, ErrorKind};
+
+use super::family::{IconFamily, MASTER_ICON_TYPES};
+use super::icontype::IconType;
+use super::image::Image;
+
+/// A named set of (non-mask) icon types to include in an icon family, for
+/// use with [`IconFamily::add_icons_for_types`](
+/// struct.IconFamily.html#method.add_icons_for_types).
+///
+/// # Examples
+/// ```
+/// use icns::{IconType, TypeSet};
+/// assert_eq!(TypeSet::APPLE_RECOMMENDED.icon_types().len(), 10);
+/// assert!(TypeSet::MINIMAL.contains(IconType::RGBA32_512x512_2x));
+/// const CUSTOM: TypeSet = TypeSet::new(&[IconType::RGBA32_256x256]);
+/// assert_eq!(CUSTOM.icon_types(), &[IconType::RGBA32_256x256]);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TypeSet {
+    icon_types: &'static [IconType],
+}
+
+impl TypeSet {
+    /// The types that Apple recommends for application icons, as produced
+    /// by `iconutil` from a complete `.iconset`: 16x16, 32x32, 128x128,
+    /// 256x256, and 512x512, each at both 1x and 2x density.  This is the
+    /// same set used by [`IconFamily::from_master_image`](
+    /// struct.IconFamily.html#method.from_master_image).
+    pub const APPLE_RECOMMENDED: TypeSet = TypeSet::new(MASTER_ICON_TYPES);
+
+    /// Every PNG-based icon type, which is the set typically generated by
+    /// app bundlers (such as Tauri's) that don't need to support Mac OS X
+    /// versions older than 10.7.
+    pub const TAURI_DEFAULT: TypeSet =
+        TypeSet::new(&[IconType::RGBA32_16x16,
+                       IconType::RGBA32_16x16_2x,
+                       IconType::RGBA32_32x32,
+                       IconType::RGBA32_32x32_2x,
+                       IconType::RGBA32_64x64,
+                       IconType::RGBA32_128x128,
+                       IconType::RGBA32_128x128_2x,
+                       IconType::RGBA32_256x256,
+                       IconType::RGBA32_256x256_2x,
+                       IconType::RGBA32_512x512,
+                       IconType::RGBA32_512x512_2x]);
+
+    /// A small set of PNG-based types that still looks reasonable at every
+    /// size that Finder and the Dock display (with the system scaling the
+    /// nearest icon as needed): 32x32, 128x128, and 512x512@2x.
+    pub const MINIMAL: TypeSet = TypeSet::new(&[IconType::RGBA32_32x32,
+                                                IconType::RGBA32_128x128,
+                                                IconType::RGBA32_512x512_2x]);
+
+    /// Creates a custom type set.
+    pub const fn new(icon_types: &'static [IconType]) -> TypeSet {
+        TypeSet { icon_types }
+    }
+
+    /// Returns the icon types in this set.
+    pub fn icon_types(self) -> &'static [IconType] {
+        self.icon_types
+    }
+
+    /// Returns true if this set includes the given icon type.
+    pub fn contains(self, icon_type: IconType) -> bool {
+        self.icon_types.contains(&icon_type)
+    }
+}
+
+impl AsRef<[IconType]> for TypeSet {
+    fn as_ref(&self) -> &[IconType] {
+        self.icon_types
+    }
+}
+
+impl IconFamily {
+    /// Scales the given square image down to each of the given icon types
+    /// (which can be a [`TypeSet`](struct.TypeSet.html) preset), and adds
+    /// the results to the family (along with their masks, where needed).
+    /// Returns an error if the image is not square, or is smaller than the
+    /// largest of the icon types, in which case the family is left
+    /// unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "pngio")] {
+    /// use icns::{IconFamily, Image, PixelFormat, TypeSet};
+    /// let image = Image::new(PixelFormat::RGBA, 1024, 1024);
+    /// let mut family = IconFamily::new();
+    /// family.add_icons_for_types(&image, TypeSet::MINIMAL).unwrap();
+    /// assert_eq!(family.available_icons(), TypeSet::MINIMAL.icon_types());
+    /// # }
+    /// ```
+    pub fn add_icons_for_types<T>(&mut self,
+                                  image: &Image,
+                                  icon_types: T)
+                                  -> io::Result<()>
+        where T: AsRef<[IconType]>
+    {
+        let icon_types = icon_types.as_ref();
+        let largest = icon_types.iter()
+            .map(|icon_type| icon_type.pixel_width())
+            .max()
+            .unwrap_or(0);
+        if image.width() != image.height() || image.width() < largest {
+            let msg = format!("image must be square and at least {}x{} (was \
+                               {}x{})",
+                              largest,
+                              largest,
+                              image.width(),
+                              image.height());
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let mut updated = self.clone();
+        let mut scaled: Vec<Image> = Vec::new();
+        for &icon_type in icon_types {
+            let size = icon_type.pixel_width();
+            // Several icon types share the same pixel size, so reuse scaled
+            // images where possible.
+            let index = match scaled.iter().position(|i| i.width() == size) {
+                Some(index) => index,
+                None => {
+                    scaled.push(image.resize(size, size));
+                    scaled.len() - 1
+                }
+            };
+            updated.add_icon_with_type(&scaled[index], icon_type)?;
+        }
+        self.elements = updated.elements;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "pngio")]
+    use super::super::image::PixelFormat;
+
+    #[test]
+    fn preset_type_sets() {
+        for set in &[TypeSet::APPLE_RECOMMENDED,
+                     TypeSet::TAURI_DEFAULT,
+                     TypeSet::MINIMAL] {
+            let types = set.icon_types();
+            assert!(types.iter().all(|icon_type| !icon_type.is_mask()));
+            for (index, icon_type) in types.iter().enumerate() {
+                assert!(!types[(index + 1)..].contains(icon_type));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn add_icons_for_type_set() {
+        let image = Image::new(PixelFormat::RGBA, 512, 512);
+        let mut family = IconFamily::new();
+        assert!(family.add_icons_for_types(&image, TypeSet::TAURI_DEFAULT)
+            .is_err());
+        assert!(family.is_empty());
+        let icon_types = [IconType::RGB24_32x32, IconType::RGBA32_256x256_2x];
+        family.add_icons_for_types(&image, &icon_types[..]).unwrap();
+        assert_eq!(family.available_icons(), icon_types.to_vec());
+        assert_eq!(family.elements.len(), 3);
+    }
+}
@@ -0,0 +1,180 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Error, ErrorKind};
+
+use super::element::IconElement;
+use super::family::IconFamily;
+use super::icontype::OSType;
+use super::payload::PayloadFormat;
+use super::pngcheck::PngHeader;
+
+/// A problem with the data payload of one element in an icon family, as
+/// found by [`IconFamily::validate`](struct.IconFamily.html#method.validate).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationProblem {
+    /// The index of the invalid element within the family.
+    pub index: usize,
+    /// The OSType of the invalid element.
+    pub ostype: OSType,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationProblem {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out,
+               "element {} ('{}'): {}",
+               self.index,
+               self.ostype,
+               self.message)
+    }
+}
+
+/// The error returned (wrapped in an `io::Error` of kind `InvalidData`) when
+/// reading an icon family with [`ReadLimits::validate_payloads`](
+/// struct.ReadLimits.html#method.validate_payloads) enabled, if any element
+/// payloads are invalid.  It can be recovered from the `io::Error` with
+/// `get_ref` and `downcast_ref`.
+///
+/// # Examples
+/// ```
+/// use icns::{IconFamily, ReadLimits, ValidationError};
+/// // A 16x16 mask element should have 256 bytes of data, not 1:
+/// let data: &[u8] = b"icns\0\0\0\x11s8mk\0\0\0\x09\0";
+/// let limits = ReadLimits::new().validate_payloads(true);
+/// let error = IconFamily::read_with_limits(data, &limits).unwrap_err();
+/// let error = error.get_ref()
+///     .and_then(|error| error.downcast_ref::<ValidationError>())
+///     .unwrap();
+/// assert_eq!(error.problems.len(), 1);
+/// assert_eq!(error.problems[0].index, 0);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    /// Every problem that was found, in element order.
+    pub problems: Vec<ValidationProblem>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "icon family has invalid elements")?;
+        for (index, problem) in self.problems.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { "; " };
+            write!(out, "{}{}", separator, problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ValidationError {}
+
+impl IconElement {
+    /// Checks that this element's data payload is valid for its icon type,
+    /// without necessarily decoding the whole image: RLE data must
+    /// decompress cleanly, masks must have the right length, and PNG data
+    /// must have intact chunks and the right dimensions.  (The dimensions of
+    /// JPEG 2000 data can't be checked.)  Elements that don't encode a
+    /// supported icon type are always considered valid.
+    pub fn validate(&self) -> io::Result<()> {
+        let icon_type = match self.icon_type() {
+            Some(icon_type) => icon_type,
+            None => return Ok(()),
+        };
+        match self.payload_format() {
+            PayloadFormat::PNG => {}
+            PayloadFormat::JPEG2000 => return Ok(()),
+            _ => return self.decode_image().map(|_| ()),
+        }
+        if let Some(problem) = self.check_png_chunks() {
+            let msg = format!("damaged PNG data: {}", problem);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let header = PngHeader::parse(&self.data)?;
+        let width = icon_type.pixel_width();
+        let height = icon_type.pixel_height();
+        if header.width != width || header.height != height {
+            let msg = format!("PNG has wrong dimensions ({}x{} instead of \
+                               {}x{})",
+                              header.width,
+                              header.height,
+                              width,
+                              height);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        Ok(())
+    }
+}
+
+impl IconFamily {
+    /// Checks every element in the family with
+    /// [`IconElement::validate`](struct.IconElement.html#method.validate),
+    /// and returns all the problems found (or an empty vector if every
+    /// element is valid).
+    pub fn validate(&self) -> Vec<ValidationProblem> {
+        self.elements
+            .iter()
+            .enumerate()
+            .filter_map(|(index, element)| {
+                element.validate().err().map(|error| {
+                    ValidationProblem {
+                        index,
+                        ostype: element.ostype,
+                        message: error.to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::family::ReadLimits;
+    use super::super::icontype::IconType;
+    use super::super::image::{Image, PixelFormat};
+    use std::io::Cursor;
+
+    #[test]
+    #[cfg(feature = "pngio")]
+    fn validate_elements() {
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        family.add_icon_with_type(&image, IconType::RGBA32_32x32).unwrap();
+        family.elements.push(IconElement::new(OSType(*b"quux"), vec![1]));
+        assert_eq!(family.validate(), vec![]);
+        // Corrupt the RLE data, truncate the mask, and put the PNG data in
+        // an element of the wrong size.
+        family.elements[0].data = vec![0x80];
+        family.elements[1].data.pop();
+        family.elements[2].ostype = OSType(*b"ic07");
+        let problems = family.validate();
+        assert_eq!(problems.iter().map(|p| p.index).collect::<Vec<_>>(),
+                   vec![0, 1, 2]);
+        assert_eq!(problems[2].message,
+                   "PNG has wrong dimensions (32x32 instead of 128x128)");
+    }
+
+    #[test]
+    fn read_with_validation() {
+        let image = Image::new(PixelFormat::RGBA, 16, 16);
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+        family.elements[0].data = vec![0x80];
+        family.elements[1].data.truncate(10);
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        assert!(IconFamily::read(Cursor::new(&data)).is_ok());
+        let limits = ReadLimits::new().validate_payloads(true);
+        let error = IconFamily::read_with_limits(Cursor::new(&data), &limits)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = error.into_inner()
+            .unwrap()
+            .downcast::<ValidationError>()
+            .unwrap();
+        assert_eq!(error.problems.len(), 2);
+        assert_eq!(error.problems[1].ostype, OSType(*b"s8mk"));
+    }
+}
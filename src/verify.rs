@@ -0,0 +1,181 @@
+use super::family::IconFamily;
+use super::icontype::IconType;
+use super::image::{Image, PixelFormat};
+
+/// The result of re-encoding a single icon, as reported by
+/// [`IconFamily::verify_reencode`](
+/// struct.IconFamily.html#method.verify_reencode).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ReencodeCheck {
+    /// The icon type that was checked.
+    pub icon_type: IconType,
+    /// The number of pixels whose RGBA values changed when the icon was
+    /// re-encoded and decoded again (zero if `error` is set).
+    pub differing_pixels: u64,
+    /// A description of the error, if the icon couldn't be decoded or
+    /// re-encoded.
+    pub error: Option<String>,
+}
+
+impl ReencodeCheck {
+    /// Returns true if re-encoding the icon succeeded and left every pixel
+    /// unchanged.
+    pub fn is_lossless(&self) -> bool {
+        self.error.is_none() && self.differing_pixels == 0
+    }
+}
+
+/// A report of whether re-encoding each icon in an icon family is lossless,
+/// as returned by [`IconFamily::verify_reencode`](
+/// struct.IconFamily.html#method.verify_reencode).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReencodeReport {
+    /// The result for each (complete) icon in the family, in the order that
+    /// the icons appear in the family.
+    pub checks: Vec<ReencodeCheck>,
+}
+
+impl ReencodeReport {
+    /// Returns true if re-encoding every icon was lossless.
+    pub fn is_lossless(&self) -> bool {
+        self.checks.iter().all(ReencodeCheck::is_lossless)
+    }
+
+    /// Returns the icon types whose pixels changed when re-encoded.
+    pub fn changed_types(&self) -> Vec<IconType> {
+        self.checks
+            .iter()
+            .filter(|check| check.error.is_none() && !check.is_lossless())
+            .map(|check| check.icon_type)
+            .collect()
+    }
+
+    /// Returns the icon types that couldn't be decoded or re-encoded.
+    pub fn failed_types(&self) -> Vec<IconType> {
+        self.checks
+            .iter()
+            .filter(|check| check.error.is_some())
+            .map(|check| check.icon_type)
+            .collect()
+    }
+}
+
+impl IconFamily {
+    /// Decodes each (complete) icon in the family, re-encodes it with the
+    /// same icon type (as [`add_icon_with_type`](
+    /// #method.add_icon_with_type) would, using this family's settings),
+    /// decodes the result again, and compares the pixels of the two decoded
+    /// images.  This checks whether the family's icons survive being
+    /// re-encoded by this library (for example, before converting or
+    /// optimizing the family).  Each icon is only compared with its own
+    /// re-encoding, so this can't detect changes that were already made to
+    /// the family's data; to check an optimization pipeline, decode the
+    /// icons before and after it and compare those instead.
+    ///
+    /// An icon that can't be decoded or re-encoded doesn't stop the other
+    /// icons from being checked; its error is recorded in its
+    /// [`ReencodeCheck`](struct.ReencodeCheck.html) instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use icns::{IconFamily, IconType, Image, PixelFormat};
+    /// let mut family = IconFamily::new();
+    /// let image = Image::new(PixelFormat::RGBA, 16, 16);
+    /// family.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+    /// let report = family.verify_reencode();
+    /// assert_eq!(report.checks.len(), 1);
+    /// assert!(report.is_lossless());
+    /// ```
+    pub fn verify_reencode(&self) -> ReencodeReport {
+        let mut scratch = self.empty_copy();
+        let checks = self.available_icons()
+            .into_iter()
+            .map(|icon_type| {
+                let result = self.get_icon_with_type(icon_type)
+                    .and_then(|original| {
+                        scratch.elements.clear();
+                        scratch.add_icon_with_type(&original, icon_type)?;
+                        let reencoded =
+                            scratch.get_icon_with_type(icon_type)?;
+                        Ok(differing_pixels(&original, &reencoded))
+                    });
+                match result {
+                    Ok(differing_pixels) => {
+                        ReencodeCheck {
+                            icon_type,
+                            differing_pixels,
+                            error: None,
+                        }
+                    }
+                    Err(error) => {
+                        ReencodeCheck {
+                            icon_type,
+                            differing_pixels: 0,
+                            error: Some(error.to_string()),
+                        }
+                    }
+                }
+            })
+            .collect();
+        ReencodeReport { checks }
+    }
+}
+
+/// Returns the number of pixels whose RGBA values differ between two images
+/// (counting every pixel as different if the dimensions differ).
+fn differing_pixels(first: &Image, second: &Image) -> u64 {
+    if first.width() != second.width() || first.height() != second.height() {
+        return u64::from(first.width()) * u64::from(first.height());
+    }
+    let first = first.convert_to(PixelFormat::RGBA);
+    let second = second.convert_to(PixelFormat::RGBA);
+    first.data()
+        .chunks(4)
+        .zip(second.data().chunks(4))
+        .filter(|&(a, b)| a != b)
+        .count() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::element::IconElement;
+    use super::super::fixtures::test_image;
+
+    #[test]
+    fn reencode_is_lossless() {
+        let mut family = IconFamily::new();
+        let image = test_image(PixelFormat::RGBA, 32, 32);
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        let report = family.verify_reencode();
+        assert!(report.is_lossless());
+        assert!(report.changed_types().is_empty());
+    }
+
+    #[test]
+    fn count_differing_pixels() {
+        let first = test_image(PixelFormat::RGBA, 4, 4);
+        let mut second = first.clone();
+        second.data_mut()[0] ^= 1;
+        second.data_mut()[7] ^= 1;
+        assert_eq!(differing_pixels(&first, &second), 2);
+        let small = test_image(PixelFormat::RGBA, 2, 2);
+        assert_eq!(differing_pixels(&first, &small), 16);
+    }
+
+    #[test]
+    fn undecodable_icon_is_reported() {
+        let image = test_image(PixelFormat::RGBA, 32, 32);
+        let mut family = IconFamily::from_elements(vec![
+            IconElement::new(IconType::RGB24_16x16.ostype(), vec![0xff]),
+            IconElement::new(IconType::Mask8_16x16.ostype(), vec![0; 256]),
+        ]);
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        let report = family.verify_reencode();
+        assert_eq!(report.checks.len(), 2);
+        assert!(!report.is_lossless());
+        assert_eq!(report.failed_types(), vec![IconType::RGB24_16x16]);
+        assert!(report.changed_types().is_empty());
+        assert!(report.checks[1].is_lossless());
+    }
+}
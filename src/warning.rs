@@ -0,0 +1,375 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::element::IT32_PREFIX;
+use super::family::IconFamily;
+use super::iconutil::TOC_OSTYPE;
+use super::icontype::{IconType, OSType};
+use super::kind::is_known_ostype;
+
+/// A recoverable oddity found while reading an icon family with
+/// [`IconFamily::read_with`](struct.IconFamily.html#method.read_with), or
+/// while decoding an icon with [`IconFamily::get_icon_with_warnings`](
+/// struct.IconFamily.html#method.get_icon_with_warnings).  None of these
+/// prevent the family from being used, but each suggests that the file was
+/// produced by a tool that doesn't quite follow Apple's conventions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// An element has an OSType that is neither a supported icon type nor a
+    /// known metadata type (such as `TOC ` or `icnV`).
+    UnknownOSType {
+        /// The index of the element within the family.
+        index: usize,
+        /// The element's OSType.
+        ostype: OSType,
+    },
+    /// An element has the same OSType as an earlier element; only the first
+    /// one will be used by methods like `get_icon_with_type`.
+    DuplicateType {
+        /// The index of the duplicate element within the family.
+        index: usize,
+        /// The index of the earlier element with the same OSType.
+        first_index: usize,
+        /// The OSType shared by both elements.
+        ostype: OSType,
+    },
+    /// The total length declared in the file header is less than the total
+    /// length of the elements that were actually read (which happens when
+    /// the last element runs past the declared end of the file).
+    LengthMismatch {
+        /// The total file length declared in the header.
+        declared: u32,
+        /// The number of bytes actually consumed, including the header.
+        actual: u32,
+    },
+    /// The total length declared in the file header is greater than the
+    /// total length of the elements that were actually read (which happens
+    /// when the file ends, between two elements, before the declared end).
+    LengthSlack {
+        /// The total file length declared in the header.
+        declared: u32,
+        /// The number of bytes actually read, including the header.
+        actual: u32,
+    },
+    /// An `it32` element is missing the four zero bytes that normally
+    /// precede its RLE data.  (The data can still be decoded.)
+    MissingIt32Prefix {
+        /// The index of the element within the family.
+        index: usize,
+    },
+    /// The `TOC ` (table of contents) element's data payload isn't a whole
+    /// number of eight-byte entries.  (Any complete entries are still
+    /// checked; see [`TableOfContentsMismatch`](
+    /// #variant.TableOfContentsMismatch).)
+    TableOfContentsTrailingBytes {
+        /// The index of the `TOC ` element within the family.
+        index: usize,
+        /// The number of bytes after the last complete entry.
+        count: usize,
+    },
+    /// An entry in the `TOC ` (table of contents) element doesn't match the
+    /// element at the same position among the family's other elements.
+    TableOfContentsMismatch {
+        /// The position of the entry within the table.
+        entry: usize,
+        /// The OSType and element length listed by the entry, or `None` if
+        /// the table has too few entries.
+        listed: Option<(OSType, u32)>,
+        /// The OSType and total length of the element at that position, or
+        /// `None` if the family has too few elements.
+        found: Option<(OSType, u32)>,
+    },
+    /// An icon's separate mask element is missing, so the icon was decoded
+    /// as fully opaque (see [`MissingMask`](enum.MissingMask.html)).
+    MissingMask {
+        /// The OSType of the missing mask element.
+        ostype: OSType,
+    },
+    /// A mask element's data payload had the wrong length, so it was
+    /// truncated or padded to fit (see [`MaskLength`](enum.MaskLength.html)).
+    MaskLengthMismatch {
+        /// The OSType of the mask element.
+        ostype: OSType,
+        /// The length of the mask element's data payload.
+        actual: usize,
+        /// The length that the mask type calls for.
+        expected: usize,
+    },
+    /// The requested icon type wasn't in the family, so it was synthesized
+    /// by scaling another icon (see [`MissingIcon`](enum.MissingIcon.html)).
+    SynthesizedIcon {
+        /// The OSType of the requested icon type.
+        ostype: OSType,
+        /// The OSType of the icon that was scaled in its place.
+        source: OSType,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::UnknownOSType { index, ostype } => {
+                write!(out, "element {} has unknown type '{}'", index, ostype)
+            }
+            Warning::DuplicateType { index, first_index, ostype } => {
+                write!(out,
+                       "element {} duplicates type '{}' of element {}",
+                       index,
+                       ostype,
+                       first_index)
+            }
+            Warning::LengthMismatch { declared, actual } => {
+                write!(out,
+                       "header declares {} bytes, but elements total {}",
+                       declared,
+                       actual)
+            }
+            Warning::LengthSlack { declared, actual } => {
+                write!(out,
+                       "header declares {} bytes, but the file ends after {}",
+                       declared,
+                       actual)
+            }
+            Warning::MissingIt32Prefix { index } => {
+                write!(out,
+                       "element {} ('it32') lacks the four-byte zero prefix",
+                       index)
+            }
+            Warning::TableOfContentsTrailingBytes { index, count } => {
+                write!(out,
+                       "element {} ('TOC ') has {} trailing byte(s) after its \
+                        last entry",
+                       index,
+                       count)
+            }
+            Warning::TableOfContentsMismatch { entry, listed, found } => {
+                match (listed, found) {
+                    (Some((ostype, length)), Some((found, found_length))) => {
+                        write!(out,
+                               "table of contents entry {} lists '{}' ({} \
+                                bytes), but found '{}' ({} bytes)",
+                               entry,
+                               ostype,
+                               length,
+                               found,
+                               found_length)
+                    }
+                    (Some((ostype, length)), None) => {
+                        write!(out,
+                               "table of contents entry {} lists '{}' ({} \
+                                bytes), but there is no such element",
+                               entry,
+                               ostype,
+                               length)
+                    }
+                    (None, Some((found, _))) => {
+                        write!(out,
+                               "element '{}' is missing from the table of \
+                                contents",
+                               found)
+                    }
+                    (None, None) => {
+                        write!(out,
+                               "table of contents entry {} is empty",
+                               entry)
+                    }
+                }
+            }
+            Warning::MissingMask { ostype } => {
+                write!(out, "mask element '{}' is missing", ostype)
+            }
+            Warning::MaskLengthMismatch { ostype, actual, expected } => {
+                write!(out,
+                       "mask element '{}' has {} bytes instead of {}",
+                       ostype,
+                       actual,
+                       expected)
+            }
+            Warning::SynthesizedIcon { ostype, source } => {
+                write!(out, "'{}' was synthesized from '{}'", ostype, source)
+            }
+        }
+    }
+}
+
+/// Returns the recoverable oddities in a family that was just read from a
+/// file whose header declared the given total length, in the order they
+/// occur.
+pub(crate) fn read_warnings(family: &IconFamily,
+                            declared: u32)
+                            -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let actual = family.total_length();
+    if actual > declared {
+        warnings.push(Warning::LengthMismatch { declared, actual });
+    } else if actual < declared {
+        warnings.push(Warning::LengthSlack { declared, actual });
+    }
+    let mut first_indices = HashMap::new();
+    for (index, element) in family.elements.iter().enumerate() {
+        let ostype = element.ostype;
+        let icon_type = element.icon_type();
+        if icon_type.is_none() && !is_known_ostype(ostype) {
+            warnings.push(Warning::UnknownOSType { index, ostype });
+        }
+        if let Some(&first_index) = first_indices.get(&ostype) {
+            warnings.push(Warning::DuplicateType {
+                index,
+                first_index,
+                ostype,
+            });
+        } else {
+            first_indices.insert(ostype, index);
+        }
+        if icon_type == Some(IconType::RGB24_128x128) &&
+           !element.data.starts_with(&IT32_PREFIX) {
+            warnings.push(Warning::MissingIt32Prefix { index });
+        }
+        if ostype == TOC_OSTYPE && first_indices[&ostype] == index {
+            check_table_of_contents(family, index, &mut warnings);
+        }
+    }
+    warnings
+}
+
+/// Compares the entries of the `TOC ` element at the given index, each of
+/// which is an OSType and an element length, against the family's other
+/// elements, adding a warning for each discrepancy.
+fn check_table_of_contents(family: &IconFamily,
+                           index: usize,
+                           warnings: &mut Vec<Warning>) {
+    let data = &family.elements[index].data;
+    let count = data.len() % 8;
+    if count != 0 {
+        warnings.push(Warning::TableOfContentsTrailingBytes { index, count });
+    }
+    let listed: Vec<(OSType, u32)> = data.chunks_exact(8)
+        .map(|chunk| {
+            let ostype = OSType([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            (ostype, BigEndian::read_u32(&chunk[4..]))
+        })
+        .collect();
+    let found: Vec<(OSType, u32)> = family.elements
+        .iter()
+        .filter(|element| element.ostype != TOC_OSTYPE)
+        .map(|element| (element.ostype, element.total_length()))
+        .collect();
+    for entry in 0..listed.len().max(found.len()) {
+        let listed = listed.get(entry).cloned();
+        let found = found.get(entry).cloned();
+        if listed != found {
+            warnings.push(Warning::TableOfContentsMismatch {
+                entry,
+                listed,
+                found,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixtures::FixtureBuilder;
+    use super::super::iconutil::toc_element;
+    use super::super::image::{Image, PixelFormat};
+    use super::super::options::ReadOptions;
+
+    #[test]
+    fn clean_family_has_no_warnings() {
+        let data = FixtureBuilder::new()
+            .icon(IconType::RGB24_128x128)
+            .version_element()
+            .build();
+        let (read, warnings) =
+            IconFamily::read_with(data.as_slice(), &ReadOptions::new())
+                .unwrap();
+        assert_eq!(read.elements.len(), 3);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn it32_without_prefix_and_length_overrun() {
+        let mut family = IconFamily::new();
+        let image = Image::new(PixelFormat::RGB, 128, 128);
+        family.add_icon_with_type(&image, IconType::RGB24_128x128).unwrap();
+        family.elements[0].data.drain(..4);
+        let mut data = Vec::new();
+        family.write(&mut data).unwrap();
+        // Make the header claim the file is one byte shorter than it is:
+        let actual = family.total_length();
+        let declared = actual - 1;
+        data[4..8].copy_from_slice(&declared.to_be_bytes());
+        let (_, warnings) =
+            IconFamily::read_with(data.as_slice(), &ReadOptions::new())
+                .unwrap();
+        assert_eq!(warnings,
+                   vec![Warning::LengthMismatch { declared, actual },
+                        Warning::MissingIt32Prefix { index: 0 }]);
+    }
+
+    #[test]
+    fn file_ending_before_declared_length() {
+        let data: &[u8] = b"icns\0\0\0\x20icnV\0\0\0\x0c\0\0\0\0";
+        assert!(IconFamily::read(data).is_err());
+        let (family, warnings) =
+            IconFamily::read_with(data, &ReadOptions::new()).unwrap();
+        assert_eq!(family.elements().len(), 1);
+        assert_eq!(warnings,
+                   vec![Warning::LengthSlack {
+                            declared: 32,
+                            actual: 20,
+                        }]);
+        // Ending partway through an element is still an error:
+        assert!(IconFamily::read_with(&data[..18], &ReadOptions::new())
+            .is_err());
+    }
+
+    #[test]
+    fn stale_table_of_contents() {
+        let mut family = FixtureBuilder::new()
+            .icon(IconType::RGB24_16x16)
+            .version_element()
+            .family();
+        let toc = toc_element(&family.elements);
+        family.elements.insert(0, toc.clone());
+        assert!(read_warnings(&family, family.total_length()).is_empty());
+        let is32 = (OSType(*b"is32"), family.elements[1].total_length());
+        let icnv = (OSType(*b"icnV"), 12);
+        // Misstate the first entry's length, and replace the last entry with
+        // a stray byte:
+        family.elements[0].data[7] ^= 1;
+        family.elements[0].data.truncate(16);
+        family.elements[0].data.push(0);
+        let warnings = read_warnings(&family, family.total_length());
+        assert_eq!(warnings,
+                   vec![Warning::TableOfContentsTrailingBytes {
+                            index: 0,
+                            count: 1,
+                        },
+                        Warning::TableOfContentsMismatch {
+                            entry: 0,
+                            listed: Some((is32.0, is32.1 ^ 1)),
+                            found: Some(is32),
+                        },
+                        Warning::TableOfContentsMismatch {
+                            entry: 2,
+                            listed: None,
+                            found: Some(icnv),
+                        }]);
+        assert_eq!(warnings[2].to_string(),
+                   "element 'icnV' is missing from the table of contents");
+        // A second TOC element is a duplicate, but isn't checked itself:
+        family.elements[0] = toc.clone();
+        family.elements.push(toc);
+        let warnings = read_warnings(&family, family.total_length());
+        assert_eq!(warnings,
+                   vec![Warning::DuplicateType {
+                            index: 4,
+                            first_index: 0,
+                            ostype: TOC_OSTYPE,
+                        }]);
+    }
+}
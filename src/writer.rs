@@ -0,0 +1,141 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use std::io::{self, Error, ErrorKind, Seek, SeekFrom, Write};
+
+use super::element::IconElement;
+use super::family::{ICNS_MAGIC_LITERAL, ICON_FAMILY_HEADER_LENGTH};
+use super::icontype::IconType;
+use super::image::Image;
+
+/// Writes an ICNS file one element at a time, without needing to hold the
+/// whole icon family in memory or know its length in advance.  A placeholder
+/// file length is written when the writer is created, and is patched with
+/// the real length (by seeking back to the start of the file) when the writer
+/// is [finished](#method.finish).
+///
+/// # Examples
+/// ```
+/// use icns::{IconFamily, IconFamilyWriter, IconType, Image, PixelFormat};
+/// use std::io::Cursor;
+/// let mut writer = IconFamilyWriter::new(Cursor::new(Vec::new())).unwrap();
+/// let image = Image::new(PixelFormat::RGBA, 16, 16);
+/// writer.add_icon_with_type(&image, IconType::RGB24_16x16).unwrap();
+/// let data = writer.finish().unwrap().into_inner();
+/// let family = IconFamily::read(Cursor::new(data)).unwrap();
+/// assert!(family.has_icon_with_type(IconType::RGB24_16x16));
+/// ```
+pub struct IconFamilyWriter<W: Write + Seek> {
+    writer: W,
+    start: u64,
+    length: u32,
+}
+
+impl<W: Write + Seek> IconFamilyWriter<W> {
+    /// Creates a new writer that will write an ICNS file starting at the
+    /// current position of the underlying writer, and writes the file header
+    /// (with a placeholder length).
+    pub fn new(mut writer: W) -> io::Result<IconFamilyWriter<W>> {
+        let start = writer.stream_position()?;
+        writer.write_all(ICNS_MAGIC_LITERAL)?;
+        writer.write_u32::<BigEndian>(0)?;
+        Ok(IconFamilyWriter {
+            writer,
+            start,
+            length: ICON_FAMILY_HEADER_LENGTH,
+        })
+    }
+
+    /// Writes an element to the file.  Returns an error if this would make
+    /// the file longer than the ICNS format allows.
+    pub fn write_element(&mut self, element: &IconElement) -> io::Result<()> {
+        let length = self.length
+            .checked_add(element.total_length())
+            .ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput,
+                           "icon family is too large for an ICNS file")
+            })?;
+        element.write(self.writer.by_ref())?;
+        self.length = length;
+        Ok(())
+    }
+
+    /// Encodes the image using the given icon type and writes it to the file
+    /// (along with its mask element, if the type has an associated mask
+    /// type), as with
+    /// [`IconFamily::add_icon_with_type`](
+    /// struct.IconFamily.html#method.add_icon_with_type).  Returns an error
+    /// if the image has the wrong dimensions for the selected type.
+    pub fn add_icon_with_type(&mut self,
+                              image: &Image,
+                              icon_type: IconType)
+                              -> io::Result<()> {
+        let element = IconElement::encode_image_with_type(image, icon_type)?;
+        let mask = match icon_type.mask_type() {
+            Some(mask_type) => {
+                Some(IconElement::encode_image_with_type(image, mask_type)?)
+            }
+            None => None,
+        };
+        self.write_element(&element)?;
+        if let Some(mask) = mask {
+            self.write_element(&mask)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the length of the file written so far, in bytes, including
+    /// the length of the header.
+    pub fn total_length(&self) -> u32 {
+        self.length
+    }
+
+    /// Patches the file header with the real file length, and returns the
+    /// underlying writer, positioned at the end of the file.
+    pub fn finish(mut self) -> io::Result<W> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.start + 4))?;
+        self.writer.write_u32::<BigEndian>(self.length)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::family::IconFamily;
+    use super::super::icontype::OSType;
+    use super::super::image::PixelFormat;
+    use std::io::Cursor;
+
+    #[test]
+    fn write_elements_after_existing_data() {
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write_all(b"junk").unwrap();
+        let mut writer = IconFamilyWriter::new(cursor).unwrap();
+        writer.write_element(&IconElement::new(OSType(*b"quux"), vec![1]))
+            .unwrap();
+        writer.write_element(&IconElement::new(OSType(*b"baz!"), vec![2, 3]))
+            .unwrap();
+        assert_eq!(writer.total_length(), 27);
+        let cursor = writer.finish().unwrap();
+        assert_eq!(cursor.position(), 31);
+        let expected: &[u8] = b"junkicns\0\0\0\x1b\
+                                quux\0\0\0\x09\x01\
+                                baz!\0\0\0\x0a\x02\x03";
+        assert_eq!(cursor.get_ref(), expected);
+    }
+
+    #[test]
+    fn write_same_as_icon_family() {
+        let image = Image::new(PixelFormat::RGBA, 32, 32);
+        let mut family = IconFamily::new();
+        family.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        let mut expected = Vec::new();
+        family.write(&mut expected).unwrap();
+        let mut writer = IconFamilyWriter::new(Cursor::new(Vec::new()))
+            .unwrap();
+        writer.add_icon_with_type(&image, IconType::RGB24_32x32).unwrap();
+        assert_eq!(writer.finish().unwrap().into_inner(), expected);
+    }
+}